@@ -0,0 +1,526 @@
+//!
+//! DBC file model and parser
+//!
+//! Only the parts that describe message layouts are read: messages (`BO_`), signals
+//! (`SG_`), value descriptions (`VAL_`), signal value types (`SIG_VALTYPE_`) and the
+//! `VFrameFormat` attribute of messages. Everything else is skipped.
+//!
+use crate::Error;
+
+/// Id of the pseudo message holding signals that are not part of any message
+const INDEPENDENT_SIGNALS_ID: u32 = 0xC000_0000;
+/// Set in the DBC id of extended frames
+const EXTENDED_FLAG: u32 = 0x8000_0000;
+
+/// `VFrameFormat` values of CAN FD messages
+const FRAME_FORMAT_STANDARD_FD: i64 = 14;
+const FRAME_FORMAT_EXTENDED_FD: i64 = 15;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dbc {
+    pub messages: Vec<Message>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// Without the extended flag
+    pub id: u32,
+    pub extended: bool,
+    pub name: String,
+    /// Payload length in bytes
+    pub length: usize,
+    pub transmitter: String,
+    pub fd_format: bool,
+    pub signals: Vec<Signal>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplexing {
+    None,
+    /// The multiplexor switch of the message
+    Multiplexor,
+    /// Only present if the multiplexor has the given value
+    Multiplexed(u64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signal {
+    pub name: String,
+    pub multiplexing: Multiplexing,
+    pub start: u16,
+    pub length: u8,
+    pub byte_order: ByteOrder,
+    pub signed: bool,
+    pub factor: f64,
+    pub offset: f64,
+    pub min: f64,
+    pub max: f64,
+    pub unit: String,
+    /// Value descriptions, in the order of the DBC
+    pub values: Vec<(i64, String)>,
+}
+
+impl Message {
+    pub fn signal_mut(&mut self, name: &str) -> Option<&mut Signal> {
+        self.signals.iter_mut().find(|signal| signal.name == name)
+    }
+
+    pub fn multiplexor(&self) -> Option<&Signal> {
+        self.signals
+            .iter()
+            .find(|signal| signal.multiplexing == Multiplexing::Multiplexor)
+    }
+}
+
+impl Signal {
+    /// Whether the signal fits into a message of `length` bytes
+    fn fits(&self, length: usize) -> bool {
+        let bits = length * 8;
+        match self.byte_order {
+            ByteOrder::LittleEndian => self.start as usize + self.length as usize <= bits,
+            ByteOrder::BigEndian => {
+                // The least significant bit is the furthest one in the byte sequence
+                let msb_byte = self.start as usize / 8;
+                let bits_in_msb_byte = self.start as usize % 8 + 1;
+                let remaining = (self.length as usize).saturating_sub(bits_in_msb_byte);
+                msb_byte + remaining.div_ceil(8) < length
+            }
+        }
+    }
+}
+
+/// Parse the message layouts of a DBC file
+pub fn parse(source: &str) -> Result<Dbc, Error> {
+    let mut dbc = Dbc {
+        messages: Vec::new(),
+    };
+    let mut lines = source.lines().enumerate().peekable();
+    let mut in_new_symbols = false;
+    // Signals following a skipped message are skipped as well
+    let mut in_skipped_message = false;
+
+    while let Some((index, line)) = lines.next() {
+        let number = index + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        // The new symbols section lists keywords, one per indented line
+        if in_new_symbols && line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        in_new_symbols = false;
+
+        let keyword = trimmed.split_whitespace().next().unwrap_or_default();
+        match keyword {
+            "NS_" => in_new_symbols = true,
+            "BO_" => {
+                let message = parse_message(trimmed, number)?;
+                in_skipped_message = message.is_none();
+                dbc.messages.extend(message);
+            }
+            "SG_" => {
+                let Some(message) = dbc.messages.last_mut() else {
+                    continue;
+                };
+                if in_skipped_message {
+                    continue;
+                }
+                let signal = parse_signal(trimmed, number)?;
+                if !signal.fits(message.length) {
+                    return Err(Error::new(
+                        number,
+                        format!("Signal {} exceeds message {}", signal.name, message.name),
+                    ));
+                }
+                message.signals.push(signal);
+            }
+            "VAL_" | "SIG_VALTYPE_" | "BA_" => {
+                let statement = read_statement(trimmed, &mut lines);
+                match keyword {
+                    "VAL_" => parse_value_descriptions(&statement, number, &mut dbc)?,
+                    "SIG_VALTYPE_" => parse_value_type(&statement, number, &dbc)?,
+                    _ => parse_attribute(&statement, number, &mut dbc)?,
+                }
+            }
+            // Comments, attribute definitions etc. may span several lines
+            _ if !trimmed.contains(';') && has_statement_end(keyword) => {
+                read_statement(trimmed, &mut lines);
+            }
+            _ => {}
+        }
+    }
+
+    for message in &dbc.messages {
+        check_multiplexing(message)?;
+    }
+
+    Ok(dbc)
+}
+
+/// Keywords whose statements end with a semicolon
+fn has_statement_end(keyword: &str) -> bool {
+    matches!(
+        keyword,
+        "CM_"
+            | "BA_DEF_"
+            | "BA_DEF_DEF_"
+            | "BA_DEF_REL_"
+            | "BA_DEF_DEF_REL_"
+            | "BA_REL_"
+            | "VAL_TABLE_"
+            | "EV_"
+            | "ENVVAR_DATA_"
+            | "SIG_GROUP_"
+            | "BO_TX_BU_"
+            | "SG_MUL_VAL_"
+            | "CAT_DEF_"
+            | "CAT_"
+            | "FILTER"
+            | "SIGTYPE_"
+            | "SIG_TYPE_REF_"
+    )
+}
+
+/// Join the lines of a statement up to the semicolon that is not part of a string
+fn read_statement<'a>(first: &str, lines: &mut impl Iterator<Item = (usize, &'a str)>) -> String {
+    let mut statement = first.to_string();
+    while !ends_statement(&statement) {
+        let Some((_, line)) = lines.next() else {
+            break;
+        };
+        statement.push('\n');
+        statement.push_str(line);
+    }
+    statement
+}
+
+fn ends_statement(statement: &str) -> bool {
+    let mut in_string = false;
+    let mut previous = '\0';
+    for c in statement.chars() {
+        match c {
+            '"' if previous != '\\' => in_string = !in_string,
+            ';' if !in_string => return true,
+            _ => {}
+        }
+        previous = c;
+    }
+    false
+}
+
+/// Splits a statement into words, numbers, strings and punctuation
+struct Tokens<'a> {
+    rest: &'a str,
+    line: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(statement: &'a str, line: usize) -> Self {
+        Tokens {
+            rest: statement,
+            line,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error::new(self.line, message.into())
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.rest = self.rest.trim_start();
+        let first = self.rest.chars().next()?;
+
+        let length = if first == '"' {
+            let mut escaped = false;
+            let end = self.rest[1..].find(|c| {
+                let end = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                end
+            });
+            end.map_or(self.rest.len(), |end| end + 2)
+        } else if "|@(),[]:;".contains(first) {
+            1
+        } else if first == '+' || first == '-' {
+            // A sign of a number or the sign of a signal (`@1+`)
+            let number = self.rest[1..].starts_with(|c: char| c.is_ascii_digit() || c == '.');
+            if number {
+                1 + number_length(&self.rest[1..])
+            } else {
+                1
+            }
+        } else if first.is_ascii_digit() || first == '.' {
+            number_length(self.rest)
+        } else {
+            self.rest
+                .find(|c: char| c.is_whitespace() || "|@(),[]:;\"".contains(c))
+                .unwrap_or(self.rest.len())
+        };
+
+        let (token, rest) = self.rest.split_at(length);
+        self.rest = rest;
+        Some(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), Error> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            token => Err(self.error(format!(
+                "Expected `{}`, found `{}`",
+                expected,
+                token.unwrap_or("end of statement")
+            ))),
+        }
+    }
+
+    fn word(&mut self) -> Result<&'a str, Error> {
+        self.next()
+            .ok_or_else(|| self.error("Unexpected end of statement"))
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let token = self.word()?;
+        if token.len() < 2 || !token.starts_with('"') || !token.ends_with('"') {
+            return Err(self.error(format!("Expected a string, found `{}`", token)));
+        }
+        Ok(token[1..token.len() - 1].replace("\\\"", "\""))
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, Error> {
+        let token = self.word()?;
+        token
+            .parse()
+            .map_err(|_| self.error(format!("Expected a number, found `{}`", token)))
+    }
+}
+
+/// Length of the number at the start of `text`, including exponents like `1E-005`
+fn number_length(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut length = 0;
+    while length < bytes.len() {
+        let c = bytes[length];
+        let exponent_sign =
+            (c == b'+' || c == b'-') && length > 0 && matches!(bytes[length - 1], b'e' | b'E');
+        if !(c.is_ascii_digit() || matches!(c, b'.' | b'e' | b'E') || exponent_sign) {
+            break;
+        }
+        length += 1;
+    }
+    length
+}
+
+/// `BO_ <id> <name>: <length> <transmitter>`, None for the pseudo message of
+/// independent signals
+fn parse_message(line: &str, number: usize) -> Result<Option<Message>, Error> {
+    let mut tokens = Tokens::new(line, number);
+    tokens.expect("BO_")?;
+    let raw_id: u32 = tokens.number()?;
+    let name = tokens.word()?.to_string();
+    tokens.expect(":")?;
+    let length = tokens.number()?;
+    let transmitter = tokens.next().unwrap_or("Vector__XXX").to_string();
+
+    if raw_id == INDEPENDENT_SIGNALS_ID {
+        return Ok(None);
+    }
+    if length > 64 {
+        return Err(tokens.error(format!("Message {} exceeds 64 bytes", name)));
+    }
+
+    let extended = raw_id & EXTENDED_FLAG != 0;
+    let id = raw_id & !EXTENDED_FLAG;
+    if (!extended && id > 0x7FF) || id > 0x1FFF_FFFF {
+        return Err(tokens.error(format!("Invalid id of message {}", name)));
+    }
+
+    Ok(Some(Message {
+        id,
+        extended,
+        name,
+        length,
+        transmitter,
+        fd_format: false,
+        signals: Vec::new(),
+    }))
+}
+
+/// `SG_ <name> [M|m<n>] : <start>|<length>@<order><sign> (<factor>,<offset>)
+/// [<min>|<max>] "<unit>" <receivers>`
+fn parse_signal(line: &str, number: usize) -> Result<Signal, Error> {
+    let mut tokens = Tokens::new(line, number);
+    tokens.expect("SG_")?;
+    let name = tokens.word()?.to_string();
+
+    let multiplexing = match tokens.word()? {
+        ":" => Multiplexing::None,
+        "M" => {
+            tokens.expect(":")?;
+            Multiplexing::Multiplexor
+        }
+        indicator if indicator.starts_with('m') => {
+            let value = indicator[1..].parse().map_err(|_| {
+                tokens.error(format!(
+                    "Extended multiplexing of signal {} is not supported",
+                    name
+                ))
+            })?;
+            tokens.expect(":")?;
+            Multiplexing::Multiplexed(value)
+        }
+        token => return Err(tokens.error(format!("Unexpected `{}`", token))),
+    };
+
+    let start = tokens.number()?;
+    tokens.expect("|")?;
+    let length: u8 = tokens.number()?;
+    tokens.expect("@")?;
+    let byte_order = match tokens.word()? {
+        "1" => ByteOrder::LittleEndian,
+        "0" => ByteOrder::BigEndian,
+        token => return Err(tokens.error(format!("Invalid byte order `{}`", token))),
+    };
+    let signed = match tokens.word()? {
+        "+" => false,
+        "-" => true,
+        token => return Err(tokens.error(format!("Invalid value type `{}`", token))),
+    };
+    tokens.expect("(")?;
+    let factor = tokens.number()?;
+    tokens.expect(",")?;
+    let offset = tokens.number()?;
+    tokens.expect(")")?;
+    tokens.expect("[")?;
+    let min = tokens.number()?;
+    tokens.expect("|")?;
+    let max = tokens.number()?;
+    tokens.expect("]")?;
+    let unit = tokens.string()?;
+
+    if !(1..=64).contains(&length) {
+        return Err(tokens.error(format!("Invalid length of signal {}", name)));
+    }
+    if factor == 0.0 {
+        return Err(tokens.error(format!("Signal {} has a factor of 0", name)));
+    }
+
+    Ok(Signal {
+        name,
+        multiplexing,
+        start,
+        length,
+        byte_order,
+        signed,
+        factor,
+        offset,
+        min,
+        max,
+        unit,
+        values: Vec::new(),
+    })
+}
+
+/// `VAL_ <message id> <signal> <value> "<description>" ... ;`, value descriptions of
+/// environment variables are skipped
+fn parse_value_descriptions(statement: &str, number: usize, dbc: &mut Dbc) -> Result<(), Error> {
+    let mut tokens = Tokens::new(statement, number);
+    tokens.expect("VAL_")?;
+    let Ok(raw_id) = tokens.word()?.parse::<u32>() else {
+        return Ok(());
+    };
+    let signal_name = tokens.word()?;
+    let signal = find_message(dbc, raw_id)
+        .and_then(|message| message.signal_mut(signal_name))
+        .ok_or_else(|| tokens.error(format!("Unknown signal {}", signal_name)))?;
+
+    loop {
+        match tokens.word()? {
+            ";" => return Ok(()),
+            value => {
+                let value = value
+                    .parse()
+                    .map_err(|_| tokens.error(format!("Invalid value `{}`", value)))?;
+                let description = tokens.string()?;
+                signal.values.push((value, description));
+            }
+        }
+    }
+}
+
+/// `SIG_VALTYPE_ <message id> <signal> : <type>;`, only integer signals are supported
+fn parse_value_type(statement: &str, number: usize, dbc: &Dbc) -> Result<(), Error> {
+    let mut tokens = Tokens::new(statement, number);
+    tokens.expect("SIG_VALTYPE_")?;
+    let raw_id: u32 = tokens.number()?;
+    let signal = tokens.word()?;
+    tokens.expect(":")?;
+    let value_type: u8 = tokens.number()?;
+
+    if find_message_ref(dbc, raw_id).is_some() && value_type != 0 {
+        return Err(tokens.error(format!("Floating point signal {} is not supported", signal)));
+    }
+    Ok(())
+}
+
+/// `BA_ "VFrameFormat" BO_ <message id> <value>;`, other attributes are skipped
+fn parse_attribute(statement: &str, number: usize, dbc: &mut Dbc) -> Result<(), Error> {
+    let mut tokens = Tokens::new(statement, number);
+    tokens.expect("BA_")?;
+    if tokens.string()? != "VFrameFormat" || tokens.word()? != "BO_" {
+        return Ok(());
+    }
+    let raw_id: u32 = tokens.number()?;
+    let format: i64 = tokens.number()?;
+
+    if let Some(message) = find_message(dbc, raw_id) {
+        message.fd_format = matches!(format, FRAME_FORMAT_STANDARD_FD | FRAME_FORMAT_EXTENDED_FD);
+    }
+    Ok(())
+}
+
+fn find_message(dbc: &mut Dbc, raw_id: u32) -> Option<&mut Message> {
+    let extended = raw_id & EXTENDED_FLAG != 0;
+    let id = raw_id & !EXTENDED_FLAG;
+    dbc.messages
+        .iter_mut()
+        .find(|message| message.id == id && message.extended == extended)
+}
+
+fn find_message_ref(dbc: &Dbc, raw_id: u32) -> Option<&Message> {
+    let extended = raw_id & EXTENDED_FLAG != 0;
+    let id = raw_id & !EXTENDED_FLAG;
+    dbc.messages
+        .iter()
+        .find(|message| message.id == id && message.extended == extended)
+}
+
+/// Only simple multiplexing: at most one multiplexor, multiplexed signals need it
+fn check_multiplexing(message: &Message) -> Result<(), Error> {
+    let multiplexors = message
+        .signals
+        .iter()
+        .filter(|signal| signal.multiplexing == Multiplexing::Multiplexor)
+        .count();
+    let multiplexed = message
+        .signals
+        .iter()
+        .any(|signal| matches!(signal.multiplexing, Multiplexing::Multiplexed(_)));
+
+    if multiplexors > 1 || (multiplexed && multiplexors == 0) {
+        return Err(Error::new(
+            0,
+            format!(
+                "Message {} needs exactly one multiplexor for its multiplexed signals",
+                message.name
+            ),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,85 @@
+//!
+//! Generates CAN message types for `tc37x-hal` from DBC files
+//!
+//! Every message of the DBC becomes a `no_std` type holding the raw payload, with a
+//! getter and setter per signal that applies factor, offset and the minimum and
+//! maximum. Signals with value descriptions get an enum, multiplexed signals are
+//! only present for their multiplexor value. The types implement
+//! `tc37x_hal::can::dbc::DbcMessage` to convert from `CanRxFrame` and to
+//! `CanTxFrame`.
+//!
+//! Run it from a build script and include the output:
+//!
+//! ```no_run
+//! let dbc = std::fs::read_to_string("vehicle.dbc").unwrap();
+//! let code = tc37x_dbc_codegen::generate(&dbc).unwrap();
+//! let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+//! std::fs::write(out.join("vehicle.rs"), code).unwrap();
+//! ```
+//!
+use std::fmt;
+
+pub mod dbc;
+mod rust;
+
+/// A DBC file that cannot be turned into Rust code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// Line of the DBC file, 0 if the error is not tied to a line
+    pub line: usize,
+    pub message: String,
+}
+
+impl Error {
+    pub(crate) fn new(line: usize, message: String) -> Self {
+        Error { line, message }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Code generation options
+pub struct Generator {
+    hal_crate: String,
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Generator {
+            hal_crate: "tc37x_hal".to_string(),
+        }
+    }
+}
+
+impl Generator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Path of the HAL crate in the generated code, e.g. if it is renamed
+    pub fn hal_crate(mut self, path: &str) -> Self {
+        self.hal_crate = path.to_string();
+        self
+    }
+
+    /// Generate the message types of a DBC file
+    pub fn generate(&self, dbc: &str) -> Result<String, Error> {
+        let dbc = dbc::parse(dbc)?;
+        rust::generate(&dbc, &self.hal_crate)
+    }
+}
+
+/// Generate the message types of a DBC file with the default options
+pub fn generate(dbc: &str) -> Result<String, Error> {
+    Generator::new().generate(dbc)
+}
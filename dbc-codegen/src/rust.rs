@@ -0,0 +1,744 @@
+//!
+//! Rust code for the messages of a DBC file
+//!
+use std::{collections::HashSet, fmt::Write};
+
+use crate::{
+    dbc::{ByteOrder, Dbc, Message, Multiplexing, Signal},
+    Error,
+};
+
+/// Lints that generated code may trip, e.g. casts between identical types
+const ALLOW: &str = "#[allow(dead_code, clippy::all)]";
+
+/// Names that cannot be used as methods as they are
+const RESERVED: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+    "pub", "ref", "return", "self", "static", "struct", "super", "trait", "true", "try", "type",
+    "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "macro",
+    "override", "priv", "typeof", "unsized", "virtual", "yield", "new",
+];
+
+/// How the physical value of a signal is represented
+enum Physical {
+    /// The raw value, factor 1 and offset 0
+    Raw,
+    /// Integer factor and offset
+    Integer(&'static str),
+    Float(&'static str),
+    /// Value descriptions, the name of the enum
+    Enum(String),
+}
+
+/// A signal together with the names used in the generated code
+struct SignalCode<'a> {
+    signal: &'a Signal,
+    /// `snake_case` name the methods are derived from, see [getter]
+    name: String,
+    raw_type: &'static str,
+    physical: Physical,
+    /// Getter and raw value of the multiplexor, for multiplexed signals
+    multiplexor: Option<(String, u64)>,
+}
+
+pub fn generate(dbc: &Dbc, hal: &str) -> Result<String, Error> {
+    let mut code = String::from("// Generated by tc37x-dbc-codegen from a DBC file, do not edit\n");
+    let mut types = HashSet::new();
+
+    for message in &dbc.messages {
+        let name = type_name(&message.name, "Message");
+        if !types.insert(name.clone()) {
+            return Err(duplicate(&name));
+        }
+
+        let signals = signal_code(message, &name)?;
+        for signal in &signals {
+            if let Physical::Enum(enum_name) = &signal.physical {
+                if !types.insert(enum_name.clone()) {
+                    return Err(duplicate(enum_name));
+                }
+                write_enum(&mut code, signal, enum_name, &name)?;
+            }
+        }
+        write_message(&mut code, message, &name, &signals, hal);
+    }
+
+    Ok(code)
+}
+
+fn duplicate(name: &str) -> Error {
+    Error::new(0, format!("The DBC results in two items named {}", name))
+}
+
+fn signal_code<'a>(message: &'a Message, type_name: &str) -> Result<Vec<SignalCode<'a>>, Error> {
+    let multiplexor = message.multiplexor().map(method_name);
+    let mut names = HashSet::new();
+
+    message
+        .signals
+        .iter()
+        .map(|signal| {
+            let name = method_name(signal);
+            if !names.insert(name.clone()) {
+                return Err(duplicate(&format!("{}::{}", type_name, name)));
+            }
+
+            let physical = if !signal.values.is_empty() {
+                Physical::Enum(format!(
+                    "{}{}",
+                    type_name,
+                    self::type_name(&signal.name, "")
+                ))
+            } else {
+                physical(signal)
+            };
+            let multiplexor = match signal.multiplexing {
+                Multiplexing::Multiplexed(value) => {
+                    Some((multiplexor.clone().unwrap_or_default(), value))
+                }
+                _ => None,
+            };
+
+            let raw_type = raw_type(signal);
+            let limits = match physical {
+                Physical::Raw => Some(raw_limits(signal, raw_type)),
+                Physical::Integer(integer_type) => Some(integer_limits(signal, integer_type)),
+                Physical::Float(_) | Physical::Enum(_) => None,
+            };
+            if let Some((min, max)) = limits {
+                if min > max {
+                    return Err(Error::new(
+                        0,
+                        format!(
+                            "The limits of {}::{} exclude every value of the signal",
+                            type_name, name
+                        ),
+                    ));
+                }
+            }
+
+            Ok(SignalCode {
+                signal,
+                name,
+                raw_type,
+                physical,
+                multiplexor,
+            })
+        })
+        .collect()
+}
+
+fn raw_type(signal: &Signal) -> &'static str {
+    match (signal.signed, signal.length) {
+        (false, 1..=8) => "u8",
+        (false, 9..=16) => "u16",
+        (false, 17..=32) => "u32",
+        (false, _) => "u64",
+        (true, 1..=8) => "i8",
+        (true, 9..=16) => "i16",
+        (true, 17..=32) => "i32",
+        (true, _) => "i64",
+    }
+}
+
+/// Smallest and largest raw value
+fn raw_range(signal: &Signal) -> (i128, i128) {
+    let length = signal.length as u32;
+    if signal.signed {
+        (-(1 << (length - 1)), (1 << (length - 1)) - 1)
+    } else {
+        (0, (1 << length) - 1)
+    }
+}
+
+fn physical(signal: &Signal) -> Physical {
+    if signal.factor == 1.0 && signal.offset == 0.0 {
+        return Physical::Raw;
+    }
+
+    let integer = signal.factor.fract() == 0.0 && signal.offset.fract() == 0.0;
+    if integer && signal.length <= 32 {
+        let (raw_min, raw_max) = raw_range(signal);
+        let factor = signal.factor as i128;
+        let offset = signal.offset as i128;
+        let (a, b) = (raw_min * factor + offset, raw_max * factor + offset);
+        return Physical::Integer(integer_type(a.min(b), a.max(b)));
+    }
+
+    // f32 holds 24 significant bits
+    Physical::Float(if signal.length <= 24 { "f32" } else { "f64" })
+}
+
+fn integer_type(min: i128, max: i128) -> &'static str {
+    const TYPES: [(&str, i128, i128); 8] = [
+        ("u8", 0, u8::MAX as i128),
+        ("i8", i8::MIN as i128, i8::MAX as i128),
+        ("u16", 0, u16::MAX as i128),
+        ("i16", i16::MIN as i128, i16::MAX as i128),
+        ("u32", 0, u32::MAX as i128),
+        ("i32", i32::MIN as i128, i32::MAX as i128),
+        ("u64", 0, u64::MAX as i128),
+        ("i64", i64::MIN as i128, i64::MAX as i128),
+    ];
+    TYPES
+        .iter()
+        .find(|(_, type_min, type_max)| *type_min <= min && max <= *type_max)
+        .map_or("i64", |(name, _, _)| name)
+}
+
+fn type_range(name: &str) -> (i128, i128) {
+    match name {
+        "u8" => (0, u8::MAX as i128),
+        "u16" => (0, u16::MAX as i128),
+        "u32" => (0, u32::MAX as i128),
+        "u64" => (0, u64::MAX as i128),
+        "i8" => (i8::MIN as i128, i8::MAX as i128),
+        "i16" => (i16::MIN as i128, i16::MAX as i128),
+        "i32" => (i32::MIN as i128, i32::MAX as i128),
+        _ => (i64::MIN as i128, i64::MAX as i128),
+    }
+}
+
+/// `CamelCase` of a DBC name, `prefix` is put in front of names starting with a digit
+fn type_name(name: &str, prefix: &str) -> String {
+    let mut camel = String::new();
+    for part in name.split(|c: char| !c.is_ascii_alphanumeric()) {
+        let mut chars = part.chars();
+        let Some(first) = chars.next() else {
+            continue;
+        };
+        camel.push(first.to_ascii_uppercase());
+        if part.chars().all(|c| !c.is_ascii_lowercase()) {
+            camel.extend(chars.map(|c| c.to_ascii_lowercase()));
+        } else {
+            camel.extend(chars);
+        }
+    }
+
+    if camel.is_empty() || camel.starts_with(|c: char| c.is_ascii_digit()) {
+        let prefix = if prefix.is_empty() { "Value" } else { prefix };
+        camel.insert_str(0, prefix);
+    }
+    camel
+}
+
+/// `snake_case` of a DBC name
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::new();
+
+    for (index, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !snake.is_empty() && !snake.ends_with('_') {
+                snake.push('_');
+            }
+            continue;
+        }
+        if c.is_ascii_uppercase() && index > 0 {
+            let previous = chars[index - 1];
+            let next_lower = chars.get(index + 1).is_some_and(|c| c.is_ascii_lowercase());
+            let boundary = previous.is_ascii_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_ascii_uppercase() && next_lower);
+            if boundary && !snake.ends_with('_') {
+                snake.push('_');
+            }
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+
+    let snake = snake.trim_end_matches('_').to_string();
+    if snake.is_empty() || snake.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("signal_{}", snake)
+    } else {
+        snake
+    }
+}
+
+fn method_name(signal: &Signal) -> String {
+    snake_case(&signal.name)
+}
+
+/// The getter of a signal, the other methods have a prefix or suffix
+fn getter(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Float literal, always with a decimal point or exponent
+fn float(value: f64) -> String {
+    format!("{:?}", value)
+}
+
+fn byte_order(order: ByteOrder) -> &'static str {
+    match order {
+        ByteOrder::LittleEndian => "LittleEndian",
+        ByteOrder::BigEndian => "BigEndian",
+    }
+}
+
+fn write_enum(
+    code: &mut String,
+    signal: &SignalCode,
+    name: &str,
+    message: &str,
+) -> Result<(), Error> {
+    let raw_type = signal.raw_type;
+    let (raw_min, raw_max) = raw_range(signal.signal);
+    let mut values = Vec::new();
+    let mut variants = HashSet::new();
+
+    for (value, description) in &signal.signal.values {
+        if !(raw_min..=raw_max).contains(&(*value as i128)) {
+            return Err(Error::new(
+                0,
+                format!(
+                    "Value {} of signal {} exceeds its raw range",
+                    value, signal.signal.name
+                ),
+            ));
+        }
+        // Later descriptions of the same value are unreachable
+        if values.iter().any(|(other, _, _)| other == value) {
+            continue;
+        }
+        let mut variant = type_name(description, "Value");
+        if variant == "Other" || !variants.insert(variant.clone()) {
+            variant = format!("{}{}", variant, value.unsigned_abs());
+            variants.insert(variant.clone());
+        }
+        values.push((*value, variant, description));
+    }
+
+    let _ = writeln!(code);
+    let _ = writeln!(code, "/// Values of [{}::{}]", message, signal.name);
+    let _ = writeln!(code, "#[derive(Clone, Copy, PartialEq, Eq, Debug)]");
+    let _ = writeln!(code, "{}", ALLOW);
+    let _ = writeln!(code, "pub enum {} {{", name);
+    for (value, variant, description) in &values {
+        let _ = writeln!(code, "    /// {}: {}", value, description);
+        let _ = writeln!(code, "    {},", variant);
+    }
+    let _ = writeln!(code, "    /// A value without description");
+    let _ = writeln!(code, "    Other({}),", raw_type);
+    let _ = writeln!(code, "}}");
+
+    let _ = writeln!(code);
+    let _ = writeln!(code, "{}", ALLOW);
+    let _ = writeln!(code, "impl From<{}> for {} {{", raw_type, name);
+    let _ = writeln!(code, "    fn from(raw: {}) -> Self {{", raw_type);
+    let _ = writeln!(code, "        match raw {{");
+    for (value, variant, _) in &values {
+        let _ = writeln!(code, "            {} => {}::{},", value, name, variant);
+    }
+    let _ = writeln!(code, "            raw => {}::Other(raw),", name);
+    let _ = writeln!(code, "        }}");
+    let _ = writeln!(code, "    }}");
+    let _ = writeln!(code, "}}");
+
+    let _ = writeln!(code);
+    let _ = writeln!(code, "{}", ALLOW);
+    let _ = writeln!(code, "impl From<{}> for {} {{", name, raw_type);
+    let _ = writeln!(code, "    fn from(value: {}) -> Self {{", name);
+    let _ = writeln!(code, "        match value {{");
+    for (value, variant, _) in &values {
+        let _ = writeln!(code, "            {}::{} => {},", name, variant, value);
+    }
+    let _ = writeln!(code, "            {}::Other(raw) => raw,", name);
+    let _ = writeln!(code, "        }}");
+    let _ = writeln!(code, "    }}");
+    let _ = writeln!(code, "}}");
+
+    Ok(())
+}
+
+fn write_message(
+    code: &mut String,
+    message: &Message,
+    name: &str,
+    signals: &[SignalCode],
+    hal: &str,
+) {
+    let length = message.length;
+    let id = if message.extended {
+        format!("{}::can::CanID::Extended(0x{:X})", hal, message.id)
+    } else {
+        format!("{}::can::CanID::Standard(0x{:X})", hal, message.id)
+    };
+
+    let _ = writeln!(code);
+    let _ = writeln!(
+        code,
+        "/// Message `{}` (0x{:X}), sent by `{}`",
+        message.name, message.id, message.transmitter
+    );
+    let _ = writeln!(code, "#[derive(Clone, Copy, PartialEq, Eq, Debug)]");
+    let _ = writeln!(code, "{}", ALLOW);
+    let _ = writeln!(code, "pub struct {} {{", name);
+    let _ = writeln!(code, "    raw: [u8; {}],", length);
+    let _ = writeln!(code, "}}");
+
+    let _ = writeln!(code);
+    let _ = writeln!(code, "{}", ALLOW);
+    let _ = writeln!(code, "impl {} {{", name);
+    let _ = writeln!(code, "    /// All signals at a raw value of 0");
+    let _ = writeln!(code, "    pub const fn new() -> Self {{");
+    let _ = writeln!(code, "        {} {{ raw: [0; {}] }}", name, length);
+    let _ = writeln!(code, "    }}");
+    for signal in signals {
+        write_signal(code, signal, hal);
+    }
+    let _ = writeln!(code, "}}");
+
+    let _ = writeln!(code);
+    let _ = writeln!(code, "{}", ALLOW);
+    let _ = writeln!(code, "impl Default for {} {{", name);
+    let _ = writeln!(code, "    fn default() -> Self {{");
+    let _ = writeln!(code, "        Self::new()");
+    let _ = writeln!(code, "    }}");
+    let _ = writeln!(code, "}}");
+
+    let _ = writeln!(code);
+    let _ = writeln!(code, "{}", ALLOW);
+    let _ = writeln!(code, "impl {}::can::dbc::DbcMessage for {} {{", hal, name);
+    let _ = writeln!(code, "    const ID: {}::can::CanID = {};", hal, id);
+    let _ = writeln!(code, "    const LENGTH: usize = {};", length);
+    if message.fd_format {
+        let _ = writeln!(code, "    const FD_FORMAT: bool = true;");
+    }
+    let _ = writeln!(code);
+    let _ = writeln!(
+        code,
+        "    fn from_bytes(data: &[u8]) -> Result<Self, {}::can::dbc::DbcError> {{",
+        hal
+    );
+    let _ = writeln!(code, "        if data.len() < {} {{", length);
+    let _ = writeln!(
+        code,
+        "            return Err({}::can::dbc::DbcError::InvalidLength(data.len()));",
+        hal
+    );
+    let _ = writeln!(code, "        }}");
+    let _ = writeln!(code, "        let mut raw = [0; {}];", length);
+    let _ = writeln!(code, "        raw.copy_from_slice(&data[..{}]);", length);
+    let _ = writeln!(code, "        Ok({} {{ raw }})", name);
+    let _ = writeln!(code, "    }}");
+    let _ = writeln!(code);
+    let _ = writeln!(code, "    fn as_bytes(&self) -> &[u8] {{");
+    let _ = writeln!(code, "        &self.raw");
+    let _ = writeln!(code, "    }}");
+    let _ = writeln!(code, "}}");
+}
+
+fn write_signal(code: &mut String, signal: &SignalCode, hal: &str) {
+    let SignalCode {
+        signal: dbc_signal,
+        name,
+        raw_type,
+        ..
+    } = signal;
+    let dbc = format!("{}::can::dbc", hal);
+    let location = format!(
+        "{}, {}, {}::ByteOrder::{}",
+        dbc_signal.start,
+        dbc_signal.length,
+        dbc,
+        byte_order(dbc_signal.byte_order)
+    );
+    let physical_type = match &signal.physical {
+        Physical::Raw => raw_type.to_string(),
+        Physical::Integer(name) | Physical::Float(name) => name.to_string(),
+        Physical::Enum(name) => name.clone(),
+    };
+    let multiplexed = signal.multiplexor.is_some();
+    let wrap = |type_name: &str| {
+        if multiplexed {
+            format!("Option<{}>", type_name)
+        } else {
+            type_name.to_string()
+        }
+    };
+
+    // Documentation of the getter
+    let _ = writeln!(code);
+    let _ = write!(code, "    /// Signal `{}`", dbc_signal.name);
+    if !dbc_signal.unit.is_empty() {
+        let _ = write!(code, " in {}", dbc_signal.unit);
+    }
+    if has_limits(dbc_signal) {
+        let _ = write!(code, ", {} to {}", dbc_signal.min, dbc_signal.max);
+    }
+    let _ = writeln!(code);
+    if let Some((multiplexor, value)) = &signal.multiplexor {
+        let _ = writeln!(code, "    ///");
+        let _ = writeln!(
+            code,
+            "    /// Only present if [Self::{}] is {}",
+            getter(multiplexor),
+            value
+        );
+    }
+
+    // Physical getter
+    let _ = writeln!(
+        code,
+        "    pub fn {}(&self) -> {} {{",
+        getter(name),
+        wrap(&physical_type)
+    );
+    let conversion = match &signal.physical {
+        Physical::Raw => "raw".to_string(),
+        Physical::Integer(type_name) => format!(
+            "(raw as i64 * {} + {}) as {}",
+            dbc_signal.factor as i64, dbc_signal.offset as i64, type_name
+        ),
+        Physical::Float(type_name) => format!(
+            "raw as {} * {} + {}",
+            type_name,
+            float(dbc_signal.factor),
+            float(dbc_signal.offset)
+        ),
+        Physical::Enum(type_name) => format!("{}::from(raw)", type_name),
+    };
+    if multiplexed {
+        let _ = writeln!(
+            code,
+            "        self.{}_raw().map(|raw| {})",
+            name, conversion
+        );
+    } else {
+        let _ = writeln!(code, "        let raw = self.{}_raw();", name);
+        let _ = writeln!(code, "        {}", conversion);
+    }
+    let _ = writeln!(code, "    }}");
+
+    // Raw getter
+    let _ = writeln!(code);
+    let _ = writeln!(code, "    /// Raw value of [Self::{}]", getter(name));
+    let _ = writeln!(
+        code,
+        "    pub fn {}_raw(&self) -> {} {{",
+        name,
+        wrap(raw_type)
+    );
+    if let Some((multiplexor, value)) = &signal.multiplexor {
+        let _ = writeln!(
+            code,
+            "        if self.{}_raw() as u64 != {} {{",
+            multiplexor, value
+        );
+        let _ = writeln!(code, "            return None;");
+        let _ = writeln!(code, "        }}");
+    }
+    let extract = format!("{}::extract(&self.raw, {})", dbc, location);
+    let raw = if dbc_signal.signed {
+        format!(
+            "{}::sign_extend({}, {}) as {}",
+            dbc, extract, dbc_signal.length, raw_type
+        )
+    } else {
+        format!("{} as {}", extract, raw_type)
+    };
+    if multiplexed {
+        let _ = writeln!(code, "        Some({})", raw);
+    } else {
+        let _ = writeln!(code, "        {}", raw);
+    }
+    let _ = writeln!(code, "    }}");
+
+    // Physical setter
+    let _ = writeln!(code);
+    let _ = write!(code, "    /// Set [Self::{}]", getter(name));
+    if multiplexed {
+        let _ = write!(code, " and the multiplexor");
+    }
+    let _ = writeln!(code);
+    let _ = writeln!(
+        code,
+        "    pub fn set_{}(&mut self, value: {}) -> Result<(), {}::DbcError> {{",
+        name, physical_type, dbc
+    );
+    write_range_check(code, signal, &physical_type, &dbc);
+    let (raw_min, raw_max) = raw_range(dbc_signal);
+    match &signal.physical {
+        Physical::Raw => {
+            // One check for both the limits and the values of the signal
+            let (min, max) = raw_limits(dbc_signal, raw_type);
+            if (min, max) != type_range(raw_type) {
+                let _ = writeln!(code, "        if !({}..={}).contains(&value) {{", min, max);
+                let _ = writeln!(
+                    code,
+                    "            return Err({}::DbcError::OutOfRange);",
+                    dbc
+                );
+                let _ = writeln!(code, "        }}");
+            }
+            let _ = writeln!(code, "        self.set_{}_raw(value);", name);
+        }
+        Physical::Integer(_) => {
+            let _ = writeln!(
+                code,
+                "        let scaled = value as i128 - {};",
+                dbc_signal.offset as i64
+            );
+            let _ = writeln!(code, "        // Round half away from zero");
+            let _ = writeln!(
+                code,
+                "        let raw = (2 * scaled + scaled.signum() * {}) / {};",
+                (dbc_signal.factor as i64).abs(),
+                2 * dbc_signal.factor as i64
+            );
+            let _ = writeln!(
+                code,
+                "        if !({}..={}).contains(&raw) {{",
+                raw_min, raw_max
+            );
+            let _ = writeln!(
+                code,
+                "            return Err({}::DbcError::OutOfRange);",
+                dbc
+            );
+            let _ = writeln!(code, "        }}");
+            let _ = writeln!(code, "        self.set_{}_raw(raw as {});", name, raw_type);
+        }
+        Physical::Float(_) => {
+            let _ = writeln!(
+                code,
+                "        let scaled = (value - {}) / {};",
+                float(dbc_signal.offset),
+                float(dbc_signal.factor)
+            );
+            let _ = writeln!(code, "        // Round half away from zero");
+            let _ = writeln!(
+                code,
+                "        let raw = if scaled < 0.0 {{ scaled - 0.5 }} else {{ scaled + 0.5 }};"
+            );
+            let _ = writeln!(
+                code,
+                "        if raw <= {} || raw >= {} {{",
+                float((raw_min - 1) as f64),
+                float((raw_max + 1) as f64)
+            );
+            let _ = writeln!(
+                code,
+                "            return Err({}::DbcError::OutOfRange);",
+                dbc
+            );
+            let _ = writeln!(code, "        }}");
+            let _ = writeln!(code, "        self.set_{}_raw(raw as {});", name, raw_type);
+        }
+        Physical::Enum(_) => {
+            // Other values may exceed the signal
+            let _ = writeln!(code, "        let raw: {} = value.into();", raw_type);
+            if (raw_min, raw_max) != type_range(raw_type) {
+                let _ = writeln!(
+                    code,
+                    "        if !({}..={}).contains(&raw) {{",
+                    raw_min, raw_max
+                );
+                let _ = writeln!(
+                    code,
+                    "            return Err({}::DbcError::OutOfRange);",
+                    dbc
+                );
+                let _ = writeln!(code, "        }}");
+            }
+            let _ = writeln!(code, "        self.set_{}_raw(raw);", name);
+        }
+    }
+    let _ = writeln!(code, "        Ok(())");
+    let _ = writeln!(code, "    }}");
+
+    // Raw setter
+    let _ = writeln!(code);
+    let _ = write!(
+        code,
+        "    /// Set the raw value of [Self::{}]",
+        getter(name)
+    );
+    if multiplexed {
+        let _ = write!(code, " and the multiplexor");
+    }
+    let _ = writeln!(code);
+    let _ = writeln!(
+        code,
+        "    pub fn set_{}_raw(&mut self, raw: {}) {{",
+        name, raw_type
+    );
+    if let Some((multiplexor, value)) = &signal.multiplexor {
+        let _ = writeln!(
+            code,
+            "        self.set_{}_raw({} as _);",
+            multiplexor, value
+        );
+    }
+    let _ = writeln!(
+        code,
+        "        {}::insert(&mut self.raw, {}, raw as u64);",
+        dbc, location
+    );
+    let _ = writeln!(code, "    }}");
+}
+
+/// DBC files without limits have a minimum and maximum of 0
+fn has_limits(signal: &Signal) -> bool {
+    (signal.min != 0.0 || signal.max != 0.0) && signal.min <= signal.max
+}
+
+/// Minimum and maximum of an integer signal within the range of its type, all values of
+/// the type without limits. The minimum exceeds the maximum if the limits exclude
+/// every value.
+fn integer_limits(signal: &Signal, type_name: &str) -> (i128, i128) {
+    let (type_min, type_max) = type_range(type_name);
+    if !has_limits(signal) {
+        return (type_min, type_max);
+    }
+    // Limits beyond the type need no check
+    let min = (signal.min.ceil() as i128).max(type_min);
+    let max = (signal.max.floor() as i128).min(type_max);
+    (min, max)
+}
+
+/// Limits of a signal without factor and offset, within its values
+fn raw_limits(signal: &Signal, raw_type: &str) -> (i128, i128) {
+    let (min, max) = integer_limits(signal, raw_type);
+    let (raw_min, raw_max) = raw_range(signal);
+    (min.max(raw_min), max.min(raw_max))
+}
+
+/// Refuse values outside of the minimum and maximum of the signal
+fn write_range_check(code: &mut String, signal: &SignalCode, physical_type: &str, dbc: &str) {
+    let dbc_signal = signal.signal;
+    if !has_limits(dbc_signal) {
+        return;
+    }
+
+    let condition = match &signal.physical {
+        // Enums take no other values, raw values are checked together with their range
+        Physical::Enum(_) | Physical::Raw => return,
+        Physical::Float(_) => format!(
+            "!({}..={}).contains(&value)",
+            float(dbc_signal.min),
+            float(dbc_signal.max)
+        ),
+        Physical::Integer(_) => {
+            let (min, max) = integer_limits(dbc_signal, physical_type);
+            if (min, max) == type_range(physical_type) {
+                return;
+            }
+            format!("!({}..={}).contains(&value)", min, max)
+        }
+    };
+
+    let _ = writeln!(code, "        if {} {{", condition);
+    let _ = writeln!(
+        code,
+        "            return Err({}::DbcError::OutOfRange);",
+        dbc
+    );
+    let _ = writeln!(code, "        }}");
+}
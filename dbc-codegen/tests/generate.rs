@@ -0,0 +1,117 @@
+use tc37x_dbc_codegen::{
+    dbc::{parse, ByteOrder, Multiplexing},
+    generate, Generator,
+};
+
+const SAMPLE: &str = include_str!("sample.dbc");
+
+#[test]
+fn parses_messages() {
+    let dbc = parse(SAMPLE).unwrap();
+    let names: Vec<_> = dbc.messages.iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(names, ["EngineData", "BodyStatus", "GatewayInfo"]);
+
+    let engine = &dbc.messages[0];
+    assert_eq!(
+        (engine.id, engine.extended, engine.length),
+        (0x100, false, 8)
+    );
+    assert_eq!(engine.transmitter, "Engine");
+    assert_eq!(engine.signals.len(), 5);
+
+    let body = &dbc.messages[1];
+    assert_eq!((body.id, body.extended), (0x200, true));
+    assert!(!body.fd_format);
+
+    let gateway = &dbc.messages[2];
+    assert!(gateway.fd_format);
+    assert_eq!(gateway.length, 64);
+    assert_eq!(gateway.signals.len(), 3);
+}
+
+#[test]
+fn parses_signals() {
+    let dbc = parse(SAMPLE).unwrap();
+    let engine = &dbc.messages[0];
+
+    let torque = &engine.signals[2];
+    assert_eq!(torque.name, "Torque");
+    assert_eq!((torque.start, torque.length), (24, 12));
+    assert_eq!(torque.byte_order, ByteOrder::LittleEndian);
+    assert!(torque.signed);
+    assert_eq!((torque.factor, torque.offset), (0.5, 0.0));
+    assert_eq!((torque.min, torque.max), (-1000.0, 1000.0));
+    assert_eq!(torque.unit, "Nm");
+
+    let counter = &engine.signals[4];
+    assert_eq!(counter.byte_order, ByteOrder::BigEndian);
+
+    let gear = &engine.signals[3];
+    assert_eq!(gear.values.len(), 5);
+    assert_eq!(gear.values[4], (7, "Not available".to_string()));
+
+    let body = &dbc.messages[1];
+    assert_eq!(body.signals[0].multiplexing, Multiplexing::Multiplexor);
+    assert_eq!(body.signals[1].multiplexing, Multiplexing::Multiplexed(0));
+    assert_eq!(body.signals[3].multiplexing, Multiplexing::Multiplexed(1));
+}
+
+#[test]
+fn generates_message_types() {
+    let code = generate(SAMPLE).unwrap();
+
+    assert!(code.contains("pub struct EngineData {"));
+    assert!(code.contains("CanID::Standard(0x100)"));
+    assert!(code.contains("CanID::Extended(0x200)"));
+    assert!(code.contains("const FD_FORMAT: bool = true;"));
+    assert!(code.contains("pub fn engine_speed(&self) -> f32"));
+    assert!(code.contains("pub fn coolant_temp(&self) -> i16"));
+    assert!(code.contains("pub fn torque_raw(&self) -> i16"));
+    assert!(code.contains("pub fn gear_selector(&self) -> EngineDataGearSelector"));
+    assert!(code.contains("NotAvailable,"));
+    assert!(code.contains("pub fn lights(&self) -> Option<BodyStatusLights>"));
+    assert!(code.contains("pub fn voltage(&self) -> Option<f32>"));
+    assert!(!code.contains("Orphan"));
+}
+
+#[test]
+fn generates_hal_path() {
+    let code = Generator::new().hal_crate("hal").generate(SAMPLE).unwrap();
+    assert!(code.contains("impl hal::can::dbc::DbcMessage for EngineData"));
+    assert!(!code.contains("tc37x_hal"));
+}
+
+#[test]
+fn rejects_signal_outside_of_message() {
+    let dbc = "BO_ 1 Short: 1 Node\n SG_ Wide : 4|8@1+ (1,0) [0|0] \"\" Node\n";
+    let error = parse(dbc).unwrap_err();
+    assert_eq!(error.line, 2);
+}
+
+#[test]
+fn rejects_float_signals() {
+    let dbc = "BO_ 1 Float: 4 Node\n SG_ Value : 0|32@1- (1,0) [0|0] \"\" Node\n\
+               SIG_VALTYPE_ 1 Value : 1;\n";
+    assert_eq!(parse(dbc).unwrap_err().line, 3);
+}
+
+#[test]
+fn rejects_duplicate_names() {
+    let dbc = "BO_ 1 Status: 1 Node\n SG_ Mode : 0|4@1+ (1,0) [0|0] \"\" Node\n \
+               SG_ MODE : 4|4@1+ (1,0) [0|0] \"\" Node\n";
+    assert!(generate(dbc).is_err());
+}
+
+#[test]
+fn rejects_limits_outside_of_signal() {
+    let dbc = "BO_ 1 Status: 1 Node\n SG_ Mode : 0|4@1+ (1,0) [20|30] \"\" Node\n";
+    assert!(generate(dbc).is_err());
+}
+
+/// The generated sample is compiled and tested by the HAL, see `tests/dbc.rs` there.
+/// After changes to the generator, update it with the output of `generate(SAMPLE)`.
+#[test]
+fn generated_sample_is_up_to_date() {
+    let code = generate(SAMPLE).unwrap();
+    assert_eq!(code, include_str!("generated/sample.rs"));
+}
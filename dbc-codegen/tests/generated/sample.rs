@@ -0,0 +1,544 @@
+// Generated by tc37x-dbc-codegen from a DBC file, do not edit
+
+/// Values of [EngineData::gear_selector]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(dead_code, clippy::all)]
+pub enum EngineDataGearSelector {
+    /// 0: Park
+    Park,
+    /// 1: Reverse
+    Reverse,
+    /// 2: Neutral
+    Neutral,
+    /// 3: Drive
+    Drive,
+    /// 7: Not available
+    NotAvailable,
+    /// A value without description
+    Other(u8),
+}
+
+#[allow(dead_code, clippy::all)]
+impl From<u8> for EngineDataGearSelector {
+    fn from(raw: u8) -> Self {
+        match raw {
+            0 => EngineDataGearSelector::Park,
+            1 => EngineDataGearSelector::Reverse,
+            2 => EngineDataGearSelector::Neutral,
+            3 => EngineDataGearSelector::Drive,
+            7 => EngineDataGearSelector::NotAvailable,
+            raw => EngineDataGearSelector::Other(raw),
+        }
+    }
+}
+
+#[allow(dead_code, clippy::all)]
+impl From<EngineDataGearSelector> for u8 {
+    fn from(value: EngineDataGearSelector) -> Self {
+        match value {
+            EngineDataGearSelector::Park => 0,
+            EngineDataGearSelector::Reverse => 1,
+            EngineDataGearSelector::Neutral => 2,
+            EngineDataGearSelector::Drive => 3,
+            EngineDataGearSelector::NotAvailable => 7,
+            EngineDataGearSelector::Other(raw) => raw,
+        }
+    }
+}
+
+/// Message `EngineData` (0x100), sent by `Engine`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(dead_code, clippy::all)]
+pub struct EngineData {
+    raw: [u8; 8],
+}
+
+#[allow(dead_code, clippy::all)]
+impl EngineData {
+    /// All signals at a raw value of 0
+    pub const fn new() -> Self {
+        EngineData { raw: [0; 8] }
+    }
+
+    /// Signal `EngineSpeed` in rpm, 0 to 16383.75
+    pub fn engine_speed(&self) -> f32 {
+        let raw = self.engine_speed_raw();
+        raw as f32 * 0.25 + 0.0
+    }
+
+    /// Raw value of [Self::engine_speed]
+    pub fn engine_speed_raw(&self) -> u16 {
+        tc37x_hal::can::dbc::extract(&self.raw, 0, 16, tc37x_hal::can::dbc::ByteOrder::LittleEndian) as u16
+    }
+
+    /// Set [Self::engine_speed]
+    pub fn set_engine_speed(&mut self, value: f32) -> Result<(), tc37x_hal::can::dbc::DbcError> {
+        if !(0.0..=16383.75).contains(&value) {
+            return Err(tc37x_hal::can::dbc::DbcError::OutOfRange);
+        }
+        let scaled = (value - 0.0) / 0.25;
+        // Round half away from zero
+        let raw = if scaled < 0.0 { scaled - 0.5 } else { scaled + 0.5 };
+        if raw <= -1.0 || raw >= 65536.0 {
+            return Err(tc37x_hal::can::dbc::DbcError::OutOfRange);
+        }
+        self.set_engine_speed_raw(raw as u16);
+        Ok(())
+    }
+
+    /// Set the raw value of [Self::engine_speed]
+    pub fn set_engine_speed_raw(&mut self, raw: u16) {
+        tc37x_hal::can::dbc::insert(&mut self.raw, 0, 16, tc37x_hal::can::dbc::ByteOrder::LittleEndian, raw as u64);
+    }
+
+    /// Signal `CoolantTemp` in degC, -40 to 215
+    pub fn coolant_temp(&self) -> i16 {
+        let raw = self.coolant_temp_raw();
+        (raw as i64 * 1 + -40) as i16
+    }
+
+    /// Raw value of [Self::coolant_temp]
+    pub fn coolant_temp_raw(&self) -> u8 {
+        tc37x_hal::can::dbc::extract(&self.raw, 16, 8, tc37x_hal::can::dbc::ByteOrder::LittleEndian) as u8
+    }
+
+    /// Set [Self::coolant_temp]
+    pub fn set_coolant_temp(&mut self, value: i16) -> Result<(), tc37x_hal::can::dbc::DbcError> {
+        if !(-40..=215).contains(&value) {
+            return Err(tc37x_hal::can::dbc::DbcError::OutOfRange);
+        }
+        let scaled = value as i128 - -40;
+        // Round half away from zero
+        let raw = (2 * scaled + scaled.signum() * 1) / 2;
+        if !(0..=255).contains(&raw) {
+            return Err(tc37x_hal::can::dbc::DbcError::OutOfRange);
+        }
+        self.set_coolant_temp_raw(raw as u8);
+        Ok(())
+    }
+
+    /// Set the raw value of [Self::coolant_temp]
+    pub fn set_coolant_temp_raw(&mut self, raw: u8) {
+        tc37x_hal::can::dbc::insert(&mut self.raw, 16, 8, tc37x_hal::can::dbc::ByteOrder::LittleEndian, raw as u64);
+    }
+
+    /// Signal `Torque` in Nm, -1000 to 1000
+    pub fn torque(&self) -> f32 {
+        let raw = self.torque_raw();
+        raw as f32 * 0.5 + 0.0
+    }
+
+    /// Raw value of [Self::torque]
+    pub fn torque_raw(&self) -> i16 {
+        tc37x_hal::can::dbc::sign_extend(tc37x_hal::can::dbc::extract(&self.raw, 24, 12, tc37x_hal::can::dbc::ByteOrder::LittleEndian), 12) as i16
+    }
+
+    /// Set [Self::torque]
+    pub fn set_torque(&mut self, value: f32) -> Result<(), tc37x_hal::can::dbc::DbcError> {
+        if !(-1000.0..=1000.0).contains(&value) {
+            return Err(tc37x_hal::can::dbc::DbcError::OutOfRange);
+        }
+        let scaled = (value - 0.0) / 0.5;
+        // Round half away from zero
+        let raw = if scaled < 0.0 { scaled - 0.5 } else { scaled + 0.5 };
+        if raw <= -2049.0 || raw >= 2048.0 {
+            return Err(tc37x_hal::can::dbc::DbcError::OutOfRange);
+        }
+        self.set_torque_raw(raw as i16);
+        Ok(())
+    }
+
+    /// Set the raw value of [Self::torque]
+    pub fn set_torque_raw(&mut self, raw: i16) {
+        tc37x_hal::can::dbc::insert(&mut self.raw, 24, 12, tc37x_hal::can::dbc::ByteOrder::LittleEndian, raw as u64);
+    }
+
+    /// Signal `GearSelector`
+    pub fn gear_selector(&self) -> EngineDataGearSelector {
+        let raw = self.gear_selector_raw();
+        EngineDataGearSelector::from(raw)
+    }
+
+    /// Raw value of [Self::gear_selector]
+    pub fn gear_selector_raw(&self) -> u8 {
+        tc37x_hal::can::dbc::extract(&self.raw, 36, 3, tc37x_hal::can::dbc::ByteOrder::LittleEndian) as u8
+    }
+
+    /// Set [Self::gear_selector]
+    pub fn set_gear_selector(&mut self, value: EngineDataGearSelector) -> Result<(), tc37x_hal::can::dbc::DbcError> {
+        let raw: u8 = value.into();
+        if !(0..=7).contains(&raw) {
+            return Err(tc37x_hal::can::dbc::DbcError::OutOfRange);
+        }
+        self.set_gear_selector_raw(raw);
+        Ok(())
+    }
+
+    /// Set the raw value of [Self::gear_selector]
+    pub fn set_gear_selector_raw(&mut self, raw: u8) {
+        tc37x_hal::can::dbc::insert(&mut self.raw, 36, 3, tc37x_hal::can::dbc::ByteOrder::LittleEndian, raw as u64);
+    }
+
+    /// Signal `Counter`, 0 to 15
+    pub fn counter(&self) -> u8 {
+        let raw = self.counter_raw();
+        raw
+    }
+
+    /// Raw value of [Self::counter]
+    pub fn counter_raw(&self) -> u8 {
+        tc37x_hal::can::dbc::extract(&self.raw, 63, 4, tc37x_hal::can::dbc::ByteOrder::BigEndian) as u8
+    }
+
+    /// Set [Self::counter]
+    pub fn set_counter(&mut self, value: u8) -> Result<(), tc37x_hal::can::dbc::DbcError> {
+        if !(0..=15).contains(&value) {
+            return Err(tc37x_hal::can::dbc::DbcError::OutOfRange);
+        }
+        self.set_counter_raw(value);
+        Ok(())
+    }
+
+    /// Set the raw value of [Self::counter]
+    pub fn set_counter_raw(&mut self, raw: u8) {
+        tc37x_hal::can::dbc::insert(&mut self.raw, 63, 4, tc37x_hal::can::dbc::ByteOrder::BigEndian, raw as u64);
+    }
+}
+
+#[allow(dead_code, clippy::all)]
+impl Default for EngineData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(dead_code, clippy::all)]
+impl tc37x_hal::can::dbc::DbcMessage for EngineData {
+    const ID: tc37x_hal::can::CanID = tc37x_hal::can::CanID::Standard(0x100);
+    const LENGTH: usize = 8;
+
+    fn from_bytes(data: &[u8]) -> Result<Self, tc37x_hal::can::dbc::DbcError> {
+        if data.len() < 8 {
+            return Err(tc37x_hal::can::dbc::DbcError::InvalidLength(data.len()));
+        }
+        let mut raw = [0; 8];
+        raw.copy_from_slice(&data[..8]);
+        Ok(EngineData { raw })
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+}
+
+/// Values of [BodyStatus::lights]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(dead_code, clippy::all)]
+pub enum BodyStatusLights {
+    /// 0: Off
+    Off,
+    /// 1: Low beam
+    LowBeam,
+    /// 2: High beam
+    HighBeam,
+    /// A value without description
+    Other(u8),
+}
+
+#[allow(dead_code, clippy::all)]
+impl From<u8> for BodyStatusLights {
+    fn from(raw: u8) -> Self {
+        match raw {
+            0 => BodyStatusLights::Off,
+            1 => BodyStatusLights::LowBeam,
+            2 => BodyStatusLights::HighBeam,
+            raw => BodyStatusLights::Other(raw),
+        }
+    }
+}
+
+#[allow(dead_code, clippy::all)]
+impl From<BodyStatusLights> for u8 {
+    fn from(value: BodyStatusLights) -> Self {
+        match value {
+            BodyStatusLights::Off => 0,
+            BodyStatusLights::LowBeam => 1,
+            BodyStatusLights::HighBeam => 2,
+            BodyStatusLights::Other(raw) => raw,
+        }
+    }
+}
+
+/// Message `BodyStatus` (0x200), sent by `Body`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(dead_code, clippy::all)]
+pub struct BodyStatus {
+    raw: [u8; 4],
+}
+
+#[allow(dead_code, clippy::all)]
+impl BodyStatus {
+    /// All signals at a raw value of 0
+    pub const fn new() -> Self {
+        BodyStatus { raw: [0; 4] }
+    }
+
+    /// Signal `Mode`
+    pub fn mode(&self) -> u8 {
+        let raw = self.mode_raw();
+        raw
+    }
+
+    /// Raw value of [Self::mode]
+    pub fn mode_raw(&self) -> u8 {
+        tc37x_hal::can::dbc::extract(&self.raw, 7, 8, tc37x_hal::can::dbc::ByteOrder::BigEndian) as u8
+    }
+
+    /// Set [Self::mode]
+    pub fn set_mode(&mut self, value: u8) -> Result<(), tc37x_hal::can::dbc::DbcError> {
+        self.set_mode_raw(value);
+        Ok(())
+    }
+
+    /// Set the raw value of [Self::mode]
+    pub fn set_mode_raw(&mut self, raw: u8) {
+        tc37x_hal::can::dbc::insert(&mut self.raw, 7, 8, tc37x_hal::can::dbc::ByteOrder::BigEndian, raw as u64);
+    }
+
+    /// Signal `DoorsOpen`
+    ///
+    /// Only present if [Self::mode] is 0
+    pub fn doors_open(&self) -> Option<u8> {
+        self.doors_open_raw().map(|raw| raw)
+    }
+
+    /// Raw value of [Self::doors_open]
+    pub fn doors_open_raw(&self) -> Option<u8> {
+        if self.mode_raw() as u64 != 0 {
+            return None;
+        }
+        Some(tc37x_hal::can::dbc::extract(&self.raw, 15, 8, tc37x_hal::can::dbc::ByteOrder::BigEndian) as u8)
+    }
+
+    /// Set [Self::doors_open] and the multiplexor
+    pub fn set_doors_open(&mut self, value: u8) -> Result<(), tc37x_hal::can::dbc::DbcError> {
+        self.set_doors_open_raw(value);
+        Ok(())
+    }
+
+    /// Set the raw value of [Self::doors_open] and the multiplexor
+    pub fn set_doors_open_raw(&mut self, raw: u8) {
+        self.set_mode_raw(0 as _);
+        tc37x_hal::can::dbc::insert(&mut self.raw, 15, 8, tc37x_hal::can::dbc::ByteOrder::BigEndian, raw as u64);
+    }
+
+    /// Signal `Lights`
+    ///
+    /// Only present if [Self::mode] is 1
+    pub fn lights(&self) -> Option<BodyStatusLights> {
+        self.lights_raw().map(|raw| BodyStatusLights::from(raw))
+    }
+
+    /// Raw value of [Self::lights]
+    pub fn lights_raw(&self) -> Option<u8> {
+        if self.mode_raw() as u64 != 1 {
+            return None;
+        }
+        Some(tc37x_hal::can::dbc::extract(&self.raw, 15, 2, tc37x_hal::can::dbc::ByteOrder::BigEndian) as u8)
+    }
+
+    /// Set [Self::lights] and the multiplexor
+    pub fn set_lights(&mut self, value: BodyStatusLights) -> Result<(), tc37x_hal::can::dbc::DbcError> {
+        let raw: u8 = value.into();
+        if !(0..=3).contains(&raw) {
+            return Err(tc37x_hal::can::dbc::DbcError::OutOfRange);
+        }
+        self.set_lights_raw(raw);
+        Ok(())
+    }
+
+    /// Set the raw value of [Self::lights] and the multiplexor
+    pub fn set_lights_raw(&mut self, raw: u8) {
+        self.set_mode_raw(1 as _);
+        tc37x_hal::can::dbc::insert(&mut self.raw, 15, 2, tc37x_hal::can::dbc::ByteOrder::BigEndian, raw as u64);
+    }
+
+    /// Signal `Voltage` in V, 0 to 65.535
+    ///
+    /// Only present if [Self::mode] is 1
+    pub fn voltage(&self) -> Option<f32> {
+        self.voltage_raw().map(|raw| raw as f32 * 0.001 + 0.0)
+    }
+
+    /// Raw value of [Self::voltage]
+    pub fn voltage_raw(&self) -> Option<u16> {
+        if self.mode_raw() as u64 != 1 {
+            return None;
+        }
+        Some(tc37x_hal::can::dbc::extract(&self.raw, 23, 16, tc37x_hal::can::dbc::ByteOrder::BigEndian) as u16)
+    }
+
+    /// Set [Self::voltage] and the multiplexor
+    pub fn set_voltage(&mut self, value: f32) -> Result<(), tc37x_hal::can::dbc::DbcError> {
+        if !(0.0..=65.535).contains(&value) {
+            return Err(tc37x_hal::can::dbc::DbcError::OutOfRange);
+        }
+        let scaled = (value - 0.0) / 0.001;
+        // Round half away from zero
+        let raw = if scaled < 0.0 { scaled - 0.5 } else { scaled + 0.5 };
+        if raw <= -1.0 || raw >= 65536.0 {
+            return Err(tc37x_hal::can::dbc::DbcError::OutOfRange);
+        }
+        self.set_voltage_raw(raw as u16);
+        Ok(())
+    }
+
+    /// Set the raw value of [Self::voltage] and the multiplexor
+    pub fn set_voltage_raw(&mut self, raw: u16) {
+        self.set_mode_raw(1 as _);
+        tc37x_hal::can::dbc::insert(&mut self.raw, 23, 16, tc37x_hal::can::dbc::ByteOrder::BigEndian, raw as u64);
+    }
+}
+
+#[allow(dead_code, clippy::all)]
+impl Default for BodyStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(dead_code, clippy::all)]
+impl tc37x_hal::can::dbc::DbcMessage for BodyStatus {
+    const ID: tc37x_hal::can::CanID = tc37x_hal::can::CanID::Extended(0x200);
+    const LENGTH: usize = 4;
+
+    fn from_bytes(data: &[u8]) -> Result<Self, tc37x_hal::can::dbc::DbcError> {
+        if data.len() < 4 {
+            return Err(tc37x_hal::can::dbc::DbcError::InvalidLength(data.len()));
+        }
+        let mut raw = [0; 4];
+        raw.copy_from_slice(&data[..4]);
+        Ok(BodyStatus { raw })
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+}
+
+/// Message `GatewayInfo` (0x200), sent by `Gateway`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(dead_code, clippy::all)]
+pub struct GatewayInfo {
+    raw: [u8; 64],
+}
+
+#[allow(dead_code, clippy::all)]
+impl GatewayInfo {
+    /// All signals at a raw value of 0
+    pub const fn new() -> Self {
+        GatewayInfo { raw: [0; 64] }
+    }
+
+    /// Signal `Uptime` in s
+    pub fn uptime(&self) -> u32 {
+        let raw = self.uptime_raw();
+        raw
+    }
+
+    /// Raw value of [Self::uptime]
+    pub fn uptime_raw(&self) -> u32 {
+        tc37x_hal::can::dbc::extract(&self.raw, 0, 32, tc37x_hal::can::dbc::ByteOrder::LittleEndian) as u32
+    }
+
+    /// Set [Self::uptime]
+    pub fn set_uptime(&mut self, value: u32) -> Result<(), tc37x_hal::can::dbc::DbcError> {
+        self.set_uptime_raw(value);
+        Ok(())
+    }
+
+    /// Set the raw value of [Self::uptime]
+    pub fn set_uptime_raw(&mut self, raw: u32) {
+        tc37x_hal::can::dbc::insert(&mut self.raw, 0, 32, tc37x_hal::can::dbc::ByteOrder::LittleEndian, raw as u64);
+    }
+
+    /// Signal `Payload`
+    pub fn payload(&self) -> u8 {
+        let raw = self.payload_raw();
+        raw
+    }
+
+    /// Raw value of [Self::payload]
+    pub fn payload_raw(&self) -> u8 {
+        tc37x_hal::can::dbc::extract(&self.raw, 504, 8, tc37x_hal::can::dbc::ByteOrder::LittleEndian) as u8
+    }
+
+    /// Set [Self::payload]
+    pub fn set_payload(&mut self, value: u8) -> Result<(), tc37x_hal::can::dbc::DbcError> {
+        self.set_payload_raw(value);
+        Ok(())
+    }
+
+    /// Set the raw value of [Self::payload]
+    pub fn set_payload_raw(&mut self, raw: u8) {
+        tc37x_hal::can::dbc::insert(&mut self.raw, 504, 8, tc37x_hal::can::dbc::ByteOrder::LittleEndian, raw as u64);
+    }
+
+    /// Signal `Odometer` in km, 0 to 600000
+    pub fn odometer(&self) -> u32 {
+        let raw = self.odometer_raw();
+        (raw as i64 * 10 + 0) as u32
+    }
+
+    /// Raw value of [Self::odometer]
+    pub fn odometer_raw(&self) -> u16 {
+        tc37x_hal::can::dbc::extract(&self.raw, 32, 16, tc37x_hal::can::dbc::ByteOrder::LittleEndian) as u16
+    }
+
+    /// Set [Self::odometer]
+    pub fn set_odometer(&mut self, value: u32) -> Result<(), tc37x_hal::can::dbc::DbcError> {
+        if !(0..=600000).contains(&value) {
+            return Err(tc37x_hal::can::dbc::DbcError::OutOfRange);
+        }
+        let scaled = value as i128 - 0;
+        // Round half away from zero
+        let raw = (2 * scaled + scaled.signum() * 10) / 20;
+        if !(0..=65535).contains(&raw) {
+            return Err(tc37x_hal::can::dbc::DbcError::OutOfRange);
+        }
+        self.set_odometer_raw(raw as u16);
+        Ok(())
+    }
+
+    /// Set the raw value of [Self::odometer]
+    pub fn set_odometer_raw(&mut self, raw: u16) {
+        tc37x_hal::can::dbc::insert(&mut self.raw, 32, 16, tc37x_hal::can::dbc::ByteOrder::LittleEndian, raw as u64);
+    }
+}
+
+#[allow(dead_code, clippy::all)]
+impl Default for GatewayInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(dead_code, clippy::all)]
+impl tc37x_hal::can::dbc::DbcMessage for GatewayInfo {
+    const ID: tc37x_hal::can::CanID = tc37x_hal::can::CanID::Standard(0x200);
+    const LENGTH: usize = 64;
+    const FD_FORMAT: bool = true;
+
+    fn from_bytes(data: &[u8]) -> Result<Self, tc37x_hal::can::dbc::DbcError> {
+        if data.len() < 64 {
+            return Err(tc37x_hal::can::dbc::DbcError::InvalidLength(data.len()));
+        }
+        let mut raw = [0; 64];
+        raw.copy_from_slice(&data[..64]);
+        Ok(GatewayInfo { raw })
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+}
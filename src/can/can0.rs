@@ -34,6 +34,7 @@ mod mem {
 
     impl<'r, Node0, Node1> CanModule for CanModule0<'r, Node0, Node1> {
         type RAM = CanModule0RAM;
+        type Node = tc37x_pac::can0::NODE;
     }
 
     /// Type defining the RAM for can module 0
@@ -44,8 +45,15 @@ mod mem {
     // # Safety
     // Data comes from the reference manual
     unsafe impl CanModuleRAM for CanModule0RAM {
-        const RAM_LOCATION: *mut u8 = 0xF0_20_00_00 as *mut u8;
         const RAM_SIZE: usize = 0x8000;
+
+        fn ram_location() -> *mut u8 {
+            0xF0_20_00_00 as *mut u8
+        }
+
+        fn data_sync() {
+            unsafe { core::arch::asm!("dsync") };
+        }
     }
 }
 pub use mem::*;
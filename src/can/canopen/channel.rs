@@ -0,0 +1,72 @@
+//!
+//! Drives a [CanOpen] slave with a [CanNode]
+//!
+use crate::{
+    can::{
+        memory::module_ram::CanBuffer,
+        node::{
+            connection::Connected, receive::RxFifo0, transceive::TxDedicated, CanNode, Running,
+        },
+        CanModule,
+    },
+    time::Instant,
+};
+
+use super::{CanOpen, CanOpenEvent};
+
+/// A CANopen slave on a running node, see [CanOpenChannel::process]
+///
+/// All frames in FIFO 0 are passed to the slave, use its
+/// [acceptance filters](CanOpen::acceptance_filters) to keep other frames out.
+pub struct CanOpenChannel<'a> {
+    slave: CanOpen<'a>,
+}
+
+impl<'a> CanOpenChannel<'a> {
+    pub fn new(slave: CanOpen<'a>) -> Self {
+        CanOpenChannel { slave }
+    }
+
+    pub fn slave(&self) -> &CanOpen<'a> {
+        &self.slave
+    }
+
+    pub fn slave_mut(&mut self) -> &mut CanOpen<'a> {
+        &mut self.slave
+    }
+
+    /// Receive pending frames, check the timers and transmit queued frames. Call this
+    /// regularly (at least once per millisecond for exact PDO timing), events are
+    /// passed to `on_event`.
+    #[allow(clippy::type_complexity)]
+    pub fn process<'r, 'mem, C: Connected, TB: CanBuffer, RB: CanBuffer, M: CanModule>(
+        &mut self,
+        node: &mut CanNode<
+            'r,
+            C,
+            Running,
+            TxDedicated<'mem, TB, M::RAM>,
+            RxFifo0<'mem, RB, M::RAM>,
+            M,
+        >,
+        now: &Instant,
+        mut on_event: impl FnMut(CanOpenEvent),
+    ) {
+        while let Some(frame) = node.try_receive_fifo0() {
+            if let Some(event) = self.slave.on_frame(frame.get_id(), frame.data(), now) {
+                on_event(event);
+            }
+        }
+
+        while let Some(event) = self.slave.poll(now) {
+            on_event(event);
+        }
+
+        while let Some(buffer) = node.acquire_transmit_buffer() {
+            let Some(frame) = self.slave.next_frame::<TB>() else {
+                break;
+            };
+            buffer.set_frame(frame).send();
+        }
+    }
+}
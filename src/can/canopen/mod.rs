@@ -0,0 +1,503 @@
+//!
+//! Minimal CANopen (CiA 301) slave
+//!
+//! [CanOpen] implements the NMT slave state machine, the heartbeat producer and
+//! consumer, an SDO server with expedited and segmented transfers and up to
+//! [MAX_PDOS] RPDOs and TPDOs. The communication parameters and the PDO mappings are
+//! taken from the [ObjectDictionary], which is defined in Rust.
+//!
+//! Like [IsoTp](super::isotp::IsoTp) it never touches the hardware: frames are passed
+//! in with [CanOpen::on_frame], pulled out with [CanOpen::next_frame] and the current
+//! time is passed in explicitly. [CanOpenChannel] drives it with a
+//! [CanNode](crate::can::node::CanNode).
+//!
+use core::time::Duration;
+
+use defmt::Format;
+
+use crate::{
+    can::{
+        memory::{
+            filter::{FilterAction, StandardFilter},
+            module_ram::CanBuffer,
+        },
+        queue::{FrameQueue, QueuedFrame},
+        CanID, CanTxFrame,
+    },
+    time::Instant,
+};
+
+mod channel;
+mod nmt;
+mod od;
+mod pdo;
+mod sdo;
+
+pub use channel::CanOpenChannel;
+pub use nmt::{NmtState, MAX_HEARTBEAT_CONSUMERS};
+pub use od::{Access, Object, ObjectDictionary, Value};
+pub use pdo::MAX_PDOS;
+pub use sdo::SdoAbortCode;
+
+/// Function codes, the COB-ID is the function code plus the node id
+const NMT: u16 = 0x000;
+const SDO_TX: u16 = 0x580;
+const SDO_RX: u16 = 0x600;
+const HEARTBEAT: u16 = 0x700;
+
+/// COB-ID of the SYNC message
+const SYNC_COB_ID: u16 = 0x1005;
+const DEFAULT_SYNC: u16 = 0x080;
+
+/// Frames queued for transmission at most
+const QUEUE_LENGTH: usize = 16;
+
+#[derive(Clone, Copy)]
+pub struct CanOpenConfig {
+    /// 1 to 127
+    pub node_id: u8,
+    /// Time the client gets to continue a segmented SDO transfer
+    pub sdo_timeout: Duration,
+}
+
+impl CanOpenConfig {
+    pub const fn new(node_id: u8) -> Self {
+        CanOpenConfig {
+            node_id,
+            sdo_timeout: Duration::from_millis(1000),
+        }
+    }
+}
+
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum CanOpenEvent {
+    /// Started, stopped or entered pre-operational by NMT
+    StateChanged(NmtState),
+    /// Reset the application; the node is pre-operational again
+    ResetNode,
+    /// Reset the communication parameters; the node is pre-operational again
+    ResetCommunication,
+    /// No heartbeat of a consumed node within its consumer time
+    HeartbeatTimeout(u8),
+    /// An entry was written over SDO
+    ObjectWritten { index: u16, sub_index: u8 },
+    /// The objects mapped to an event driven RPDO were written
+    RpdoReceived(u8),
+    /// Synchronous RPDOs were written and synchronous TPDOs sent
+    Sync,
+}
+
+/// A CANopen slave, see the [module](self) documentation
+pub struct CanOpen<'a> {
+    config: CanOpenConfig,
+    state: NmtState,
+    dictionary: ObjectDictionary<'a>,
+    queue: FrameQueue<QUEUE_LENGTH>,
+    sdo: sdo::SdoState,
+    /// Holds the value of segmented transfers
+    sdo_buffer: &'a mut [u8],
+    next_heartbeat: Option<Instant>,
+    /// Last heartbeat of the consumed nodes, None until the first one
+    heartbeats: [Option<Instant>; MAX_HEARTBEAT_CONSUMERS],
+    tpdos: [pdo::TpdoState; MAX_PDOS],
+    rpdos: [Option<pdo::PendingRpdo>; MAX_PDOS],
+}
+
+impl<'a> CanOpen<'a> {
+    /// The node stays initialising until [CanOpen::start] is called. Segmented SDO
+    /// transfers of values longer than the buffer are refused.
+    pub fn new(
+        config: CanOpenConfig,
+        dictionary: ObjectDictionary<'a>,
+        sdo_buffer: &'a mut [u8],
+    ) -> Self {
+        defmt::assert!(
+            (1..=127).contains(&config.node_id),
+            "Invalid node id {}",
+            config.node_id
+        );
+
+        CanOpen {
+            config,
+            state: NmtState::Initialising,
+            dictionary,
+            queue: FrameQueue::new(),
+            sdo: sdo::SdoState::Idle,
+            sdo_buffer,
+            next_heartbeat: None,
+            heartbeats: [None; MAX_HEARTBEAT_CONSUMERS],
+            tpdos: [pdo::TpdoState::new(); MAX_PDOS],
+            rpdos: [None; MAX_PDOS],
+        }
+    }
+
+    pub fn config(&self) -> &CanOpenConfig {
+        &self.config
+    }
+
+    pub fn dictionary(&self) -> &ObjectDictionary<'a> {
+        &self.dictionary
+    }
+
+    pub fn dictionary_mut(&mut self) -> &mut ObjectDictionary<'a> {
+        &mut self.dictionary
+    }
+
+    /// Process a received frame
+    pub fn on_frame(&mut self, id: CanID, data: &[u8], now: &Instant) -> Option<CanOpenEvent> {
+        let CanID::Standard(id) = id else {
+            return None;
+        };
+
+        if id == NMT {
+            return self.on_nmt(data, now);
+        }
+        if (HEARTBEAT + 1..HEARTBEAT + 0x80).contains(&id) {
+            self.on_heartbeat((id - HEARTBEAT) as u8, now);
+            return None;
+        }
+        // Stopped nodes only take part in error control
+        if matches!(self.state, NmtState::Initialising | NmtState::Stopped) {
+            return None;
+        }
+
+        if id == self.sync_id() {
+            self.on_sync(now)
+        } else if id == SDO_RX + self.config.node_id as u16 {
+            self.on_sdo_request(data, now)
+        } else {
+            self.on_rpdo(id, data)
+        }
+    }
+
+    /// The next frame to send, if any
+    pub fn next_frame<B: CanBuffer>(&mut self) -> Option<CanTxFrame<B>> {
+        Some(self.queue.pop()?.to_tx_frame())
+    }
+
+    /// Produce heartbeats and event driven TPDOs and check the timers, returns the
+    /// next event, if any
+    pub fn poll(&mut self, now: &Instant) -> Option<CanOpenEvent> {
+        if self.state == NmtState::Initialising {
+            return None;
+        }
+        self.poll_sdo(now);
+        self.poll_pdos(now);
+        self.poll_heartbeat(now)
+    }
+
+    /// Standard acceptance filters that store the frames of this node in FIFO 0: NMT,
+    /// SYNC, SDO requests, heartbeats and the RPDOs valid at the time of the call.
+    ///
+    /// Returns the number of filters written, `filters` needs room for
+    /// `3 + MAX_PDOS` elements.
+    pub fn acceptance_filters(&self, filters: &mut [StandardFilter]) -> usize {
+        defmt::assert!(
+            filters.len() >= 3 + MAX_PDOS,
+            "Not enough room for {} CANopen filters",
+            3 + MAX_PDOS
+        );
+
+        filters[0] = StandardFilter::dual(NMT, self.sync_id(), FilterAction::StoreFifo0);
+        filters[1] = StandardFilter::classic(
+            SDO_RX + self.config.node_id as u16,
+            0x7FF,
+            FilterAction::StoreFifo0,
+        );
+        filters[2] =
+            StandardFilter::range(HEARTBEAT + 1, HEARTBEAT + 0x7F, FilterAction::StoreFifo0);
+
+        let mut count = 3;
+        for id in (0..MAX_PDOS).filter_map(|pdo| self.rpdo_id(pdo)) {
+            filters[count] = StandardFilter::classic(id, 0x7FF, FilterAction::StoreFifo0);
+            count += 1;
+        }
+        count
+    }
+
+    fn sync_id(&self) -> u16 {
+        self.dictionary
+            .get(SYNC_COB_ID, 0)
+            .map_or(DEFAULT_SYNC, |cob_id| (cob_id & 0x7FF) as u16)
+    }
+
+    fn enqueue(&mut self, id: CanID, data: &[u8]) {
+        if self.queue.push(QueuedFrame::new(id, data)).is_err() {
+            defmt::warn!("CANopen queue full, dropping {}", id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NODE_ID: u8 = 5;
+    const SDO_REQUEST: CanID = CanID::Standard(SDO_RX + NODE_ID as u16);
+    const SYNC: CanID = CanID::Standard(DEFAULT_SYNC);
+
+    fn at(millis: u64) -> Instant {
+        Instant::from_time_since_boot(Duration::from_millis(millis))
+    }
+
+    /// A started, pre-operational node
+    fn node<'a>(objects: &'a mut [Object<'a>], sdo_buffer: &'a mut [u8]) -> CanOpen<'a> {
+        let config = CanOpenConfig::new(NODE_ID);
+        let mut node = CanOpen::new(config, ObjectDictionary::new(objects), sdo_buffer);
+        node.start(&at(0));
+        assert!(sent(&mut node) == [(CanID::Standard(0x705), vec![0x00])]);
+        node
+    }
+
+    /// The queued frames
+    fn sent(node: &mut CanOpen) -> Vec<(CanID, Vec<u8>)> {
+        core::iter::from_fn(|| node.queue.pop())
+            .map(|frame| (frame.id, frame.data().to_vec()))
+            .collect()
+    }
+
+    /// Pass an SDO request at the given millisecond, returns the response
+    fn sdo(node: &mut CanOpen, request: [u8; 8], millis: u64) -> [u8; 8] {
+        node.on_frame(SDO_REQUEST, &request, &at(millis));
+        let mut responses = sent(node);
+        assert_eq!(responses.len(), 1);
+        let (id, response) = responses.remove(0);
+        assert!(id == CanID::Standard(SDO_TX + NODE_ID as u16));
+        response.try_into().unwrap()
+    }
+
+    fn abort(index: u16, sub_index: u8, code: SdoAbortCode) -> [u8; 8] {
+        let [index_0, index_1] = index.to_le_bytes();
+        let [code_0, code_1, code_2, code_3] = (code as u32).to_le_bytes();
+        [
+            0x80, index_0, index_1, sub_index, code_0, code_1, code_2, code_3,
+        ]
+    }
+
+    #[test]
+    fn segmented_download() {
+        let mut domain = [0; 10];
+        let mut objects = [Object::new(
+            0x2000,
+            0,
+            Access::ReadWrite,
+            Value::Bytes(&mut domain),
+        )];
+        let mut buffer = [0; 16];
+        let mut node = node(&mut objects, &mut buffer);
+
+        // 10 bytes indicated, the second segment repeats the toggle bit
+        let initiate = [0x21, 0x00, 0x20, 0x00, 10, 0, 0, 0];
+        assert_eq!(
+            sdo(&mut node, initiate, 0),
+            [0x60, 0x00, 0x20, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            sdo(&mut node, [0x00, 1, 2, 3, 4, 5, 6, 7], 0),
+            [0x20, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            sdo(&mut node, [0x09, 8, 9, 10, 0, 0, 0, 0], 0),
+            abort(0x2000, 0, SdoAbortCode::ToggleBitNotAlternated)
+        );
+        assert!(node.dictionary.read(0x2000, 0) == Ok(&[0; 10]));
+
+        // The transfer is over, segments are unexpected
+        assert_eq!(
+            sdo(&mut node, [0x19, 8, 9, 10, 0, 0, 0, 0], 0),
+            abort(0, 0, SdoAbortCode::InvalidCommandSpecifier)
+        );
+
+        assert_eq!(
+            sdo(&mut node, initiate, 0),
+            [0x60, 0x00, 0x20, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            sdo(&mut node, [0x00, 1, 2, 3, 4, 5, 6, 7], 0),
+            [0x20, 0, 0, 0, 0, 0, 0, 0]
+        );
+        node.on_frame(SDO_REQUEST, &[0x19, 8, 9, 10, 0, 0, 0, 0], &at(0));
+        assert!(sent(&mut node) == [(CanID::Standard(0x585), vec![0x30, 0, 0, 0, 0, 0, 0, 0])]);
+        assert!(node.dictionary.read(0x2000, 0) == Ok(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]));
+
+        // Fewer bytes than indicated
+        assert_eq!(
+            sdo(&mut node, initiate, 0),
+            [0x60, 0x00, 0x20, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            sdo(&mut node, [0x09, 1, 2, 3, 0, 0, 0, 0], 0),
+            abort(0x2000, 0, SdoAbortCode::LengthTooLow)
+        );
+    }
+
+    #[test]
+    fn segmented_upload() {
+        let mut objects = [Object::new(
+            0x1008,
+            0,
+            Access::Constant,
+            Value::Constant(b"Actuator board"),
+        )];
+        let mut buffer = [0; 16];
+        let mut node = node(&mut objects, &mut buffer);
+
+        let initiate = [0x40, 0x08, 0x10, 0x00, 0, 0, 0, 0];
+        assert_eq!(
+            sdo(&mut node, initiate, 0),
+            [0x41, 0x08, 0x10, 0, 14, 0, 0, 0]
+        );
+        assert_eq!(
+            sdo(&mut node, [0x60, 0, 0, 0, 0, 0, 0, 0], 0),
+            *b"\x00Actuato"
+        );
+        assert_eq!(
+            sdo(&mut node, [0x60, 0, 0, 0, 0, 0, 0, 0], 0),
+            abort(0x1008, 0, SdoAbortCode::ToggleBitNotAlternated)
+        );
+
+        assert_eq!(
+            sdo(&mut node, initiate, 0),
+            [0x41, 0x08, 0x10, 0, 14, 0, 0, 0]
+        );
+        assert_eq!(
+            sdo(&mut node, [0x60, 0, 0, 0, 0, 0, 0, 0], 0),
+            *b"\x00Actuato"
+        );
+        assert_eq!(
+            sdo(&mut node, [0x70, 0, 0, 0, 0, 0, 0, 0], 0),
+            *b"\x11r board"
+        );
+        assert_eq!(
+            sdo(&mut node, [0x60, 0, 0, 0, 0, 0, 0, 0], 0),
+            abort(0, 0, SdoAbortCode::InvalidCommandSpecifier)
+        );
+    }
+
+    #[test]
+    fn abort_codes() {
+        let mut domain = [0; 32];
+        let mut objects = [
+            Object::new(0x1008, 0, Access::Constant, Value::Constant(b"Actuator")),
+            Object::new(0x2000, 0, Access::ReadWrite, Value::Bytes(&mut domain)),
+            Object::new(0x2001, 0, Access::ReadWrite, Value::u32(0)),
+            Object::new(0x2002, 0, Access::WriteOnly, Value::u8(0)),
+        ];
+        let mut buffer = [0; 16];
+        let mut node = node(&mut objects, &mut buffer);
+
+        let cases = [
+            (
+                [0x40, 0x00, 0x30, 0x00, 0, 0, 0, 0],
+                abort(0x3000, 0, SdoAbortCode::ObjectDoesNotExist),
+            ),
+            (
+                [0x40, 0x01, 0x20, 0x01, 0, 0, 0, 0],
+                abort(0x2001, 1, SdoAbortCode::SubIndexDoesNotExist),
+            ),
+            (
+                [0x40, 0x02, 0x20, 0x00, 0, 0, 0, 0],
+                abort(0x2002, 0, SdoAbortCode::WriteOnly),
+            ),
+            (
+                [0x23, 0x08, 0x10, 0x00, 1, 2, 3, 4],
+                abort(0x1008, 0, SdoAbortCode::ReadOnly),
+            ),
+            // 2 bytes for a 4 byte number
+            (
+                [0x2B, 0x01, 0x20, 0x00, 1, 2, 0, 0],
+                abort(0x2001, 0, SdoAbortCode::LengthTooLow),
+            ),
+            // More than the SDO buffer holds
+            (
+                [0x21, 0x00, 0x20, 0x00, 32, 0, 0, 0],
+                abort(0x2000, 0, SdoAbortCode::OutOfMemory),
+            ),
+            (
+                [0xE0, 0x01, 0x20, 0x00, 0, 0, 0, 0],
+                abort(0x2001, 0, SdoAbortCode::InvalidCommandSpecifier),
+            ),
+        ];
+        for (request, response) in cases {
+            assert_eq!(sdo(&mut node, request, 0), response);
+        }
+
+        // The client does not continue in time
+        let initiate = [0x21, 0x00, 0x20, 0x00, 10, 0, 0, 0];
+        assert_eq!(
+            sdo(&mut node, initiate, 0),
+            [0x60, 0x00, 0x20, 0, 0, 0, 0, 0]
+        );
+        assert!(node.poll(&at(999)).is_none());
+        assert!(sent(&mut node).is_empty());
+        node.poll(&at(1_000));
+        assert!(
+            sent(&mut node)
+                == [(
+                    CanID::Standard(0x585),
+                    abort(0x2000, 0, SdoAbortCode::Timeout).to_vec()
+                )]
+        );
+
+        // An abort of the client ends the transfer without response
+        assert_eq!(
+            sdo(&mut node, initiate, 0),
+            [0x60, 0x00, 0x20, 0, 0, 0, 0, 0]
+        );
+        node.on_frame(
+            SDO_REQUEST,
+            &abort(0x2000, 0, SdoAbortCode::GeneralError),
+            &at(0),
+        );
+        node.poll(&at(2_000));
+        assert!(sent(&mut node).is_empty());
+    }
+
+    #[test]
+    fn tpdo_every_third_sync() {
+        let mut objects = [
+            Object::new(0x1800, 1, Access::ReadWrite, Value::u32(0x185)),
+            Object::new(0x1800, 2, Access::ReadWrite, Value::u8(3)),
+            Object::new(0x1A00, 0, Access::ReadWrite, Value::u8(1)),
+            Object::new(0x1A00, 1, Access::ReadWrite, Value::u32(0x2000_0010)),
+            // Synchronous acyclic, sent with the SYNC after the trigger
+            Object::new(0x1801, 1, Access::ReadWrite, Value::u32(0x285)),
+            Object::new(0x1801, 2, Access::ReadWrite, Value::u8(0)),
+            Object::new(0x1A01, 0, Access::ReadWrite, Value::u8(1)),
+            Object::new(0x1A01, 1, Access::ReadWrite, Value::u32(0x2000_0010)),
+            Object::new(0x2000, 0, Access::ReadWrite, Value::u16(0x1234)),
+        ];
+        let mut buffer = [0; 16];
+        let mut node = node(&mut objects, &mut buffer);
+
+        // Only operational nodes send PDOs
+        for _ in 0..3 {
+            assert!(node.on_frame(SYNC, &[], &at(0)).is_none());
+        }
+        node.on_frame(CanID::Standard(NMT), &[0x01, NODE_ID], &at(0));
+        assert!(node.state() == NmtState::Operational);
+        assert!(sent(&mut node).is_empty());
+
+        let tpdo = (CanID::Standard(0x185), vec![0x34, 0x12]);
+        let mut sent_per_sync = vec![];
+        for sync in 1..=7 {
+            if sync == 5 {
+                node.trigger_tpdo(1);
+            }
+            assert!(node.on_frame(SYNC, &[], &at(sync)) == Some(CanOpenEvent::Sync));
+            sent_per_sync.push(sent(&mut node));
+        }
+        assert!(
+            sent_per_sync
+                == [
+                    vec![],
+                    vec![],
+                    vec![tpdo.clone()],
+                    vec![],
+                    vec![(CanID::Standard(0x285), vec![0x34, 0x12])],
+                    vec![tpdo.clone()],
+                    vec![],
+                ]
+        );
+    }
+}
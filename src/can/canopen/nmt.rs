@@ -0,0 +1,145 @@
+//!
+//! NMT slave state machine and heartbeat producer/consumer
+//!
+use core::time::Duration;
+
+use defmt::Format;
+
+use crate::{can::CanID, time::Instant};
+
+use super::{CanOpen, CanOpenEvent, HEARTBEAT};
+
+/// NMT command specifiers
+const NMT_START: u8 = 0x01;
+const NMT_STOP: u8 = 0x02;
+const NMT_ENTER_PRE_OPERATIONAL: u8 = 0x80;
+const NMT_RESET_NODE: u8 = 0x81;
+const NMT_RESET_COMMUNICATION: u8 = 0x82;
+
+/// Consumer heartbeat time, sub-indices 1 to [MAX_HEARTBEAT_CONSUMERS]
+const CONSUMER_HEARTBEAT_TIME: u16 = 0x1016;
+/// Producer heartbeat time in milliseconds
+const PRODUCER_HEARTBEAT_TIME: u16 = 0x1017;
+
+/// Nodes monitored at most
+pub const MAX_HEARTBEAT_CONSUMERS: usize = 8;
+
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum NmtState {
+    Initialising,
+    PreOperational,
+    Operational,
+    Stopped,
+}
+
+impl NmtState {
+    /// State as sent in the heartbeat
+    fn to_u8(self) -> u8 {
+        match self {
+            NmtState::Initialising => 0x00,
+            NmtState::Stopped => 0x04,
+            NmtState::Operational => 0x05,
+            NmtState::PreOperational => 0x7F,
+        }
+    }
+}
+
+impl<'a> CanOpen<'a> {
+    /// Send the boot-up message and enter pre-operational
+    pub fn start(&mut self, now: &Instant) {
+        self.enqueue(self.heartbeat_id(), &[NmtState::Initialising.to_u8()]);
+        self.next_heartbeat = None;
+        self.heartbeats = [None; MAX_HEARTBEAT_CONSUMERS];
+        self.set_state(NmtState::PreOperational, now);
+    }
+
+    pub fn state(&self) -> NmtState {
+        self.state
+    }
+
+    pub(super) fn on_nmt(&mut self, data: &[u8], now: &Instant) -> Option<CanOpenEvent> {
+        let [command, node_id] = *data.get(..2)? else {
+            return None;
+        };
+        if node_id != 0 && node_id != self.config.node_id {
+            return None;
+        }
+
+        let state = match command {
+            NMT_START => NmtState::Operational,
+            NMT_STOP => NmtState::Stopped,
+            NMT_ENTER_PRE_OPERATIONAL => NmtState::PreOperational,
+            NMT_RESET_NODE => {
+                self.start(now);
+                return Some(CanOpenEvent::ResetNode);
+            }
+            NMT_RESET_COMMUNICATION => {
+                self.start(now);
+                return Some(CanOpenEvent::ResetCommunication);
+            }
+            _ => return None,
+        };
+
+        if state == self.state {
+            return None;
+        }
+        self.set_state(state, now);
+        Some(CanOpenEvent::StateChanged(state))
+    }
+
+    /// Start monitoring or refresh the node, if it is consumed
+    pub(super) fn on_heartbeat(&mut self, node_id: u8, now: &Instant) {
+        for (index, last_seen) in self.heartbeats.iter_mut().enumerate() {
+            if consumer(&self.dictionary, index).is_some_and(|(id, _)| id == node_id) {
+                *last_seen = Some(*now);
+            }
+        }
+    }
+
+    /// Produce the heartbeat and check the consumed ones
+    pub(super) fn poll_heartbeat(&mut self, now: &Instant) -> Option<CanOpenEvent> {
+        let producer_time = self.dictionary.get(PRODUCER_HEARTBEAT_TIME, 0).unwrap_or(0);
+        if producer_time != 0 {
+            let next = self.next_heartbeat.get_or_insert(*now);
+            if *now >= *next {
+                *next = now + Duration::from_millis(producer_time as u64);
+                self.enqueue(self.heartbeat_id(), &[self.state.to_u8()]);
+            }
+        }
+
+        for index in 0..MAX_HEARTBEAT_CONSUMERS {
+            let (Some((node_id, time)), Some(last_seen)) =
+                (consumer(&self.dictionary, index), self.heartbeats[index])
+            else {
+                continue;
+            };
+            if *now >= &last_seen + time {
+                // Monitoring restarts with the next heartbeat
+                self.heartbeats[index] = None;
+                defmt::warn!("Heartbeat of node {} timed out", node_id);
+                return Some(CanOpenEvent::HeartbeatTimeout(node_id));
+            }
+        }
+        None
+    }
+
+    fn set_state(&mut self, state: NmtState, now: &Instant) {
+        defmt::debug!("NMT state {}", state);
+        self.state = state;
+        if state == NmtState::Operational {
+            self.reset_pdos(now);
+        }
+    }
+
+    fn heartbeat_id(&self) -> CanID {
+        CanID::Standard(HEARTBEAT + self.config.node_id as u16)
+    }
+}
+
+/// Node id and time of a consumer heartbeat entry, if configured
+fn consumer(dictionary: &super::ObjectDictionary<'_>, index: usize) -> Option<(u8, Duration)> {
+    let entry = dictionary.get(CONSUMER_HEARTBEAT_TIME, index as u8 + 1)?;
+    let node_id = (entry >> 16) as u8;
+    let time = entry & 0xFFFF;
+    (node_id != 0 && time != 0).then(|| (node_id, Duration::from_millis(time as u64)))
+}
@@ -0,0 +1,237 @@
+//!
+//! Object dictionary defined in Rust
+//!
+use defmt::Format;
+
+use super::sdo::SdoAbortCode;
+
+/// Access rights of an entry over SDO
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+    /// Read only, for values that never change such as the identity
+    Constant,
+}
+
+impl Access {
+    fn readable(&self) -> bool {
+        !matches!(self, Access::WriteOnly)
+    }
+
+    fn writable(&self) -> bool {
+        matches!(self, Access::WriteOnly | Access::ReadWrite)
+    }
+}
+
+/// Value of an entry
+pub enum Value<'a> {
+    /// Number of up to 32 bits, little endian
+    Number { data: [u8; 4], length: u8 },
+    /// Bytes that are never written, e.g. a visible string
+    Constant(&'static [u8]),
+    /// Bytes of fixed length, e.g. a domain; shorter writes are zero padded
+    Bytes(&'a mut [u8]),
+}
+
+impl<'a> Value<'a> {
+    pub const fn u8(value: u8) -> Self {
+        Self::number(value as u32, 1)
+    }
+
+    pub const fn u16(value: u16) -> Self {
+        Self::number(value as u32, 2)
+    }
+
+    pub const fn u32(value: u32) -> Self {
+        Self::number(value, 4)
+    }
+
+    pub const fn i8(value: i8) -> Self {
+        Self::number(value as u32, 1)
+    }
+
+    pub const fn i16(value: i16) -> Self {
+        Self::number(value as u32, 2)
+    }
+
+    pub const fn i32(value: i32) -> Self {
+        Self::number(value as u32, 4)
+    }
+
+    const fn number(value: u32, length: u8) -> Self {
+        Value::Number {
+            data: value.to_le_bytes(),
+            length,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Value::Number { data, length } => &data[..*length as usize],
+            Value::Constant(bytes) => bytes,
+            Value::Bytes(bytes) => bytes,
+        }
+    }
+
+    fn write(&mut self, value: &[u8]) -> Result<(), SdoAbortCode> {
+        let (bytes, exact_length) = match self {
+            Value::Number { data, length } => (&mut data[..*length as usize], true),
+            Value::Constant(_) => return Err(SdoAbortCode::ReadOnly),
+            Value::Bytes(bytes) => (&mut **bytes, false),
+        };
+
+        if value.len() > bytes.len() {
+            return Err(SdoAbortCode::LengthTooHigh);
+        }
+        if exact_length && value.len() < bytes.len() {
+            return Err(SdoAbortCode::LengthTooLow);
+        }
+
+        bytes[..value.len()].copy_from_slice(value);
+        bytes[value.len()..].fill(0);
+        Ok(())
+    }
+}
+
+/// Entry of the object dictionary, e.g.
+///
+/// ```ignore
+/// let mut objects = [
+///     Object::new(0x1000, 0, Access::Constant, Value::u32(0x0002_0192)),
+///     Object::new(0x1008, 0, Access::Constant, Value::Constant(b"Actuator")),
+///     Object::new(0x1017, 0, Access::ReadWrite, Value::u16(1000)),
+///     Object::new(0x6000, 1, Access::ReadWrite, Value::u8(0)),
+/// ];
+/// let dictionary = ObjectDictionary::new(&mut objects);
+/// ```
+pub struct Object<'a> {
+    pub index: u16,
+    pub sub_index: u8,
+    pub access: Access,
+    pub value: Value<'a>,
+}
+
+impl<'a> Object<'a> {
+    pub const fn new(index: u16, sub_index: u8, access: Access, value: Value<'a>) -> Self {
+        Object {
+            index,
+            sub_index,
+            access,
+            value,
+        }
+    }
+}
+
+/// The entries of a node, looked up linearly
+///
+/// Communication parameters are read from the dictionary whenever they are used, so
+/// they can be changed over SDO:
+/// * `0x1005` COB-ID of the SYNC message (defaults to `0x080`)
+/// * `0x1016` heartbeat consumer times (`node_id << 16 | time_ms`)
+/// * `0x1017` heartbeat producer time in milliseconds
+/// * `0x1400..`/`0x1600..` RPDO communication and mapping parameters
+/// * `0x1800..`/`0x1A00..` TPDO communication and mapping parameters
+pub struct ObjectDictionary<'a> {
+    objects: &'a mut [Object<'a>],
+}
+
+impl<'a> ObjectDictionary<'a> {
+    pub fn new(objects: &'a mut [Object<'a>]) -> Self {
+        ObjectDictionary { objects }
+    }
+
+    /// The value of an entry, regardless of its access rights
+    pub fn read(&self, index: u16, sub_index: u8) -> Result<&[u8], SdoAbortCode> {
+        Ok(self.find(index, sub_index)?.value.as_bytes())
+    }
+
+    /// Change the value of an entry, regardless of its access rights (except for
+    /// constants)
+    pub fn write(&mut self, index: u16, sub_index: u8, value: &[u8]) -> Result<(), SdoAbortCode> {
+        let object = self.find_mut(index, sub_index)?;
+        if matches!(object.access, Access::Constant) {
+            return Err(SdoAbortCode::ReadOnly);
+        }
+        object.value.write(value)
+    }
+
+    /// The value of a number entry, zero extended
+    pub fn get(&self, index: u16, sub_index: u8) -> Option<u32> {
+        match self.find(index, sub_index).ok()?.value {
+            Value::Number { data, length } => {
+                let mut value = [0; 4];
+                value[..length as usize].copy_from_slice(&data[..length as usize]);
+                Some(u32::from_le_bytes(value))
+            }
+            _ => None,
+        }
+    }
+
+    /// Change the value of a number entry, truncated to its length (except for constants)
+    pub fn set(&mut self, index: u16, sub_index: u8, value: u32) -> Result<(), SdoAbortCode> {
+        let object = self.find_mut(index, sub_index)?;
+        if matches!(object.access, Access::Constant) {
+            return Err(SdoAbortCode::ReadOnly);
+        }
+        match &mut object.value {
+            Value::Number { data, length } => {
+                let length = *length as usize;
+                data[..length].copy_from_slice(&value.to_le_bytes()[..length]);
+                Ok(())
+            }
+            _ => Err(SdoAbortCode::TypeMismatch),
+        }
+    }
+
+    pub(super) fn sdo_read(&self, index: u16, sub_index: u8) -> Result<&[u8], SdoAbortCode> {
+        let object = self.find(index, sub_index)?;
+        if !object.access.readable() {
+            return Err(SdoAbortCode::WriteOnly);
+        }
+        Ok(object.value.as_bytes())
+    }
+
+    pub(super) fn sdo_write(
+        &mut self,
+        index: u16,
+        sub_index: u8,
+        value: &[u8],
+    ) -> Result<(), SdoAbortCode> {
+        self.sdo_check_write(index, sub_index)?;
+        self.find_mut(index, sub_index)?.value.write(value)
+    }
+
+    /// Whether the entry exists and can be written over SDO
+    pub(super) fn sdo_check_write(&self, index: u16, sub_index: u8) -> Result<(), SdoAbortCode> {
+        if !self.find(index, sub_index)?.access.writable() {
+            return Err(SdoAbortCode::ReadOnly);
+        }
+        Ok(())
+    }
+
+    fn find(&self, index: u16, sub_index: u8) -> Result<&Object<'a>, SdoAbortCode> {
+        self.objects
+            .iter()
+            .find(|object| object.index == index && object.sub_index == sub_index)
+            .ok_or_else(|| self.missing(index))
+    }
+
+    fn find_mut(&mut self, index: u16, sub_index: u8) -> Result<&mut Object<'a>, SdoAbortCode> {
+        let missing = self.missing(index);
+        self.objects
+            .iter_mut()
+            .find(|object| object.index == index && object.sub_index == sub_index)
+            .ok_or(missing)
+    }
+
+    /// Abort code of an entry that does not exist
+    fn missing(&self, index: u16) -> SdoAbortCode {
+        if self.objects.iter().any(|object| object.index == index) {
+            SdoAbortCode::SubIndexDoesNotExist
+        } else {
+            SdoAbortCode::ObjectDoesNotExist
+        }
+    }
+}
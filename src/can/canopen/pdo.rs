@@ -0,0 +1,258 @@
+//!
+//! Process data objects, mapped and triggered as configured in the object dictionary
+//!
+use core::time::Duration;
+
+use crate::{can::CanID, time::Instant};
+
+use super::{CanOpen, CanOpenEvent, NmtState};
+
+/// PDOs of each direction
+pub const MAX_PDOS: usize = 4;
+
+const RPDO_COMMUNICATION: u16 = 0x1400;
+const RPDO_MAPPING: u16 = 0x1600;
+const TPDO_COMMUNICATION: u16 = 0x1800;
+const TPDO_MAPPING: u16 = 0x1A00;
+
+/// Communication parameter sub-indices
+const COB_ID: u8 = 1;
+const TRANSMISSION_TYPE: u8 = 2;
+const INHIBIT_TIME: u8 = 3;
+const EVENT_TIMER: u8 = 5;
+
+/// Set in the COB-ID of disabled PDOs
+const COB_ID_INVALID: u32 = 1 << 31;
+
+/// Transmission types: 0 is synchronous on event, 1 to 240 every n-th SYNC and 254
+/// and 255 event driven
+const SYNCHRONOUS_MAX: u8 = 240;
+const EVENT_DRIVEN: u8 = 254;
+
+/// Mapped objects per PDO at most
+const MAX_MAPPED_OBJECTS: u8 = 8;
+
+#[derive(Clone, Copy)]
+pub(super) struct TpdoState {
+    /// SYNCs since the last synchronous transmission
+    syncs: u8,
+    /// Triggered by the application
+    triggered: bool,
+    inhibited_until: Option<Instant>,
+    /// Expiry of the event timer
+    next_event: Option<Instant>,
+}
+
+impl TpdoState {
+    pub(super) const fn new() -> Self {
+        TpdoState {
+            syncs: 0,
+            triggered: false,
+            inhibited_until: None,
+            next_event: None,
+        }
+    }
+}
+
+/// Data of a synchronous RPDO, applied with the next SYNC
+#[derive(Clone, Copy)]
+pub(super) struct PendingRpdo {
+    data: [u8; 8],
+    length: u8,
+}
+
+impl<'a> CanOpen<'a> {
+    /// Send the TPDO: synchronous acyclic TPDOs with the next SYNC, event driven
+    /// ones as soon as the inhibit time allows
+    pub fn trigger_tpdo(&mut self, pdo: usize) {
+        self.tpdos[pdo].triggered = true;
+    }
+
+    /// COB-ID of the RPDO, if it is valid
+    pub(super) fn rpdo_id(&self, pdo: usize) -> Option<u16> {
+        self.cob_id(RPDO_COMMUNICATION, pdo)
+    }
+
+    pub(super) fn on_rpdo(&mut self, id: u16, data: &[u8]) -> Option<CanOpenEvent> {
+        if self.state != NmtState::Operational {
+            return None;
+        }
+        let pdo = (0..MAX_PDOS).find(|pdo| self.cob_id(RPDO_COMMUNICATION, *pdo) == Some(id))?;
+
+        if self.transmission_type(RPDO_COMMUNICATION, pdo) <= SYNCHRONOUS_MAX {
+            let mut pending = PendingRpdo {
+                data: [0; 8],
+                length: data.len().min(8) as u8,
+            };
+            pending.data[..pending.length as usize]
+                .copy_from_slice(&data[..pending.length as usize]);
+            self.rpdos[pdo] = Some(pending);
+            return None;
+        }
+
+        self.write_rpdo(pdo, data);
+        Some(CanOpenEvent::RpdoReceived(pdo as u8))
+    }
+
+    pub(super) fn on_sync(&mut self, now: &Instant) -> Option<CanOpenEvent> {
+        if self.state != NmtState::Operational {
+            return None;
+        }
+
+        for pdo in 0..MAX_PDOS {
+            if let Some(pending) = self.rpdos[pdo].take() {
+                self.write_rpdo(pdo, &pending.data[..pending.length as usize]);
+            }
+        }
+
+        for pdo in 0..MAX_PDOS {
+            let transmission_type = self.transmission_type(TPDO_COMMUNICATION, pdo);
+            let tpdo = &mut self.tpdos[pdo];
+            let due = match transmission_type {
+                0 => core::mem::take(&mut tpdo.triggered),
+                1..=SYNCHRONOUS_MAX => {
+                    tpdo.syncs += 1;
+                    tpdo.syncs >= transmission_type
+                }
+                _ => false,
+            };
+            if due {
+                tpdo.syncs = 0;
+                self.send_tpdo(pdo, now);
+            }
+        }
+        Some(CanOpenEvent::Sync)
+    }
+
+    /// Send event driven TPDOs that are triggered or whose event timer expired
+    pub(super) fn poll_pdos(&mut self, now: &Instant) {
+        if self.state != NmtState::Operational {
+            return;
+        }
+
+        for pdo in 0..MAX_PDOS {
+            if self.transmission_type(TPDO_COMMUNICATION, pdo) < EVENT_DRIVEN {
+                continue;
+            }
+            let tpdo = &self.tpdos[pdo];
+            if tpdo.inhibited_until.is_some_and(|until| *now < until) {
+                continue;
+            }
+            if tpdo.triggered || tpdo.next_event.is_some_and(|event| *now >= event) {
+                self.send_tpdo(pdo, now);
+            }
+        }
+    }
+
+    /// Restart the event timers, when entering operational
+    pub(super) fn reset_pdos(&mut self, now: &Instant) {
+        self.rpdos = [None; MAX_PDOS];
+        for pdo in 0..MAX_PDOS {
+            self.tpdos[pdo] = TpdoState {
+                next_event: self.event_timer(pdo).map(|timer| now + timer),
+                ..TpdoState::new()
+            };
+        }
+    }
+
+    fn send_tpdo(&mut self, pdo: usize, now: &Instant) {
+        let Some(id) = self.cob_id(TPDO_COMMUNICATION, pdo) else {
+            return;
+        };
+
+        let mut data = [0; 8];
+        let mut length = 0;
+        for (index, sub_index, size) in self.mapping(TPDO_MAPPING, pdo) {
+            let Ok(value) = self.dictionary.read(index, sub_index) else {
+                defmt::warn!(
+                    "TPDO {} maps missing object 0x{:04X}:{}",
+                    pdo,
+                    index,
+                    sub_index
+                );
+                return;
+            };
+            let Some(value) = value.get(..size) else {
+                return;
+            };
+            data[length..length + size].copy_from_slice(value);
+            length += size;
+        }
+
+        let inhibit_time = self
+            .dictionary
+            .get(TPDO_COMMUNICATION + pdo as u16, INHIBIT_TIME)
+            .unwrap_or(0);
+        let next_event = self.event_timer(pdo).map(|timer| now + timer);
+        let tpdo = &mut self.tpdos[pdo];
+        tpdo.triggered = false;
+        tpdo.inhibited_until = Some(now + Duration::from_micros(100 * inhibit_time as u64));
+        tpdo.next_event = next_event;
+
+        self.enqueue(CanID::Standard(id), &data[..length]);
+    }
+
+    fn write_rpdo(&mut self, pdo: usize, data: &[u8]) {
+        let mut offset = 0;
+        for (index, sub_index, size) in self.mapping(RPDO_MAPPING, pdo) {
+            let Some(value) = data.get(offset..offset + size) else {
+                defmt::warn!("RPDO {} is too short", pdo);
+                return;
+            };
+            if self.dictionary.write(index, sub_index, value).is_err() {
+                defmt::warn!(
+                    "RPDO {} maps invalid object 0x{:04X}:{}",
+                    pdo,
+                    index,
+                    sub_index
+                );
+            }
+            offset += size;
+        }
+    }
+
+    /// The mapped objects (index, sub-index, length in bytes), only whole bytes are
+    /// supported and at most 8 of them
+    fn mapping(&self, base: u16, pdo: usize) -> impl Iterator<Item = (u16, u8, usize)> + 'static {
+        let mut entries = [(0, 0, 0); MAX_MAPPED_OBJECTS as usize];
+        let count = self
+            .dictionary
+            .get(base + pdo as u16, 0)
+            .unwrap_or(0)
+            .min(MAX_MAPPED_OBJECTS as u32) as u8;
+
+        let mut total = 0;
+        let mut valid = 0;
+        for (entry, sub_index) in entries.iter_mut().zip(1..=count) {
+            let Some(mapping) = self.dictionary.get(base + pdo as u16, sub_index) else {
+                break;
+            };
+            let bits = mapping as u8 as usize;
+            if !bits.is_multiple_of(8) || total + bits / 8 > 8 {
+                break;
+            }
+            total += bits / 8;
+            *entry = ((mapping >> 16) as u16, (mapping >> 8) as u8, bits / 8);
+            valid += 1;
+        }
+        entries.into_iter().take(valid)
+    }
+
+    fn cob_id(&self, base: u16, pdo: usize) -> Option<u16> {
+        let cob_id = self.dictionary.get(base + pdo as u16, COB_ID)?;
+        (cob_id & COB_ID_INVALID == 0).then_some((cob_id & 0x7FF) as u16)
+    }
+
+    fn transmission_type(&self, base: u16, pdo: usize) -> u8 {
+        self.dictionary
+            .get(base + pdo as u16, TRANSMISSION_TYPE)
+            .map_or(EVENT_DRIVEN, |value| value as u8)
+    }
+
+    fn event_timer(&self, pdo: usize) -> Option<Duration> {
+        let timer = self
+            .dictionary
+            .get(TPDO_COMMUNICATION + pdo as u16, EVENT_TIMER)?;
+        (timer != 0).then(|| Duration::from_millis(timer as u64))
+    }
+}
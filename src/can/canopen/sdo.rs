@@ -0,0 +1,353 @@
+//!
+//! SDO server with expedited and segmented transfers
+//!
+use defmt::Format;
+
+use crate::{can::CanID, time::Instant};
+
+use super::{CanOpen, CanOpenEvent, SDO_TX};
+
+/// Client command specifiers, upper three bits of the first byte
+const CCS_DOWNLOAD_SEGMENT: u8 = 0;
+const CCS_INITIATE_DOWNLOAD: u8 = 1;
+const CCS_INITIATE_UPLOAD: u8 = 2;
+const CCS_UPLOAD_SEGMENT: u8 = 3;
+const CCS_ABORT: u8 = 4;
+
+/// Server command specifiers, already shifted
+const SCS_UPLOAD_SEGMENT: u8 = 0x00;
+const SCS_DOWNLOAD_SEGMENT: u8 = 0x20;
+const SCS_INITIATE_UPLOAD: u8 = 0x40;
+const SCS_INITIATE_DOWNLOAD: u8 = 0x60;
+const SCS_ABORT: u8 = 0x80;
+
+/// Command byte flags
+const EXPEDITED: u8 = 0x02;
+const SIZE_INDICATED: u8 = 0x01;
+const TOGGLE: u8 = 0x10;
+const LAST_SEGMENT: u8 = 0x01;
+
+/// Data bytes of a segment
+const SEGMENT_LENGTH: usize = 7;
+
+/// Reason of an SDO abort
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SdoAbortCode {
+    ToggleBitNotAlternated = 0x0503_0000,
+    Timeout = 0x0504_0000,
+    InvalidCommandSpecifier = 0x0504_0001,
+    OutOfMemory = 0x0504_0005,
+    UnsupportedAccess = 0x0601_0000,
+    WriteOnly = 0x0601_0001,
+    ReadOnly = 0x0601_0002,
+    ObjectDoesNotExist = 0x0602_0000,
+    CannotBeMapped = 0x0604_0041,
+    MappingTooLong = 0x0604_0042,
+    TypeMismatch = 0x0607_0010,
+    LengthTooHigh = 0x0607_0012,
+    LengthTooLow = 0x0607_0013,
+    SubIndexDoesNotExist = 0x0609_0011,
+    InvalidValue = 0x0609_0030,
+    GeneralError = 0x0800_0000,
+    CannotStore = 0x0800_0020,
+    DeviceState = 0x0800_0022,
+}
+
+pub(super) enum SdoState {
+    Idle,
+    Download {
+        index: u16,
+        sub_index: u8,
+        toggle: bool,
+        received: usize,
+        size: Option<usize>,
+        deadline: Instant,
+    },
+    Upload {
+        index: u16,
+        sub_index: u8,
+        toggle: bool,
+        sent: usize,
+        length: usize,
+        deadline: Instant,
+    },
+}
+
+impl SdoState {
+    fn multiplexer(&self) -> Option<(u16, u8)> {
+        match *self {
+            SdoState::Idle => None,
+            SdoState::Download {
+                index, sub_index, ..
+            }
+            | SdoState::Upload {
+                index, sub_index, ..
+            } => Some((index, sub_index)),
+        }
+    }
+}
+
+impl<'a> CanOpen<'a> {
+    pub(super) fn on_sdo_request(&mut self, data: &[u8], now: &Instant) -> Option<CanOpenEvent> {
+        // SDO frames always have 8 bytes
+        let request: &[u8; 8] = data.try_into().ok()?;
+        let index = u16::from_le_bytes([request[1], request[2]]);
+        let sub_index = request[3];
+
+        let command = request[0] >> 5;
+        let result = match (command, &self.sdo) {
+            (CCS_ABORT, _) => {
+                self.sdo = SdoState::Idle;
+                return None;
+            }
+            (CCS_INITIATE_DOWNLOAD, _) => self.initiate_download(index, sub_index, request, now),
+            (CCS_INITIATE_UPLOAD, _) => self.initiate_upload(index, sub_index, now),
+            (CCS_DOWNLOAD_SEGMENT, SdoState::Download { .. }) => {
+                self.download_segment(request, now)
+            }
+            (CCS_UPLOAD_SEGMENT, SdoState::Upload { .. }) => self.upload_segment(request[0], now),
+            _ => Err(SdoAbortCode::InvalidCommandSpecifier),
+        };
+
+        match result {
+            Ok(event) => event,
+            Err(code) => {
+                // Segments do not carry the multiplexer, it is 0 outside of a transfer
+                let (index, sub_index) = match self.sdo.multiplexer() {
+                    Some(multiplexer) => multiplexer,
+                    None if matches!(command, CCS_DOWNLOAD_SEGMENT | CCS_UPLOAD_SEGMENT) => (0, 0),
+                    None => (index, sub_index),
+                };
+                self.sdo_abort(index, sub_index, code);
+                None
+            }
+        }
+    }
+
+    /// Abort a transfer that was not continued in time
+    pub(super) fn poll_sdo(&mut self, now: &Instant) {
+        let deadline = match self.sdo {
+            SdoState::Download { deadline, .. } | SdoState::Upload { deadline, .. } => deadline,
+            SdoState::Idle => return,
+        };
+        if *now >= deadline {
+            if let Some((index, sub_index)) = self.sdo.multiplexer() {
+                self.sdo_abort(index, sub_index, SdoAbortCode::Timeout);
+            }
+        }
+    }
+
+    fn initiate_download(
+        &mut self,
+        index: u16,
+        sub_index: u8,
+        request: &[u8; 8],
+        now: &Instant,
+    ) -> Result<Option<CanOpenEvent>, SdoAbortCode> {
+        self.sdo = SdoState::Idle;
+        let command = request[0];
+
+        if command & EXPEDITED != 0 {
+            let length = if command & SIZE_INDICATED != 0 {
+                4 - ((command >> 2) & 0x3) as usize
+            } else {
+                // Unspecified size, as long as the entry
+                self.dictionary
+                    .read(index, sub_index)
+                    .map_or(4, |value| value.len().min(4))
+            };
+            self.dictionary
+                .sdo_write(index, sub_index, &request[4..4 + length])?;
+            self.sdo_respond(SCS_INITIATE_DOWNLOAD, index, sub_index, [0; 4]);
+            return Ok(Some(CanOpenEvent::ObjectWritten { index, sub_index }));
+        }
+
+        self.dictionary.sdo_check_write(index, sub_index)?;
+        let size = (command & SIZE_INDICATED != 0)
+            .then(|| u32::from_le_bytes([request[4], request[5], request[6], request[7]]) as usize);
+        if size.is_some_and(|size| size > self.sdo_buffer.len()) {
+            return Err(SdoAbortCode::OutOfMemory);
+        }
+
+        self.sdo = SdoState::Download {
+            index,
+            sub_index,
+            toggle: false,
+            received: 0,
+            size,
+            deadline: now + self.config.sdo_timeout,
+        };
+        self.sdo_respond(SCS_INITIATE_DOWNLOAD, index, sub_index, [0; 4]);
+        Ok(None)
+    }
+
+    fn download_segment(
+        &mut self,
+        request: &[u8; 8],
+        now: &Instant,
+    ) -> Result<Option<CanOpenEvent>, SdoAbortCode> {
+        let SdoState::Download {
+            index,
+            sub_index,
+            toggle,
+            received,
+            size,
+            ..
+        } = self.sdo
+        else {
+            return Err(SdoAbortCode::InvalidCommandSpecifier);
+        };
+
+        let command = request[0];
+        if (command & TOGGLE != 0) != toggle {
+            return Err(SdoAbortCode::ToggleBitNotAlternated);
+        }
+
+        let unused = ((command >> 1) & 0x7) as usize;
+        let segment = &request[1..1 + SEGMENT_LENGTH - unused];
+        let buffer = self
+            .sdo_buffer
+            .get_mut(received..received + segment.len())
+            .ok_or(SdoAbortCode::OutOfMemory)?;
+        buffer.copy_from_slice(segment);
+        let received = received + segment.len();
+
+        let response = SCS_DOWNLOAD_SEGMENT | (command & TOGGLE);
+        if command & LAST_SEGMENT == 0 {
+            self.sdo = SdoState::Download {
+                index,
+                sub_index,
+                toggle: !toggle,
+                received,
+                size,
+                deadline: now + self.config.sdo_timeout,
+            };
+            self.sdo_respond_segment(response, &[]);
+            return Ok(None);
+        }
+
+        match size {
+            Some(size) if size < received => return Err(SdoAbortCode::LengthTooHigh),
+            Some(size) if size > received => return Err(SdoAbortCode::LengthTooLow),
+            _ => {}
+        }
+        self.dictionary
+            .sdo_write(index, sub_index, &self.sdo_buffer[..received])?;
+        self.sdo = SdoState::Idle;
+        self.sdo_respond_segment(response, &[]);
+        Ok(Some(CanOpenEvent::ObjectWritten { index, sub_index }))
+    }
+
+    fn initiate_upload(
+        &mut self,
+        index: u16,
+        sub_index: u8,
+        now: &Instant,
+    ) -> Result<Option<CanOpenEvent>, SdoAbortCode> {
+        self.sdo = SdoState::Idle;
+        let value = self.dictionary.sdo_read(index, sub_index)?;
+        let length = value.len();
+
+        // Expedited transfers carry 1 to 4 bytes, empty values take a single empty segment
+        if (1..=4).contains(&length) {
+            let mut data = [0; 4];
+            data[..length].copy_from_slice(value);
+            let command =
+                SCS_INITIATE_UPLOAD | (((4 - length) as u8) << 2) | EXPEDITED | SIZE_INDICATED;
+            self.sdo_respond(command, index, sub_index, data);
+            return Ok(None);
+        }
+
+        // Copied, so the value stays consistent during the transfer
+        self.sdo_buffer
+            .get_mut(..length)
+            .ok_or(SdoAbortCode::OutOfMemory)?
+            .copy_from_slice(value);
+        self.sdo = SdoState::Upload {
+            index,
+            sub_index,
+            toggle: false,
+            sent: 0,
+            length,
+            deadline: now + self.config.sdo_timeout,
+        };
+        self.sdo_respond(
+            SCS_INITIATE_UPLOAD | SIZE_INDICATED,
+            index,
+            sub_index,
+            (length as u32).to_le_bytes(),
+        );
+        Ok(None)
+    }
+
+    fn upload_segment(
+        &mut self,
+        command: u8,
+        now: &Instant,
+    ) -> Result<Option<CanOpenEvent>, SdoAbortCode> {
+        let SdoState::Upload {
+            index,
+            sub_index,
+            toggle,
+            sent,
+            length,
+            ..
+        } = self.sdo
+        else {
+            return Err(SdoAbortCode::InvalidCommandSpecifier);
+        };
+
+        if (command & TOGGLE != 0) != toggle {
+            return Err(SdoAbortCode::ToggleBitNotAlternated);
+        }
+
+        let count = (length - sent).min(SEGMENT_LENGTH);
+        let last = sent + count == length;
+        let mut response =
+            SCS_UPLOAD_SEGMENT | (command & TOGGLE) | (((SEGMENT_LENGTH - count) as u8) << 1);
+        if last {
+            response |= LAST_SEGMENT;
+            self.sdo = SdoState::Idle;
+        } else {
+            self.sdo = SdoState::Upload {
+                index,
+                sub_index,
+                toggle: !toggle,
+                sent: sent + count,
+                length,
+                deadline: now + self.config.sdo_timeout,
+            };
+        }
+
+        let mut segment = [0; SEGMENT_LENGTH];
+        segment[..count].copy_from_slice(&self.sdo_buffer[sent..sent + count]);
+        self.sdo_respond_segment(response, &segment);
+        Ok(None)
+    }
+
+    fn sdo_abort(&mut self, index: u16, sub_index: u8, code: SdoAbortCode) {
+        defmt::debug!("SDO abort 0x{:04X}:{}: {}", index, sub_index, code);
+        self.sdo = SdoState::Idle;
+        self.sdo_respond(SCS_ABORT, index, sub_index, (code as u32).to_le_bytes());
+    }
+
+    fn sdo_respond(&mut self, command: u8, index: u16, sub_index: u8, data: [u8; 4]) {
+        let [index_0, index_1] = index.to_le_bytes();
+        let response = [
+            command, index_0, index_1, sub_index, data[0], data[1], data[2], data[3],
+        ];
+        self.enqueue(self.sdo_tx_id(), &response);
+    }
+
+    fn sdo_respond_segment(&mut self, command: u8, segment: &[u8]) {
+        let mut response = [0; 8];
+        response[0] = command;
+        response[1..1 + segment.len()].copy_from_slice(segment);
+        self.enqueue(self.sdo_tx_id(), &response);
+    }
+
+    fn sdo_tx_id(&self) -> CanID {
+        CanID::Standard(SDO_TX + self.config.node_id as u16)
+    }
+}
@@ -0,0 +1,178 @@
+//!
+//! CRC routines of the AUTOSAR CRC library, shared by the E2E profiles and the UDS
+//! bootloader
+//!
+//! The functions only update a running CRC, start values and final XOR are applied by
+//! the callers, which makes chaining over several slices explicit.
+//!
+
+/// CRC8 SAE J1850, polynomial 0x1D
+const CRC8_TABLE: [u8; 256] = table8(0x1D);
+/// CRC8H2F, polynomial 0x2F
+const CRC8H2F_TABLE: [u8; 256] = table8(0x2F);
+/// CRC16 CCITT, polynomial 0x1021
+const CRC16_TABLE: [u16; 256] = table16(0x1021);
+/// CRC32P4, polynomial 0xF4ACFB13 (reflected)
+const CRC32P4_TABLE: [u32; 256] = table32_reflected(0xC8DF_352F);
+/// CRC32 IEEE 802.3, polynomial 0x04C11DB7 (reflected)
+const CRC32_TABLE: [u32; 256] = table32_reflected(0xEDB8_8320);
+
+pub(crate) fn crc8(crc: u8, data: &[u8]) -> u8 {
+    data.iter()
+        .fold(crc, |crc, byte| CRC8_TABLE[(crc ^ byte) as usize])
+}
+
+pub(crate) fn crc8h2f(crc: u8, data: &[u8]) -> u8 {
+    data.iter()
+        .fold(crc, |crc, byte| CRC8H2F_TABLE[(crc ^ byte) as usize])
+}
+
+pub(crate) fn crc16(crc: u16, data: &[u8]) -> u16 {
+    data.iter().fold(crc, |crc, byte| {
+        (crc << 8) ^ CRC16_TABLE[((crc >> 8) as u8 ^ byte) as usize]
+    })
+}
+
+pub(crate) fn crc32p4(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, byte| {
+        (crc >> 8) ^ CRC32P4_TABLE[(crc as u8 ^ byte) as usize]
+    })
+}
+
+pub(crate) fn crc32(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, byte| {
+        (crc >> 8) ^ CRC32_TABLE[(crc as u8 ^ byte) as usize]
+    })
+}
+
+const fn table8(polynomial: u8) -> [u8; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ polynomial
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+const fn table16(polynomial: u16) -> [u16; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = (index as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ polynomial
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+const fn table32_reflected(polynomial: u32) -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ polynomial
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check values of the AUTOSAR CRC library specification
+    const DATA: [&[u8]; 7] = [
+        &[0x00, 0x00, 0x00, 0x00],
+        &[0xF2, 0x01, 0x83],
+        &[0x0F, 0xAA, 0x00, 0x55],
+        &[0x00, 0xFF, 0x55, 0x11],
+        &[0x33, 0x22, 0x55, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF],
+        &[0x92, 0x6B, 0x55],
+        &[0xFF, 0xFF, 0xFF, 0xFF],
+    ];
+
+    #[test]
+    fn crc8_check_values() {
+        let expected = [0x59, 0x37, 0x79, 0xB8, 0xCB, 0x8C, 0x74];
+        for (data, expected) in DATA.iter().zip(expected) {
+            assert_eq!(crc8(0xFF, data) ^ 0xFF, expected);
+        }
+    }
+
+    #[test]
+    fn crc8h2f_check_values() {
+        let expected = [0x12, 0xC2, 0xC6, 0x77, 0x11, 0x33, 0x6C];
+        for (data, expected) in DATA.iter().zip(expected) {
+            assert_eq!(crc8h2f(0xFF, data) ^ 0xFF, expected);
+        }
+    }
+
+    #[test]
+    fn crc16_check_values() {
+        let expected = [0x84C0, 0xD374, 0x2023, 0xB8F9, 0xF53F, 0x0745, 0x1D0F];
+        for (data, expected) in DATA.iter().zip(expected) {
+            assert_eq!(crc16(0xFFFF, data), expected);
+        }
+    }
+
+    #[test]
+    fn crc32p4_check_values() {
+        let expected = [
+            0x6FB3_2240,
+            0x4F72_1A25,
+            0x2066_2DF8,
+            0x9BD7_996E,
+            0xA65A_343D,
+            0xEE68_8A78,
+            0xFFFF_FFFF,
+        ];
+        for (data, expected) in DATA.iter().zip(expected) {
+            assert_eq!(crc32p4(0xFFFF_FFFF, data) ^ 0xFFFF_FFFF, expected);
+        }
+    }
+
+    #[test]
+    fn crc32_check_values() {
+        let expected = [
+            0x2144_DF1C,
+            0x24AB_9D77,
+            0xB6C9_B287,
+            0x32A0_6212,
+            0xB0AE_863D,
+            0x9CDE_A29B,
+            0xFFFF_FFFF,
+        ];
+        for (data, expected) in DATA.iter().zip(expected) {
+            assert_eq!(crc32(0xFFFF_FFFF, data) ^ 0xFFFF_FFFF, expected);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//!
+//! Sends the messages of a [CyclicScheduler] with a [CanNode]
+//!
+use crate::{
+    can::{
+        memory::module_ram::CanBuffer,
+        node::{connection::Connected, transceive::TxDedicated, CanNode, Running},
+        CanModule,
+    },
+    time::Instant,
+};
+
+use super::CyclicScheduler;
+
+impl<'a, B: CanBuffer> CyclicScheduler<'a, B> {
+    /// Send the due messages, message `n` in the reserved transmit buffer `n`. A message
+    /// whose previous transmission is still pending is skipped for this period.
+    ///
+    /// Returns when the next message is due.
+    pub fn process<'r, 'mem, C: Connected, R, M: CanModule>(
+        &mut self,
+        node: &mut CanNode<'r, C, Running, TxDedicated<'mem, B, M::RAM>, R, M>,
+        now: &Instant,
+    ) -> Option<Instant> {
+        self.poll(now, |index, frame| {
+            match node.dedicated_transmit_buffer(index) {
+                Some(buffer) => {
+                    buffer.set_frame(frame.clone()).send();
+                    true
+                }
+                None => false,
+            }
+        })
+    }
+}
@@ -0,0 +1,227 @@
+//!
+//! Periodic transmission of frames
+//!
+//! A [CyclicScheduler] owns a set of [CyclicMessage]s, each with a period, a phase
+//! offset and an optional update callback that runs right before every transmission
+//! (e.g. to increment an alive counter). [CyclicScheduler::poll] decides which
+//! messages are due; [CyclicScheduler::process] sends them on a node, message `n` in
+//! the reserved transmit buffer `n` (see
+//! [reserve_tx_buffers](crate::can::node::CanNode::reserve_tx_buffers)), so every
+//! update of a message replaces the whole buffer at once.
+//!
+//! The scheduler is driven by calling it regularly or from the STM compare interrupt:
+//! both return the instant the next message is due, to be passed to
+//! [set_alarm](crate::time::set_alarm). If that instant is already reached, no
+//! interrupt comes and the scheduler has to be run again right away:
+//!
+//! ```ignore
+//! while let Some(next) = scheduler.process(&mut node, &Instant::now()) {
+//!     if time::set_alarm(&next) {
+//!         break;
+//!     }
+//! }
+//! ```
+//!
+use core::time::Duration;
+
+use crate::{
+    can::{memory::module_ram::CanBuffer, CanTxFrame},
+    time::Instant,
+};
+
+mod channel;
+
+/// Updates the frame before a transmission, gets the number of transmissions so far
+pub type UpdateFrame<B> = fn(&mut CanTxFrame<B>, u32);
+
+/// Messages per scheduler at most, one per transmit buffer
+const MAX_MESSAGES: usize = 32;
+
+/// A periodic frame, e.g.
+///
+/// ```ignore
+/// let mut messages = [
+///     CyclicMessage::new(status_frame, Duration::from_millis(10)),
+///     CyclicMessage::new(alive_frame, Duration::from_millis(100)).on_update(increment_counter),
+/// ];
+/// let mut scheduler = CyclicScheduler::new(&mut messages);
+/// ```
+pub struct CyclicMessage<B: CanBuffer> {
+    frame: CanTxFrame<B>,
+    period: Duration,
+    offset: Duration,
+    update: Option<UpdateFrame<B>>,
+    /// None while the scheduler is stopped
+    next: Option<Instant>,
+    sent: u32,
+    missed: u32,
+}
+
+impl<B: CanBuffer> CyclicMessage<B> {
+    pub fn new(frame: CanTxFrame<B>, period: Duration) -> Self {
+        defmt::assert!(!period.is_zero(), "Period must not be zero");
+        CyclicMessage {
+            frame,
+            period,
+            offset: Duration::ZERO,
+            update: None,
+            next: None,
+            sent: 0,
+            missed: 0,
+        }
+    }
+
+    /// Delay of the first transmission after [CyclicScheduler::start]
+    pub fn with_offset(self, offset: Duration) -> Self {
+        CyclicMessage { offset, ..self }
+    }
+
+    pub fn on_update(self, update: UpdateFrame<B>) -> Self {
+        CyclicMessage {
+            update: Some(update),
+            ..self
+        }
+    }
+
+    pub fn frame(&self) -> &CanTxFrame<B> {
+        &self.frame
+    }
+
+    /// Change the frame, the change is sent as a whole with the next transmission
+    pub fn frame_mut(&mut self) -> &mut CanTxFrame<B> {
+        &mut self.frame
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    pub fn offset(&self) -> Duration {
+        self.offset
+    }
+
+    /// Number of transmissions so far
+    pub fn sent(&self) -> u32 {
+        self.sent
+    }
+
+    /// Number of periods skipped, because the scheduler ran late or the previous
+    /// transmission was still pending
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+}
+
+pub struct CyclicScheduler<'a, B: CanBuffer> {
+    messages: &'a mut [CyclicMessage<B>],
+}
+
+impl<'a, B: CanBuffer> CyclicScheduler<'a, B> {
+    pub fn new(messages: &'a mut [CyclicMessage<B>]) -> Self {
+        defmt::assert!(
+            messages.len() <= MAX_MESSAGES,
+            "Cannot schedule more than {} messages",
+            MAX_MESSAGES
+        );
+        CyclicScheduler { messages }
+    }
+
+    pub fn messages(&self) -> &[CyclicMessage<B>] {
+        self.messages
+    }
+
+    pub fn message_mut(&mut self, index: usize) -> &mut CyclicMessage<B> {
+        &mut self.messages[index]
+    }
+
+    /// Choose the phase offsets (multiples of `tick`) such that as few messages as
+    /// possible are due at the same time, which keeps the transmission jitter low.
+    /// Messages are placed in order, each at the earliest offset with the fewest
+    /// collisions with the messages placed before.
+    pub fn assign_offsets(&mut self, tick: Duration) {
+        defmt::assert!(!tick.is_zero(), "Tick must not be zero");
+        let tick = tick.as_nanos();
+        let ticks = |duration: Duration| (duration.as_nanos() / tick) as u64;
+
+        for index in 0..self.messages.len() {
+            let (placed, rest) = self.messages.split_at_mut(index);
+            let message = &mut rest[0];
+            let period = ticks(message.period).max(1);
+
+            let collisions = |offset: u64| {
+                placed
+                    .iter()
+                    .filter(|other| {
+                        // Both are due together every lcm, if the offsets match modulo gcd
+                        let common = gcd(period, ticks(other.period).max(1));
+                        offset % common == ticks(other.offset) % common
+                    })
+                    .count()
+            };
+            let offset = (0..period)
+                .min_by_key(|offset| collisions(*offset))
+                .unwrap_or(0);
+            message.offset = Duration::from_nanos((offset as u128 * tick) as u64);
+        }
+    }
+
+    /// Schedule all messages, relative to now
+    pub fn start(&mut self, now: &Instant) {
+        for message in self.messages.iter_mut() {
+            message.next = Some(now + message.offset);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        for message in self.messages.iter_mut() {
+            message.next = None;
+        }
+    }
+
+    /// When the next message is due, None while stopped
+    pub fn next_due(&self) -> Option<Instant> {
+        self.messages
+            .iter()
+            .filter_map(|message| message.next)
+            .min()
+    }
+
+    /// Update and hand out the due messages with their index to `send`, which returns
+    /// whether the frame could be sent. Returns when the next message is due.
+    pub fn poll(
+        &mut self,
+        now: &Instant,
+        mut send: impl FnMut(u8, &CanTxFrame<B>) -> bool,
+    ) -> Option<Instant> {
+        for (index, message) in self.messages.iter_mut().enumerate() {
+            let Some(next) = message.next else {
+                continue;
+            };
+            if *now < next {
+                continue;
+            }
+
+            if let Some(update) = message.update {
+                update(&mut message.frame, message.sent);
+            }
+            if send(index as u8, &message.frame) {
+                message.sent = message.sent.wrapping_add(1);
+            } else {
+                message.missed = message.missed.wrapping_add(1);
+            }
+
+            // Keep the phase, periods that passed meanwhile are skipped
+            let late = (*now - next).as_nanos() / message.period.as_nanos();
+            message.missed = message.missed.wrapping_add(late as u32);
+            message.next = Some(&next + message.period * (late as u32 + 1));
+        }
+        self.next_due()
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
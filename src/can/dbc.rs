@@ -0,0 +1,230 @@
+//!
+//! Runtime support for message types generated from DBC files
+//!
+//! The `tc37x-dbc-codegen` crate turns a DBC file into one type per message, usually
+//! from a build script:
+//!
+//! ```ignore
+//! // build.rs
+//! let dbc = std::fs::read_to_string("vehicle.dbc").unwrap();
+//! let code = tc37x_dbc_codegen::generate(&dbc).unwrap();
+//! let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+//! std::fs::write(out.join("vehicle.rs"), code).unwrap();
+//!
+//! // src/vehicle.rs
+//! include!(concat!(env!("OUT_DIR"), "/vehicle.rs"));
+//! ```
+//!
+//! The generated types keep the raw payload and implement [DbcMessage], signals are
+//! read and written with [extract] and [insert].
+//!
+use defmt::Format;
+
+use super::{memory::module_ram::CanBuffer, CanID, CanRxFrame, CanTxFrame};
+
+/// Byte order of a signal, `@1` (Intel) and `@0` (Motorola) in the DBC
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum DbcError {
+    /// The frame has a different id than the message
+    WrongId(CanID),
+    /// The payload is shorter than the message
+    InvalidLength(usize),
+    /// The value is outside of the minimum and maximum of the signal
+    OutOfRange,
+}
+
+/// A message type generated from a DBC file
+pub trait DbcMessage: Sized {
+    const ID: CanID;
+    /// Payload length in bytes
+    const LENGTH: usize;
+    /// The message is sent as CAN FD frame (`VFrameFormat`)
+    const FD_FORMAT: bool = false;
+
+    /// Take the raw payload, longer payloads are truncated
+    fn from_bytes(data: &[u8]) -> Result<Self, DbcError>;
+
+    fn as_bytes(&self) -> &[u8];
+
+    /// Decode a received frame, checking its id and length
+    fn from_rx_frame<B: CanBuffer>(frame: &CanRxFrame<B>) -> Result<Self, DbcError> {
+        if frame.get_id() != Self::ID {
+            return Err(DbcError::WrongId(frame.get_id()));
+        }
+        Self::from_bytes(frame.data())
+    }
+
+    /// The frame to transmit, messages longer than the buffer cannot be sent
+    fn to_tx_frame<B: CanBuffer>(&self) -> Option<CanTxFrame<B>> {
+        if Self::LENGTH > B::BUFFER_SIZE {
+            return None;
+        }
+        let mut frame = CanTxFrame::<B>::default();
+        frame.set_id(Self::ID);
+        frame.set_data(self.as_bytes());
+        if Self::FD_FORMAT {
+            frame.set_fd_format(true);
+        }
+        Some(frame)
+    }
+}
+
+/// Positions of the bits of a signal together with their significance (0 is the
+/// least significant bit)
+///
+/// Big endian start bits are the position of the most significant bit in the DBC
+/// numbering (bit 7 of byte 0 is 7, bit 0 of byte 1 is 8), the following bits go
+/// down within a byte and continue at the top of the next byte.
+fn positions(start: u16, length: u8, order: ByteOrder) -> impl Iterator<Item = (u8, usize)> {
+    let mut position = start as usize;
+    (0..length).map(move |index| match order {
+        ByteOrder::LittleEndian => (index, start as usize + index as usize),
+        ByteOrder::BigEndian => {
+            let current = position;
+            position = if position.is_multiple_of(8) {
+                position + 15
+            } else {
+                position - 1
+            };
+            (length - 1 - index, current)
+        }
+    })
+}
+
+/// Read the raw value of a signal of `length` (1 to 64) bits
+pub fn extract(data: &[u8], start: u16, length: u8, order: ByteOrder) -> u64 {
+    positions(start, length, order).fold(0, |value, (bit, position)| {
+        let set = data[position / 8] & (1 << (position % 8)) != 0;
+        value | ((set as u64) << bit)
+    })
+}
+
+/// Write the raw value of a signal of `length` (1 to 64) bits, upper bits of `value`
+/// are ignored
+pub fn insert(data: &mut [u8], start: u16, length: u8, order: ByteOrder, value: u64) {
+    for (bit, position) in positions(start, length, order) {
+        let mask = 1 << (position % 8);
+        if value & (1 << bit) != 0 {
+            data[position / 8] |= mask;
+        } else {
+            data[position / 8] &= !mask;
+        }
+    }
+}
+
+/// Interpret the raw value of a signed signal of `length` bits
+pub fn sign_extend(raw: u64, length: u8) -> i64 {
+    let shift = 64 - length as u32;
+    ((raw << shift) as i64) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn little_endian() {
+        let mut data = [0; 8];
+        insert(&mut data, 0, 16, ByteOrder::LittleEndian, 0x1234);
+        assert_eq!(data[..2], [0x34, 0x12]);
+        assert_eq!(extract(&data, 0, 16, ByteOrder::LittleEndian), 0x1234);
+
+        // 12 bits from bit 4 of byte 3 into byte 4
+        let mut data = [0; 8];
+        insert(&mut data, 28, 12, ByteOrder::LittleEndian, 0xABC);
+        assert_eq!(data[3..5], [0xC0, 0xAB]);
+        assert_eq!(extract(&data, 28, 12, ByteOrder::LittleEndian), 0xABC);
+    }
+
+    #[test]
+    fn big_endian() {
+        // Start bit 7 is the most significant bit of byte 0
+        let mut data = [0; 8];
+        insert(&mut data, 7, 16, ByteOrder::BigEndian, 0x1234);
+        assert_eq!(data[..2], [0x12, 0x34]);
+        assert_eq!(extract(&data, 7, 16, ByteOrder::BigEndian), 0x1234);
+
+        // 12 bits from bit 3 of byte 2 into the upper bits of byte 3
+        let mut data = [0; 8];
+        insert(&mut data, 19, 12, ByteOrder::BigEndian, 0xABC);
+        assert_eq!(data[2..4], [0x0A, 0xBC]);
+        assert_eq!(extract(&data, 19, 12, ByteOrder::BigEndian), 0xABC);
+
+        // 4 bits in the top of the last byte
+        let mut data = [0; 8];
+        insert(&mut data, 63, 4, ByteOrder::BigEndian, 0x9);
+        assert_eq!(data[7], 0x90);
+    }
+
+    #[test]
+    fn other_bits_are_kept() {
+        for order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+            let mut data = [0xFF; 4];
+            insert(&mut data, 11, 6, order, 0);
+            insert(&mut data, 11, 6, order, 0x2A);
+            assert_eq!(extract(&data, 11, 6, order), 0x2A);
+
+            let mut cleared = data;
+            insert(&mut cleared, 11, 6, order, 0x3F);
+            assert_eq!(cleared, [0xFF; 4]);
+        }
+    }
+
+    #[test]
+    fn upper_bits_are_ignored() {
+        let mut data = [0; 2];
+        insert(&mut data, 4, 8, ByteOrder::LittleEndian, 0xFFF);
+        assert_eq!(data, [0xF0, 0x0F]);
+    }
+
+    #[test]
+    fn full_width() {
+        let value = 0x0123_4567_89AB_CDEF;
+        for (start, order) in [(0, ByteOrder::LittleEndian), (7, ByteOrder::BigEndian)] {
+            let mut data = [0; 8];
+            insert(&mut data, start, 64, order, value);
+            assert_eq!(extract(&data, start, 64, order), value);
+        }
+    }
+
+    #[test]
+    fn signed_values() {
+        for (start, order) in [(3, ByteOrder::LittleEndian), (5, ByteOrder::BigEndian)] {
+            for value in [-2048_i64, -501, -1, 0, 1, 2047] {
+                let mut data = [0; 4];
+                insert(&mut data, start, 12, order, value as u64);
+                assert_eq!(sign_extend(extract(&data, start, 12, order), 12), value);
+            }
+        }
+        assert_eq!(sign_extend(0xFFFF_FFFF_FFFF_FFFF, 64), -1);
+        assert_eq!(sign_extend(0x1, 1), -1);
+    }
+
+    #[test]
+    fn round_trip_every_position() {
+        for order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+            for length in 1..=16 {
+                for start in 0..64 {
+                    let fits = positions(start, length, order).all(|(_, bit)| bit < 64);
+                    if !fits {
+                        continue;
+                    }
+                    let value = 0xA5A5 & ((1 << length) - 1);
+                    let mut data = [0; 8];
+                    insert(&mut data, start, length, order, value);
+                    assert_eq!(extract(&data, start, length, order), value);
+                    assert_eq!(
+                        data.iter().map(|byte| byte.count_ones()).sum::<u32>(),
+                        value.count_ones()
+                    );
+                }
+            }
+        }
+    }
+}
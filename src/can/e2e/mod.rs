@@ -0,0 +1,99 @@
+//!
+//! AUTOSAR End-to-End protection of CAN payloads
+//!
+//! Profiles 1, 2 and 11 protect short payloads with a CRC8 and a 4 bit alive counter,
+//! profile 4 (CRC32) and profile 5 (CRC16) longer ones with a header in the payload.
+//! Every profile has a protector for the sender, which writes counter and CRC into the
+//! payload (see [E2EProtect]), and a checker for the receiver, which evaluates them
+//! to an [E2ECheckStatus] (see [E2ECheck]). The [E2EStateMachine] condenses the
+//! statuses of several frames into whether the data can be trusted.
+//!
+//! ```ignore
+//! let mut protector = P05Protector::new(P05Config::new(0x1234));
+//! frame.set_data(&[0, 0, 0, speed_low, speed_high, 0, 0, 0]);
+//! protector.protect_frame(&mut frame);
+//!
+//! // On the receiver
+//! let status = checker.check_frame(&rx_frame);
+//! if state_machine.update(status) == E2EState::Valid { ... }
+//! ```
+//!
+//! Everything here only works on byte slices, none of it touches the hardware.
+//!
+use defmt::Format;
+
+use crate::can::{memory::module_ram::CanBuffer, CanRxFrame, CanTxFrame};
+
+mod profile1;
+mod profile11;
+mod profile2;
+mod profile4;
+mod profile5;
+mod state_machine;
+
+pub use profile1::{DataIdMode, P01Checker, P01Config, P01Protector};
+pub use profile11::{P11Checker, P11Config, P11DataIdMode, P11Protector};
+pub use profile2::{P02Checker, P02Config, P02Protector};
+pub use profile4::{P04Checker, P04Config, P04Protector};
+pub use profile5::{P05Checker, P05Config, P05Protector};
+pub use state_machine::{E2EState, E2EStateMachine, E2EStateMachineConfig};
+
+/// Result of checking a single payload
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum E2ECheckStatus {
+    /// Valid, the counter incremented by one
+    Ok,
+    /// No frame was received since the last check, passed in by the application
+    NoNewData,
+    /// Wrong CRC, data id or length, or a payload too short for the profile
+    Error,
+    /// Valid, but the counter did not change
+    Repeated,
+    /// Valid, some frames were lost in between but no more than allowed
+    OkSomeLost,
+    /// Valid, but the counter jumped further than allowed
+    WrongSequence,
+}
+
+/// Sender side of a profile
+pub trait E2EProtect {
+    /// Write the protection into the payload and advance the counter
+    fn protect(&mut self, data: &mut [u8]);
+
+    /// Protect the payload of a frame, its data must already be set
+    fn protect_frame<B: CanBuffer>(&mut self, frame: &mut CanTxFrame<B>) {
+        self.protect(frame.data_mut());
+    }
+}
+
+/// Receiver side of a profile
+pub trait E2ECheck {
+    /// Check the protection of a received payload
+    fn check(&mut self, data: &[u8]) -> E2ECheckStatus;
+
+    fn check_frame<B: CanBuffer>(&mut self, frame: &CanRxFrame<B>) -> E2ECheckStatus {
+        self.check(frame.data())
+    }
+}
+
+/// Evaluate how far the counter moved since the last valid payload
+fn counter_status(delta: u32, max_delta_counter: u32) -> E2ECheckStatus {
+    match delta {
+        0 => E2ECheckStatus::Repeated,
+        1 => E2ECheckStatus::Ok,
+        delta if delta <= max_delta_counter => E2ECheckStatus::OkSomeLost,
+        _ => E2ECheckStatus::WrongSequence,
+    }
+}
+
+/// Read the nibble at the given bit offset (a multiple of 4)
+fn read_nibble(data: &[u8], offset: usize) -> u8 {
+    (data[offset / 8] >> (offset % 8)) & 0x0F
+}
+
+/// Write the nibble at the given bit offset (a multiple of 4)
+fn write_nibble(data: &mut [u8], offset: usize, value: u8) {
+    let shift = offset % 8;
+    let byte = &mut data[offset / 8];
+    *byte = (*byte & !(0x0F << shift)) | ((value & 0x0F) << shift);
+}
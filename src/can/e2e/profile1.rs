@@ -0,0 +1,215 @@
+//!
+//! E2E profile 1: CRC8 SAE J1850 over the data id and the payload, 4 bit counter
+//!
+use defmt::Format;
+
+use crate::can::crc::crc8;
+
+use super::{counter_status, read_nibble, write_nibble, E2ECheck, E2ECheckStatus, E2EProtect};
+
+/// Counter values 0 to 14, 15 is invalid
+const COUNTER_MODULO: u8 = 15;
+
+/// Which bytes of the 16 bit data id are included in the CRC
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum DataIdMode {
+    /// Low byte, then high byte
+    Both,
+    /// Low byte with even counters, high byte with odd counters
+    Alternating,
+    /// Only the low byte, the high byte must be 0
+    Low,
+    /// Low byte in the CRC, the low nibble of the high byte is sent in the payload
+    Nibble,
+}
+
+/// Offsets are in bits, counter and data id nibble are aligned to 4 bits and the CRC
+/// to 8 bits
+#[derive(Format, Clone, Copy)]
+pub struct P01Config {
+    pub data_id: u16,
+    pub data_id_mode: DataIdMode,
+    pub crc_offset: usize,
+    pub counter_offset: usize,
+    pub data_id_nibble_offset: usize,
+    /// Counter increments up to this are still accepted as [E2ECheckStatus::OkSomeLost]
+    pub max_delta_counter: u8,
+}
+
+impl P01Config {
+    /// The default layout: CRC in byte 0, counter in the low and data id nibble in the
+    /// high nibble of byte 1
+    pub const fn new(data_id: u16, data_id_mode: DataIdMode) -> Self {
+        P01Config {
+            data_id,
+            data_id_mode,
+            crc_offset: 0,
+            counter_offset: 8,
+            data_id_nibble_offset: 12,
+            max_delta_counter: 1,
+        }
+    }
+}
+
+pub struct P01Protector {
+    config: P01Config,
+    counter: u8,
+}
+
+impl P01Protector {
+    pub fn new(config: P01Config) -> Self {
+        P01Protector { config, counter: 0 }
+    }
+
+    /// Counter of the next payload
+    pub fn counter(&self) -> u8 {
+        self.counter
+    }
+}
+
+impl E2EProtect for P01Protector {
+    fn protect(&mut self, data: &mut [u8]) {
+        let config = &self.config;
+        defmt::assert!(
+            fits(
+                data,
+                config.crc_offset,
+                config.counter_offset,
+                config.data_id_nibble_offset
+            ),
+            "Payload too short for E2E profile 1"
+        );
+
+        write_nibble(data, config.counter_offset, self.counter);
+        if config.data_id_mode == DataIdMode::Nibble {
+            write_nibble(
+                data,
+                config.data_id_nibble_offset,
+                (config.data_id >> 8) as u8,
+            );
+        }
+        data[config.crc_offset / 8] = crc(data, config, self.counter);
+
+        self.counter = (self.counter + 1) % COUNTER_MODULO;
+    }
+}
+
+pub struct P01Checker {
+    config: P01Config,
+    /// Counter of the last valid payload
+    last_counter: u8,
+}
+
+impl P01Checker {
+    pub fn new(config: P01Config) -> Self {
+        P01Checker {
+            config,
+            // A first counter of 0 counts as increment by one
+            last_counter: COUNTER_MODULO - 1,
+        }
+    }
+}
+
+impl E2ECheck for P01Checker {
+    fn check(&mut self, data: &[u8]) -> E2ECheckStatus {
+        let config = &self.config;
+        if !fits(
+            data,
+            config.crc_offset,
+            config.counter_offset,
+            config.data_id_nibble_offset,
+        ) {
+            return E2ECheckStatus::Error;
+        }
+
+        let counter = read_nibble(data, config.counter_offset);
+        if counter >= COUNTER_MODULO
+            || data[config.crc_offset / 8] != crc(data, config, counter)
+            || (config.data_id_mode == DataIdMode::Nibble
+                && read_nibble(data, config.data_id_nibble_offset)
+                    != (config.data_id >> 8) as u8 & 0x0F)
+        {
+            return E2ECheckStatus::Error;
+        }
+
+        let delta = (counter + COUNTER_MODULO - self.last_counter) % COUNTER_MODULO;
+        self.last_counter = counter;
+        counter_status(delta as u32, config.max_delta_counter as u32)
+    }
+}
+
+fn crc(data: &[u8], config: &P01Config, counter: u8) -> u8 {
+    let [low, high] = config.data_id.to_le_bytes();
+    let data_id: &[u8] = match config.data_id_mode {
+        DataIdMode::Both => &[low, high],
+        DataIdMode::Alternating if counter.is_multiple_of(2) => &[low],
+        DataIdMode::Alternating => &[high],
+        DataIdMode::Low => &[low],
+        DataIdMode::Nibble => &[low, 0],
+    };
+    payload_crc(data, config.crc_offset, data_id)
+}
+
+/// CRC of profiles 1 and 11: over the data id bytes, then the payload without the CRC
+/// byte. The start value 0xFF and the final XOR of the CRC library cancel out with the
+/// ones of the profile.
+pub(super) fn payload_crc(data: &[u8], crc_offset: usize, data_id: &[u8]) -> u8 {
+    let crc_byte = crc_offset / 8;
+    let crc = crc8(0x00, data_id);
+    let crc = crc8(crc, &data[..crc_byte]);
+    crc8(crc, &data[crc_byte + 1..])
+}
+
+/// Whether all fields are within the payload
+pub(super) fn fits(
+    data: &[u8],
+    crc_offset: usize,
+    counter_offset: usize,
+    data_id_nibble_offset: usize,
+) -> bool {
+    let bits = data.len() * 8;
+    crc_offset + 8 <= bits && counter_offset + 4 <= bits && data_id_nibble_offset + 4 <= bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Examples of the AUTOSAR E2E protocol specification, counters 0 and 1
+    #[test]
+    fn specification_example() {
+        let config = P01Config::new(0x123, DataIdMode::Both);
+        let mut protector = P01Protector::new(config);
+        let mut checker = P01Checker::new(config);
+
+        for expected in [[0xCC, 0x00], [0x91, 0x01]] {
+            let mut data = [0; 8];
+            protector.protect(&mut data);
+            assert_eq!(data, [expected[0], expected[1], 0, 0, 0, 0, 0, 0]);
+            assert!(checker.check(&data) == E2ECheckStatus::Ok);
+        }
+    }
+
+    #[test]
+    fn counter_skips_15() {
+        let config = P01Config::new(0x123, DataIdMode::Alternating);
+        let mut protector = P01Protector::new(config);
+        let mut checker = P01Checker::new(config);
+
+        for counter in (0..15).chain(0..2) {
+            let mut data = [0; 8];
+            protector.protect(&mut data);
+            assert_eq!(data[1] & 0xF, counter);
+            assert!(checker.check(&data) == E2ECheckStatus::Ok);
+        }
+    }
+
+    #[test]
+    fn wrong_data_id() {
+        let mut data = [0; 8];
+        P01Protector::new(P01Config::new(0x123, DataIdMode::Both)).protect(&mut data);
+
+        let mut checker = P01Checker::new(P01Config::new(0x124, DataIdMode::Both));
+        assert!(checker.check(&data) == E2ECheckStatus::Error);
+    }
+}
@@ -0,0 +1,171 @@
+//!
+//! E2E profile 11: like profile 1 with the data id modes of the newer specifications
+//!
+use defmt::Format;
+
+use super::{
+    counter_status,
+    profile1::{fits, payload_crc},
+    read_nibble, write_nibble, E2ECheck, E2ECheckStatus, E2EProtect,
+};
+
+/// Counter values 0 to 14
+const COUNTER_MODULO: u8 = 15;
+
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum P11DataIdMode {
+    /// Both bytes of the 16 bit data id are included in the CRC
+    Both,
+    /// A 12 bit data id: the low byte is included in the CRC, the high nibble is sent
+    /// in the payload
+    Nibble,
+}
+
+/// Offsets are in bits, counter and data id nibble are aligned to 4 bits and the CRC
+/// to 8 bits
+#[derive(Format, Clone, Copy)]
+pub struct P11Config {
+    pub data_id: u16,
+    pub data_id_mode: P11DataIdMode,
+    pub crc_offset: usize,
+    pub counter_offset: usize,
+    pub data_id_nibble_offset: usize,
+    /// Counter increments up to this are still accepted as [E2ECheckStatus::OkSomeLost]
+    pub max_delta_counter: u8,
+}
+
+impl P11Config {
+    /// The default layout: CRC in byte 0, counter in the low and data id nibble in the
+    /// high nibble of byte 1
+    pub const fn new(data_id: u16, data_id_mode: P11DataIdMode) -> Self {
+        P11Config {
+            data_id,
+            data_id_mode,
+            crc_offset: 0,
+            counter_offset: 8,
+            data_id_nibble_offset: 12,
+            max_delta_counter: 1,
+        }
+    }
+
+    fn fits(&self, data: &[u8]) -> bool {
+        fits(
+            data,
+            self.crc_offset,
+            self.counter_offset,
+            self.data_id_nibble_offset,
+        )
+    }
+
+    fn crc(&self, data: &[u8]) -> u8 {
+        let [low, high] = self.data_id.to_le_bytes();
+        match self.data_id_mode {
+            P11DataIdMode::Both => payload_crc(data, self.crc_offset, &[low, high]),
+            P11DataIdMode::Nibble => payload_crc(data, self.crc_offset, &[low, 0]),
+        }
+    }
+}
+
+pub struct P11Protector {
+    config: P11Config,
+    counter: u8,
+}
+
+impl P11Protector {
+    pub fn new(config: P11Config) -> Self {
+        P11Protector { config, counter: 0 }
+    }
+
+    /// Counter of the next payload
+    pub fn counter(&self) -> u8 {
+        self.counter
+    }
+}
+
+impl E2EProtect for P11Protector {
+    fn protect(&mut self, data: &mut [u8]) {
+        let config = &self.config;
+        defmt::assert!(config.fits(data), "Payload too short for E2E profile 11");
+
+        write_nibble(data, config.counter_offset, self.counter);
+        if config.data_id_mode == P11DataIdMode::Nibble {
+            write_nibble(
+                data,
+                config.data_id_nibble_offset,
+                (config.data_id >> 8) as u8,
+            );
+        }
+        data[config.crc_offset / 8] = config.crc(data);
+
+        self.counter = (self.counter + 1) % COUNTER_MODULO;
+    }
+}
+
+pub struct P11Checker {
+    config: P11Config,
+    /// Counter of the last valid payload
+    last_counter: u8,
+}
+
+impl P11Checker {
+    pub fn new(config: P11Config) -> Self {
+        P11Checker {
+            config,
+            // A first counter of 0 counts as increment by one
+            last_counter: COUNTER_MODULO - 1,
+        }
+    }
+}
+
+impl E2ECheck for P11Checker {
+    fn check(&mut self, data: &[u8]) -> E2ECheckStatus {
+        let config = &self.config;
+        if !config.fits(data) {
+            return E2ECheckStatus::Error;
+        }
+
+        let counter = read_nibble(data, config.counter_offset);
+        let nibble_valid = config.data_id_mode == P11DataIdMode::Both
+            || read_nibble(data, config.data_id_nibble_offset)
+                == (config.data_id >> 8) as u8 & 0x0F;
+        if counter >= COUNTER_MODULO
+            || !nibble_valid
+            || data[config.crc_offset / 8] != config.crc(data)
+        {
+            return E2ECheckStatus::Error;
+        }
+
+        let delta = (counter + COUNTER_MODULO - self.last_counter) % COUNTER_MODULO;
+        self.last_counter = counter;
+        counter_status(delta as u32, config.max_delta_counter as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Examples of the AUTOSAR E2E protocol specification, counters 0 and 1
+    fn specification_example(mode: P11DataIdMode, expected: [[u8; 2]; 2]) {
+        let config = P11Config::new(0x123, mode);
+        let mut protector = P11Protector::new(config);
+        let mut checker = P11Checker::new(config);
+
+        for expected in expected {
+            let mut data = [0; 8];
+            protector.protect(&mut data);
+            assert_eq!(data, [expected[0], expected[1], 0, 0, 0, 0, 0, 0]);
+            assert!(checker.check(&data) == E2ECheckStatus::Ok);
+        }
+    }
+
+    #[test]
+    fn specification_example_both() {
+        specification_example(P11DataIdMode::Both, [[0xCC, 0x00], [0x91, 0x01]]);
+    }
+
+    #[test]
+    fn specification_example_nibble() {
+        specification_example(P11DataIdMode::Nibble, [[0x2A, 0x10], [0x77, 0x11]]);
+    }
+}
@@ -0,0 +1,94 @@
+//!
+//! E2E profile 2: CRC8H2F over the payload and a data id chosen by the counter
+//!
+use defmt::Format;
+
+use crate::can::crc::crc8h2f;
+
+use super::{counter_status, read_nibble, write_nibble, E2ECheck, E2ECheckStatus, E2EProtect};
+
+/// CRC in byte 0, counter in the low nibble of byte 1
+const CRC_BYTE: usize = 0;
+const COUNTER_OFFSET: usize = 8;
+const COUNTER_MODULO: u8 = 16;
+
+#[derive(Format, Clone, Copy)]
+pub struct P02Config {
+    /// The data id included in the CRC, indexed by the counter
+    pub data_id_list: [u8; 16],
+    /// Counter increments up to this are still accepted as [E2ECheckStatus::OkSomeLost]
+    pub max_delta_counter: u8,
+}
+
+impl P02Config {
+    pub const fn new(data_id_list: [u8; 16]) -> Self {
+        P02Config {
+            data_id_list,
+            max_delta_counter: 1,
+        }
+    }
+
+    fn crc(&self, data: &[u8], counter: u8) -> u8 {
+        let crc = crc8h2f(0xFF, &data[CRC_BYTE + 1..]);
+        crc8h2f(crc, &[self.data_id_list[counter as usize]]) ^ 0xFF
+    }
+}
+
+pub struct P02Protector {
+    config: P02Config,
+    counter: u8,
+}
+
+impl P02Protector {
+    pub fn new(config: P02Config) -> Self {
+        P02Protector { config, counter: 0 }
+    }
+
+    /// Counter of the last payload
+    pub fn counter(&self) -> u8 {
+        self.counter
+    }
+}
+
+impl E2EProtect for P02Protector {
+    fn protect(&mut self, data: &mut [u8]) {
+        defmt::assert!(data.len() >= 2, "Payload too short for E2E profile 2");
+
+        // The counter is incremented before it is sent, the first payload has counter 1
+        self.counter = (self.counter + 1) % COUNTER_MODULO;
+        write_nibble(data, COUNTER_OFFSET, self.counter);
+        data[CRC_BYTE] = self.config.crc(data, self.counter);
+    }
+}
+
+pub struct P02Checker {
+    config: P02Config,
+    /// Counter of the last valid payload
+    last_counter: u8,
+}
+
+impl P02Checker {
+    pub fn new(config: P02Config) -> Self {
+        P02Checker {
+            config,
+            last_counter: 0,
+        }
+    }
+}
+
+impl E2ECheck for P02Checker {
+    fn check(&mut self, data: &[u8]) -> E2ECheckStatus {
+        if data.len() < 2 {
+            return E2ECheckStatus::Error;
+        }
+
+        let counter = read_nibble(data, COUNTER_OFFSET);
+        if data[CRC_BYTE] != self.config.crc(data, counter) {
+            return E2ECheckStatus::Error;
+        }
+
+        let delta = counter.wrapping_sub(self.last_counter) % COUNTER_MODULO;
+        self.last_counter = counter;
+        counter_status(delta as u32, self.config.max_delta_counter as u32)
+    }
+}
@@ -0,0 +1,152 @@
+//!
+//! E2E profile 4: 12 byte header with length, 16 bit counter, 32 bit data id and CRC32P4
+//!
+use defmt::Format;
+
+use crate::can::crc::crc32p4;
+
+use super::{counter_status, E2ECheck, E2ECheckStatus, E2EProtect};
+
+/// Big endian header: length (2 bytes), counter (2 bytes), data id (4 bytes), CRC
+/// (4 bytes)
+const HEADER_LENGTH: usize = 12;
+const COUNTER: usize = 2;
+const DATA_ID: usize = 4;
+const CRC: usize = 8;
+
+#[derive(Format, Clone, Copy)]
+pub struct P04Config {
+    pub data_id: u32,
+    /// Position of the header in the payload, in bytes
+    pub offset: usize,
+    /// Counter increments up to this are still accepted as [E2ECheckStatus::OkSomeLost]
+    pub max_delta_counter: u16,
+}
+
+impl P04Config {
+    pub const fn new(data_id: u32) -> Self {
+        P04Config {
+            data_id,
+            offset: 0,
+            max_delta_counter: 1,
+        }
+    }
+
+    fn fits(&self, data: &[u8]) -> bool {
+        self.offset + HEADER_LENGTH <= data.len() && data.len() <= u16::MAX as usize
+    }
+
+    /// CRC over the payload without the CRC field
+    fn crc(&self, data: &[u8]) -> u32 {
+        let crc = crc32p4(0xFFFF_FFFF, &data[..self.offset + CRC]);
+        crc32p4(crc, &data[self.offset + HEADER_LENGTH..]) ^ 0xFFFF_FFFF
+    }
+}
+
+pub struct P04Protector {
+    config: P04Config,
+    counter: u16,
+}
+
+impl P04Protector {
+    pub fn new(config: P04Config) -> Self {
+        P04Protector { config, counter: 0 }
+    }
+
+    /// Counter of the next payload
+    pub fn counter(&self) -> u16 {
+        self.counter
+    }
+}
+
+impl E2EProtect for P04Protector {
+    fn protect(&mut self, data: &mut [u8]) {
+        let config = &self.config;
+        defmt::assert!(config.fits(data), "Payload too short for E2E profile 4");
+
+        let length = data.len() as u16;
+        let header = &mut data[config.offset..config.offset + HEADER_LENGTH];
+        header[..COUNTER].copy_from_slice(&length.to_be_bytes());
+        header[COUNTER..DATA_ID].copy_from_slice(&self.counter.to_be_bytes());
+        header[DATA_ID..CRC].copy_from_slice(&config.data_id.to_be_bytes());
+        let crc = config.crc(data);
+        data[config.offset + CRC..config.offset + HEADER_LENGTH]
+            .copy_from_slice(&crc.to_be_bytes());
+
+        self.counter = self.counter.wrapping_add(1);
+    }
+}
+
+pub struct P04Checker {
+    config: P04Config,
+    /// Counter of the last valid payload
+    last_counter: u16,
+}
+
+impl P04Checker {
+    pub fn new(config: P04Config) -> Self {
+        P04Checker {
+            config,
+            // A first counter of 0 counts as increment by one
+            last_counter: u16::MAX,
+        }
+    }
+}
+
+impl E2ECheck for P04Checker {
+    fn check(&mut self, data: &[u8]) -> E2ECheckStatus {
+        let config = &self.config;
+        if !config.fits(data) {
+            return E2ECheckStatus::Error;
+        }
+
+        let header = &data[config.offset..config.offset + HEADER_LENGTH];
+        let field = |start: usize, end: usize| {
+            header[start..end]
+                .iter()
+                .fold(0u32, |value, byte| (value << 8) | *byte as u32)
+        };
+        if field(0, COUNTER) != data.len() as u32
+            || field(DATA_ID, CRC) != config.data_id
+            || field(CRC, HEADER_LENGTH) != config.crc(data)
+        {
+            return E2ECheckStatus::Error;
+        }
+
+        let counter = field(COUNTER, DATA_ID) as u16;
+        let delta = counter.wrapping_sub(self.last_counter);
+        self.last_counter = counter;
+        counter_status(delta as u32, config.max_delta_counter as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Example of the AUTOSAR E2E protocol specification
+    #[test]
+    fn specification_example() {
+        let config = P04Config::new(0x0A0B_0C0D);
+        let mut data = [0; 16];
+        P04Protector::new(config).protect(&mut data);
+
+        assert_eq!(
+            data,
+            [
+                0x00, 0x10, 0x00, 0x00, 0x0A, 0x0B, 0x0C, 0x0D, 0x86, 0x2B, 0x05, 0x56, 0x00, 0x00,
+                0x00, 0x00
+            ]
+        );
+        assert!(P04Checker::new(config).check(&data) == E2ECheckStatus::Ok);
+    }
+
+    #[test]
+    fn length_is_protected() {
+        let config = P04Config::new(0x0A0B_0C0D);
+        let mut data = [0; 16];
+        P04Protector::new(config).protect(&mut data);
+
+        assert!(P04Checker::new(config).check(&data[..15]) == E2ECheckStatus::Error);
+    }
+}
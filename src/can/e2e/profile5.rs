@@ -0,0 +1,134 @@
+//!
+//! E2E profile 5: 3 byte header with CRC16 and 8 bit counter, 16 bit data id
+//!
+use defmt::Format;
+
+use crate::can::crc::crc16;
+
+use super::{counter_status, E2ECheck, E2ECheckStatus, E2EProtect};
+
+/// Little endian header: CRC (2 bytes), counter (1 byte)
+const HEADER_LENGTH: usize = 3;
+const COUNTER: usize = 2;
+
+#[derive(Format, Clone, Copy)]
+pub struct P05Config {
+    pub data_id: u16,
+    /// Position of the header in the payload, in bytes
+    pub offset: usize,
+    /// Counter increments up to this are still accepted as [E2ECheckStatus::OkSomeLost]
+    pub max_delta_counter: u8,
+}
+
+impl P05Config {
+    pub const fn new(data_id: u16) -> Self {
+        P05Config {
+            data_id,
+            offset: 0,
+            max_delta_counter: 1,
+        }
+    }
+
+    fn fits(&self, data: &[u8]) -> bool {
+        self.offset + HEADER_LENGTH <= data.len()
+    }
+
+    /// CRC over the payload without the CRC field, then the data id
+    fn crc(&self, data: &[u8]) -> u16 {
+        let crc = crc16(0xFFFF, &data[..self.offset]);
+        let crc = crc16(crc, &data[self.offset + COUNTER..]);
+        crc16(crc, &self.data_id.to_le_bytes())
+    }
+}
+
+pub struct P05Protector {
+    config: P05Config,
+    counter: u8,
+}
+
+impl P05Protector {
+    pub fn new(config: P05Config) -> Self {
+        P05Protector { config, counter: 0 }
+    }
+
+    /// Counter of the next payload
+    pub fn counter(&self) -> u8 {
+        self.counter
+    }
+}
+
+impl E2EProtect for P05Protector {
+    fn protect(&mut self, data: &mut [u8]) {
+        let config = &self.config;
+        defmt::assert!(config.fits(data), "Payload too short for E2E profile 5");
+
+        data[config.offset + COUNTER] = self.counter;
+        let crc = config.crc(data);
+        data[config.offset..config.offset + COUNTER].copy_from_slice(&crc.to_le_bytes());
+
+        self.counter = self.counter.wrapping_add(1);
+    }
+}
+
+pub struct P05Checker {
+    config: P05Config,
+    /// Counter of the last valid payload
+    last_counter: u8,
+}
+
+impl P05Checker {
+    pub fn new(config: P05Config) -> Self {
+        P05Checker {
+            config,
+            // A first counter of 0 counts as increment by one
+            last_counter: u8::MAX,
+        }
+    }
+}
+
+impl E2ECheck for P05Checker {
+    fn check(&mut self, data: &[u8]) -> E2ECheckStatus {
+        let config = &self.config;
+        if !config.fits(data) {
+            return E2ECheckStatus::Error;
+        }
+
+        let crc = u16::from_le_bytes([data[config.offset], data[config.offset + 1]]);
+        if crc != config.crc(data) {
+            return E2ECheckStatus::Error;
+        }
+
+        let counter = data[config.offset + COUNTER];
+        let delta = counter.wrapping_sub(self.last_counter);
+        self.last_counter = counter;
+        counter_status(delta as u32, config.max_delta_counter as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Example of the AUTOSAR E2E protocol specification
+    #[test]
+    fn specification_example() {
+        let config = P05Config::new(0x1234);
+        let mut data = [0; 8];
+        P05Protector::new(config).protect(&mut data);
+
+        assert_eq!(data, [0x1C, 0xCA, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert!(P05Checker::new(config).check(&data) == E2ECheckStatus::Ok);
+    }
+
+    #[test]
+    fn counter_wraps() {
+        let config = P05Config::new(0x1234);
+        let mut protector = P05Protector::new(config);
+        let mut checker = P05Checker::new(config);
+        for _ in 0..300 {
+            let mut data = [0; 8];
+            protector.protect(&mut data);
+            assert!(checker.check(&data) == E2ECheckStatus::Ok);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//!
+//! E2E state machine, evaluates the check statuses of a window of payloads
+//!
+use defmt::Format;
+
+use super::E2ECheckStatus;
+
+/// Statuses considered at most
+const MAX_WINDOW_SIZE: u8 = 32;
+
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum E2EState {
+    /// No valid payload received yet
+    NoData,
+    /// Receiving, but not enough valid payloads yet
+    Init,
+    /// The data can be used
+    Valid,
+    /// Too many errors within the window, the data must not be used
+    Invalid,
+}
+
+/// Thresholds per state, counted within the last `window_size` statuses. Only
+/// [E2ECheckStatus::Ok] and [E2ECheckStatus::OkSomeLost] count as ok and only
+/// [E2ECheckStatus::Error] as error.
+#[derive(Format, Clone, Copy)]
+pub struct E2EStateMachineConfig {
+    /// 1 to 32
+    pub window_size: u8,
+    pub min_ok_state_init: u8,
+    pub max_error_state_init: u8,
+    pub min_ok_state_valid: u8,
+    pub max_error_state_valid: u8,
+    pub min_ok_state_invalid: u8,
+    pub max_error_state_invalid: u8,
+}
+
+pub struct E2EStateMachine {
+    config: E2EStateMachineConfig,
+    state: E2EState,
+    /// One bit per status in the window, the latest in bit 0
+    ok_history: u32,
+    error_history: u32,
+}
+
+impl E2EStateMachine {
+    pub fn new(config: E2EStateMachineConfig) -> Self {
+        defmt::assert!(
+            (1..=MAX_WINDOW_SIZE).contains(&config.window_size),
+            "Invalid E2E window size {}",
+            config.window_size
+        );
+        E2EStateMachine {
+            config,
+            state: E2EState::NoData,
+            ok_history: 0,
+            error_history: 0,
+        }
+    }
+
+    pub fn state(&self) -> E2EState {
+        self.state
+    }
+
+    /// Start over in [E2EState::NoData]
+    pub fn reset(&mut self) {
+        self.state = E2EState::NoData;
+        self.clear();
+    }
+
+    /// Add the status of the latest check, returns the new state
+    pub fn update(&mut self, status: E2ECheckStatus) -> E2EState {
+        let config = self.config;
+
+        if self.state == E2EState::NoData {
+            if matches!(status, E2ECheckStatus::Error | E2ECheckStatus::NoNewData) {
+                return self.state;
+            }
+            self.state = E2EState::Init;
+            self.clear();
+        }
+
+        self.add(status);
+        let ok = self.ok_history.count_ones() as u8;
+        let errors = self.error_history.count_ones() as u8;
+
+        self.state = match self.state {
+            E2EState::Init if errors > config.max_error_state_init => E2EState::Invalid,
+            E2EState::Init if ok >= config.min_ok_state_init => E2EState::Valid,
+            E2EState::Valid
+                if errors > config.max_error_state_valid || ok < config.min_ok_state_valid =>
+            {
+                E2EState::Invalid
+            }
+            E2EState::Invalid
+                if errors <= config.max_error_state_invalid
+                    && ok >= config.min_ok_state_invalid =>
+            {
+                E2EState::Valid
+            }
+            state => state,
+        };
+        self.state
+    }
+
+    fn add(&mut self, status: E2ECheckStatus) {
+        let window = u32::MAX >> (32 - self.config.window_size as u32);
+        let ok = matches!(status, E2ECheckStatus::Ok | E2ECheckStatus::OkSomeLost);
+        let error = status == E2ECheckStatus::Error;
+        self.ok_history = ((self.ok_history << 1) | ok as u32) & window;
+        self.error_history = ((self.error_history << 1) | error as u32) & window;
+    }
+
+    fn clear(&mut self) {
+        self.ok_history = 0;
+        self.error_history = 0;
+    }
+}
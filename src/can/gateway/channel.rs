@@ -0,0 +1,123 @@
+//!
+//! Routes frames between two [CanNode]s
+//!
+use crate::{
+    can::{
+        memory::module_ram::CanBuffer,
+        node::{
+            connection::Connected, receive::RxFifo0, transceive::TxDedicated, CanNode, Running,
+        },
+        CanID, CanModule, CanRxFrame,
+    },
+    time::Instant,
+};
+
+use super::{Gateway, Port, Routed};
+
+/// A running node with a receive FIFO and dedicated transmit buffers
+type GatewayNode<'r, 'mem, C, TB, RB, M> = CanNode<
+    'r,
+    C,
+    Running,
+    TxDedicated<'mem, TB, <M as CanModule>::RAM>,
+    RxFifo0<'mem, RB, <M as CanModule>::RAM>,
+    M,
+>;
+
+impl<'a> Gateway<'a> {
+    /// Route all frames pending in FIFO 0 of both nodes, `a` is [Port::A] and `b` is
+    /// [Port::B]. Frames for the application are passed to `on_frame`.
+    ///
+    /// Call this from the receive interrupt of either node (or regularly), it clears
+    /// the new message flags before draining the FIFOs so frames arriving meanwhile
+    /// raise the interrupt again. Forwarded frames go to the next free transmit buffer,
+    /// frames that find none are counted as lost. Set up the transmit buffers of both
+    /// nodes as FIFO (see [set_tx_fifo](CanNode::set_tx_fifo)) to keep the order of the
+    /// forwarded frames; dedicated buffers are sent lowest id first, so frames of the
+    /// same id may overtake each other if they are forwarded faster than the
+    /// destination bus takes them.
+    pub fn process<
+        'r0,
+        'r1,
+        'mem0,
+        'mem1,
+        C0: Connected,
+        C1: Connected,
+        TB0: CanBuffer,
+        TB1: CanBuffer,
+        RB0: CanBuffer,
+        RB1: CanBuffer,
+        M0: CanModule,
+        M1: CanModule,
+    >(
+        &mut self,
+        a: &mut GatewayNode<'r0, 'mem0, C0, TB0, RB0, M0>,
+        b: &mut GatewayNode<'r1, 'mem1, C1, TB1, RB1, M1>,
+        now: &Instant,
+        mut on_frame: impl FnMut(Port, CanID, &[u8]),
+    ) {
+        self.route_fifo0(Port::A, a, b, now, &mut on_frame);
+        self.route_fifo0(Port::B, b, a, now, &mut on_frame);
+    }
+
+    /// Route the frames received by `node`, which is the `source` port
+    fn route_fifo0<
+        'r0,
+        'r1,
+        'mem0,
+        'mem1,
+        C0: Connected,
+        C1: Connected,
+        TB0: CanBuffer,
+        TB1: CanBuffer,
+        RB0: CanBuffer,
+        RB1: CanBuffer,
+        M0: CanModule,
+        M1: CanModule,
+    >(
+        &mut self,
+        source: Port,
+        node: &mut GatewayNode<'r0, 'mem0, C0, TB0, RB0, M0>,
+        other: &mut GatewayNode<'r1, 'mem1, C1, TB1, RB1, M1>,
+        now: &Instant,
+        on_frame: &mut impl FnMut(Port, CanID, &[u8]),
+    ) {
+        node.take_rx_fifo0_interrupt();
+
+        while let Some(frame) = node.try_receive_fifo0() {
+            match self.route(source, frame.get_id(), now) {
+                Routed::Forward { route, destination } if destination == source => {
+                    self.forward(route, &frame, node)
+                }
+                Routed::Forward { route, .. } => self.forward(route, &frame, other),
+                Routed::Application => on_frame(source, frame.get_id(), frame.data()),
+                Routed::Drop => {}
+            }
+        }
+    }
+
+    fn forward<
+        'r,
+        'mem,
+        C: Connected,
+        TB: CanBuffer,
+        RB: CanBuffer,
+        M: CanModule,
+        FB: CanBuffer,
+    >(
+        &mut self,
+        route: usize,
+        frame: &CanRxFrame<FB>,
+        node: &mut GatewayNode<'r, 'mem, C, TB, RB, M>,
+    ) {
+        let Some(tx_frame) = self.forward_frame::<FB, TB>(route, frame) else {
+            return;
+        };
+        match node.acquire_transmit_buffer() {
+            Some(buffer) => {
+                buffer.set_frame(tx_frame).send();
+            }
+            None => self.transmission_lost(route),
+        }
+    }
+}
@@ -0,0 +1,362 @@
+//!
+//! CAN to CAN gateway
+//!
+//! A [Gateway] routes the frames received on one [Port] according to a table of
+//! [Route]s: each route matches the source port and an id mask or range and either
+//! forwards the frame (optionally with a rewritten id and converted between classic
+//! CAN and CAN FD), drops it or hands it to the application. The first matching route
+//! wins, frames without a route are dropped. Every route counts what happened to its
+//! frames and can limit the rate of the forwarded frames.
+//!
+//! [Gateway::route] and [Gateway::forward_frame] are independent of the hardware, [Gateway::process] connects two
+//! running nodes and is meant to be called from their receive interrupts (see
+//! [enable_rx_fifo0_interrupt](crate::can::node::CanNode::enable_rx_fifo0_interrupt)).
+//!
+use core::time::Duration;
+
+use defmt::Format;
+
+use crate::{
+    can::{memory::module_ram::CanBuffer, CanID, CanRxFrame, CanTxFrame},
+    time::Instant,
+};
+
+mod channel;
+
+/// One of the two nodes connected by the gateway
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    A,
+    B,
+}
+
+/// The ids a route applies to, only ids of the same kind (standard or extended) match
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum RouteFilter {
+    /// Match ids for which `frame_id & mask == id & mask`
+    Classic { id: CanID, mask: u32 },
+    /// Match all ids within `from..=to`
+    Range { from: CanID, to: CanID },
+}
+
+impl RouteFilter {
+    /// Match exactly the given id
+    pub fn id(id: CanID) -> Self {
+        RouteFilter::Classic { id, mask: u32::MAX }
+    }
+
+    pub fn matches(&self, frame_id: CanID) -> bool {
+        match *self {
+            RouteFilter::Classic { id, mask } => {
+                same_kind(id, frame_id) && raw(id) & mask == raw(frame_id) & mask
+            }
+            RouteFilter::Range { from, to } => {
+                same_kind(from, frame_id) && (raw(from)..=raw(to)).contains(&raw(frame_id))
+            }
+        }
+    }
+}
+
+/// What happens to a frame that matches a route
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum RouteAction {
+    /// Send the frame unchanged (up to the [FrameFormat]) on the destination port
+    Forward {
+        destination: Port,
+    },
+    /// Send the frame on the destination port with the bits set in `mask` taken from
+    /// `id`, i.e. a mask of `u32::MAX` replaces the whole id. The kind of the new id is
+    /// the one of `id`.
+    Rewrite {
+        destination: Port,
+        id: CanID,
+        mask: u32,
+    },
+    Drop,
+    /// Pass the frame to the application instead of forwarding it
+    Application,
+}
+
+/// Format of forwarded frames
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// Same format as received
+    Keep,
+    /// Classic CAN, frames with more than 8 bytes cannot be converted and are lost
+    Classic,
+    /// CAN FD, optionally with bitrate switching. Remote frames do not exist in CAN FD
+    /// and are lost.
+    Fd { bitrate_switching: bool },
+}
+
+/// What happened to the frames of a route
+#[derive(Format, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RouteCounters {
+    /// Frames that matched the route
+    pub matched: u32,
+    /// Frames sent on the destination port
+    pub forwarded: u32,
+    /// Frames passed to the application
+    pub to_application: u32,
+    /// Frames dropped by a [RouteAction::Drop] route
+    pub dropped: u32,
+    /// Frames dropped as they came sooner than the minimum interval
+    pub rate_limited: u32,
+    /// Frames that did not fit the destination format or buffer, or found no free
+    /// transmit buffer
+    pub lost: u32,
+}
+
+/// An entry of the routing table, e.g.
+///
+/// ```ignore
+/// let mut routes = [
+///     // Diagnostics go to the application
+///     Route::new(Port::A, RouteFilter::Range { from: CanID::Standard(0x700), to: CanID::Standard(0x7FF) }, RouteAction::Application),
+///     // The body domain at most every 10ms, as CAN FD
+///     Route::new(Port::A, RouteFilter::Classic { id: CanID::Standard(0x100), mask: 0x700 }, RouteAction::Forward { destination: Port::B })
+///         .with_format(FrameFormat::Fd { bitrate_switching: true })
+///         .with_min_interval(Duration::from_millis(10)),
+/// ];
+/// let mut gateway = Gateway::new(&mut routes);
+/// ```
+pub struct Route {
+    source: Port,
+    filter: RouteFilter,
+    action: RouteAction,
+    format: FrameFormat,
+    min_interval: Option<Duration>,
+    /// Last time a frame was forwarded
+    last_forwarded: Option<Instant>,
+    counters: RouteCounters,
+}
+
+impl Route {
+    pub const fn new(source: Port, filter: RouteFilter, action: RouteAction) -> Self {
+        Route {
+            source,
+            filter,
+            action,
+            format: FrameFormat::Keep,
+            min_interval: None,
+            last_forwarded: None,
+            counters: RouteCounters {
+                matched: 0,
+                forwarded: 0,
+                to_application: 0,
+                dropped: 0,
+                rate_limited: 0,
+                lost: 0,
+            },
+        }
+    }
+
+    pub fn with_format(self, format: FrameFormat) -> Self {
+        Route { format, ..self }
+    }
+
+    /// Forward at most one frame per interval, frames in between are dropped
+    pub fn with_min_interval(self, min_interval: Duration) -> Self {
+        Route {
+            min_interval: Some(min_interval),
+            ..self
+        }
+    }
+
+    pub fn source(&self) -> Port {
+        self.source
+    }
+
+    pub fn filter(&self) -> RouteFilter {
+        self.filter
+    }
+
+    pub fn action(&self) -> RouteAction {
+        self.action
+    }
+
+    pub fn format(&self) -> FrameFormat {
+        self.format
+    }
+
+    pub fn counters(&self) -> &RouteCounters {
+        &self.counters
+    }
+}
+
+/// Outcome of [Gateway::route]
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum Routed {
+    /// Send the frame on the destination port, built by [Gateway::forward_frame]
+    Forward {
+        route: usize,
+        destination: Port,
+    },
+    /// Pass the frame to the application
+    Application,
+    Drop,
+}
+
+/// Routing engine, see the [module](self) documentation
+pub struct Gateway<'a> {
+    routes: &'a mut [Route],
+    /// Frames no route matched
+    unrouted: u32,
+}
+
+impl<'a> Gateway<'a> {
+    pub fn new(routes: &'a mut [Route]) -> Self {
+        Gateway {
+            routes,
+            unrouted: 0,
+        }
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        self.routes
+    }
+
+    /// Number of frames no route matched
+    pub fn unrouted(&self) -> u32 {
+        self.unrouted
+    }
+
+    pub fn reset_counters(&mut self) {
+        for route in self.routes.iter_mut() {
+            route.counters = RouteCounters::default();
+        }
+        self.unrouted = 0;
+    }
+
+    /// Decide what happens to a frame with the given id received on `source`
+    pub fn route(&mut self, source: Port, id: CanID, now: &Instant) -> Routed {
+        let Some(index) = self
+            .routes
+            .iter()
+            .position(|route| route.source == source && route.filter.matches(id))
+        else {
+            self.unrouted = self.unrouted.wrapping_add(1);
+            return Routed::Drop;
+        };
+
+        let route = &mut self.routes[index];
+        let counters = &mut route.counters;
+        counters.matched = counters.matched.wrapping_add(1);
+
+        let destination = match route.action {
+            RouteAction::Forward { destination } | RouteAction::Rewrite { destination, .. } => {
+                destination
+            }
+            RouteAction::Drop => {
+                counters.dropped = counters.dropped.wrapping_add(1);
+                return Routed::Drop;
+            }
+            RouteAction::Application => {
+                counters.to_application = counters.to_application.wrapping_add(1);
+                return Routed::Application;
+            }
+        };
+
+        if let (Some(interval), Some(last)) = (route.min_interval, route.last_forwarded) {
+            if *now < &last + interval {
+                counters.rate_limited = counters.rate_limited.wrapping_add(1);
+                return Routed::Drop;
+            }
+        }
+        route.last_forwarded = Some(*now);
+
+        Routed::Forward {
+            route: index,
+            destination,
+        }
+    }
+
+    /// Build the frame the given route forwards, with the rewritten id and converted
+    /// format. None if the payload does not fit the format or the transmit buffer.
+    pub fn forward_frame<RB: CanBuffer, TB: CanBuffer>(
+        &mut self,
+        route: usize,
+        frame: &CanRxFrame<RB>,
+    ) -> Option<CanTxFrame<TB>> {
+        let route = &mut self.routes[route];
+        let id = match route.action {
+            RouteAction::Rewrite { id, mask, .. } => rewrite(frame.get_id(), id, mask),
+            _ => frame.get_id(),
+        };
+
+        let Some(tx_frame) = convert(frame, id, route.format) else {
+            defmt::debug!("Cannot forward {} as {}", id, route.format);
+            route.counters.lost = route.counters.lost.wrapping_add(1);
+            return None;
+        };
+        route.counters.forwarded = route.counters.forwarded.wrapping_add(1);
+        Some(tx_frame)
+    }
+
+    /// A forwarded frame of the given route could not be sent
+    pub fn transmission_lost(&mut self, route: usize) {
+        let counters = &mut self.routes[route].counters;
+        counters.forwarded = counters.forwarded.wrapping_sub(1);
+        counters.lost = counters.lost.wrapping_add(1);
+    }
+}
+
+/// Build the frame to forward, None if the payload does not fit the format or buffer
+fn convert<RB: CanBuffer, TB: CanBuffer>(
+    frame: &CanRxFrame<RB>,
+    id: CanID,
+    format: FrameFormat,
+) -> Option<CanTxFrame<TB>> {
+    let header = frame.header();
+    if header.rtr() {
+        if let FrameFormat::Fd { .. } = format {
+            return None;
+        }
+        // No payload, the length is the one requested
+        let mut tx_frame = CanTxFrame::<TB>::default();
+        tx_frame.set_id(id);
+        tx_frame.set_remote_request(header.data_length().min(8));
+        return Some(tx_frame);
+    }
+
+    let data = frame.data();
+    if data.len() > TB::BUFFER_SIZE {
+        return None;
+    }
+
+    let mut tx_frame = CanTxFrame::<TB>::default();
+    tx_frame.set_id(id);
+    tx_frame.set_data(data);
+    match format {
+        FrameFormat::Keep if frame.is_fd_format() => {
+            tx_frame.set_fd_format(frame.bitrate_switching())
+        }
+        FrameFormat::Keep => {}
+        FrameFormat::Classic if data.len() > 8 => return None,
+        FrameFormat::Classic => {}
+        FrameFormat::Fd { bitrate_switching } => tx_frame.set_fd_format(bitrate_switching),
+    }
+    Some(tx_frame)
+}
+
+fn rewrite(id: CanID, new_id: CanID, mask: u32) -> CanID {
+    let value = (raw(id) & !mask) | (raw(new_id) & mask);
+    match new_id {
+        CanID::Standard(_) => CanID::Standard((value & 0x7FF) as u16),
+        CanID::Extended(_) => CanID::Extended(value & 0x1FFF_FFFF),
+    }
+}
+
+fn raw(id: CanID) -> u32 {
+    match id {
+        CanID::Standard(id) => id as u32,
+        CanID::Extended(id) => id,
+    }
+}
+
+fn same_kind(a: CanID, b: CanID) -> bool {
+    matches!(
+        (a, b),
+        (CanID::Standard(_), CanID::Standard(_)) | (CanID::Extended(_), CanID::Extended(_))
+    )
+}
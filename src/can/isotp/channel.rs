@@ -0,0 +1,111 @@
+//!
+//! Drives an [IsoTp] state machine with a [CanNode]
+//!
+use crate::{
+    can::{
+        memory::module_ram::CanBuffer,
+        node::{
+            connection::Connected,
+            receive::RxFifo0,
+            transceive::{TransmitStatus, TransmitToken, TxDedicated},
+            CanNode, Running,
+        },
+        CanModule,
+    },
+    time::Instant,
+};
+
+use super::{IsoTp, IsoTpError, IsoTpEvent};
+
+/// An ISO-TP channel on a running node, see [IsoTpChannel::process]
+///
+/// All frames in FIFO 0 are consumed by the channel, frames of other identifiers are
+/// dropped. Use acceptance filters so only the channel's frames end up in FIFO 0.
+pub struct IsoTpChannel<'buf> {
+    transport: IsoTp<'buf>,
+    /// Transmission of the frame handed out last
+    token: Option<TransmitToken>,
+}
+
+impl<'buf> IsoTpChannel<'buf> {
+    pub fn new(transport: IsoTp<'buf>) -> Self {
+        IsoTpChannel {
+            transport,
+            token: None,
+        }
+    }
+
+    pub fn transport(&self) -> &IsoTp<'buf> {
+        &self.transport
+    }
+
+    pub fn transport_mut(&mut self) -> &mut IsoTp<'buf> {
+        &mut self.transport
+    }
+
+    /// Start transmitting a message, see [IsoTp::send]
+    pub fn send(&mut self, message: &[u8]) -> Result<(), IsoTpError> {
+        self.transport.send(message)
+    }
+
+    /// The last message received, see [IsoTp::received]
+    pub fn received(&self) -> &[u8] {
+        self.transport.received()
+    }
+
+    /// Receive pending frames, transmit due frames and check the timeouts. Call this
+    /// regularly (at least once per separation time), returns the next event, if any.
+    #[allow(clippy::type_complexity)]
+    pub fn process<'r, 'mem, C: Connected, TB: CanBuffer, RB: CanBuffer, M: CanModule>(
+        &mut self,
+        node: &mut CanNode<
+            'r,
+            C,
+            Running,
+            TxDedicated<'mem, TB, M::RAM>,
+            RxFifo0<'mem, RB, M::RAM>,
+            M,
+        >,
+        now: &Instant,
+    ) -> Option<IsoTpEvent> {
+        // Confirm first, a flow control may answer the frame handed out last
+        if let Some(token) = &self.token {
+            match node.transmit_status(token) {
+                TransmitStatus::Pending => {}
+                TransmitStatus::Sent => {
+                    self.transport.on_transmitted(now);
+                    self.token = None;
+                }
+                // Not retried, the transmit timeout reports the failure
+                TransmitStatus::NotSent | TransmitStatus::TimedOut => self.token = None,
+            }
+        }
+
+        while let Some(frame) = node.try_receive_fifo0() {
+            self.transport.on_frame(frame.get_id(), frame.data(), now);
+        }
+
+        let event = self.transport.poll(now);
+
+        // A frame that timed out (N_As/N_Bs) must not go out late, it is cancelled and
+        // the buffer is kept until the cancellation finished
+        if let Some(token) = &self.token {
+            if !self.transport.awaits_confirmation() {
+                node.cancel_transmission(token);
+                if node.transmit_status(token) != TransmitStatus::Pending {
+                    self.token = None;
+                }
+            }
+        }
+
+        if self.token.is_none() {
+            if let Some(buffer) = node.acquire_transmit_buffer() {
+                if let Some(frame) = self.transport.next_frame::<TB>(now) {
+                    self.token = Some(buffer.set_frame(frame).send());
+                }
+            }
+        }
+
+        event
+    }
+}
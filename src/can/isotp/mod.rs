@@ -0,0 +1,743 @@
+//!
+//! ISO-TP (ISO 15765-2) transport layer, to exchange messages longer than one frame
+//!
+//! [IsoTp] is the protocol state machine only, it never touches the hardware: received
+//! frames are passed in with [IsoTp::on_frame], frames to send are pulled out with
+//! [IsoTp::next_frame] and the current time is passed in explicitly. This keeps it
+//! usable on the host, [IsoTpChannel] drives it with a [CanNode](crate::can::node::CanNode).
+//!
+//! Only one frame is handed out at a time, the next one follows after the previous one
+//! was confirmed through [IsoTp::on_transmitted].
+//!
+use core::time::Duration;
+
+use defmt::Format;
+
+use crate::{
+    can::{
+        memory::module_ram::{dlc_to_length, length_to_dlc, CanBuffer},
+        CanID, CanTxFrame,
+    },
+    time::Instant,
+};
+
+mod channel;
+
+pub use channel::IsoTpChannel;
+
+/// Largest message length that fits the 12 bit length of a first frame
+const MAX_SHORT_MESSAGE_LENGTH: usize = 0xFFF;
+
+/// Padding byte for CAN FD frames that need padding to a valid length, when no
+/// padding is configured
+const DEFAULT_PADDING: u8 = 0xCC;
+
+/// Protocol control information types, upper nibble of the first (non address) byte
+const PCI_SINGLE_FRAME: u8 = 0x0;
+const PCI_FIRST_FRAME: u8 = 0x1;
+const PCI_CONSECUTIVE_FRAME: u8 = 0x2;
+const PCI_FLOW_CONTROL: u8 = 0x3;
+
+/// How the messages of a channel are addressed
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum Addressing {
+    /// The CAN identifiers alone address the messages
+    Normal,
+    /// The first payload byte of every frame holds the target address
+    Extended {
+        /// Target address put in front of transmitted frames
+        tx_target_address: u8,
+        /// Target address expected in received frames, i.e. our own address
+        rx_target_address: u8,
+    },
+    /// The first payload byte of every frame holds the address extension, in both
+    /// directions
+    Mixed { address_extension: u8 },
+}
+
+impl Addressing {
+    fn tx_prefix(&self) -> Option<u8> {
+        match self {
+            Addressing::Normal => None,
+            Addressing::Extended {
+                tx_target_address, ..
+            } => Some(*tx_target_address),
+            Addressing::Mixed { address_extension } => Some(*address_extension),
+        }
+    }
+
+    fn rx_prefix(&self) -> Option<u8> {
+        match self {
+            Addressing::Normal => None,
+            Addressing::Extended {
+                rx_target_address, ..
+            } => Some(*rx_target_address),
+            Addressing::Mixed { address_extension } => Some(*address_extension),
+        }
+    }
+
+    /// Number of payload bytes taken by the address
+    fn length(&self) -> usize {
+        self.tx_prefix().is_some() as usize
+    }
+}
+
+/// Configuration of an ISO-TP channel, i.e. one pair of CAN identifiers
+#[derive(Clone, Copy)]
+pub struct IsoTpConfig {
+    /// Identifier of transmitted frames
+    pub tx_id: CanID,
+    /// Identifier of received frames, others are ignored
+    pub rx_id: CanID,
+    pub addressing: Addressing,
+    /// Maximum length of transmitted frames (`TX_DL`): 8 for classic CAN, 12, 16, 20,
+    /// 24, 32, 48 or 64 for CAN FD
+    pub tx_data_length: usize,
+    /// Use bitrate switching for CAN FD frames
+    pub bitrate_switching: bool,
+    /// Pad frames with this byte, to 8 bytes or the next valid CAN FD length.
+    /// Without padding only CAN FD frames longer than 8 bytes are padded (with `0xCC`).
+    pub padding: Option<u8>,
+    /// Block size requested from senders, 0 sends all consecutive frames without
+    /// further flow control
+    pub block_size: u8,
+    /// Minimum time between consecutive frames requested from senders
+    pub separation_time: Duration,
+    /// Time for a handed out frame to be transmitted (`N_As`, also used as `N_Ar`)
+    pub transmit_timeout: Duration,
+    /// Time to wait for a flow control frame (`N_Bs`)
+    pub flow_control_timeout: Duration,
+    /// Time to wait for the next consecutive frame (`N_Cr`)
+    pub consecutive_frame_timeout: Duration,
+    /// Number of flow control frames with status WAIT accepted in a row (`N_WFTmax`)
+    pub max_wait_frames: u8,
+}
+
+impl IsoTpConfig {
+    /// Classic CAN with normal addressing, padding with `0xCC`, no flow control after
+    /// the first frame and timeouts of 1 second
+    pub const fn new(tx_id: CanID, rx_id: CanID) -> Self {
+        IsoTpConfig {
+            tx_id,
+            rx_id,
+            addressing: Addressing::Normal,
+            tx_data_length: 8,
+            bitrate_switching: false,
+            padding: Some(DEFAULT_PADDING),
+            block_size: 0,
+            separation_time: Duration::ZERO,
+            transmit_timeout: Duration::from_millis(1000),
+            flow_control_timeout: Duration::from_millis(1000),
+            consecutive_frame_timeout: Duration::from_millis(1000),
+            max_wait_frames: 0,
+        }
+    }
+
+    /// Maximum payload of a single frame with the short (classic) encoding
+    fn short_single_frame_capacity(&self) -> usize {
+        7 - self.addressing.length()
+    }
+}
+
+/// Reasons for a failed transfer
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum IsoTpError {
+    /// A message is being transmitted already
+    Busy,
+    /// The message is empty or does not fit the transmit buffer
+    InvalidLength,
+    /// A frame was not transmitted in time (`N_As`/`N_Ar`)
+    TransmitTimeout,
+    /// The receiver did not send flow control in time (`N_Bs`)
+    FlowControlTimeout,
+    /// The sender did not send the next consecutive frame in time (`N_Cr`)
+    ConsecutiveFrameTimeout,
+    /// A consecutive frame was lost or duplicated
+    WrongSequenceNumber,
+    /// The receiver cannot take a message of this length
+    Overflow,
+    /// A received message does not fit the receive buffer
+    BufferOverflow,
+    /// The receiver sent more flow control frames with status WAIT than accepted
+    WaitLimitExceeded,
+    /// The receiver sent a reserved flow status
+    InvalidFlowStatus,
+}
+
+/// Outcome of a transfer, see [IsoTp::poll]
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum IsoTpEvent {
+    /// A message was received completely, it can be read with [IsoTp::received]
+    Received {
+        length: usize,
+    },
+    /// The message passed to [IsoTp::send] was transmitted completely
+    Sent,
+    TransmitFailed(IsoTpError),
+    ReceiveFailed(IsoTpError),
+}
+
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+enum FlowStatus {
+    ContinueToSend = 0,
+    Wait = 1,
+    Overflow = 2,
+}
+
+/// State entered once the handed out data frame is confirmed
+#[derive(Clone, Copy)]
+enum AfterConfirmation {
+    Done,
+    WaitFlowControl,
+    Consecutive,
+}
+
+#[derive(Clone, Copy)]
+enum TxState {
+    Idle,
+    /// The single or first frame was not handed out yet
+    Ready,
+    /// A data frame was handed out and is waiting for confirmation
+    Confirming(AfterConfirmation),
+    WaitFlowControl {
+        deadline: Instant,
+        wait_frames: u8,
+    },
+    Consecutive {
+        not_before: Instant,
+    },
+}
+
+/// Progress of the message being transmitted
+#[derive(Default, Clone, Copy)]
+struct TxProgress {
+    length: usize,
+    offset: usize,
+    sequence: u8,
+    block_size: u8,
+    block_remaining: u8,
+    separation_time: Duration,
+}
+
+#[derive(Clone, Copy)]
+enum RxState {
+    Idle,
+    Receiving {
+        length: usize,
+        offset: usize,
+        sequence: u8,
+        block_remaining: u8,
+        /// None while our flow control frame is pending
+        deadline: Option<Instant>,
+    },
+}
+
+/// The frame handed out by [IsoTp::next_frame] and not confirmed yet
+#[derive(Clone, Copy)]
+struct InFlight {
+    flow_control: bool,
+    deadline: Instant,
+}
+
+/// ISO-TP protocol state machine of one channel, see the module documentation
+pub struct IsoTp<'buf> {
+    config: IsoTpConfig,
+    tx_buffer: &'buf mut [u8],
+    rx_buffer: &'buf mut [u8],
+    tx: TxState,
+    tx_progress: TxProgress,
+    rx: RxState,
+    received_length: usize,
+    /// Flow control frame to hand out next
+    flow_control: Option<FlowStatus>,
+    in_flight: Option<InFlight>,
+    tx_event: Option<IsoTpEvent>,
+    rx_event: Option<IsoTpEvent>,
+}
+
+impl<'buf> IsoTp<'buf> {
+    /// Create a channel, messages are limited to the size of the given buffers
+    pub fn new(config: IsoTpConfig, tx_buffer: &'buf mut [u8], rx_buffer: &'buf mut [u8]) -> Self {
+        let valid_length = matches!(
+            length_to_dlc(config.tx_data_length),
+            Some(dlc) if dlc_to_length(dlc) == config.tx_data_length
+        );
+        defmt::assert!(
+            valid_length && config.tx_data_length >= 8,
+            "Invalid ISO-TP frame length {}",
+            config.tx_data_length
+        );
+
+        IsoTp {
+            config,
+            tx_buffer,
+            rx_buffer,
+            tx: TxState::Idle,
+            tx_progress: TxProgress::default(),
+            rx: RxState::Idle,
+            received_length: 0,
+            flow_control: None,
+            in_flight: None,
+            tx_event: None,
+            rx_event: None,
+        }
+    }
+
+    pub fn config(&self) -> &IsoTpConfig {
+        &self.config
+    }
+
+    /// Start transmitting a message, the outcome is reported by [IsoTp::poll]
+    pub fn send(&mut self, message: &[u8]) -> Result<(), IsoTpError> {
+        if self.is_transmitting() {
+            return Err(IsoTpError::Busy);
+        }
+        if message.is_empty() || message.len() > self.tx_buffer.len() {
+            return Err(IsoTpError::InvalidLength);
+        }
+
+        self.tx_buffer[..message.len()].copy_from_slice(message);
+        self.tx_progress = TxProgress {
+            length: message.len(),
+            sequence: 1,
+            ..TxProgress::default()
+        };
+        self.tx = TxState::Ready;
+        self.tx_event = None;
+
+        Ok(())
+    }
+
+    /// Whether a message is being transmitted
+    pub fn is_transmitting(&self) -> bool {
+        !matches!(self.tx, TxState::Idle)
+    }
+
+    /// The last message received, valid after [IsoTpEvent::Received] until the next
+    /// message starts
+    pub fn received(&self) -> &[u8] {
+        &self.rx_buffer[..self.received_length]
+    }
+
+    /// Whether a handed out frame waits for [IsoTp::on_transmitted]
+    pub fn awaits_confirmation(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// The next frame to transmit, if any is due. Call [IsoTp::on_transmitted] once
+    /// it was sent.
+    pub fn next_frame<B: CanBuffer>(&mut self, now: &Instant) -> Option<CanTxFrame<B>> {
+        defmt::assert!(
+            B::BUFFER_SIZE >= self.config.tx_data_length,
+            "Transmit buffers are too small for the ISO-TP frame length"
+        );
+
+        if self.in_flight.is_some() {
+            return None;
+        }
+
+        if let Some(status) = self.flow_control.take() {
+            self.in_flight = Some(InFlight {
+                flow_control: true,
+                deadline: now + self.config.transmit_timeout,
+            });
+            return Some(self.flow_control_frame(status));
+        }
+
+        let frame = match self.tx {
+            TxState::Ready => self.first_or_single_frame(),
+            TxState::Consecutive { not_before } if *now >= not_before => self.consecutive_frame(),
+            _ => return None,
+        };
+
+        self.in_flight = Some(InFlight {
+            flow_control: false,
+            deadline: now + self.config.transmit_timeout,
+        });
+        Some(frame)
+    }
+
+    /// The frame handed out last was transmitted
+    pub fn on_transmitted(&mut self, now: &Instant) {
+        let Some(in_flight) = self.in_flight.take() else {
+            return;
+        };
+
+        if in_flight.flow_control {
+            if let RxState::Receiving { deadline, .. } = &mut self.rx {
+                *deadline = Some(now + self.config.consecutive_frame_timeout);
+            }
+            return;
+        }
+
+        if let TxState::Confirming(next) = self.tx {
+            self.tx = match next {
+                AfterConfirmation::Done => {
+                    self.tx_event = Some(IsoTpEvent::Sent);
+                    TxState::Idle
+                }
+                AfterConfirmation::WaitFlowControl => TxState::WaitFlowControl {
+                    deadline: now + self.config.flow_control_timeout,
+                    wait_frames: 0,
+                },
+                AfterConfirmation::Consecutive => TxState::Consecutive {
+                    not_before: now + self.tx_progress.separation_time,
+                },
+            };
+        }
+    }
+
+    /// Process a received frame, frames of other identifiers or addresses are ignored
+    pub fn on_frame(&mut self, id: CanID, data: &[u8], now: &Instant) {
+        if id != self.config.rx_id {
+            return;
+        }
+
+        let data = match self.config.addressing.rx_prefix() {
+            Some(address) => match data.split_first() {
+                Some((&first, rest)) if first == address => rest,
+                _ => return,
+            },
+            None => data,
+        };
+
+        let Some(&pci) = data.first() else {
+            return;
+        };
+
+        match pci >> 4 {
+            PCI_SINGLE_FRAME => self.on_single_frame(data),
+            PCI_FIRST_FRAME => self.on_first_frame(data),
+            PCI_CONSECUTIVE_FRAME => self.on_consecutive_frame(data, now),
+            PCI_FLOW_CONTROL => self.on_flow_control(data, now),
+            _ => defmt::trace!("Ignoring frame with unknown ISO-TP frame type {}", pci),
+        }
+    }
+
+    /// Check the timeouts and return the next event, if any
+    pub fn poll(&mut self, now: &Instant) -> Option<IsoTpEvent> {
+        if let Some(in_flight) = self.in_flight {
+            if *now >= in_flight.deadline {
+                self.in_flight = None;
+                if in_flight.flow_control {
+                    self.fail_reception(IsoTpError::TransmitTimeout);
+                } else {
+                    self.fail_transmission(IsoTpError::TransmitTimeout);
+                }
+            }
+        }
+
+        if let TxState::WaitFlowControl { deadline, .. } = self.tx {
+            if *now >= deadline {
+                self.fail_transmission(IsoTpError::FlowControlTimeout);
+            }
+        }
+
+        if let RxState::Receiving {
+            deadline: Some(deadline),
+            ..
+        } = self.rx
+        {
+            if *now >= deadline {
+                self.fail_reception(IsoTpError::ConsecutiveFrameTimeout);
+            }
+        }
+
+        self.tx_event.take().or_else(|| self.rx_event.take())
+    }
+
+    fn fail_transmission(&mut self, error: IsoTpError) {
+        defmt::debug!("ISO-TP transmission failed: {}", error);
+        self.tx = TxState::Idle;
+        self.tx_event = Some(IsoTpEvent::TransmitFailed(error));
+    }
+
+    fn fail_reception(&mut self, error: IsoTpError) {
+        defmt::debug!("ISO-TP reception failed: {}", error);
+        self.rx = RxState::Idle;
+        self.rx_event = Some(IsoTpEvent::ReceiveFailed(error));
+    }
+
+    /// A single or first frame starts a new message, an ongoing reception is dropped
+    fn start_reception(&mut self) {
+        if let RxState::Receiving { .. } = self.rx {
+            defmt::warn!("ISO-TP reception interrupted by a new message");
+        }
+        self.rx = RxState::Idle;
+        self.received_length = 0;
+        self.flow_control = None;
+    }
+
+    fn on_single_frame(&mut self, data: &[u8]) {
+        let (length, header) = match data[0] & 0xF {
+            // Escape sequence of CAN FD single frames
+            0 if data.len() > 8 - self.config.addressing.length() => (data[1] as usize, 2),
+            0 => return,
+            length => (length as usize, 1),
+        };
+
+        if length == 0 || header + length > data.len() {
+            return;
+        }
+
+        self.start_reception();
+
+        if length > self.rx_buffer.len() {
+            self.fail_reception(IsoTpError::BufferOverflow);
+            return;
+        }
+
+        self.rx_buffer[..length].copy_from_slice(&data[header..header + length]);
+        self.received_length = length;
+        self.rx_event = Some(IsoTpEvent::Received { length });
+    }
+
+    fn on_first_frame(&mut self, data: &[u8]) {
+        if data.len() < 2 {
+            return;
+        }
+
+        let (length, header) = match ((data[0] as usize & 0xF) << 8) | data[1] as usize {
+            // Escape sequence for messages longer than 4095 bytes
+            0 if data.len() >= 6 => (
+                u32::from_be_bytes([data[2], data[3], data[4], data[5]]) as usize,
+                6,
+            ),
+            0 => return,
+            length => (length, 2),
+        };
+
+        // A first frame has to be followed by consecutive frames
+        if length <= data.len() - header {
+            return;
+        }
+
+        self.start_reception();
+
+        if length > self.rx_buffer.len() {
+            self.flow_control = Some(FlowStatus::Overflow);
+            self.fail_reception(IsoTpError::BufferOverflow);
+            return;
+        }
+
+        let first = data.len() - header;
+        self.rx_buffer[..first].copy_from_slice(&data[header..]);
+        self.rx = RxState::Receiving {
+            length,
+            offset: first,
+            sequence: 1,
+            block_remaining: self.config.block_size,
+            deadline: None,
+        };
+        self.flow_control = Some(FlowStatus::ContinueToSend);
+    }
+
+    fn on_consecutive_frame(&mut self, data: &[u8], now: &Instant) {
+        let RxState::Receiving {
+            length,
+            offset,
+            sequence,
+            block_remaining,
+            ..
+        } = self.rx
+        else {
+            return;
+        };
+
+        if data[0] & 0xF != sequence {
+            self.fail_reception(IsoTpError::WrongSequenceNumber);
+            return;
+        }
+
+        let count = (length - offset).min(data.len() - 1);
+        self.rx_buffer[offset..offset + count].copy_from_slice(&data[1..1 + count]);
+        let offset = offset + count;
+
+        if offset == length {
+            self.rx = RxState::Idle;
+            self.received_length = length;
+            self.rx_event = Some(IsoTpEvent::Received { length });
+            return;
+        }
+
+        let block_remaining = match self.config.block_size {
+            0 => 0,
+            _ if block_remaining == 1 => {
+                self.flow_control = Some(FlowStatus::ContinueToSend);
+                self.config.block_size
+            }
+            _ => block_remaining - 1,
+        };
+
+        self.rx = RxState::Receiving {
+            length,
+            offset,
+            sequence: (sequence + 1) & 0xF,
+            block_remaining,
+            // The timeout restarts once our flow control frame is sent
+            deadline: if self.flow_control.is_some() {
+                None
+            } else {
+                Some(now + self.config.consecutive_frame_timeout)
+            },
+        };
+    }
+
+    fn on_flow_control(&mut self, data: &[u8], now: &Instant) {
+        if data.len() < 3 {
+            return;
+        }
+
+        // The flow control may be processed before the first frame was confirmed
+        if let TxState::Confirming(AfterConfirmation::WaitFlowControl) = self.tx {
+            self.on_transmitted(now);
+        }
+
+        let TxState::WaitFlowControl { wait_frames, .. } = self.tx else {
+            return;
+        };
+
+        match data[0] & 0xF {
+            status if status == FlowStatus::ContinueToSend as u8 => {
+                self.tx_progress.block_size = data[1];
+                self.tx_progress.block_remaining = data[1];
+                self.tx_progress.separation_time = decode_separation_time(data[2]);
+                self.tx = TxState::Consecutive { not_before: *now };
+            }
+            status if status == FlowStatus::Wait as u8 => {
+                if wait_frames >= self.config.max_wait_frames {
+                    self.fail_transmission(IsoTpError::WaitLimitExceeded);
+                } else {
+                    self.tx = TxState::WaitFlowControl {
+                        deadline: now + self.config.flow_control_timeout,
+                        wait_frames: wait_frames + 1,
+                    };
+                }
+            }
+            status if status == FlowStatus::Overflow as u8 => {
+                self.fail_transmission(IsoTpError::Overflow)
+            }
+            _ => self.fail_transmission(IsoTpError::InvalidFlowStatus),
+        }
+    }
+
+    fn first_or_single_frame<B: CanBuffer>(&mut self) -> CanTxFrame<B> {
+        let length = self.tx_progress.length;
+        let address_length = self.config.addressing.length();
+        let fd_capacity = self.config.tx_data_length - 2 - address_length;
+
+        if length <= self.config.short_single_frame_capacity() {
+            self.tx = TxState::Confirming(AfterConfirmation::Done);
+            return self.frame(&[(PCI_SINGLE_FRAME << 4) | length as u8], ..length);
+        }
+
+        if self.config.tx_data_length > 8 && length <= fd_capacity {
+            self.tx = TxState::Confirming(AfterConfirmation::Done);
+            return self.frame(&[PCI_SINGLE_FRAME << 4, length as u8], ..length);
+        }
+
+        self.tx = TxState::Confirming(AfterConfirmation::WaitFlowControl);
+
+        if length <= MAX_SHORT_MESSAGE_LENGTH {
+            let first = self.config.tx_data_length - 2 - address_length;
+            self.tx_progress.offset = first;
+            let pci = [(PCI_FIRST_FRAME << 4) | (length >> 8) as u8, length as u8];
+            self.frame(&pci, ..first)
+        } else {
+            let first = self.config.tx_data_length - 6 - address_length;
+            self.tx_progress.offset = first;
+            let [b0, b1, b2, b3] = (length as u32).to_be_bytes();
+            self.frame(&[PCI_FIRST_FRAME << 4, 0, b0, b1, b2, b3], ..first)
+        }
+    }
+
+    fn consecutive_frame<B: CanBuffer>(&mut self) -> CanTxFrame<B> {
+        let progress = self.tx_progress;
+        let capacity = self.config.tx_data_length - 1 - self.config.addressing.length();
+        let end = progress.length.min(progress.offset + capacity);
+
+        self.tx_progress.offset = end;
+        self.tx_progress.sequence = (progress.sequence + 1) & 0xF;
+
+        let next = if end == progress.length {
+            AfterConfirmation::Done
+        } else if progress.block_size == 0 {
+            AfterConfirmation::Consecutive
+        } else if progress.block_remaining == 1 {
+            self.tx_progress.block_remaining = progress.block_size;
+            AfterConfirmation::WaitFlowControl
+        } else {
+            self.tx_progress.block_remaining -= 1;
+            AfterConfirmation::Consecutive
+        };
+        self.tx = TxState::Confirming(next);
+
+        self.frame(
+            &[(PCI_CONSECUTIVE_FRAME << 4) | progress.sequence],
+            progress.offset..end,
+        )
+    }
+
+    fn flow_control_frame<B: CanBuffer>(&self, status: FlowStatus) -> CanTxFrame<B> {
+        let pci = [
+            (PCI_FLOW_CONTROL << 4) | status as u8,
+            self.config.block_size,
+            encode_separation_time(self.config.separation_time),
+        ];
+        self.frame(&pci, 0..0)
+    }
+
+    /// Build a frame from the address, the given protocol control information and
+    /// the given range of the transmit buffer
+    fn frame<B: CanBuffer, R: core::slice::SliceIndex<[u8], Output = [u8]>>(
+        &self,
+        pci: &[u8],
+        range: R,
+    ) -> CanTxFrame<B> {
+        let mut bytes = [0u8; 64];
+        let mut length = 0;
+
+        let address = self.config.addressing.tx_prefix();
+        let parts = [address.as_slice(), pci, &self.tx_buffer[range]];
+        for part in parts {
+            bytes[length..length + part.len()].copy_from_slice(part);
+            length += part.len();
+        }
+
+        let padded_length = match self.config.padding {
+            Some(_) if length <= 8 => 8,
+            None if length <= 8 => length,
+            // Cannot fail, frames are at most 64 bytes
+            _ => dlc_to_length(length_to_dlc(length).unwrap()),
+        };
+        bytes[length..padded_length].fill(self.config.padding.unwrap_or(DEFAULT_PADDING));
+
+        let mut frame = CanTxFrame::<B>::default();
+        frame.set_id(self.config.tx_id);
+        frame.set_data(&bytes[..padded_length]);
+        if self.config.tx_data_length > 8 {
+            frame.set_fd_format(self.config.bitrate_switching);
+        }
+        frame
+    }
+}
+
+/// Decode `STmin`, reserved values are treated as the longest time (127 ms)
+fn decode_separation_time(raw: u8) -> Duration {
+    match raw {
+        0x00..=0x7F => Duration::from_millis(raw as u64),
+        0xF1..=0xF9 => Duration::from_micros(100 * (raw - 0xF0) as u64),
+        _ => Duration::from_millis(0x7F),
+    }
+}
+
+/// Encode `STmin`, rounding up to the next representable value
+fn encode_separation_time(time: Duration) -> u8 {
+    if time.is_zero() {
+        0
+    } else if time <= Duration::from_micros(900) {
+        0xF0 + time.as_micros().div_ceil(100) as u8
+    } else {
+        time.as_micros().div_ceil(1000).min(0x7F) as u8
+    }
+}
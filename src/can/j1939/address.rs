@@ -0,0 +1,129 @@
+//!
+//! Address claiming (J1939-81)
+//!
+use core::time::Duration;
+
+use crate::time::Instant;
+
+use super::{
+    J1939Event, J1939Id, Name, Pgn, DEFAULT_PRIORITY, GLOBAL_ADDRESS, J1939, NULL_ADDRESS,
+};
+
+/// Time after the address claim without contending claim until the address is used
+const CLAIM_TIME: Duration = Duration::from_millis(250);
+
+pub(super) enum ClaimState {
+    /// [J1939::start_address_claim] was not called yet
+    Unclaimed,
+    Claiming {
+        address: u8,
+        until: Instant,
+    },
+    Claimed(u8),
+    CannotClaim,
+}
+
+impl<'buf> J1939<'buf> {
+    /// Claim the preferred address, the address is usable once
+    /// [J1939Event::AddressClaimed] is reported
+    pub fn start_address_claim(&mut self, now: &Instant) {
+        self.claim_address(self.config.preferred_address, now);
+    }
+
+    /// The claimed address, None while claiming
+    pub fn address(&self) -> Option<u8> {
+        match self.claim {
+            ClaimState::Claimed(address) => Some(address),
+            _ => None,
+        }
+    }
+
+    fn claim_address(&mut self, address: u8, now: &Instant) {
+        defmt::debug!("Claiming J1939 address {}", address);
+        self.send_claim(address);
+        self.claim = ClaimState::Claiming {
+            address,
+            until: now + CLAIM_TIME,
+        };
+    }
+
+    fn send_claim(&mut self, source: u8) {
+        let id = J1939Id {
+            priority: DEFAULT_PRIORITY,
+            pgn: Pgn::ADDRESS_CLAIMED,
+            source,
+            destination: GLOBAL_ADDRESS,
+        };
+        let name = u64::from(self.config.name).to_le_bytes();
+        self.enqueue(id, &name);
+    }
+
+    /// The address claim of another node, contending claims of our address are won
+    /// by the lower NAME
+    pub(super) fn on_address_claimed(
+        &mut self,
+        source: u8,
+        data: &[u8],
+        now: &Instant,
+    ) -> Option<J1939Event<'static>> {
+        let name = Name::from(u64::from_le_bytes(data.try_into().ok()?));
+        if source == NULL_ADDRESS || name == self.config.name {
+            return None;
+        }
+        self.set_taken(source);
+
+        let address = match self.claim {
+            ClaimState::Claiming { address, .. } | ClaimState::Claimed(address) => address,
+            _ => return None,
+        };
+        if source != address {
+            return None;
+        }
+
+        if self.config.name < name {
+            // Defend the address
+            self.send_claim(address);
+            return None;
+        }
+
+        defmt::info!("J1939 address {} lost to {}", address, name);
+        match self.next_free_address() {
+            Some(address) if self.config.name.arbitrary_address_capable() => {
+                self.claim_address(address, now);
+                None
+            }
+            _ => {
+                self.send_claim(NULL_ADDRESS);
+                self.claim = ClaimState::CannotClaim;
+                Some(J1939Event::CannotClaimAddress)
+            }
+        }
+    }
+
+    /// Answer a request for the address claimed PGN with our claim
+    pub(super) fn on_address_claim_request(&mut self) {
+        match self.claim {
+            ClaimState::Claiming { address, .. } | ClaimState::Claimed(address) => {
+                self.send_claim(address)
+            }
+            ClaimState::CannotClaim => self.send_claim(NULL_ADDRESS),
+            ClaimState::Unclaimed => {}
+        }
+    }
+
+    pub(super) fn poll_claim(&mut self, now: &Instant) -> Option<J1939Event<'static>> {
+        match self.claim {
+            ClaimState::Claiming { address, until } if *now >= until => {
+                defmt::debug!("J1939 address {} claimed", address);
+                self.claim = ClaimState::Claimed(address);
+                Some(J1939Event::AddressClaimed(address))
+            }
+            _ => None,
+        }
+    }
+
+    fn next_free_address(&self) -> Option<u8> {
+        let (first, last) = self.config.arbitrary_addresses;
+        (first..=last).find(|address| !self.is_taken(*address))
+    }
+}
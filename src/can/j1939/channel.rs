@@ -0,0 +1,89 @@
+//!
+//! Drives a [J1939] controller application with a [CanNode]
+//!
+use crate::{
+    can::{
+        memory::module_ram::CanBuffer,
+        node::{
+            connection::Connected,
+            receive::RxFifo0,
+            transceive::{TransmitStatus, TransmitToken, TxDedicated},
+            CanNode, Running,
+        },
+        CanModule,
+    },
+    time::Instant,
+};
+
+use super::{J1939Event, J1939};
+
+/// A J1939 controller application on a running node, see [J1939Channel::process]
+///
+/// All frames in FIFO 0 are passed to the controller application, use the
+/// [acceptance filters](super::acceptance_filters) to keep other frames out.
+pub struct J1939Channel<'buf> {
+    application: J1939<'buf>,
+    /// Transmission of the frame handed out last, packets must not overtake each other
+    token: Option<TransmitToken>,
+}
+
+impl<'buf> J1939Channel<'buf> {
+    pub fn new(application: J1939<'buf>) -> Self {
+        J1939Channel {
+            application,
+            token: None,
+        }
+    }
+
+    pub fn application(&self) -> &J1939<'buf> {
+        &self.application
+    }
+
+    pub fn application_mut(&mut self) -> &mut J1939<'buf> {
+        &mut self.application
+    }
+
+    /// Receive pending frames, check the timers and transmit due frames. Call this
+    /// regularly (every few milliseconds), events are passed to `on_event`.
+    ///
+    /// Messages borrow the received frame, answers are sent with
+    /// [application_mut](J1939Channel::application_mut) after processing.
+    #[allow(clippy::type_complexity)]
+    pub fn process<'r, 'mem, C: Connected, TB: CanBuffer, RB: CanBuffer, M: CanModule>(
+        &mut self,
+        node: &mut CanNode<
+            'r,
+            C,
+            Running,
+            TxDedicated<'mem, TB, M::RAM>,
+            RxFifo0<'mem, RB, M::RAM>,
+            M,
+        >,
+        now: &Instant,
+        mut on_event: impl FnMut(J1939Event<'_>),
+    ) {
+        while let Some(frame) = node.try_receive_fifo0() {
+            if let Some(event) = self.application.on_frame(frame.get_id(), frame.data(), now) {
+                on_event(event);
+            }
+        }
+
+        while let Some(event) = self.application.poll(now) {
+            on_event(event);
+        }
+
+        if let Some(token) = &self.token {
+            if node.transmit_status(token) == TransmitStatus::Pending {
+                return;
+            }
+            // Lost frames are not retried, the peer's timeouts take care of them
+            self.token = None;
+        }
+
+        if let Some(buffer) = node.acquire_transmit_buffer() {
+            if let Some(frame) = self.application.next_frame::<TB>(now) {
+                self.token = Some(buffer.set_frame(frame).send());
+            }
+        }
+    }
+}
@@ -0,0 +1,852 @@
+//!
+//! SAE J1939 stack on extended identifiers
+//!
+//! [J1939] is a single controller application: it claims an address (J1939-81),
+//! answers requests for its address claim and exchanges messages of up to 1785 bytes
+//! with the transport protocols (BAM and RTS/CTS, J1939-21). Like
+//! [IsoTp](super::isotp::IsoTp) it never touches the hardware: frames are passed in
+//! with [J1939::on_frame], pulled out with [J1939::next_frame] and the current time
+//! is passed in explicitly. [J1939Channel] drives it with a
+//! [CanNode](crate::can::node::CanNode).
+//!
+//! Only one transfer per direction is handled at a time.
+//!
+use core::time::Duration;
+
+use bitfield_struct::bitfield;
+use defmt::Format;
+
+use crate::{
+    can::{
+        memory::{
+            filter::{ExtendedFilter, FilterAction},
+            module_ram::CanBuffer,
+        },
+        queue::{FrameQueue, QueuedFrame},
+        CanID, CanTxFrame,
+    },
+    time::Instant,
+};
+
+mod address;
+mod channel;
+mod transport;
+
+pub use channel::J1939Channel;
+pub use transport::AbortReason;
+
+/// Destination address of frames for all nodes
+pub const GLOBAL_ADDRESS: u8 = 0xFF;
+
+/// Source address of a node without address, e.g. in the cannot claim message
+pub const NULL_ADDRESS: u8 = 0xFE;
+
+/// Largest message length of the transport protocols (255 packets of 7 bytes)
+pub const MAX_MESSAGE_LENGTH: usize = 1785;
+
+/// Priority of messages sent by the stack itself
+const DEFAULT_PRIORITY: u8 = 6;
+
+/// Frames queued for transmission at most, e.g. claims, CTS and acknowledgements
+const QUEUE_LENGTH: usize = 8;
+
+/// Parameter group number
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub struct Pgn(u32);
+
+impl Pgn {
+    pub const ACKNOWLEDGEMENT: Pgn = Pgn(0xE800);
+    pub const REQUEST: Pgn = Pgn(0xEA00);
+    pub const TP_DATA_TRANSFER: Pgn = Pgn(0xEB00);
+    pub const TP_CONNECTION_MANAGEMENT: Pgn = Pgn(0xEC00);
+    pub const ADDRESS_CLAIMED: Pgn = Pgn(0xEE00);
+
+    /// An 18 bit PGN, the lower byte of PDU1 format PGNs is ignored
+    pub const fn new(pgn: u32) -> Self {
+        let pgn = pgn & 0x3_FFFF;
+        if (pgn >> 8) & 0xFF < 240 {
+            Pgn(pgn & !0xFF)
+        } else {
+            Pgn(pgn)
+        }
+    }
+
+    pub const fn value(&self) -> u32 {
+        self.0
+    }
+
+    /// PDU format (PF) field
+    pub const fn pdu_format(&self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    /// PDU1 format PGNs are sent to a destination address, PDU2 format PGNs are
+    /// broadcast and carry a group extension instead
+    pub const fn is_pdu1(&self) -> bool {
+        self.pdu_format() < 240
+    }
+
+    /// PGN as sent in requests and transport protocol messages
+    pub const fn to_bytes(self) -> [u8; 3] {
+        [self.0 as u8, (self.0 >> 8) as u8, (self.0 >> 16) as u8]
+    }
+
+    pub const fn from_bytes(bytes: [u8; 3]) -> Self {
+        Pgn::new((bytes[0] as u32) | ((bytes[1] as u32) << 8) | ((bytes[2] as u32) << 16))
+    }
+}
+
+/// Fields of a J1939 identifier
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub struct J1939Id {
+    /// 0 (highest) to 7 (lowest)
+    pub priority: u8,
+    pub pgn: Pgn,
+    pub source: u8,
+    /// [GLOBAL_ADDRESS] for PDU2 format PGNs
+    pub destination: u8,
+}
+
+impl J1939Id {
+    /// Decode an extended identifier, standard identifiers are not J1939 frames
+    pub fn from_can_id(id: CanID) -> Option<Self> {
+        let CanID::Extended(id) = id else {
+            return None;
+        };
+
+        let pgn = Pgn((id >> 8) & 0x3_FFFF);
+        let (pgn, destination) = if pgn.is_pdu1() {
+            (Pgn(pgn.0 & !0xFF), (id >> 8) as u8)
+        } else {
+            (pgn, GLOBAL_ADDRESS)
+        };
+
+        Some(J1939Id {
+            priority: ((id >> 26) & 0x7) as u8,
+            pgn,
+            source: id as u8,
+            destination,
+        })
+    }
+
+    pub fn to_can_id(&self) -> CanID {
+        let mut id = ((self.priority as u32 & 0x7) << 26) | (self.pgn.0 << 8) | self.source as u32;
+        if self.pgn.is_pdu1() {
+            id |= (self.destination as u32) << 8;
+        }
+        CanID::Extended(id)
+    }
+}
+
+/// 64 bit NAME of a controller application, the lower NAME wins address conflicts
+#[bitfield(u64)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub struct Name {
+    #[bits(21)]
+    pub identity_number: u32,
+    #[bits(11)]
+    pub manufacturer_code: u16,
+    #[bits(3)]
+    pub ecu_instance: u8,
+    #[bits(5)]
+    pub function_instance: u8,
+    #[bits(8)]
+    pub function: u8,
+    #[bits(1)]
+    reserved_48: u8,
+    #[bits(7)]
+    pub vehicle_system: u8,
+    #[bits(4)]
+    pub vehicle_system_instance: u8,
+    #[bits(3)]
+    pub industry_group: u8,
+    /// Whether another address may be claimed when the preferred one is lost
+    #[bits(1)]
+    pub arbitrary_address_capable: bool,
+}
+
+impl Format for Name {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "Name(0x{:016X})", u64::from(*self))
+    }
+}
+
+/// Control byte of the acknowledgement PGN
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Acknowledgement {
+    Positive = 0,
+    Negative = 1,
+    AccessDenied = 2,
+    CannotRespond = 3,
+}
+
+#[derive(Clone, Copy)]
+pub struct J1939Config {
+    pub name: Name,
+    pub preferred_address: u8,
+    /// Addresses tried in order when the preferred address is lost, only if the
+    /// NAME is arbitrary address capable
+    pub arbitrary_addresses: (u8, u8),
+    /// Packets requested per CTS when receiving a RTS/CTS transfer
+    pub max_packets_per_cts: u8,
+    /// Time between the packets of a broadcast transfer, 50 to 200 ms
+    pub broadcast_packet_interval: Duration,
+}
+
+impl J1939Config {
+    /// Default configuration, arbitrary addresses from the self-configurable range
+    /// 128 to 247
+    pub const fn new(name: Name, preferred_address: u8) -> Self {
+        J1939Config {
+            name,
+            preferred_address,
+            arbitrary_addresses: (128, 247),
+            max_packets_per_cts: 16,
+            broadcast_packet_interval: Duration::from_millis(50),
+        }
+    }
+}
+
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum J1939Error {
+    /// No address claimed (yet)
+    NoAddress,
+    /// The message exceeds [MAX_MESSAGE_LENGTH] or the transmit buffer
+    TooLong,
+    /// A transfer is still in progress
+    Busy,
+    /// Too many frames queued, the frame was dropped
+    QueueFull,
+}
+
+#[derive(Format, PartialEq, Eq)]
+pub enum J1939Event<'a> {
+    /// A message for this controller application or all nodes; messages of the
+    /// transport protocols carry the PGN of the transferred message
+    Message {
+        id: J1939Id,
+        data: &'a [u8],
+    },
+    /// The PGN is requested, answer with the message or an acknowledgement
+    Request {
+        pgn: Pgn,
+        source: u8,
+        destination: u8,
+    },
+    /// The address can be used from now on
+    AddressClaimed(u8),
+    /// The address is lost and no other one is available
+    CannotClaimAddress,
+    /// The transfer started with [J1939::send] completed
+    TransferComplete,
+    TransferAborted(AbortReason),
+    /// A transfer to this controller application was aborted
+    ReceiveAborted(AbortReason),
+}
+
+/// A J1939 controller application, see the [module](self) documentation
+pub struct J1939<'buf> {
+    config: J1939Config,
+    claim: address::ClaimState,
+    /// Addresses claimed by other nodes, one bit per address
+    taken: [u32; 8],
+    queue: FrameQueue<QUEUE_LENGTH>,
+    tx: transport::TxSession,
+    rx: transport::RxSession,
+    /// Outcome of the last transfer, reported by [J1939::poll]
+    tx_result: Option<Result<(), AbortReason>>,
+    tx_buffer: &'buf mut [u8],
+    rx_buffer: &'buf mut [u8],
+}
+
+impl<'buf> J1939<'buf> {
+    /// The buffers hold messages of the transport protocols, larger messages than
+    /// they fit are refused
+    pub fn new(config: J1939Config, tx_buffer: &'buf mut [u8], rx_buffer: &'buf mut [u8]) -> Self {
+        J1939 {
+            config,
+            claim: address::ClaimState::Unclaimed,
+            taken: [0; 8],
+            queue: FrameQueue::new(),
+            tx: transport::TxSession::Idle,
+            rx: transport::RxSession::Idle,
+            tx_result: None,
+            tx_buffer,
+            rx_buffer,
+        }
+    }
+
+    pub fn config(&self) -> &J1939Config {
+        &self.config
+    }
+
+    /// Send a message, messages longer than 8 bytes are broadcast (BAM) to the global
+    /// address and sent with RTS/CTS to other addresses. The destination of PDU2
+    /// format PGNs is ignored.
+    pub fn send(
+        &mut self,
+        pgn: Pgn,
+        destination: u8,
+        priority: u8,
+        data: &[u8],
+        now: &Instant,
+    ) -> Result<(), J1939Error> {
+        let source = self.address().ok_or(J1939Error::NoAddress)?;
+        let destination = if pgn.is_pdu1() {
+            destination
+        } else {
+            GLOBAL_ADDRESS
+        };
+
+        if data.len() <= 8 {
+            let id = J1939Id {
+                priority,
+                pgn,
+                source,
+                destination,
+            };
+            return self.push(id, data);
+        }
+
+        if data.len() > MAX_MESSAGE_LENGTH || data.len() > self.tx_buffer.len() {
+            return Err(J1939Error::TooLong);
+        }
+        self.start_transfer(pgn, destination, data, now)
+    }
+
+    /// Request a PGN from the destination (or all nodes)
+    pub fn request(&mut self, pgn: Pgn, destination: u8, now: &Instant) -> Result<(), J1939Error> {
+        self.send(
+            Pgn::REQUEST,
+            destination,
+            DEFAULT_PRIORITY,
+            &pgn.to_bytes(),
+            now,
+        )
+    }
+
+    /// Acknowledge a request of the PGN, e.g. with [Acknowledgement::Negative] for
+    /// PGNs that are not supported
+    pub fn acknowledge(
+        &mut self,
+        acknowledgement: Acknowledgement,
+        pgn: Pgn,
+        requester: u8,
+        now: &Instant,
+    ) -> Result<(), J1939Error> {
+        let [pgn_0, pgn_1, pgn_2] = pgn.to_bytes();
+        let data = [
+            acknowledgement as u8,
+            0xFF,
+            0xFF,
+            0xFF,
+            requester,
+            pgn_0,
+            pgn_1,
+            pgn_2,
+        ];
+        self.send(
+            Pgn::ACKNOWLEDGEMENT,
+            GLOBAL_ADDRESS,
+            DEFAULT_PRIORITY,
+            &data,
+            now,
+        )
+    }
+
+    /// Process a received frame, frames to other addresses are ignored
+    pub fn on_frame<'a>(
+        &'a mut self,
+        id: CanID,
+        data: &'a [u8],
+        now: &Instant,
+    ) -> Option<J1939Event<'a>> {
+        let id = J1939Id::from_can_id(id)?;
+
+        // Address claims are global, anything else has to wait for the claim
+        if id.pgn == Pgn::ADDRESS_CLAIMED {
+            return self.on_address_claimed(id.source, data, now);
+        }
+        if id.destination != GLOBAL_ADDRESS && Some(id.destination) != self.address() {
+            return None;
+        }
+
+        match id.pgn {
+            Pgn::REQUEST => {
+                let pgn = Pgn::from_bytes(data.get(..3)?.try_into().ok()?);
+                if pgn == Pgn::ADDRESS_CLAIMED {
+                    self.on_address_claim_request();
+                    return None;
+                }
+                // Requests need a source address to answer
+                self.address()?;
+                Some(J1939Event::Request {
+                    pgn,
+                    source: id.source,
+                    destination: id.destination,
+                })
+            }
+            Pgn::TP_CONNECTION_MANAGEMENT => self.on_connection_management(id, data, now),
+            Pgn::TP_DATA_TRANSFER => self.on_data_transfer(id, data, now),
+            _ => Some(J1939Event::Message { id, data }),
+        }
+    }
+
+    /// The next frame to send, if any
+    pub fn next_frame<B: CanBuffer>(&mut self, now: &Instant) -> Option<CanTxFrame<B>> {
+        let frame = match self.queue.pop() {
+            Some(frame) => frame,
+            None => self.next_data_transfer(now)?,
+        };
+
+        Some(frame.to_tx_frame())
+    }
+
+    /// Check the timers, returns the next event, if any
+    pub fn poll(&mut self, now: &Instant) -> Option<J1939Event<'static>> {
+        if let Some(event) = self.poll_claim(now) {
+            return Some(event);
+        }
+        self.poll_transport(now)
+    }
+
+    /// Acceptance filters for this controller application, see [acceptance_filters]
+    pub fn acceptance_filters(&self, filters: &mut [ExtendedFilter]) -> usize {
+        match self.address() {
+            Some(address) => acceptance_filters(&[address], filters),
+            None => acceptance_filters(&[], filters),
+        }
+    }
+
+    /// Queue a frame, Err if the queue is full
+    fn push(&mut self, id: J1939Id, data: &[u8]) -> Result<(), J1939Error> {
+        self.queue
+            .push(QueuedFrame::new(id.to_can_id(), data))
+            .map_err(|_| J1939Error::QueueFull)
+    }
+
+    fn enqueue(&mut self, id: J1939Id, data: &[u8]) {
+        if self.push(id, data).is_err() {
+            defmt::warn!("J1939 queue full, dropping {}", id);
+        }
+    }
+
+    fn is_taken(&self, address: u8) -> bool {
+        self.taken[address as usize / 32] & (1 << (address % 32)) != 0
+    }
+
+    fn set_taken(&mut self, address: u8) {
+        self.taken[address as usize / 32] |= 1 << (address % 32);
+    }
+}
+
+/// Extended acceptance filters that store all frames of the controller applications
+/// with the given (claimed) addresses in FIFO 0: PDU2 frames, PDU1 frames to the
+/// global address and PDU1 frames to one of the addresses.
+///
+/// Returns the number of filters written, `filters` needs room for
+/// `addresses.len() + 2` elements. Filters can be updated while the node is running
+/// with an [ExtendedFilterTable](crate::can::node::filter::ExtendedFilterTable).
+pub fn acceptance_filters(addresses: &[u8], filters: &mut [ExtendedFilter]) -> usize {
+    // PDU2 format has all four upper bits of the PDU format set
+    const PDU2_MASK: u32 = 0x00F0_0000;
+    // PDU specific field, the destination address in PDU1 format
+    const DESTINATION_MASK: u32 = 0x0000_FF00;
+
+    defmt::assert!(
+        filters.len() >= addresses.len() + 2,
+        "Not enough room for {} J1939 filters",
+        addresses.len() + 2
+    );
+
+    filters[0] = ExtendedFilter::classic(PDU2_MASK, PDU2_MASK, FilterAction::StoreFifo0);
+    filters[1] = ExtendedFilter::classic(
+        (GLOBAL_ADDRESS as u32) << 8,
+        DESTINATION_MASK,
+        FilterAction::StoreFifo0,
+    );
+    for (filter, address) in filters[2..].iter_mut().zip(addresses) {
+        *filter = ExtendedFilter::classic(
+            (*address as u32) << 8,
+            DESTINATION_MASK,
+            FilterAction::StoreFifo0,
+        );
+    }
+    addresses.len() + 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROPRIETARY_A: Pgn = Pgn::new(0xEF00);
+
+    fn at(millis: u64) -> Instant {
+        Instant::from_time_since_boot(Duration::from_millis(millis))
+    }
+
+    fn name(identity_number: u32, arbitrary_address_capable: bool) -> Name {
+        Name::new()
+            .with_identity_number(identity_number)
+            .with_arbitrary_address_capable(arbitrary_address_capable)
+    }
+
+    /// A controller application that claimed the address at 250 ms
+    fn claimed<'buf>(
+        address: u8,
+        tx_buffer: &'buf mut [u8],
+        rx_buffer: &'buf mut [u8],
+    ) -> J1939<'buf> {
+        let config = J1939Config::new(name(address as u32, true), address);
+        let mut node = J1939::new(config, tx_buffer, rx_buffer);
+        node.start_address_claim(&at(0));
+        assert!(node.poll(&at(250)) == Some(J1939Event::AddressClaimed(address)));
+        sent(&mut node, &at(250));
+        node
+    }
+
+    type Frame = (J1939Id, Vec<u8>);
+
+    /// The queued frames and the data transfer packets due
+    fn sent(node: &mut J1939, now: &Instant) -> Vec<Frame> {
+        core::iter::from_fn(|| node.queue.pop().or_else(|| node.next_data_transfer(now)))
+            .map(|frame| {
+                (
+                    J1939Id::from_can_id(frame.id).unwrap(),
+                    frame.data().to_vec(),
+                )
+            })
+            .collect()
+    }
+
+    fn claim(source: u8, name: Name) -> (CanID, [u8; 8]) {
+        let id = J1939Id {
+            priority: DEFAULT_PRIORITY,
+            pgn: Pgn::ADDRESS_CLAIMED,
+            source,
+            destination: GLOBAL_ADDRESS,
+        };
+        (id.to_can_id(), u64::from(name).to_le_bytes())
+    }
+
+    /// Connection management frame
+    fn cm(source: u8, destination: u8) -> CanID {
+        J1939Id {
+            priority: 7,
+            pgn: Pgn::TP_CONNECTION_MANAGEMENT,
+            source,
+            destination,
+        }
+        .to_can_id()
+    }
+
+    fn abort(reason: u8, pgn: Pgn) -> Vec<u8> {
+        let [pgn_0, pgn_1, pgn_2] = pgn.to_bytes();
+        vec![255, reason, 0xFF, 0xFF, 0xFF, pgn_0, pgn_1, pgn_2]
+    }
+
+    /// Pass the frames between the nodes until both are quiet. Returns the messages
+    /// received by `b` and the frames `b` sent.
+    fn exchange(a: &mut J1939, b: &mut J1939, now: &Instant) -> (Vec<Frame>, Vec<Vec<u8>>) {
+        let mut messages = vec![];
+        let mut responses = vec![];
+        loop {
+            let frames = sent(a, now);
+            for (id, data) in &frames {
+                if let Some(J1939Event::Message { id, data }) =
+                    b.on_frame(id.to_can_id(), data, now)
+                {
+                    messages.push((id, data.to_vec()));
+                }
+            }
+            let replies = sent(b, now);
+            for (id, data) in &replies {
+                a.on_frame(id.to_can_id(), data, now);
+                responses.push(data.clone());
+            }
+            if frames.is_empty() && replies.is_empty() {
+                return (messages, responses);
+            }
+        }
+    }
+
+    #[test]
+    fn pgn_encoding() {
+        // The destination of PDU1 format PGNs is not part of the PGN
+        assert!(Pgn::new(0x1_EF25) == Pgn::new(0x1_EF00));
+        assert!(Pgn::new(0x1_EF25).is_pdu1());
+        assert_eq!(Pgn::new(0xFEF1).value(), 0xFEF1);
+        assert!(!Pgn::new(0xFEF1).is_pdu1());
+        assert_eq!(Pgn::new(0x1_FEF1).to_bytes(), [0xF1, 0xFE, 0x01]);
+        assert!(Pgn::from_bytes([0x25, 0xEF, 0x00]) == PROPRIETARY_A);
+
+        let pdu1 = J1939Id {
+            priority: 6,
+            pgn: PROPRIETARY_A,
+            source: 0x80,
+            destination: 0x25,
+        };
+        assert!(pdu1.to_can_id() == CanID::Extended(0x18EF_2580));
+        assert!(J1939Id::from_can_id(CanID::Extended(0x18EF_2580)) == Some(pdu1));
+
+        // PDU2 format PGNs are broadcast
+        let pdu2 = J1939Id::from_can_id(CanID::Extended(0x0CFE_F100)).unwrap();
+        assert!(pdu2.pgn == Pgn::new(0xFEF1));
+        assert_eq!(
+            (pdu2.priority, pdu2.source, pdu2.destination),
+            (3, 0x00, GLOBAL_ADDRESS)
+        );
+        assert!(pdu2.to_can_id() == CanID::Extended(0x0CFE_F100));
+
+        assert!(J1939Id::from_can_id(CanID::Standard(0x123)).is_none());
+    }
+
+    #[test]
+    fn address_claim_won() {
+        let (mut tx_buffer, mut rx_buffer) = ([0; 16], [0; 16]);
+        let config = J1939Config::new(name(1, false), 0x80);
+        let mut node = J1939::new(config, &mut tx_buffer, &mut rx_buffer);
+        node.start_address_claim(&at(0));
+        let (id, data) = claim(0x80, name(1, false));
+        assert!(sent(&mut node, &at(0)) == [(J1939Id::from_can_id(id).unwrap(), data.to_vec())]);
+
+        // The higher NAME loses, the claim is repeated
+        let (id, data) = claim(0x80, name(2, false));
+        assert!(node.on_frame(id, &data, &at(100)).is_none());
+        let (claim_id, claim_data) = claim(0x80, name(1, false));
+        assert!(
+            sent(&mut node, &at(100))
+                == [(J1939Id::from_can_id(claim_id).unwrap(), claim_data.to_vec())]
+        );
+
+        assert!(node.poll(&at(249)).is_none());
+        assert!(node.poll(&at(250)) == Some(J1939Event::AddressClaimed(0x80)));
+        assert_eq!(node.address(), Some(0x80));
+    }
+
+    #[test]
+    fn address_claim_lost() {
+        let (mut tx_buffer, mut rx_buffer) = ([0; 16], [0; 16]);
+        let mut config = J1939Config::new(name(5, true), 128);
+        config.arbitrary_addresses = (128, 130);
+        let mut node = J1939::new(config, &mut tx_buffer, &mut rx_buffer);
+
+        // 129 is taken, the claim of 128 is lost to a lower NAME
+        let (id, data) = claim(129, name(3, false));
+        node.on_frame(id, &data, &at(0));
+        node.start_address_claim(&at(0));
+        let (id, data) = claim(128, name(4, false));
+        assert!(node.on_frame(id, &data, &at(100)).is_none());
+        let (claim_id, claim_data) = claim(130, name(5, true));
+        assert!(
+            sent(&mut node, &at(100))[1..]
+                == [(J1939Id::from_can_id(claim_id).unwrap(), claim_data.to_vec())]
+        );
+        assert!(node.poll(&at(349)).is_none());
+        assert!(node.poll(&at(350)) == Some(J1939Event::AddressClaimed(130)));
+
+        // No free address is left
+        let (id, data) = claim(130, name(1, false));
+        assert!(node.on_frame(id, &data, &at(400)) == Some(J1939Event::CannotClaimAddress));
+        assert_eq!(node.address(), None);
+        let (claim_id, claim_data) = claim(NULL_ADDRESS, name(5, true));
+        let cannot_claim = (J1939Id::from_can_id(claim_id).unwrap(), claim_data.to_vec());
+        assert!(sent(&mut node, &at(400)) == [cannot_claim.clone()]);
+
+        // Requests for the address claim are answered with the cannot claim message
+        let request = J1939Id {
+            priority: DEFAULT_PRIORITY,
+            pgn: Pgn::REQUEST,
+            source: 0x10,
+            destination: GLOBAL_ADDRESS,
+        };
+        assert!(node
+            .on_frame(
+                request.to_can_id(),
+                &Pgn::ADDRESS_CLAIMED.to_bytes(),
+                &at(500)
+            )
+            .is_none());
+        assert!(sent(&mut node, &at(500)) == [cannot_claim]);
+        assert!(node.send(PROPRIETARY_A, 0x10, 6, &[1], &at(500)) == Err(J1939Error::NoAddress));
+    }
+
+    #[test]
+    fn connection_mode_transfer_of_the_largest_message() {
+        let (mut a_tx, mut a_rx) = ([0; MAX_MESSAGE_LENGTH], [0; 16]);
+        let (mut b_tx, mut b_rx) = ([0; 16], [0; MAX_MESSAGE_LENGTH]);
+        let mut a = claimed(0x10, &mut a_tx, &mut a_rx);
+        let mut b = claimed(0x20, &mut b_tx, &mut b_rx);
+
+        let message: Vec<u8> = (0..MAX_MESSAGE_LENGTH).map(|i| (i * 7) as u8).collect();
+        assert!(a.send(PROPRIETARY_A, 0x20, 6, &message, &at(300)).is_ok());
+        assert!(a.send(PROPRIETARY_A, 0x20, 6, &message, &at(300)) == Err(J1939Error::Busy));
+        let (messages, responses) = exchange(&mut a, &mut b, &at(300));
+
+        assert_eq!(messages.len(), 1);
+        let (id, data) = &messages[0];
+        assert!(id.pgn == PROPRIETARY_A);
+        assert_eq!((id.source, id.destination), (0x10, 0x20));
+        assert_eq!(data, &message);
+
+        // 255 packets, 16 per CTS, then the end of message acknowledgement
+        let clear_to_send: Vec<_> = responses.iter().filter(|data| data[0] == 17).collect();
+        assert_eq!(clear_to_send.len(), 16);
+        assert_eq!(clear_to_send[15][..3], [17, 15, 241]);
+        assert_eq!(
+            responses.last().unwrap(),
+            &[19, 0xF9, 0x06, 255, 0xFF, 0x00, 0xEF, 0x00]
+        );
+        assert!(a.poll(&at(300)) == Some(J1939Event::TransferComplete));
+        assert!(!a.is_transferring());
+
+        assert!(a.send(PROPRIETARY_A, 0x20, 6, &[0; 1786], &at(300)) == Err(J1939Error::TooLong));
+    }
+
+    #[test]
+    fn broadcast_transfer() {
+        let (mut a_tx, mut a_rx) = ([0; 32], [0; 16]);
+        let (mut b_tx, mut b_rx) = ([0; 16], [0; 32]);
+        let mut a = claimed(0x10, &mut a_tx, &mut a_rx);
+        let mut b = claimed(0x20, &mut b_tx, &mut b_rx);
+
+        let message: Vec<u8> = (1..=20).collect();
+        assert!(a
+            .send(PROPRIETARY_A, GLOBAL_ADDRESS, 6, &message, &at(300))
+            .is_ok());
+        let frames = sent(&mut a, &at(300));
+        assert!(frames[0].0.to_can_id() == CanID::Extended(0x1CEC_FF10));
+        assert_eq!(frames[0].1, [32, 20, 0, 3, 0xFF, 0x00, 0xEF, 0x00]);
+        assert_eq!(frames.len(), 1);
+        assert!(b
+            .on_frame(frames[0].0.to_can_id(), &frames[0].1, &at(300))
+            .is_none());
+
+        // A packet every 50 ms, the last one padded
+        let mut packets = vec![];
+        let mut received = vec![];
+        for millis in [349, 350, 400, 450] {
+            for (id, data) in sent(&mut a, &at(millis)) {
+                assert!(id.to_can_id() == CanID::Extended(0x1CEB_FF10));
+                if let Some(J1939Event::Message { id, data }) =
+                    b.on_frame(id.to_can_id(), &data, &at(millis))
+                {
+                    assert!(id.pgn == PROPRIETARY_A && id.destination == GLOBAL_ADDRESS);
+                    received.push(data.to_vec());
+                }
+                packets.push((millis, data));
+            }
+        }
+        assert!(
+            packets
+                == [
+                    (350, vec![1, 1, 2, 3, 4, 5, 6, 7]),
+                    (400, vec![2, 8, 9, 10, 11, 12, 13, 14]),
+                    (450, vec![3, 15, 16, 17, 18, 19, 20, 0xFF]),
+                ]
+        );
+        assert!(received == [message]);
+        assert!(a.poll(&at(450)) == Some(J1939Event::TransferComplete));
+        // Broadcasts are not acknowledged
+        assert!(sent(&mut b, &at(450)).is_empty());
+    }
+
+    #[test]
+    fn connection_aborts() {
+        let (mut tx_buffer, mut rx_buffer) = ([0; 64], [0; 64]);
+        let mut node = claimed(0x20, &mut tx_buffer, &mut rx_buffer);
+        let [pgn_0, pgn_1, pgn_2] = PROPRIETARY_A.to_bytes();
+        let rts = |length: u16, packets: u8| {
+            let [length_0, length_1] = length.to_le_bytes();
+            [16, length_0, length_1, packets, 0xFF, pgn_0, pgn_1, pgn_2]
+        };
+        let data_transfer = |source: u8| {
+            J1939Id {
+                priority: 7,
+                pgn: Pgn::TP_DATA_TRANSFER,
+                source,
+                destination: 0x20,
+            }
+            .to_can_id()
+        };
+        let sent_data = |node: &mut J1939| -> Vec<Vec<u8>> {
+            sent(node, &at(300))
+                .into_iter()
+                .map(|(_, data)| data)
+                .collect()
+        };
+
+        // More than the receive buffer and than the transport protocols carry
+        node.on_frame(cm(0x10, 0x20), &rts(100, 15), &at(300));
+        node.on_frame(cm(0x10, 0x20), &rts(1786, 0xFF), &at(300));
+        assert!(sent_data(&mut node) == [abort(2, PROPRIETARY_A), abort(9, PROPRIETARY_A)]);
+
+        // A second sender has to wait
+        node.on_frame(cm(0x10, 0x20), &rts(20, 3), &at(300));
+        node.on_frame(cm(0x11, 0x20), &rts(20, 3), &at(300));
+        assert!(
+            sent_data(&mut node)
+                == [
+                    vec![17, 3, 1, 0xFF, 0xFF, 0x00, 0xEF, 0x00],
+                    abort(1, PROPRIETARY_A)
+                ]
+        );
+
+        // Packets out of order abort the connection
+        assert!(
+            node.on_frame(data_transfer(0x10), &[2, 0, 0, 0, 0, 0, 0, 0], &at(300))
+                == Some(J1939Event::ReceiveAborted(AbortReason::BadSequenceNumber))
+        );
+        assert!(sent_data(&mut node) == [abort(7, PROPRIETARY_A)]);
+
+        node.on_frame(cm(0x10, 0x20), &rts(20, 3), &at(300));
+        sent_data(&mut node);
+        assert!(node
+            .on_frame(data_transfer(0x10), &[1, 0, 0, 0, 0, 0, 0, 0], &at(300))
+            .is_none());
+        assert!(
+            node.on_frame(data_transfer(0x10), &[1, 0, 0, 0, 0, 0, 0, 0], &at(300))
+                == Some(J1939Event::ReceiveAborted(
+                    AbortReason::DuplicateSequenceNumber
+                ))
+        );
+        assert!(sent_data(&mut node) == [abort(8, PROPRIETARY_A)]);
+
+        // Sending: a CTS during the packets aborts, so does a missing CTS
+        let message = [0x55; 20];
+        assert!(node
+            .send(PROPRIETARY_A, 0x10, 6, &message, &at(300))
+            .is_ok());
+        assert_eq!(sent_data(&mut node)[0], rts(20, 3));
+        let cts = [17, 3, 1, 0xFF, 0xFF, pgn_0, pgn_1, pgn_2];
+        node.on_frame(cm(0x10, 0x20), &cts, &at(300));
+        node.on_frame(cm(0x10, 0x20), &cts, &at(300));
+        assert!(
+            node.poll(&at(300))
+                == Some(J1939Event::TransferAborted(
+                    AbortReason::ClearToSendWhileTransferring
+                ))
+        );
+        assert!(sent_data(&mut node) == [abort(4, PROPRIETARY_A)]);
+
+        assert!(node
+            .send(PROPRIETARY_A, 0x10, 6, &message, &at(300))
+            .is_ok());
+        sent_data(&mut node);
+        assert!(node.poll(&at(1549)).is_none());
+        assert!(node.poll(&at(1550)) == Some(J1939Event::TransferAborted(AbortReason::Timeout)));
+        assert!(sent_data(&mut node) == [abort(3, PROPRIETARY_A)]);
+
+        // The receiver aborts
+        assert!(node
+            .send(PROPRIETARY_A, 0x10, 6, &message, &at(2000))
+            .is_ok());
+        assert!(node
+            .on_frame(cm(0x10, 0x20), &abort(2, PROPRIETARY_A), &at(2000))
+            .is_none());
+        assert!(
+            node.poll(&at(2000)) == Some(J1939Event::TransferAborted(AbortReason::ResourcesNeeded))
+        );
+    }
+}
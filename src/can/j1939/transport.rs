@@ -0,0 +1,603 @@
+//!
+//! Transport protocols (J1939-21): broadcast announce (BAM) and connection mode
+//! data transfer (RTS/CTS)
+//!
+use core::time::Duration;
+
+use defmt::Format;
+
+use crate::{can::queue::QueuedFrame, time::Instant};
+
+use super::{J1939Error, J1939Event, J1939Id, Pgn, GLOBAL_ADDRESS, J1939, NULL_ADDRESS};
+
+/// Connection management control bytes
+const CM_REQUEST_TO_SEND: u8 = 16;
+const CM_CLEAR_TO_SEND: u8 = 17;
+const CM_END_OF_MESSAGE_ACK: u8 = 19;
+const CM_BROADCAST_ANNOUNCE: u8 = 32;
+const CM_ABORT: u8 = 255;
+
+/// Maximum time between two packets
+const T1: Duration = Duration::from_millis(750);
+/// Maximum time between a CTS and the next packet
+const T2: Duration = Duration::from_millis(1250);
+/// Maximum time between the last packet and the next CTS or end of message ack
+const T3: Duration = Duration::from_millis(1250);
+/// Maximum time between a CTS holding the connection open and the next CTS
+const T4: Duration = Duration::from_millis(1050);
+
+const TP_PRIORITY: u8 = 7;
+
+/// Data bytes per packet
+const PACKET_LENGTH: usize = 7;
+
+/// Reason of a connection abort
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum AbortReason {
+    AlreadyInSession,
+    ResourcesNeeded,
+    Timeout,
+    ClearToSendWhileTransferring,
+    MaxRetransmitRequests,
+    UnexpectedDataTransfer,
+    BadSequenceNumber,
+    DuplicateSequenceNumber,
+    MessageTooLarge,
+    /// Any reason not listed by J1939-21 (code 250), e.g. a truncated data transfer packet
+    Unspecified,
+    Other(u8),
+}
+
+impl AbortReason {
+    fn to_u8(self) -> u8 {
+        match self {
+            AbortReason::AlreadyInSession => 1,
+            AbortReason::ResourcesNeeded => 2,
+            AbortReason::Timeout => 3,
+            AbortReason::ClearToSendWhileTransferring => 4,
+            AbortReason::MaxRetransmitRequests => 5,
+            AbortReason::UnexpectedDataTransfer => 6,
+            AbortReason::BadSequenceNumber => 7,
+            AbortReason::DuplicateSequenceNumber => 8,
+            AbortReason::MessageTooLarge => 9,
+            AbortReason::Unspecified => 250,
+            AbortReason::Other(reason) => reason,
+        }
+    }
+
+    fn from_u8(reason: u8) -> Self {
+        match reason {
+            1 => AbortReason::AlreadyInSession,
+            2 => AbortReason::ResourcesNeeded,
+            3 => AbortReason::Timeout,
+            4 => AbortReason::ClearToSendWhileTransferring,
+            5 => AbortReason::MaxRetransmitRequests,
+            6 => AbortReason::UnexpectedDataTransfer,
+            7 => AbortReason::BadSequenceNumber,
+            8 => AbortReason::DuplicateSequenceNumber,
+            9 => AbortReason::MessageTooLarge,
+            250 => AbortReason::Unspecified,
+            reason => AbortReason::Other(reason),
+        }
+    }
+}
+
+/// A message transferred with the transport protocol
+#[derive(Clone, Copy)]
+pub(super) struct Transfer {
+    pgn: Pgn,
+    /// Destination when sending, source when receiving
+    peer: u8,
+    length: usize,
+    packets: u8,
+}
+
+impl Transfer {
+    fn new(pgn: Pgn, peer: u8, length: usize) -> Self {
+        Transfer {
+            pgn,
+            peer,
+            length,
+            packets: length.div_ceil(PACKET_LENGTH) as u8,
+        }
+    }
+
+    /// Connection management message with the size and the PGN of the transfer
+    fn connection_management(&self, control: u8, byte_4: u8) -> [u8; 8] {
+        let [pgn_0, pgn_1, pgn_2] = self.pgn.to_bytes();
+        let [length_0, length_1] = (self.length as u16).to_le_bytes();
+        [
+            control,
+            length_0,
+            length_1,
+            self.packets,
+            byte_4,
+            pgn_0,
+            pgn_1,
+            pgn_2,
+        ]
+    }
+
+    fn clear_to_send(&self, packets: u8, next_packet: u8) -> [u8; 8] {
+        let [pgn_0, pgn_1, pgn_2] = self.pgn.to_bytes();
+        [
+            CM_CLEAR_TO_SEND,
+            packets,
+            next_packet,
+            0xFF,
+            0xFF,
+            pgn_0,
+            pgn_1,
+            pgn_2,
+        ]
+    }
+}
+
+pub(super) enum TxSession {
+    Idle,
+    Broadcast {
+        transfer: Transfer,
+        next_packet: u8,
+        not_before: Instant,
+    },
+    WaitClearToSend {
+        transfer: Transfer,
+        deadline: Instant,
+    },
+    Sending {
+        transfer: Transfer,
+        next_packet: u8,
+        last_packet: u8,
+    },
+    WaitEndOfMessage {
+        transfer: Transfer,
+        deadline: Instant,
+    },
+}
+
+impl TxSession {
+    fn transfer(&self) -> Option<&Transfer> {
+        match self {
+            TxSession::Idle => None,
+            TxSession::Broadcast { transfer, .. }
+            | TxSession::WaitClearToSend { transfer, .. }
+            | TxSession::Sending { transfer, .. }
+            | TxSession::WaitEndOfMessage { transfer, .. } => Some(transfer),
+        }
+    }
+}
+
+pub(super) enum RxSession {
+    Idle,
+    Broadcast {
+        transfer: Transfer,
+        next_packet: u8,
+        deadline: Instant,
+    },
+    Connected {
+        transfer: Transfer,
+        next_packet: u8,
+        last_packet: u8,
+        /// Packets per CTS
+        window: u8,
+        deadline: Instant,
+    },
+}
+
+impl<'buf> J1939<'buf> {
+    /// Whether a transfer started with [J1939::send] is in progress
+    pub fn is_transferring(&self) -> bool {
+        !matches!(self.tx, TxSession::Idle)
+    }
+
+    pub(super) fn start_transfer(
+        &mut self,
+        pgn: Pgn,
+        destination: u8,
+        data: &[u8],
+        now: &Instant,
+    ) -> Result<(), J1939Error> {
+        if self.is_transferring() {
+            return Err(J1939Error::Busy);
+        }
+
+        let transfer = Transfer::new(pgn, destination, data.len());
+        self.tx_buffer[..data.len()].copy_from_slice(data);
+
+        if destination == GLOBAL_ADDRESS {
+            let announce = transfer.connection_management(CM_BROADCAST_ANNOUNCE, 0xFF);
+            self.push(self.connection_id(GLOBAL_ADDRESS), &announce)?;
+            self.tx = TxSession::Broadcast {
+                transfer,
+                next_packet: 1,
+                not_before: now + self.config.broadcast_packet_interval,
+            };
+        } else {
+            // No limit of packets per CTS
+            let request = transfer.connection_management(CM_REQUEST_TO_SEND, 0xFF);
+            self.push(self.connection_id(destination), &request)?;
+            self.tx = TxSession::WaitClearToSend {
+                transfer,
+                deadline: now + T3,
+            };
+        }
+        Ok(())
+    }
+
+    /// The next packet of the transfer, if one is due
+    pub(super) fn next_data_transfer(&mut self, now: &Instant) -> Option<QueuedFrame> {
+        match self.tx {
+            TxSession::Broadcast {
+                transfer,
+                next_packet,
+                not_before,
+            } if *now >= not_before => {
+                self.tx = if next_packet == transfer.packets {
+                    self.tx_result = Some(Ok(()));
+                    TxSession::Idle
+                } else {
+                    TxSession::Broadcast {
+                        transfer,
+                        next_packet: next_packet + 1,
+                        not_before: now + self.config.broadcast_packet_interval,
+                    }
+                };
+                Some(self.data_packet(&transfer, next_packet))
+            }
+            TxSession::Sending {
+                transfer,
+                next_packet,
+                last_packet,
+            } => {
+                self.tx = if next_packet == transfer.packets {
+                    TxSession::WaitEndOfMessage {
+                        transfer,
+                        deadline: now + T3,
+                    }
+                } else if next_packet == last_packet {
+                    TxSession::WaitClearToSend {
+                        transfer,
+                        deadline: now + T3,
+                    }
+                } else {
+                    TxSession::Sending {
+                        transfer,
+                        next_packet: next_packet + 1,
+                        last_packet,
+                    }
+                };
+                Some(self.data_packet(&transfer, next_packet))
+            }
+            _ => None,
+        }
+    }
+
+    pub(super) fn on_connection_management(
+        &mut self,
+        id: J1939Id,
+        data: &[u8],
+        now: &Instant,
+    ) -> Option<J1939Event<'static>> {
+        let data: &[u8; 8] = data.get(..8)?.try_into().ok()?;
+        let pgn = Pgn::from_bytes([data[5], data[6], data[7]]);
+        let length = u16::from_le_bytes([data[1], data[2]]) as usize;
+
+        match data[0] {
+            CM_BROADCAST_ANNOUNCE if id.destination == GLOBAL_ADDRESS => {
+                self.on_broadcast_announce(Transfer::new(pgn, id.source, length), now);
+                None
+            }
+            CM_REQUEST_TO_SEND if id.destination != GLOBAL_ADDRESS => {
+                self.on_request_to_send(Transfer::new(pgn, id.source, length), data[4], now);
+                None
+            }
+            CM_CLEAR_TO_SEND => {
+                self.on_clear_to_send(id.source, pgn, data[1], data[2], now);
+                None
+            }
+            CM_END_OF_MESSAGE_ACK => {
+                if let TxSession::WaitEndOfMessage { transfer, .. } = self.tx {
+                    if transfer.peer == id.source && transfer.pgn == pgn {
+                        self.tx = TxSession::Idle;
+                        self.tx_result = Some(Ok(()));
+                    }
+                }
+                None
+            }
+            CM_ABORT => self.on_abort(id.source, pgn, AbortReason::from_u8(data[1])),
+            _ => None,
+        }
+    }
+
+    pub(super) fn on_data_transfer<'a>(
+        &'a mut self,
+        id: J1939Id,
+        data: &[u8],
+        now: &Instant,
+    ) -> Option<J1939Event<'a>> {
+        let (&sequence_number, payload) = data.split_first()?;
+
+        let (transfer, next_packet) = match self.rx {
+            RxSession::Broadcast {
+                transfer,
+                next_packet,
+                ..
+            } if id.destination == GLOBAL_ADDRESS => (transfer, next_packet),
+            RxSession::Connected {
+                transfer,
+                next_packet,
+                ..
+            } if id.destination != GLOBAL_ADDRESS => (transfer, next_packet),
+            _ => return None,
+        };
+        if transfer.peer != id.source {
+            return None;
+        }
+
+        if sequence_number != next_packet {
+            let reason = if sequence_number < next_packet {
+                AbortReason::DuplicateSequenceNumber
+            } else {
+                AbortReason::BadSequenceNumber
+            };
+            return Some(self.abort_receive(reason));
+        }
+
+        let offset = (sequence_number as usize - 1) * PACKET_LENGTH;
+        let length = PACKET_LENGTH.min(transfer.length - offset);
+        let Some(payload) = payload.get(..length) else {
+            return Some(self.abort_receive(AbortReason::Unspecified));
+        };
+        self.rx_buffer[offset..offset + length].copy_from_slice(payload);
+
+        if sequence_number == transfer.packets {
+            if id.destination != GLOBAL_ADDRESS {
+                let ack = transfer.connection_management(CM_END_OF_MESSAGE_ACK, 0xFF);
+                self.enqueue(self.connection_id(transfer.peer), &ack);
+            }
+            self.rx = RxSession::Idle;
+            return Some(J1939Event::Message {
+                id: J1939Id {
+                    pgn: transfer.pgn,
+                    ..id
+                },
+                data: &self.rx_buffer[..transfer.length],
+            });
+        }
+
+        let clear_to_send = match &mut self.rx {
+            RxSession::Broadcast {
+                next_packet,
+                deadline,
+                ..
+            } => {
+                *next_packet += 1;
+                *deadline = now + T1;
+                None
+            }
+            RxSession::Connected {
+                next_packet,
+                last_packet,
+                window,
+                deadline,
+                ..
+            } => {
+                *next_packet += 1;
+                if sequence_number == *last_packet {
+                    let packets = (*window).min(transfer.packets - sequence_number);
+                    *last_packet = sequence_number + packets;
+                    *deadline = now + T2;
+                    Some(transfer.clear_to_send(packets, *next_packet))
+                } else {
+                    *deadline = now + T1;
+                    None
+                }
+            }
+            RxSession::Idle => None,
+        };
+        if let Some(clear_to_send) = clear_to_send {
+            self.enqueue(self.connection_id(transfer.peer), &clear_to_send);
+        }
+        None
+    }
+
+    /// Report the transfer outcome and time out transfers
+    pub(super) fn poll_transport(&mut self, now: &Instant) -> Option<J1939Event<'static>> {
+        match self.tx {
+            TxSession::WaitClearToSend { deadline, .. }
+            | TxSession::WaitEndOfMessage { deadline, .. }
+                if *now >= deadline =>
+            {
+                self.abort_transfer(AbortReason::Timeout);
+            }
+            _ => {}
+        }
+
+        match self.rx {
+            RxSession::Broadcast { deadline, .. } | RxSession::Connected { deadline, .. }
+                if *now >= deadline =>
+            {
+                return Some(self.abort_receive(AbortReason::Timeout));
+            }
+            _ => {}
+        }
+
+        match self.tx_result.take()? {
+            Ok(()) => Some(J1939Event::TransferComplete),
+            Err(reason) => Some(J1939Event::TransferAborted(reason)),
+        }
+    }
+
+    fn on_broadcast_announce(&mut self, transfer: Transfer, now: &Instant) {
+        // A broadcast does not interrupt a connection, but replaces a broadcast
+        if matches!(self.rx, RxSession::Connected { .. }) || !self.fits(&transfer) {
+            return;
+        }
+        self.rx = RxSession::Broadcast {
+            transfer,
+            next_packet: 1,
+            deadline: now + T1,
+        };
+    }
+
+    fn on_request_to_send(&mut self, transfer: Transfer, max_packets: u8, now: &Instant) {
+        let reason = match self.rx {
+            RxSession::Connected {
+                transfer: current, ..
+            } if current.peer != transfer.peer => Some(AbortReason::AlreadyInSession),
+            _ if transfer.length > super::MAX_MESSAGE_LENGTH => Some(AbortReason::MessageTooLarge),
+            _ if !self.fits(&transfer) => Some(AbortReason::ResourcesNeeded),
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            defmt::debug!("Refusing J1939 transfer: {}", reason);
+            self.send_abort(transfer.peer, transfer.pgn, reason);
+            return;
+        }
+
+        let window = self.config.max_packets_per_cts.min(max_packets).max(1);
+        let packets = window.min(transfer.packets);
+        self.rx = RxSession::Connected {
+            transfer,
+            next_packet: 1,
+            last_packet: packets,
+            window,
+            deadline: now + T2,
+        };
+        self.enqueue(
+            self.connection_id(transfer.peer),
+            &transfer.clear_to_send(packets, 1),
+        );
+    }
+
+    fn on_clear_to_send(
+        &mut self,
+        source: u8,
+        pgn: Pgn,
+        packets: u8,
+        next_packet: u8,
+        now: &Instant,
+    ) {
+        match self.tx {
+            TxSession::WaitClearToSend { transfer, .. }
+                if transfer.peer == source && transfer.pgn == pgn =>
+            {
+                if packets == 0 {
+                    // Hold the connection open
+                    self.tx = TxSession::WaitClearToSend {
+                        transfer,
+                        deadline: now + T4,
+                    };
+                } else if next_packet == 0 || next_packet > transfer.packets {
+                    self.abort_transfer(AbortReason::BadSequenceNumber);
+                } else {
+                    self.tx = TxSession::Sending {
+                        transfer,
+                        next_packet,
+                        last_packet: (next_packet - 1)
+                            .saturating_add(packets)
+                            .min(transfer.packets),
+                    };
+                }
+            }
+            TxSession::Sending { transfer, .. } | TxSession::WaitEndOfMessage { transfer, .. }
+                if transfer.peer == source && transfer.pgn == pgn =>
+            {
+                self.abort_transfer(AbortReason::ClearToSendWhileTransferring);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_abort(
+        &mut self,
+        source: u8,
+        pgn: Pgn,
+        reason: AbortReason,
+    ) -> Option<J1939Event<'static>> {
+        if self
+            .tx
+            .transfer()
+            .is_some_and(|transfer| transfer.peer == source && transfer.pgn == pgn)
+        {
+            defmt::debug!("J1939 transfer aborted by the receiver: {}", reason);
+            self.tx = TxSession::Idle;
+            self.tx_result = Some(Err(reason));
+        }
+
+        // Broadcasts cannot be aborted
+        if let RxSession::Connected { transfer, .. } = self.rx {
+            if transfer.peer == source && transfer.pgn == pgn {
+                self.rx = RxSession::Idle;
+                return Some(J1939Event::ReceiveAborted(reason));
+            }
+        }
+        None
+    }
+
+    /// Abort the transfer we send, the outcome is reported by [J1939::poll]
+    fn abort_transfer(&mut self, reason: AbortReason) {
+        if let Some(transfer) = self.tx.transfer().copied() {
+            defmt::debug!("Aborting J1939 transfer: {}", reason);
+            if transfer.peer != GLOBAL_ADDRESS {
+                self.send_abort(transfer.peer, transfer.pgn, reason);
+            }
+            self.tx = TxSession::Idle;
+            self.tx_result = Some(Err(reason));
+        }
+    }
+
+    /// Abort the transfer we receive, broadcasts are dropped silently
+    fn abort_receive(&mut self, reason: AbortReason) -> J1939Event<'static> {
+        if let RxSession::Connected { transfer, .. } = self.rx {
+            self.send_abort(transfer.peer, transfer.pgn, reason);
+        }
+        defmt::debug!("J1939 receive aborted: {}", reason);
+        self.rx = RxSession::Idle;
+        J1939Event::ReceiveAborted(reason)
+    }
+
+    fn send_abort(&mut self, destination: u8, pgn: Pgn, reason: AbortReason) {
+        let [pgn_0, pgn_1, pgn_2] = pgn.to_bytes();
+        let abort = [
+            CM_ABORT,
+            reason.to_u8(),
+            0xFF,
+            0xFF,
+            0xFF,
+            pgn_0,
+            pgn_1,
+            pgn_2,
+        ];
+        self.enqueue(self.connection_id(destination), &abort);
+    }
+
+    fn fits(&self, transfer: &Transfer) -> bool {
+        transfer.length > 8
+            && transfer.length <= super::MAX_MESSAGE_LENGTH
+            && transfer.length <= self.rx_buffer.len()
+    }
+
+    fn connection_id(&self, destination: u8) -> J1939Id {
+        J1939Id {
+            priority: TP_PRIORITY,
+            pgn: Pgn::TP_CONNECTION_MANAGEMENT,
+            source: self.address().unwrap_or(NULL_ADDRESS),
+            destination,
+        }
+    }
+
+    fn data_packet(&self, transfer: &Transfer, packet: u8) -> QueuedFrame {
+        let id = J1939Id {
+            pgn: Pgn::TP_DATA_TRANSFER,
+            ..self.connection_id(transfer.peer)
+        };
+
+        let offset = (packet as usize - 1) * PACKET_LENGTH;
+        let end = (offset + PACKET_LENGTH).min(transfer.length);
+        let mut data = [0xFF; 8];
+        data[0] = packet;
+        data[1..1 + end - offset].copy_from_slice(&self.tx_buffer[offset..end]);
+        QueuedFrame::new(id.to_can_id(), &data)
+    }
+}
@@ -0,0 +1,163 @@
+//!
+//! Vector ASC log lines
+//!
+use crate::{
+    can::{memory::module_ram::length_to_dlc, CanID},
+    time::Instant,
+};
+
+use super::{Direction, Line, LoggedFrame};
+
+/// Flags of CAN FD lines
+const FLAG_EDL: u32 = 1 << 12;
+const FLAG_BRS: u32 = 1 << 13;
+const FLAG_ESI: u32 = 1 << 14;
+
+/// Width of the id column of classic frames
+const ID_WIDTH: usize = 15;
+/// Width of the id column of CAN FD frames, followed by an empty symbolic name
+const FD_ID_WIDTH: usize = 8;
+const FD_SYMBOLIC_NAME_WIDTH: usize = 32;
+
+/// Encodes frames into ASC lines like ` 1.250000 1  123             Rx   d 2 11 22`,
+/// CAN FD frames into `CANFD` lines
+///
+/// Timestamps are relative to the start of the measurement, the lines of a log
+/// follow the [Asc::header].
+pub struct Asc {
+    /// 1 based channel number
+    channel: u8,
+    start: Instant,
+}
+
+impl Asc {
+    pub fn new(channel: u8, start: Instant) -> Self {
+        defmt::assert!(channel > 0, "ASC channels start at 1");
+        Asc { channel, start }
+    }
+
+    /// Write the header of a log into `buffer`, `date` is the start of the measurement
+    /// like `Sun Oct 18 09:41:00.000 am 2026`. Returns the length of the header, None
+    /// if it does not fit.
+    pub fn header(&self, date: &str, buffer: &mut [u8]) -> Option<usize> {
+        let mut line = Line::new(buffer);
+        line.push(b"date ");
+        line.push(date.as_bytes());
+        line.push(b"\nbase hex  timestamps absolute\nno internal events logged\n");
+        line.finish()
+    }
+
+    /// Write the line of a frame, including the line feed, into `buffer`. Returns the
+    /// length of the line, None if it does not fit.
+    pub fn encode(
+        &self,
+        frame: &impl LoggedFrame,
+        direction: Direction,
+        timestamp: &Instant,
+        buffer: &mut [u8],
+    ) -> Option<usize> {
+        let mut line = Line::new(buffer);
+        // Frames from before the start, e.g. still queued, are logged at the start
+        let time = timestamp
+            .time_since_boot()
+            .saturating_sub(self.start.time_since_boot());
+
+        line.push(b" ");
+        line.decimal(time.as_secs(), 1);
+        line.push(b".");
+        line.decimal(time.subsec_micros() as u64, 6);
+        line.push(b" ");
+
+        let direction = match direction {
+            Direction::Rx => b"Rx  ",
+            Direction::Tx => b"Tx  ",
+        };
+
+        if frame.is_fd_format() {
+            line.push(b"CANFD ");
+            line.spaces(3_usize.saturating_sub(super::decimal_digits(self.channel as u64)));
+            line.decimal(self.channel as u64, 1);
+            line.push(b" ");
+            line.push(direction);
+            line.push(b" ");
+            line.spaces(FD_ID_WIDTH.saturating_sub(id_width(frame.id())));
+            push_id(&mut line, frame.id());
+            line.spaces(2 + FD_SYMBOLIC_NAME_WIDTH + 2);
+
+            let mut flags = FLAG_EDL;
+            if frame.bitrate_switching() {
+                flags |= FLAG_BRS;
+            }
+            if frame.error_state() {
+                flags |= FLAG_ESI;
+            }
+            // Cannot fail, frames are at most 64 bytes
+            let dlc = length_to_dlc(frame.length()).unwrap();
+
+            line.decimal(frame.bitrate_switching() as u64, 1);
+            line.push(b" ");
+            line.decimal(frame.error_state() as u64, 1);
+            line.push(b" ");
+            line.push(&[b"0123456789abcdef"[dlc as usize]]);
+            line.push(b" ");
+            line.spaces(2_usize.saturating_sub(super::decimal_digits(frame.data().len() as u64)));
+            line.decimal(frame.data().len() as u64, 1);
+            line.push(b" ");
+            push_data(&mut line, frame.data());
+            // Message duration, length and the flags, then CRC and bit timings
+            line.push(b"        0    0 ");
+            line.spaces(8 - super::hex_digits(flags));
+            line.hex(flags, 1);
+            for _ in 0..5 {
+                line.push(b"        0");
+            }
+        } else {
+            line.decimal(self.channel as u64, 1);
+            line.push(b"  ");
+            push_id(&mut line, frame.id());
+            line.spaces(ID_WIDTH.saturating_sub(id_width(frame.id())));
+            line.push(b" ");
+            line.push(direction);
+            line.push(if frame.is_remote_request() {
+                b" r "
+            } else {
+                b" d "
+            });
+            // Classic frames are at most 8 bytes, the length is its own code
+            line.hex(frame.length() as u32, 1);
+            line.push(b" ");
+            push_data(&mut line, frame.data());
+        }
+
+        line.push(b"\n");
+        line.finish()
+    }
+}
+
+/// Hex id, extended ids are marked with a trailing `x`
+fn push_id(line: &mut Line, id: CanID) {
+    match id {
+        CanID::Standard(id) => line.hex(id as u32, 1),
+        CanID::Extended(id) => {
+            line.hex(id, 1);
+            line.push(b"x");
+        }
+    }
+}
+
+fn id_width(id: CanID) -> usize {
+    match id {
+        CanID::Standard(id) => super::hex_digits(id as u32),
+        CanID::Extended(id) => super::hex_digits(id) + 1,
+    }
+}
+
+/// Hex bytes separated by spaces
+fn push_data(line: &mut Line, data: &[u8]) {
+    for (index, byte) in data.iter().enumerate() {
+        if index > 0 {
+            line.push(b" ");
+        }
+        line.hex(*byte as u32, 2);
+    }
+}
@@ -0,0 +1,90 @@
+//!
+//! `candump -L` log lines
+//!
+use core::time::Duration;
+
+use crate::{can::CanID, time::Instant};
+
+use super::{Line, LoggedFrame};
+
+/// `canfd_frame.flags`
+const CANFD_BRS: u32 = 0x01;
+const CANFD_ESI: u32 = 0x02;
+
+/// Encodes frames into `candump -L` lines like
+/// `(0000000012.345678) can0 123#1122334455667788`
+///
+/// The format does not record the direction, log received and transmitted frames
+/// under different interface names to tell them apart.
+pub struct Candump<'a> {
+    interface: &'a str,
+    /// Added to the time since boot, e.g. the Unix time at boot
+    epoch: Duration,
+}
+
+impl<'a> Candump<'a> {
+    /// Timestamps are the time since boot, see [Candump::set_epoch]
+    pub fn new(interface: &'a str) -> Self {
+        Candump {
+            interface,
+            epoch: Duration::ZERO,
+        }
+    }
+
+    /// Log timestamps relative to the given epoch, e.g. the Unix time at boot
+    pub fn set_epoch(&mut self, time_at_boot: Duration) {
+        self.epoch = time_at_boot;
+    }
+
+    /// Write the line of a frame, including the line feed, into `buffer`. Returns the
+    /// length of the line, None if it does not fit.
+    pub fn encode(
+        &self,
+        frame: &impl LoggedFrame,
+        timestamp: &Instant,
+        buffer: &mut [u8],
+    ) -> Option<usize> {
+        let mut line = Line::new(buffer);
+        let time = self.epoch.saturating_add(timestamp.time_since_boot());
+
+        line.push(b"(");
+        line.decimal(time.as_secs(), 10);
+        line.push(b".");
+        line.decimal(time.subsec_micros() as u64, 6);
+        line.push(b") ");
+        line.push(self.interface.as_bytes());
+        line.push(b" ");
+
+        match frame.id() {
+            CanID::Standard(id) => line.hex(id as u32, 3),
+            CanID::Extended(id) => line.hex(id, 8),
+        }
+        line.push(b"#");
+
+        if frame.is_remote_request() {
+            line.push(b"R");
+            // Like can-utils, a length of 0 is left out
+            if frame.length() > 0 {
+                line.hex(frame.length() as u32, 1);
+            }
+        } else {
+            if frame.is_fd_format() {
+                let mut flags = 0;
+                if frame.bitrate_switching() {
+                    flags |= CANFD_BRS;
+                }
+                if frame.error_state() {
+                    flags |= CANFD_ESI;
+                }
+                line.push(b"#");
+                line.hex(flags, 1);
+            }
+            for byte in frame.data() {
+                line.hex(*byte as u32, 2);
+            }
+        }
+
+        line.push(b"\n");
+        line.finish()
+    }
+}
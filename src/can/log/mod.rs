@@ -0,0 +1,191 @@
+//!
+//! Offline CAN log encoding
+//!
+//! Frames are encoded into one text line each, ready to be streamed over a UART or
+//! stored in flash and converted on the host:
+//!
+//! - [Candump]: the `candump -L` format of the Linux can-utils, e.g. for `canplayer`
+//! - [Asc]: the Vector ASC format with hex ids and absolute timestamps, as written by
+//!   python-can
+//!
+//! Both encode any [LoggedFrame], i.e. [CanRxFrame] and [CanTxFrame] of any
+//! [CanBuffer] size, into a caller provided buffer. A line that does not fit is not
+//! written at all.
+//!
+use defmt::Format;
+
+use crate::can::{memory::module_ram::CanBuffer, CanID, CanRxFrame, CanTxFrame};
+
+mod asc;
+mod candump;
+
+pub use asc::Asc;
+pub use candump::Candump;
+
+/// Longest line of either format: an ASC CAN FD line with 64 data bytes
+pub const MAX_LINE: usize = 400;
+
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Rx,
+    Tx,
+}
+
+/// A frame that can be logged
+pub trait LoggedFrame {
+    fn id(&self) -> CanID;
+
+    /// The payload, empty for remote frames
+    fn data(&self) -> &[u8];
+
+    /// The payload length, or the requested length of a remote frame
+    fn length(&self) -> usize;
+
+    fn is_remote_request(&self) -> bool;
+
+    fn is_fd_format(&self) -> bool;
+
+    fn bitrate_switching(&self) -> bool;
+
+    /// The error state indicator of a CAN FD frame
+    fn error_state(&self) -> bool;
+}
+
+impl<B: CanBuffer> LoggedFrame for CanRxFrame<B> {
+    fn id(&self) -> CanID {
+        self.get_id()
+    }
+
+    fn data(&self) -> &[u8] {
+        if self.is_remote_request() {
+            return &[];
+        }
+        CanRxFrame::data(self)
+    }
+
+    fn length(&self) -> usize {
+        if self.is_remote_request() {
+            return self.header().data_length();
+        }
+        CanRxFrame::data(self).len()
+    }
+
+    fn is_remote_request(&self) -> bool {
+        self.header().rtr()
+    }
+
+    fn is_fd_format(&self) -> bool {
+        CanRxFrame::is_fd_format(self)
+    }
+
+    fn bitrate_switching(&self) -> bool {
+        CanRxFrame::bitrate_switching(self)
+    }
+
+    fn error_state(&self) -> bool {
+        self.header().error_state()
+    }
+}
+
+impl<B: CanBuffer> LoggedFrame for CanTxFrame<B> {
+    fn id(&self) -> CanID {
+        self.get_id()
+    }
+
+    fn data(&self) -> &[u8] {
+        if self.is_remote_request() {
+            return &[];
+        }
+        CanTxFrame::data(self)
+    }
+
+    fn length(&self) -> usize {
+        // The data length code of a remote frame is the requested length
+        CanTxFrame::data(self).len()
+    }
+
+    fn is_remote_request(&self) -> bool {
+        CanTxFrame::is_remote_request(self)
+    }
+
+    fn is_fd_format(&self) -> bool {
+        CanTxFrame::is_fd_format(self)
+    }
+
+    fn bitrate_switching(&self) -> bool {
+        CanTxFrame::bitrate_switching(self)
+    }
+
+    fn error_state(&self) -> bool {
+        CanTxFrame::error_state(self)
+    }
+}
+
+/// Text line written into a caller provided buffer, remembering whether it fit
+struct Line<'a> {
+    buffer: &'a mut [u8],
+    length: usize,
+    overflow: bool,
+}
+
+impl<'a> Line<'a> {
+    fn new(buffer: &'a mut [u8]) -> Self {
+        Line {
+            buffer,
+            length: 0,
+            overflow: false,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        if self.overflow {
+            return;
+        }
+        match self.buffer.get_mut(self.length..self.length + bytes.len()) {
+            Some(dst) => {
+                dst.copy_from_slice(bytes);
+                self.length += bytes.len();
+            }
+            None => self.overflow = true,
+        }
+    }
+
+    fn spaces(&mut self, count: usize) {
+        for _ in 0..count {
+            self.push(b" ");
+        }
+    }
+
+    /// Upper case hex digits of `value`, zero padded to `digits`
+    fn hex(&mut self, value: u32, digits: usize) {
+        for digit in (0..digits.max(hex_digits(value))).rev() {
+            let nibble = (value >> (4 * digit)) as u8 & 0xF;
+            self.push(&[b"0123456789ABCDEF"[nibble as usize]]);
+        }
+    }
+
+    /// Decimal digits of `value`, zero padded to `digits`
+    fn decimal(&mut self, value: u64, digits: usize) {
+        for digit in (0..digits.max(decimal_digits(value))).rev() {
+            let decimal = (value / 10u64.pow(digit as u32) % 10) as u8;
+            self.push(&[b'0' + decimal]);
+        }
+    }
+
+    /// The length of the line, None if it did not fit into the buffer
+    fn finish(self) -> Option<usize> {
+        (!self.overflow).then_some(self.length)
+    }
+}
+
+fn hex_digits(value: u32) -> usize {
+    ((u32::BITS - value.leading_zeros()) as usize)
+        .div_ceil(4)
+        .max(1)
+}
+
+fn decimal_digits(value: u64) -> usize {
+    value
+        .checked_ilog10()
+        .map_or(1, |digits| digits as usize + 1)
+}
@@ -0,0 +1,171 @@
+//!
+//! Helper structures for acceptance filter elements
+//!
+use bitfield_struct::bitfield;
+use defmt::Format;
+
+/// What happens to a frame that matches a filter element (`SFEC`/`EFEC`)
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FilterAction {
+    /// The filter element is disabled
+    Disable = 0b000,
+    StoreFifo0 = 0b001,
+    StoreFifo1 = 0b010,
+    Reject = 0b011,
+    /// Flag the frame as high priority message without storing it
+    SetPriority = 0b100,
+    /// Flag the frame as high priority message and store it in FIFO 0
+    SetPriorityStoreFifo0 = 0b101,
+    /// Flag the frame as high priority message and store it in FIFO 1
+    SetPriorityStoreFifo1 = 0b110,
+}
+
+/// Interpretation of the two ids of a filter element (`SFT`/`EFT`)
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum FilterType {
+    /// Match all ids from id1 to id2 (inclusive)
+    Range = 0b00,
+    /// Match either id1 or id2
+    Dual = 0b01,
+    /// id1 is the filter, id2 the mask
+    Classic = 0b10,
+}
+
+/// A standard (11 bit) id filter element laid out in the can module ram.
+///
+/// From https://github.com/Infineon/AURIX_code_examples/blob/f1a75eea6a9cf939d6052a3cf9463ab338a17df3/code_examples/MCMCAN_1_KIT_TC375_LK/Libraries/iLLD/TC37A/Tricore/Can/Std/IfxCan.h
+#[bitfield(u32)]
+#[derive(Default)]
+pub struct StandardFilter {
+    #[bits(11)]
+    id2: u16,
+    #[bits(5)]
+    reserved_11: u8,
+    #[bits(11)]
+    id1: u16,
+    #[bits(3)]
+    action: u8,
+    #[bits(2)]
+    filter_type: u8,
+}
+
+impl StandardFilter {
+    /// Match frames for which `frame_id & mask == id & mask`
+    pub fn classic(id: u16, mask: u16, action: FilterAction) -> Self {
+        Self::with_type(FilterType::Classic, id, mask, action)
+    }
+
+    /// Match frames with an id within `from..=to`
+    pub fn range(from: u16, to: u16, action: FilterAction) -> Self {
+        Self::with_type(FilterType::Range, from, to, action)
+    }
+
+    /// Match frames with an id equal to `id1` or `id2`
+    pub fn dual(id1: u16, id2: u16, action: FilterAction) -> Self {
+        Self::with_type(FilterType::Dual, id1, id2, action)
+    }
+
+    fn with_type(filter_type: FilterType, id1: u16, id2: u16, action: FilterAction) -> Self {
+        Self::new()
+            .with_filter_type(filter_type as u8)
+            .with_action(action as u8)
+            .with_id1(id1)
+            .with_id2(id2)
+    }
+}
+
+impl Format for StandardFilter {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "StandardFilter {{ type: {}, action: {}, id1: 0x{:X}, id2: 0x{:X} }}",
+            self.filter_type(),
+            self.action(),
+            self.id1(),
+            self.id2()
+        )
+    }
+}
+
+/// An extended (29 bit) id filter element laid out in the can module ram.
+///
+/// Range filters are masked with the extended id mask of the node (`XIDAM`).
+#[derive(Default, Clone, Copy)]
+#[repr(C)]
+pub struct ExtendedFilter {
+    filter_0: ExtendedFilterF0,
+    filter_1: ExtendedFilterF1,
+}
+
+#[bitfield(u32)]
+#[derive(Default)]
+struct ExtendedFilterF0 {
+    #[bits(29)]
+    id1: u32,
+    #[bits(3)]
+    action: u8,
+}
+
+#[bitfield(u32)]
+#[derive(Default)]
+struct ExtendedFilterF1 {
+    #[bits(29)]
+    id2: u32,
+    #[bits(1)]
+    reserved_29: u8,
+    #[bits(2)]
+    filter_type: u8,
+}
+
+impl ExtendedFilter {
+    /// Match frames for which `frame_id & mask == id & mask`
+    pub fn classic(id: u32, mask: u32, action: FilterAction) -> Self {
+        Self::with_type(FilterType::Classic, id, mask, action)
+    }
+
+    /// Match frames with an id (masked with `XIDAM`) within `from..=to`
+    pub fn range(from: u32, to: u32, action: FilterAction) -> Self {
+        Self::with_type(FilterType::Range, from, to, action)
+    }
+
+    /// Match frames with an id equal to `id1` or `id2`
+    pub fn dual(id1: u32, id2: u32, action: FilterAction) -> Self {
+        Self::with_type(FilterType::Dual, id1, id2, action)
+    }
+
+    fn with_type(filter_type: FilterType, id1: u32, id2: u32, action: FilterAction) -> Self {
+        ExtendedFilter {
+            filter_0: ExtendedFilterF0::new()
+                .with_id1(id1)
+                .with_action(action as u8),
+            filter_1: ExtendedFilterF1::new()
+                .with_id2(id2)
+                .with_filter_type(filter_type as u8),
+        }
+    }
+
+    /// Copy this element to the module ram, word by word
+    ///
+    /// # Safety
+    /// `dst` must be valid for writes and properly aligned
+    pub(crate) unsafe fn write_volatile(self, dst: *mut ExtendedFilter) {
+        // Same issue as for the frames: 64 bit writes to the module ram do not work
+        core::ptr::write_volatile(core::ptr::addr_of_mut!((*dst).filter_0), self.filter_0);
+        core::ptr::write_volatile(core::ptr::addr_of_mut!((*dst).filter_1), self.filter_1);
+    }
+}
+
+impl Format for ExtendedFilter {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "ExtendedFilter {{ type: {}, action: {}, id1: 0x{:X}, id2: 0x{:X} }}",
+            self.filter_1.filter_type(),
+            self.filter_0.action(),
+            self.filter_0.id1(),
+            self.filter_1.id2()
+        )
+    }
+}
@@ -2,6 +2,7 @@
 //! This can be improved in many ways... is just here to given an idea
 use core::{cell::UnsafeCell, marker::PhantomData, mem::MaybeUninit};
 use defmt::Format;
+#[cfg(not(feature = "sim"))]
 use tc37x_pac::can0::node::{
     rxesc::{self},
     txesc,
@@ -26,7 +27,7 @@ impl<'a, M: CanModuleRAM> Format for NodeMemoryBuilder<'a, M> {
         defmt::write!(
             fmt,
             "NodeMemoryBuilder {{ address: 0x{:X}, used: {}, unused: {} }}",
-            M::RAM_LOCATION,
+            M::ram_location(),
             self.free_offset,
             M::RAM_SIZE - self.free_offset
         )
@@ -55,7 +56,7 @@ impl<'a, M: CanModuleRAM> NodeMemoryBuilder<'a, M> {
     pub fn take<T: Sized + Default>(&mut self, num: usize) -> Option<NodeMemory<'a, T, M>> {
         assert!(num <= 32, "Buffers can only be of size 32");
 
-        let start_address = (M::RAM_LOCATION as usize).checked_add(self.free_offset)?;
+        let start_address = (M::ram_location() as usize).checked_add(self.free_offset)?;
 
         // We need to make sure that the buffer we acquired is aligned for T
         let misaligned_by = start_address % core::mem::align_of::<T>();
@@ -72,7 +73,7 @@ impl<'a, M: CanModuleRAM> NodeMemoryBuilder<'a, M> {
             return None;
         }
         // SAFETY: conditions checked above
-        let buffer_address = unsafe { M::RAM_LOCATION.add(self.free_offset + padding) };
+        let buffer_address = unsafe { M::ram_location().add(self.free_offset + padding) };
 
         if extra_bytes_required > isize::MAX as usize {
             // Array too big for core::slice::from_raw_parts_mut
@@ -143,6 +144,7 @@ impl<'a, E: Sized, M: CanModuleRAM> NodeMemory<'a, E, M> {
     ///
     /// # Safety
     /// The item at the given index must not be referenced anyhwere else.
+    #[allow(clippy::mut_from_ref)]
     pub unsafe fn get(&self, idx: u8) -> Option<&mut E> {
         if idx as usize >= self.buffer.len() {
             None
@@ -178,10 +180,14 @@ mod sealed {
     pub trait Buffer {}
 }
 
-pub trait CanBuffer: sealed::Buffer + Default + AsMut<[u8]> + AsRef<[u8]> {
+pub trait CanBuffer: sealed::Buffer + Default + Clone + AsMut<[u8]> + AsRef<[u8]> {
     const BUFFER_SIZE: usize;
 
+    /// Register encoding of the buffer size (`RXESC`, `TXESC`)
+    #[cfg(not(feature = "sim"))]
     type BufferSize: Into<rxesc::F0DS_A> + Into<rxesc::F1DS_A> + Into<txesc::TBDS_A>;
+    #[cfg(feature = "sim")]
+    type BufferSize;
 
     fn buffer_size() -> Self::BufferSize;
 }
@@ -192,6 +198,7 @@ macro_rules! create_buffer_types {
             $($name),*
         }
 
+        #[cfg(not(feature = "sim"))]
         impl From<Sizes> for rxesc::F0DS_A {
             fn from(value: Sizes) -> Self {
                 match value {
@@ -199,6 +206,7 @@ macro_rules! create_buffer_types {
                 }
             }
         }
+        #[cfg(not(feature = "sim"))]
         impl From<Sizes> for rxesc::F1DS_A {
             fn from(value: Sizes) -> Self {
                 match value {
@@ -206,6 +214,7 @@ macro_rules! create_buffer_types {
                 }
             }
         }
+        #[cfg(not(feature = "sim"))]
         impl From<Sizes> for txesc::TBDS_A {
             fn from(value: Sizes) -> Self {
                 match value {
@@ -214,12 +223,19 @@ macro_rules! create_buffer_types {
             }
         }
         $(
-            #[derive(Default)]
+            #[derive(Clone)]
             #[repr(C)]
             pub struct $name {
                 pub(super) data: [u8; $size]
             }
 
+            // Arrays above 32 elements do not implement Default
+            impl Default for $name {
+                fn default() -> Self {
+                    $name { data: [0; $size] }
+                }
+            }
+
             impl sealed::Buffer for $name {}
 
             impl AsRef<[u8]> for $name {
@@ -247,4 +263,84 @@ macro_rules! create_buffer_types {
     };
 }
 
-create_buffer_types!((BufferSize8, 8, BUFFER_SIZE8, BUFFER_SIZE8, BUFFER_SIZE8));
+create_buffer_types!(
+    (BufferSize8, 8, BUFFER_SIZE8, BUFFER_SIZE8, BUFFER_SIZE8),
+    (
+        BufferSize12,
+        12,
+        BUFFER_SIZE12,
+        BUFFER_SIZE12,
+        BUFFER_SIZE12
+    ),
+    (
+        BufferSize16,
+        16,
+        BUFFER_SIZE16,
+        BUFFER_SIZE16,
+        BUFFER_SIZE16
+    ),
+    (
+        BufferSize20,
+        20,
+        BUFFER_SIZE20,
+        BUFFER_SIZE20,
+        BUFFER_SIZE20
+    ),
+    (
+        BufferSize24,
+        24,
+        BUFFER_SIZE24,
+        BUFFER_SIZE24,
+        BUFFER_SIZE24
+    ),
+    (
+        BufferSize32,
+        32,
+        BUFFER_SIZE32,
+        BUFFER_SIZE32,
+        BUFFER_SIZE32
+    ),
+    (
+        BufferSize48,
+        48,
+        BUFFER_SIZE48,
+        BUFFER_SIZE48,
+        BUFFER_SIZE48
+    ),
+    (
+        BufferSize64,
+        64,
+        BUFFER_SIZE64,
+        BUFFER_SIZE64,
+        BUFFER_SIZE64
+    )
+);
+
+/// Payload length in bytes encoded by a data length code (CAN FD lengths above 8)
+pub const fn dlc_to_length(dlc: u8) -> usize {
+    match dlc {
+        0..=8 => dlc as usize,
+        9 => 12,
+        10 => 16,
+        11 => 20,
+        12 => 24,
+        13 => 32,
+        14 => 48,
+        _ => 64,
+    }
+}
+
+/// Smallest data length code able to carry `length` bytes, None if above 64
+pub const fn length_to_dlc(length: usize) -> Option<u8> {
+    match length {
+        0..=8 => Some(length as u8),
+        9..=12 => Some(9),
+        13..=16 => Some(10),
+        17..=20 => Some(11),
+        21..=24 => Some(12),
+        25..=32 => Some(13),
+        33..=48 => Some(14),
+        49..=64 => Some(15),
+        _ => None,
+    }
+}
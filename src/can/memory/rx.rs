@@ -6,7 +6,10 @@ use defmt::Format;
 
 use crate::can::CanID;
 
-use super::{module_ram::CanBuffer, tx::TxMessageT0};
+use super::{
+    module_ram::{dlc_to_length, CanBuffer},
+    tx::TxMessageT0,
+};
 
 /// Represents a received can frame laid out in the can module ram.
 ///
@@ -16,7 +19,7 @@ use super::{module_ram::CanBuffer, tx::TxMessageT0};
 pub struct CanRxFrame<B: CanBuffer> {
     transmit_buffer_0: RxMessageT0,
     transmit_buffer_1: RxMessageT1,
-    /// Up to 8 bytes payload, actual length of data defined by [TxMessageT1::dlc]
+    /// Up to [CanBuffer::BUFFER_SIZE] bytes payload, actual length of data defined by [RxMessageT1::dlc]
     buffer: B,
 }
 
@@ -67,8 +70,98 @@ impl<B: CanBuffer> CanRxFrame<B> {
         }
     }
 
+    pub fn is_fd_format(&self) -> bool {
+        self.transmit_buffer_1.is_fd_format()
+    }
+
+    pub fn bitrate_switching(&self) -> bool {
+        self.transmit_buffer_1.bitrate_switching()
+    }
+
     pub fn data(&self) -> &[u8] {
-        let length = self.transmit_buffer_1.dlc() as usize;
+        // Frames longer than the fifo element size are truncated by the hardware
+        let length = dlc_to_length(self.transmit_buffer_1.dlc()).min(B::BUFFER_SIZE);
         &(self.buffer.as_ref()[..length])
     }
+
+    /// The header of this frame, i.e. everything but the payload
+    pub fn header(&self) -> RxFrameHeader {
+        RxFrameHeader {
+            t0: self.transmit_buffer_0,
+            t1: self.transmit_buffer_1,
+        }
+    }
+}
+
+/// The two header words of a received frame, decoded from the raw words when the
+/// frame is read in place (see [RxFrameLease](crate::can::node::receive::RxFrameLease))
+#[derive(Clone, Copy)]
+pub struct RxFrameHeader {
+    t0: RxMessageT0,
+    t1: RxMessageT1,
+}
+
+impl RxFrameHeader {
+    pub(crate) fn from_words(t0: u32, t1: u32) -> Self {
+        RxFrameHeader {
+            t0: t0.into(),
+            t1: t1.into(),
+        }
+    }
+
+    pub fn id(&self) -> CanID {
+        let id_field = self.t0.id();
+        if self.t0.is_extended() {
+            CanID::Extended(id_field)
+        } else {
+            CanID::Standard((id_field >> 18) as u16)
+        }
+    }
+
+    pub fn rtr(&self) -> bool {
+        self.t0.rtr()
+    }
+
+    pub fn error_state(&self) -> bool {
+        self.t0.error_state()
+    }
+
+    /// Payload length as sent on the bus, this may exceed the fifo element size
+    pub fn data_length(&self) -> usize {
+        dlc_to_length(self.t1.dlc())
+    }
+
+    pub fn is_fd_format(&self) -> bool {
+        self.t1.is_fd_format()
+    }
+
+    pub fn bitrate_switching(&self) -> bool {
+        self.t1.bitrate_switching()
+    }
+
+    pub fn rx_timestamp(&self) -> u16 {
+        self.t1.rx_timestamp()
+    }
+
+    pub fn filter_index(&self) -> u8 {
+        self.t1.filter_index()
+    }
+
+    pub fn accepted_non_matching_frame(&self) -> bool {
+        self.t1.accepted_non_matching_frame()
+    }
+}
+
+impl Format for RxFrameHeader {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "RxFrameHeader {{ id: {}, length: {}, rtr: {}, is_fd_format: {}, filter_index: {} }}",
+            self.id(),
+            self.data_length(),
+            self.rtr(),
+            self.is_fd_format(),
+            self.filter_index()
+        )
+    }
 }
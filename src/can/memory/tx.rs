@@ -6,7 +6,7 @@ use defmt::Format;
 
 use crate::can::CanID;
 
-use super::module_ram::CanBuffer;
+use super::module_ram::{dlc_to_length, length_to_dlc, CanBuffer};
 
 /// Simplified CanFrame structure in C representation to allow easy mem-copy... used only for printing and mem-copy
 ///
@@ -18,7 +18,7 @@ use super::module_ram::CanBuffer;
 pub struct CanTxFrame<B: CanBuffer> {
     transmit_buffer_0: TxMessageT0,
     transmit_buffer_1: TxMessageT1,
-    /// Up to 8 bytes payload, actual length of data defined by [TxMessageT1::dlc]
+    /// Up to [CanBuffer::BUFFER_SIZE] bytes payload, actual length of data defined by [TxMessageT1::dlc]
     buffer: B,
 }
 
@@ -41,8 +41,10 @@ pub struct TxMessageT0 {
 #[bitfield(u32)]
 #[derive(Default)]
 pub struct TxMessageT1 {
-    #[bits(16)]
+    #[bits(8)]
     reserved_0: u32,
+    /// Upper byte of the message marker, only used with wide message markers (`CCCR.WMM`)
+    wide_message_marker: u8,
     /// Indicates the actual length of the array in the parenting structure `CanFrame`
     #[bits(4)]
     dlc: u8,
@@ -93,17 +95,80 @@ impl<B: CanBuffer> CanTxFrame<B> {
         }
     }
 
+    /// Set the payload. More than 8 bytes turn this into a CAN FD frame, 8 bytes or less
+    /// into a classic frame, lengths that cannot be expressed by a data length code are
+    /// padded with zeros. Call [CanTxFrame::set_fd_format] afterwards to send a short
+    /// payload as CAN FD frame.
     pub fn set_data(&mut self, data: &[u8]) {
-        if data.len() > 8 {
-            defmt::panic!("CAN data length exceeds 8 ({})", data.len())
+        if data.len() > B::BUFFER_SIZE {
+            defmt::panic!(
+                "CAN data length exceeds {} ({})",
+                B::BUFFER_SIZE,
+                data.len()
+            )
         }
+        // Cannot fail, all buffers are at most 64 bytes
+        let dlc = length_to_dlc(data.len()).unwrap();
+
         self.buffer.as_mut()[..data.len()].copy_from_slice(data);
+        self.buffer.as_mut()[data.len()..dlc_to_length(dlc)].fill(0);
 
-        self.transmit_buffer_1.set_dlc(data.len() as u8);
+        self.transmit_buffer_1.set_dlc(dlc);
+        self.transmit_buffer_1.set_is_fd_format(data.len() > 8);
+        if data.len() <= 8 {
+            self.transmit_buffer_1.set_bitrate_switching(false);
+        }
     }
 
     pub fn data(&self) -> &[u8] {
-        let length = self.transmit_buffer_1.dlc() as usize;
+        let length = dlc_to_length(self.transmit_buffer_1.dlc());
         &(self.buffer.as_ref()[..length])
     }
+
+    /// The payload set with [CanTxFrame::set_data], e.g. to fill in a checksum
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        let length = dlc_to_length(self.transmit_buffer_1.dlc());
+        &mut (self.buffer.as_mut()[..length])
+    }
+
+    /// Send this as CAN FD frame, optionally with bitrate switching for the data phase
+    pub fn set_fd_format(&mut self, bitrate_switching: bool) {
+        self.transmit_buffer_1.set_is_fd_format(true);
+        self.transmit_buffer_1
+            .set_bitrate_switching(bitrate_switching);
+    }
+
+    pub fn is_fd_format(&self) -> bool {
+        self.transmit_buffer_1.is_fd_format()
+    }
+
+    pub fn bitrate_switching(&self) -> bool {
+        self.transmit_buffer_1.bitrate_switching()
+    }
+
+    /// Send this as remote frame requesting `length` bytes, remote frames are classic
+    /// CAN frames without payload
+    pub fn set_remote_request(&mut self, length: usize) {
+        defmt::assert!(length <= 8, "Remote frames request at most 8 bytes");
+        self.transmit_buffer_0.set_rtr(true);
+        self.transmit_buffer_1.set_dlc(length as u8);
+        self.transmit_buffer_1.set_is_fd_format(false);
+        self.transmit_buffer_1.set_bitrate_switching(false);
+    }
+
+    pub fn is_remote_request(&self) -> bool {
+        self.transmit_buffer_0.rtr()
+    }
+
+    pub fn error_state(&self) -> bool {
+        self.transmit_buffer_0.error_state()
+    }
+
+    /// Set the message marker, the upper byte is only used with wide message markers
+    /// (see [NodeProtocolOptions](crate::can::node::options::NodeProtocolOptions))
+    pub fn set_message_marker(&mut self, marker: u16) {
+        self.transmit_buffer_1.set_message_marker(marker as u8);
+        self.transmit_buffer_1
+            .set_wide_message_marker((marker >> 8) as u8);
+    }
 }
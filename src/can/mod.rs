@@ -1,16 +1,37 @@
 //! Basic CAN module implementation
 use defmt::Format;
 
+use node::registers::NodeRegisters;
+
+#[cfg(not(feature = "sim"))]
 pub mod can0;
+pub mod canopen;
+mod crc;
+pub mod cyclic;
+pub mod dbc;
+pub mod e2e;
+pub mod gateway;
+pub mod isotp;
+pub mod j1939;
+pub mod log;
 
 pub mod memory {
+    pub mod filter;
     pub mod module_ram;
     pub mod rx;
     pub mod tx;
 }
 
+pub mod nm;
 pub mod node;
+mod queue;
+pub mod secoc;
+#[cfg(feature = "sim")]
+pub mod sim;
+pub mod slcan;
 pub mod timing;
+pub mod uds;
+pub mod xcp;
 
 pub use memory::rx::CanRxFrame;
 pub use memory::tx::CanTxFrame;
@@ -20,20 +41,27 @@ pub use memory::tx::CanTxFrame;
 /// # Safety
 /// Unsafe as wrong data here will result in hard faults
 pub unsafe trait CanModuleRAM {
-    /// Address of the RAM
-    const RAM_LOCATION: *mut u8;
     /// The size of the RAM, in bytes
     const RAM_SIZE: usize;
+
+    /// Address of the RAM
+    fn ram_location() -> *mut u8;
+
+    /// Make sure all writes to the RAM completed before the hardware is triggered to
+    /// read them
+    fn data_sync();
 }
 
 /// Marker for a CAN peripheral
 pub trait CanModule {
     /// Associated RAM module
     type RAM: CanModuleRAM;
+    /// Register block of a single node
+    type Node: NodeRegisters;
 }
 
 /// Type-aware wrapper around a CAN identifier
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Clone, Copy)]
 pub enum CanID {
     /// A standard ID
     Standard(u16),
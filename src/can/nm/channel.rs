@@ -0,0 +1,71 @@
+//!
+//! Drives [CanNm] with a [CanNode]
+//!
+use crate::{
+    can::{
+        memory::module_ram::CanBuffer,
+        node::{
+            connection::Connected, receive::RxFifo0, transceive::TxDedicated, CanNode, Running,
+        },
+        CanModule,
+    },
+    time::Instant,
+};
+
+use super::{CanNm, NmEvent};
+
+/// Network management on a running node, see [CanNmChannel::process]
+///
+/// When [NmState::BusSleep](super::NmState::BusSleep) is reported, put the node into
+/// power-down with [CanNode::power_down], or with [CanNode::sleep] to wake up on bus
+/// activity. Wake it up before requesting the network again or when the bus wakes up.
+pub struct CanNmChannel {
+    nm: CanNm,
+}
+
+impl CanNmChannel {
+    pub fn new(nm: CanNm) -> Self {
+        CanNmChannel { nm }
+    }
+
+    pub fn nm(&self) -> &CanNm {
+        &self.nm
+    }
+
+    pub fn nm_mut(&mut self) -> &mut CanNm {
+        &mut self.nm
+    }
+
+    /// Receive pending NM PDUs, check the timers and send the NM PDU when due. Call
+    /// this at least once per millisecond, events are passed to `on_event`.
+    #[allow(clippy::type_complexity)]
+    pub fn process<'r, 'mem, C: Connected, TB: CanBuffer, RB: CanBuffer, M: CanModule>(
+        &mut self,
+        node: &mut CanNode<
+            'r,
+            C,
+            Running,
+            TxDedicated<'mem, TB, M::RAM>,
+            RxFifo0<'mem, RB, M::RAM>,
+            M,
+        >,
+        now: &Instant,
+        mut on_event: impl FnMut(NmEvent),
+    ) {
+        while let Some(frame) = node.try_receive_fifo0() {
+            if let Some(event) = self.nm.on_frame(frame.get_id(), frame.data(), now) {
+                on_event(event);
+            }
+        }
+
+        while let Some(event) = self.nm.poll(now) {
+            on_event(event);
+        }
+
+        if let Some(buffer) = node.acquire_transmit_buffer() {
+            if let Some(frame) = self.nm.next_frame::<TB>() {
+                buffer.set_frame(frame).send();
+            }
+        }
+    }
+}
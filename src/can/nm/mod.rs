@@ -0,0 +1,406 @@
+//!
+//! CAN network management (AUTOSAR CanNm)
+//!
+//! [CanNm] lets the nodes of a network go to sleep together: every node that needs
+//! the bus sends NM PDUs periodically, and once no node has sent one for
+//! [NmConfig::timeout_time] all of them prepare for bus sleep and stop after
+//! [NmConfig::wait_bus_sleep_time].
+//!
+//! The state machine follows CanNm: bus sleep, repeat message, normal operation,
+//! ready sleep and prepare bus sleep. An NM PDU carries the node id in byte 0, the
+//! control bit vector in byte 1 and up to [USER_DATA_LENGTH] bytes of user data.
+//!
+//! Like the other protocols it never touches the hardware: frames are passed in with
+//! [CanNm::on_frame], pulled out with [CanNm::next_frame] and the timers are checked
+//! with [CanNm::poll]. [CanNmChannel] drives it with a
+//! [CanNode](crate::can::node::CanNode).
+//!
+//! Powering the node down is not covered: the channel only borrows the running node
+//! and cannot change its state, the application does it when bus sleep is reported
+//! (see [CanNmChannel]).
+//!
+use core::time::Duration;
+
+use defmt::Format;
+
+use crate::{
+    can::{
+        memory::{
+            filter::{FilterAction, StandardFilter},
+            module_ram::CanBuffer,
+        },
+        CanID, CanTxFrame,
+    },
+    time::Instant,
+};
+
+mod channel;
+
+pub use channel::CanNmChannel;
+
+/// Control bit vector: ask all nodes to enter repeat message state
+pub const CBV_REPEAT_MESSAGE_REQUEST: u8 = 0x01;
+/// Control bit vector: the sender woke up the network itself
+pub const CBV_ACTIVE_WAKEUP: u8 = 0x10;
+
+/// NM PDUs are classic frames of 8 bytes
+const PDU_LENGTH: usize = 8;
+/// User data bytes following the node id and control bit vector
+pub const USER_DATA_LENGTH: usize = PDU_LENGTH - 2;
+
+#[derive(Format, Clone, Copy)]
+pub struct NmConfig {
+    pub node_id: u8,
+    /// NM PDUs are sent with the base id plus the node id, all standard ids from the
+    /// base id to the base id plus 0xFF are taken as NM PDUs
+    pub base_id: u16,
+    /// Period of the NM PDUs while the network is requested
+    pub message_cycle_time: Duration,
+    /// Delay of the first NM PDU, spreads the PDUs of the nodes after a wake-up
+    pub message_cycle_offset: Duration,
+    /// Time without NM PDUs after which the network is released
+    pub timeout_time: Duration,
+    /// Time spent in repeat message state
+    pub repeat_message_time: Duration,
+    /// Time spent in prepare bus sleep state
+    pub wait_bus_sleep_time: Duration,
+}
+
+impl NmConfig {
+    pub const fn new(node_id: u8, base_id: u16) -> Self {
+        NmConfig {
+            node_id,
+            base_id,
+            message_cycle_time: Duration::from_millis(100),
+            message_cycle_offset: Duration::from_millis(0),
+            timeout_time: Duration::from_millis(1000),
+            repeat_message_time: Duration::from_millis(1500),
+            wait_bus_sleep_time: Duration::from_millis(1500),
+        }
+    }
+
+    fn pdu_id(&self) -> u16 {
+        self.base_id + self.node_id as u16
+    }
+}
+
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum NmState {
+    /// No communication, the node may be powered down
+    BusSleep,
+    /// Communication stops, pending frames are still sent
+    PrepareBusSleep,
+    /// NM PDUs are sent to make the node known to the others
+    RepeatMessage,
+    /// The network is requested by this node
+    NormalOperation,
+    /// The network is kept awake by other nodes only
+    ReadySleep,
+}
+
+impl NmState {
+    /// Whether the bus is awake, the state is part of the network mode
+    pub fn is_network_mode(self) -> bool {
+        matches!(
+            self,
+            NmState::RepeatMessage | NmState::NormalOperation | NmState::ReadySleep
+        )
+    }
+}
+
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum NmEvent {
+    /// Entered the given state
+    StateChanged(NmState),
+    /// An NM PDU was received in bus sleep state, the application decides whether to
+    /// start with [CanNm::network_request] or [CanNm::passive_startup]
+    NetworkStartIndication,
+}
+
+/// A received NM PDU
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub struct NmPdu {
+    pub node_id: u8,
+    /// Control bit vector, see [CBV_REPEAT_MESSAGE_REQUEST] and [CBV_ACTIVE_WAKEUP]
+    pub control: u8,
+    pub user_data: [u8; USER_DATA_LENGTH],
+}
+
+/// CAN network management, see the [module](self) documentation
+pub struct CanNm {
+    config: NmConfig,
+    state: NmState,
+    /// Whether the application needs the network
+    requested: bool,
+    /// Control bit vector of the sent NM PDUs
+    control: u8,
+    user_data: [u8; USER_DATA_LENGTH],
+    /// Set while NM PDUs are sent
+    next_transmission: Option<Instant>,
+    nm_timeout: Option<Instant>,
+    repeat_message_end: Option<Instant>,
+    bus_sleep_at: Option<Instant>,
+    /// An NM PDU waiting for transmission, replaced by newer ones
+    pending: Option<[u8; PDU_LENGTH]>,
+    last_pdu: Option<NmPdu>,
+}
+
+impl CanNm {
+    /// Starts in bus sleep state without requesting the network
+    pub fn new(config: NmConfig) -> Self {
+        defmt::assert!(
+            config.base_id as u32 + 0xFF <= 0x7FF,
+            "NM base id 0x{:X} leaves the standard id range",
+            config.base_id
+        );
+
+        CanNm {
+            config,
+            state: NmState::BusSleep,
+            requested: false,
+            control: 0,
+            user_data: [0xFF; USER_DATA_LENGTH],
+            next_transmission: None,
+            nm_timeout: None,
+            repeat_message_end: None,
+            bus_sleep_at: None,
+            pending: None,
+            last_pdu: None,
+        }
+    }
+
+    pub fn config(&self) -> &NmConfig {
+        &self.config
+    }
+
+    pub fn state(&self) -> NmState {
+        self.state
+    }
+
+    pub fn is_network_requested(&self) -> bool {
+        self.requested
+    }
+
+    /// The last NM PDU received from any node
+    pub fn last_pdu(&self) -> Option<&NmPdu> {
+        self.last_pdu.as_ref()
+    }
+
+    /// User data of the following NM PDUs, missing bytes are filled with 0xFF
+    pub fn set_user_data(&mut self, data: &[u8]) {
+        defmt::assert!(
+            data.len() <= USER_DATA_LENGTH,
+            "NM user data is limited to {} bytes",
+            USER_DATA_LENGTH
+        );
+        self.user_data = [0xFF; USER_DATA_LENGTH];
+        self.user_data[..data.len()].copy_from_slice(data);
+    }
+
+    /// Request the network, waking up the bus if it is sleeping
+    pub fn network_request(&mut self, now: &Instant) -> Option<NmEvent> {
+        self.requested = true;
+        match self.state {
+            NmState::BusSleep | NmState::PrepareBusSleep => {
+                self.control |= CBV_ACTIVE_WAKEUP;
+                self.enter(NmState::RepeatMessage, now)
+            }
+            NmState::ReadySleep => self.enter(NmState::NormalOperation, now),
+            NmState::RepeatMessage | NmState::NormalOperation => None,
+        }
+    }
+
+    /// Release the network, the bus goes to sleep once no other node requests it
+    pub fn network_release(&mut self, now: &Instant) -> Option<NmEvent> {
+        self.requested = false;
+        match self.state {
+            NmState::NormalOperation => self.enter(NmState::ReadySleep, now),
+            _ => None,
+        }
+    }
+
+    /// Join a network woken up by another node without requesting it
+    pub fn passive_startup(&mut self, now: &Instant) -> Option<NmEvent> {
+        match self.state {
+            NmState::BusSleep | NmState::PrepareBusSleep => self.enter(NmState::RepeatMessage, now),
+            _ => None,
+        }
+    }
+
+    /// Ask all nodes to enter repeat message state, e.g. to detect the present nodes
+    pub fn repeat_message_request(&mut self, now: &Instant) -> Option<NmEvent> {
+        match self.state {
+            NmState::NormalOperation | NmState::ReadySleep => {
+                self.control |= CBV_REPEAT_MESSAGE_REQUEST;
+                self.enter(NmState::RepeatMessage, now)
+            }
+            _ => None,
+        }
+    }
+
+    /// Process a received frame, frames other than NM PDUs are ignored
+    pub fn on_frame(&mut self, id: CanID, data: &[u8], now: &Instant) -> Option<NmEvent> {
+        let CanID::Standard(id) = id else {
+            return None;
+        };
+        if !(self.config.base_id..=self.config.base_id + 0xFF).contains(&id) || data.len() < 2 {
+            return None;
+        }
+
+        let mut pdu = NmPdu {
+            node_id: data[0],
+            control: data[1],
+            user_data: [0xFF; USER_DATA_LENGTH],
+        };
+        let user_data = &data[2..data.len().min(PDU_LENGTH)];
+        pdu.user_data[..user_data.len()].copy_from_slice(user_data);
+        self.last_pdu = Some(pdu);
+
+        match self.state {
+            NmState::BusSleep => Some(NmEvent::NetworkStartIndication),
+            NmState::PrepareBusSleep => self.enter(NmState::RepeatMessage, now),
+            NmState::RepeatMessage => {
+                self.nm_timeout = Some(now + self.config.timeout_time);
+                None
+            }
+            NmState::NormalOperation | NmState::ReadySleep => {
+                self.nm_timeout = Some(now + self.config.timeout_time);
+                if pdu.control & CBV_REPEAT_MESSAGE_REQUEST == 0 {
+                    return None;
+                }
+                defmt::debug!("NM repeat message requested by node {}", pdu.node_id);
+                self.enter(NmState::RepeatMessage, now)
+            }
+        }
+    }
+
+    /// The next NM PDU to send, if any
+    pub fn next_frame<B: CanBuffer>(&mut self) -> Option<CanTxFrame<B>> {
+        let data = self.pending.take()?;
+        let mut frame = CanTxFrame::<B>::default();
+        frame.set_id(CanID::Standard(self.config.pdu_id()));
+        frame.set_data(&data);
+        Some(frame)
+    }
+
+    /// Send NM PDUs and check the timers, returns the next event, if any. Call this
+    /// until it returns None.
+    pub fn poll(&mut self, now: &Instant) -> Option<NmEvent> {
+        if self.next_transmission.is_some_and(|at| at <= *now) {
+            self.transmit(now);
+        }
+
+        match self.state {
+            NmState::BusSleep => None,
+            NmState::PrepareBusSleep => {
+                if self.bus_sleep_at.is_some_and(|at| at <= *now) {
+                    return self.enter(NmState::BusSleep, now);
+                }
+                None
+            }
+            NmState::RepeatMessage => {
+                if self.repeat_message_end.is_some_and(|end| end <= *now) {
+                    let state = if self.requested {
+                        NmState::NormalOperation
+                    } else {
+                        NmState::ReadySleep
+                    };
+                    return self.enter(state, now);
+                }
+                self.check_timeout(now);
+                None
+            }
+            NmState::NormalOperation => {
+                self.check_timeout(now);
+                None
+            }
+            NmState::ReadySleep => {
+                if self.nm_timeout.is_some_and(|timeout| timeout <= *now) {
+                    return self.enter(NmState::PrepareBusSleep, now);
+                }
+                None
+            }
+        }
+    }
+
+    /// Standard acceptance filter that stores the NM PDUs of all nodes in FIFO 0
+    pub fn acceptance_filter(&self) -> StandardFilter {
+        StandardFilter::range(
+            self.config.base_id,
+            self.config.base_id + 0xFF,
+            FilterAction::StoreFifo0,
+        )
+    }
+
+    /// The NM timeout is restarted while this node keeps the network awake, it only
+    /// expires when NM PDUs cannot be sent
+    fn check_timeout(&mut self, now: &Instant) {
+        if self.nm_timeout.is_some_and(|timeout| timeout <= *now) {
+            defmt::warn!("NM timeout while requesting the network");
+            self.nm_timeout = Some(now + self.config.timeout_time);
+        }
+    }
+
+    fn transmit(&mut self, now: &Instant) {
+        let mut data = [0; PDU_LENGTH];
+        data[0] = self.config.node_id;
+        data[1] = self.control;
+        data[2..].copy_from_slice(&self.user_data);
+        if self.pending.replace(data).is_some() {
+            defmt::warn!("NM PDU not sent in time, replacing it");
+        }
+
+        self.nm_timeout = Some(now + self.config.timeout_time);
+        self.next_transmission = self
+            .next_transmission
+            .map(|at| &at + self.config.message_cycle_time);
+    }
+
+    fn enter(&mut self, state: NmState, now: &Instant) -> Option<NmEvent> {
+        if state == self.state {
+            return None;
+        }
+        defmt::debug!("NM {} -> {}", self.state, state);
+
+        match state {
+            NmState::BusSleep => {
+                self.control = 0;
+                self.pending = None;
+                self.bus_sleep_at = None;
+            }
+            NmState::PrepareBusSleep => {
+                self.control = 0;
+                self.next_transmission = None;
+                self.nm_timeout = None;
+                self.repeat_message_end = None;
+                self.bus_sleep_at = Some(now + self.config.wait_bus_sleep_time);
+            }
+            NmState::RepeatMessage => {
+                self.repeat_message_end = Some(now + self.config.repeat_message_time);
+                self.nm_timeout = Some(now + self.config.timeout_time);
+                self.bus_sleep_at = None;
+                self.start_transmission(now);
+            }
+            NmState::NormalOperation => {
+                self.control &= !CBV_REPEAT_MESSAGE_REQUEST;
+                self.repeat_message_end = None;
+                self.start_transmission(now);
+            }
+            NmState::ReadySleep => {
+                self.control &= !CBV_REPEAT_MESSAGE_REQUEST;
+                self.repeat_message_end = None;
+                self.next_transmission = None;
+            }
+        }
+
+        self.state = state;
+        Some(NmEvent::StateChanged(state))
+    }
+
+    /// Keep the running message cycle or start a new one after the offset
+    fn start_transmission(&mut self, now: &Instant) {
+        if self.next_transmission.is_none() {
+            self.next_transmission = Some(now + self.config.message_cycle_offset);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//!
+//! Bitrate auto-detection for buses with an unknown configuration
+//!
+//! The node is put into bus monitoring mode, so probing never disturbs the bus: no
+//! acknowledges and no error frames are sent while cycling through the candidates.
+//!
+use core::time::Duration;
+
+use defmt::Format;
+
+use crate::{
+    can::{
+        memory::module_ram::CanBuffer,
+        timing::{CanBitrate, CanDataBitrate, Kbps},
+        CanModule,
+    },
+    time::Instant,
+};
+
+use super::{
+    connection::Connected, receive::RxFifo0, registers::NodeRegisters, CanNode, InConfiguration,
+};
+
+/// A bitrate configuration to probe the bus with
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub struct BitrateCandidate {
+    /// Nominal (arbitration phase) bitrate
+    pub nominal: Kbps,
+    /// Data phase bitrate, only set for CAN FD candidates with bitrate switching
+    pub data: Option<Kbps>,
+}
+
+impl BitrateCandidate {
+    /// A classic CAN candidate
+    pub const fn classic(nominal: Kbps) -> Self {
+        BitrateCandidate {
+            nominal,
+            data: None,
+        }
+    }
+
+    /// A CAN FD candidate with bitrate switching
+    pub const fn fd(nominal: Kbps, data: Kbps) -> Self {
+        BitrateCandidate {
+            nominal,
+            data: Some(data),
+        }
+    }
+}
+
+/// The usual automotive bitrates, classic CAN candidates are probed first
+pub const DEFAULT_CANDIDATES: [BitrateCandidate; 8] = [
+    BitrateCandidate::classic(Kbps::new(125)),
+    BitrateCandidate::classic(Kbps::new(250)),
+    BitrateCandidate::classic(Kbps::new(500)),
+    BitrateCandidate::classic(Kbps::new(1_000)),
+    BitrateCandidate::fd(Kbps::new(500), Kbps::new(2_000)),
+    BitrateCandidate::fd(Kbps::new(500), Kbps::new(4_000)),
+    BitrateCandidate::fd(Kbps::new(1_000), Kbps::new(4_000)),
+    BitrateCandidate::fd(Kbps::new(1_000), Kbps::new(5_000)),
+];
+
+impl<'r, 'mem, C: Connected, AnyTx, B: CanBuffer, M: CanModule>
+    CanNode<'r, C, InConfiguration, AnyTx, RxFifo0<'mem, B, M::RAM>, M>
+{
+    /// Cycle through the given candidates and return the first one for which a frame
+    /// was received without any protocol error (`PSR.LEC`/`PSR.DLEC`).
+    ///
+    /// Each candidate is listened to for at most `listen_window`; a candidate with a
+    /// data bitrate is only accepted once a frame with bitrate switching was received.
+    /// The node is left in configuration mode with the detected bitrate (or the last
+    /// candidate, if none matched) applied, frames received while probing are dropped.
+    pub fn detect_bitrate(
+        mut self,
+        candidates: &[BitrateCandidate],
+        listen_window: Duration,
+    ) -> (Self, Option<BitrateCandidate>) {
+        let was_monitoring = self.node.is_bus_monitoring();
+        self = self.set_bus_monitoring(true);
+
+        let mut detected = None;
+
+        for candidate in candidates {
+            let Ok(nominal) = CanBitrate::from_frequency(candidate.nominal) else {
+                defmt::warn!("Skipping unsupported candidate {}", candidate);
+                continue;
+            };
+
+            self = self.set_bitrate(&nominal);
+            self = match candidate.data.map(CanDataBitrate::from_frequency) {
+                None => self.disable_fd(),
+                Some(Ok(data)) => self.set_data_bitrate(&data),
+                Some(Err(())) => {
+                    defmt::warn!("Skipping unsupported candidate {}", candidate);
+                    continue;
+                }
+            };
+
+            if self.probe(candidate, listen_window) {
+                defmt::debug!("Detected bitrate {}", candidate);
+                detected = Some(*candidate);
+                break;
+            }
+        }
+
+        (self.set_bus_monitoring(was_monitoring), detected)
+    }
+
+    /// Leave configuration mode for at most `listen_window` and check whether the
+    /// bus traffic matches the current bit timing
+    fn probe(&mut self, candidate: &BitrateCandidate, listen_window: Duration) -> bool {
+        // Drop everything received with the previous candidate
+        while self.rx_fifo0_config.pop(self.node).is_some() {}
+
+        self.node.disable_init();
+
+        // Reading the status resets the last error codes
+        let _ = self.node.protocol_status();
+
+        let deadline = &Instant::now() + listen_window;
+        let mut verified = false;
+
+        while !verified && Instant::now() < deadline {
+            if self.node.protocol_status().indicates_protocol_error() {
+                defmt::trace!("Protocol error while probing {}", candidate);
+                break;
+            }
+
+            while let Some(frame) = self.rx_fifo0_config.pop(self.node) {
+                verified |=
+                    candidate.data.is_none() || (frame.is_fd_format() && frame.bitrate_switching());
+            }
+        }
+
+        // An error may have been flagged right after the last successful reception
+        let verified = verified && !self.node.protocol_status().indicates_protocol_error();
+
+        self.node.enable_init();
+
+        verified
+    }
+}
@@ -4,16 +4,14 @@
 //! by types
 use core::marker::PhantomData;
 
+#[cfg(not(feature = "sim"))]
 use tc37x_pac::{PORT_15, PORT_20};
+#[cfg(not(feature = "sim"))]
 use tc37x_rt::call_without_endinit;
 
 use crate::can::CanModule;
 
-use self::states::{
-    Connection, InternalBusConnected, InternalBusDisconnected, PinConnected, PinDisconnected,
-};
-
-use super::{CanNode, InConfiguration};
+use super::{registers::NodeRegisters, CanNode, InConfiguration};
 
 mod states {
     use super::CanPin;
@@ -53,7 +51,7 @@ impl<'r, P, R, T, M: CanModule>
     pub fn connect_internal_loopback(
         self,
     ) -> CanNode<'r, Connection<P, InternalBusConnected>, InConfiguration, R, T, M> {
-        self.node.npcr.modify(|_, w| w.lbm().bit(true));
+        self.node.connect_internal_loopback();
 
         CanNode {
             marker: PhantomData,
@@ -72,7 +70,7 @@ impl<'r, P: CanPin, I, R, T, M: CanModule>
         port_access: &<P as CanPin>::PeripheralPort,
     ) -> CanNode<'r, Connection<PinConnected<P>, I>, InConfiguration, R, T, M> {
         pin.setup_with(port_access);
-        self.node.npcr.modify(|_, w| w.rxsel().variant(pin.rxsel()));
+        self.node.select_rx_pin(pin.rxsel());
 
         CanNode {
             marker: PhantomData,
@@ -88,6 +86,7 @@ pub trait CanPin: Clone {
     fn rxsel(&self) -> u8;
 }
 
+#[cfg(not(feature = "sim"))]
 #[derive(Default, Clone, Copy)]
 pub enum Node0Pin {
     /// Receive Port P20.7, Transmit Port P20.8
@@ -95,6 +94,7 @@ pub enum Node0Pin {
     Rxdb = 0b001,
 }
 
+#[cfg(not(feature = "sim"))]
 impl CanPin for Node0Pin {
     type PeripheralPort = PORT_20;
     fn setup_with(&self, port_20: &PORT_20) {
@@ -116,6 +116,7 @@ impl CanPin for Node0Pin {
     }
 }
 
+#[cfg(not(feature = "sim"))]
 #[derive(Default, Clone, Copy)]
 pub enum Node1Pin {
     /// Receive Port P15.3, Transmit Port P15.2
@@ -123,6 +124,7 @@ pub enum Node1Pin {
     Rxda = 0,
 }
 
+#[cfg(not(feature = "sim"))]
 impl CanPin for Node1Pin {
     type PeripheralPort = PORT_15;
     fn setup_with(&self, port_15: &PORT_15) {
@@ -0,0 +1,317 @@
+//!
+//! Message RAM ECC error detection and reporting
+//!
+//! The message RAM is ECC protected: when the node reads a faulty word it flags a
+//! corrected single bit error (`IR.BEC`) or an uncorrectable error (`IR.BEU`), the
+//! memory controller of the RAM in the MTU raises the corresponding SMU alarm. An
+//! uncorrectable error puts the node into configuration mode, so it does not send
+//! corrupted data.
+//!
+//! [CanNode::check_ecc] reports the errors and applies the [UncorrectableEccPolicy].
+//! The error address and error injection are handled by the SRAM support hardware of
+//! the MTU, whose memory controller index depends on the device; the application
+//! provides it through [EccTracking], e.g. [MtuEccTracking].
+//!
+#[cfg(not(feature = "sim"))]
+use core::marker::PhantomData;
+
+use defmt::Format;
+#[cfg(not(feature = "sim"))]
+use tc37x_rt::wdtcon::{clear_safety_endinit, set_safety_endinit};
+
+#[cfg(not(feature = "sim"))]
+use crate::can::CanModuleRAM;
+use crate::{
+    can::CanModule,
+    reset::{software_reset, ResetType},
+};
+
+use super::{
+    registers::{Interrupt, NodeRegisters},
+    CanNode, InConfiguration, Running,
+};
+
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum EccErrorKind {
+    /// A single bit error was corrected, the data read was valid
+    Corrected,
+    /// The data read was invalid
+    Uncorrected,
+}
+
+/// A message RAM error reported by [CanNode::check_ecc]
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub struct EccError {
+    pub kind: EccErrorKind,
+    /// Address of the faulty word, if provided by the [EccTracking]
+    pub address: Option<u32>,
+}
+
+/// Outcome of [CanNode::check_ecc]
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum EccCheck {
+    /// The error was corrected or handled by the policy, the node keeps running
+    Handled(EccError),
+    /// An uncorrectable error with [UncorrectableEccPolicy::Escalate]: the node is in
+    /// configuration mode, take it over with [CanNode::reconfigure]
+    Escalate(EccError),
+}
+
+impl EccCheck {
+    pub fn error(&self) -> EccError {
+        match *self {
+            EccCheck::Handled(error) | EccCheck::Escalate(error) => error,
+        }
+    }
+}
+
+/// What to do once an uncorrectable error is detected
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum UncorrectableEccPolicy {
+    /// Leave configuration mode again, dropping pending transmissions and received
+    /// frames. The filters in the message RAM are not rewritten.
+    Reinitialize,
+    /// Keep the node in configuration mode, the application escalates the error, see
+    /// [EccCheck::Escalate]
+    Escalate,
+    /// Request a software reset
+    Reset(ResetType),
+}
+
+/// Error tracking and injection of the message RAM, usually the error tracking
+/// (`ETRR`) and ECC test registers of its memory controller in the MTU
+pub trait EccTracking {
+    /// Address of the oldest tracked error that was not taken yet
+    fn take_error_address(&mut self) -> Option<u32>;
+
+    /// Make the next read of the message RAM fail with the given kind of error, for
+    /// safety test campaigns. Returns false if injection is not supported.
+    fn inject_error(&mut self, kind: EccErrorKind) -> bool;
+}
+
+/// Without error tracking neither addresses nor injection are available
+impl EccTracking for () {
+    fn take_error_address(&mut self) -> Option<u32> {
+        None
+    }
+
+    fn inject_error(&mut self, _kind: EccErrorKind) -> bool {
+        false
+    }
+}
+
+/// Base address of the memory controller (SRAM support hardware) `x` of the MTU
+#[cfg(not(feature = "sim"))]
+const MC_BASE: usize = 0xF006_1000;
+#[cfg(not(feature = "sim"))]
+const MC_SIZE: usize = 0x100;
+
+/// ECC safety register (`MCx_ECCS`), 16 bits, safety ENDINIT protected
+#[cfg(not(feature = "sim"))]
+const ECCS: usize = 0x10;
+/// ECC enable (`ECCS.ECE`), writes while cleared keep the old check bits
+#[cfg(not(feature = "sim"))]
+const ECCS_ECE: u16 = 1 << 3;
+
+/// Memory ECC detection register (`MCx_ECCD`), 16 bits
+#[cfg(not(feature = "sim"))]
+const ECCD: usize = 0x12;
+/// Error tracking clear (`ECCD.TRC`)
+#[cfg(not(feature = "sim"))]
+const ECCD_TRC: u16 = 1 << 4;
+/// Valid bit of the first error tracking register (`ECCD.VAL`)
+#[cfg(not(feature = "sim"))]
+const ECCD_VAL0: u16 = 1 << 11;
+
+/// First error tracking register (`MCx_ETRR0`), holds the oldest error
+#[cfg(not(feature = "sim"))]
+const ETRR0: usize = 0x14;
+/// Faulty word address (`ETRR.ADDR`)
+#[cfg(not(feature = "sim"))]
+const ETRR_ADDR_MASK: u32 = 0x00FF_FFFF;
+
+/// [EccTracking] through the memory controller of the message RAM in the MTU
+///
+/// Errors are injected by rewriting one word of the message RAM with flipped bits
+/// while the ECC update is disabled (`ECCS.ECE`): the stored check bits still belong
+/// to the old value, so the next read of the word by the node fails. Pick a word the
+/// node reads soon and whose corruption is harmless, e.g. a filter element.
+#[cfg(not(feature = "sim"))]
+pub struct MtuEccTracking<R: CanModuleRAM> {
+    registers: usize,
+    /// In module offset of the word corrupted by [EccTracking::inject_error]
+    test_offset: u32,
+    marker: PhantomData<R>,
+}
+
+#[cfg(not(feature = "sim"))]
+impl<R: CanModuleRAM> MtuEccTracking<R> {
+    /// Use memory controller `index`, which has to be the one of the message RAM `R`
+    /// (see the MTU chapter of the user manual of the device)
+    pub fn new(index: u8, test_offset: u32) -> Self {
+        defmt::assert!(
+            test_offset as usize + 4 <= R::RAM_SIZE && test_offset.is_multiple_of(4),
+            "Invalid test word {:x}",
+            test_offset
+        );
+        MtuEccTracking {
+            registers: MC_BASE + index as usize * MC_SIZE,
+            test_offset,
+            marker: PhantomData,
+        }
+    }
+
+    fn read_eccd(&self) -> u16 {
+        unsafe { core::ptr::read_volatile((self.registers + ECCD) as *const u16) }
+    }
+}
+
+#[cfg(not(feature = "sim"))]
+impl<R: CanModuleRAM> EccTracking for MtuEccTracking<R> {
+    fn take_error_address(&mut self) -> Option<u32> {
+        if self.read_eccd() & ECCD_VAL0 == 0 {
+            return None;
+        }
+
+        let etrr = unsafe { core::ptr::read_volatile((self.registers + ETRR0) as *const u32) };
+        // Only one error is reported per interrupt, the tracking starts over
+        let eccd = self.registers + ECCD;
+        unsafe { core::ptr::write_volatile(eccd as *mut u16, self.read_eccd() | ECCD_TRC) };
+
+        Some(etrr & ETRR_ADDR_MASK)
+    }
+
+    fn inject_error(&mut self, kind: EccErrorKind) -> bool {
+        let word = unsafe { R::ram_location().add(self.test_offset as usize) } as *mut u32;
+        let flipped = match kind {
+            EccErrorKind::Corrected => 0b01,
+            EccErrorKind::Uncorrected => 0b11,
+        };
+        let eccs = (self.registers + ECCS) as *mut u16;
+
+        let value = unsafe { core::ptr::read_volatile(word) };
+        clear_safety_endinit();
+        unsafe {
+            let enabled = core::ptr::read_volatile(eccs);
+            core::ptr::write_volatile(eccs, enabled & !ECCS_ECE);
+            core::ptr::write_volatile(word, value ^ flipped);
+            R::data_sync();
+            core::ptr::write_volatile(eccs, enabled);
+        }
+        set_safety_endinit();
+
+        true
+    }
+}
+
+/// Counts the message RAM errors of a node, see [CanNode::check_ecc]
+pub struct EccMonitor<T: EccTracking> {
+    tracking: T,
+    policy: UncorrectableEccPolicy,
+    corrected: u32,
+    uncorrected: u32,
+}
+
+impl<T: EccTracking> EccMonitor<T> {
+    pub fn new(tracking: T, policy: UncorrectableEccPolicy) -> Self {
+        EccMonitor {
+            tracking,
+            policy,
+            corrected: 0,
+            uncorrected: 0,
+        }
+    }
+
+    pub fn corrected_count(&self) -> u32 {
+        self.corrected
+    }
+
+    pub fn uncorrected_count(&self) -> u32 {
+        self.uncorrected
+    }
+
+    pub fn policy(&self) -> UncorrectableEccPolicy {
+        self.policy
+    }
+
+    /// See [EccTracking::inject_error]
+    pub fn inject_error(&mut self, kind: EccErrorKind) -> bool {
+        defmt::info!("Injecting {} message RAM error", kind);
+        self.tracking.inject_error(kind)
+    }
+}
+
+impl<'r, AnyConnection, AnyTx, AnyRx, M: CanModule>
+    CanNode<'r, AnyConnection, InConfiguration, AnyTx, AnyRx, M>
+{
+    /// Enable the bit error corrected and uncorrected interrupts (`IE.BECE`,
+    /// `IE.BEUE`)
+    pub fn enable_ecc_interrupts(self) -> Self {
+        self.node.enable_interrupt(Interrupt::BitErrorCorrected);
+        self.node.enable_interrupt(Interrupt::BitErrorUncorrected);
+        self
+    }
+}
+
+impl<'r, AnyConnection, AnyTx, AnyRx, M: CanModule>
+    CanNode<'r, AnyConnection, Running, AnyTx, AnyRx, M>
+{
+    /// Report the next message RAM error flagged by the node, uncorrectable errors
+    /// first, and apply the policy of the monitor to those. Call this from the
+    /// interrupt (or regularly) until it returns None, e.g.
+    ///
+    /// ```ignore
+    /// while let Some(check) = node.check_ecc(&mut monitor) {
+    ///     if let EccCheck::Escalate(error) = check {
+    ///         return Err((node.reconfigure(), error));
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// The node enters configuration mode by itself on an uncorrectable error, with
+    /// [UncorrectableEccPolicy::Escalate] it is left there and must not be used as
+    /// running node any more.
+    pub fn check_ecc<T: EccTracking>(&self, monitor: &mut EccMonitor<T>) -> Option<EccCheck> {
+        let kind = if self
+            .node
+            .is_interrupt_pending(Interrupt::BitErrorUncorrected)
+        {
+            self.node.clear_interrupt(Interrupt::BitErrorUncorrected);
+            monitor.uncorrected = monitor.uncorrected.saturating_add(1);
+            EccErrorKind::Uncorrected
+        } else if self.node.is_interrupt_pending(Interrupt::BitErrorCorrected) {
+            self.node.clear_interrupt(Interrupt::BitErrorCorrected);
+            monitor.corrected = monitor.corrected.saturating_add(1);
+            EccErrorKind::Corrected
+        } else {
+            return None;
+        };
+
+        let error = EccError {
+            kind,
+            address: monitor.tracking.take_error_address(),
+        };
+
+        if kind == EccErrorKind::Corrected {
+            defmt::warn!("Corrected message RAM error {}", error);
+            return Some(EccCheck::Handled(error));
+        }
+
+        defmt::error!(
+            "Uncorrectable message RAM error {}, {}",
+            error,
+            monitor.policy
+        );
+        match monitor.policy {
+            UncorrectableEccPolicy::Reinitialize => {
+                // Entering configuration mode again drops the frames in the RAM
+                self.node.enable_init();
+                self.node.disable_init();
+            }
+            UncorrectableEccPolicy::Escalate => return Some(EccCheck::Escalate(error)),
+            UncorrectableEccPolicy::Reset(reset) => software_reset(reset),
+        }
+
+        Some(EccCheck::Handled(error))
+    }
+}
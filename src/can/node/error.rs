@@ -1,13 +1,11 @@
 //!
 //! Basic error register access for CAN
 //!
-use core::fmt::Debug;
-use defmt::{Debug2Format, Format};
-use tc37x_pac::can0::node::psr::ACT_A;
+use defmt::Format;
 
 use crate::can::CanModule;
 
-use super::{CanNode, Running};
+use super::{registers::NodeRegisters, CanNode, Running};
 
 #[derive(Format)]
 pub struct NodeErrorState {
@@ -16,55 +14,100 @@ pub struct NodeErrorState {
     protocol_status: ProtocolStatus,
 }
 
-struct DebugWrapper<T>(T);
-
-impl<T: Debug> Format for DebugWrapper<T> {
-    fn format(&self, fmt: defmt::Formatter) {
-        defmt::write!(fmt, "{}", Debug2Format(&self.0))
-    }
+/// Decoded `PSR.ACT` field
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum NodeActivity {
+    Synchronizing,
+    Idle,
+    Receiver,
+    Transmitter,
 }
 
 #[derive(Format)]
 pub struct ProtocolStatus {
-    activity: DebugWrapper<ACT_A>,
+    activity: NodeActivity,
     /// Indicates if at least one of error counter (REC/TEC) has reached the Error_Warning limit of 96
     warning_status: bool,
     in_error_passive: bool,
     bus_is_off: bool,
+    /// Last error in the arbitration phase (or the whole frame for classic CAN)
+    last_error_code: LastErrorCode,
+    /// Last error in the data phase of a CAN FD frame with bitrate switching
+    data_phase_last_error_code: LastErrorCode,
+}
+
+/// Decoded `PSR.LEC`/`PSR.DLEC` fields
+///
+/// Reading the `PSR` register resets both fields to [LastErrorCode::NoChange], hence
+/// a value different from that reports errors since the last read.
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum LastErrorCode {
+    NoError,
+    StuffError,
+    FormError,
+    AckError,
+    Bit1Error,
+    Bit0Error,
+    CrcError,
+    NoChange,
+}
+
+impl LastErrorCode {
+    /// Decode the 3 bit field value
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0b111 {
+            0 => LastErrorCode::NoError,
+            1 => LastErrorCode::StuffError,
+            2 => LastErrorCode::FormError,
+            3 => LastErrorCode::AckError,
+            4 => LastErrorCode::Bit1Error,
+            5 => LastErrorCode::Bit0Error,
+            6 => LastErrorCode::CrcError,
+            _ => LastErrorCode::NoChange,
+        }
+    }
+
+    /// Whether this code reports an actual protocol error
+    pub fn is_error(&self) -> bool {
+        !matches!(self, LastErrorCode::NoError | LastErrorCode::NoChange)
+    }
 }
 
-impl<'r, 'mem, AnyConnection, AnyTx, AnyRx, M: CanModule>
+impl<'r, AnyConnection, AnyTx, AnyRx, M: CanModule>
     CanNode<'r, AnyConnection, Running, AnyTx, AnyRx, M>
 {
     pub fn clear_error(&self) -> NodeErrorState {
-        let psr_value = self.node.psr.read();
-        let ecr_value = self.node.ecr.read();
+        let (transmit_error_counter, receive_error_counter) = self.node.error_counters();
 
         NodeErrorState {
-            transmit_error_counter: ecr_value.tec().bits(),
-            receive_error_counter: ecr_value.rec().bits(),
-            protocol_status: ProtocolStatus::from_register_values(
-                psr_value.act().variant(),
-                psr_value.ew().bit(),
-                psr_value.ep().bit(),
-                psr_value.bo().bit(),
-            ),
+            transmit_error_counter,
+            receive_error_counter,
+            protocol_status: self.node.protocol_status(),
         }
     }
 }
 
 impl ProtocolStatus {
     pub fn from_register_values(
-        activity: ACT_A,
+        activity: u8,
         warning_status: bool,
         in_error_passive: bool,
         bus_is_off: bool,
+        last_error_code: u8,
+        data_phase_last_error_code: u8,
     ) -> Self {
         Self {
-            activity: DebugWrapper(activity),
+            activity: match activity & 0b11 {
+                0b00 => NodeActivity::Synchronizing,
+                0b01 => NodeActivity::Idle,
+                0b10 => NodeActivity::Receiver,
+                _ => NodeActivity::Transmitter,
+            },
             warning_status,
             in_error_passive,
             bus_is_off,
+            last_error_code: LastErrorCode::from_bits(last_error_code),
+            data_phase_last_error_code: LastErrorCode::from_bits(data_phase_last_error_code),
         }
     }
 
@@ -72,4 +115,25 @@ impl ProtocolStatus {
     pub fn indicates_error(&self) -> bool {
         self.warning_status || self.in_error_passive || self.bus_is_off
     }
+
+    /// Check whether a protocol error occurred since the last status read
+    pub fn indicates_protocol_error(&self) -> bool {
+        self.last_error_code.is_error() || self.data_phase_last_error_code.is_error()
+    }
+
+    pub fn activity(&self) -> NodeActivity {
+        self.activity
+    }
+
+    pub fn bus_is_off(&self) -> bool {
+        self.bus_is_off
+    }
+
+    pub fn last_error_code(&self) -> LastErrorCode {
+        self.last_error_code
+    }
+
+    pub fn data_phase_last_error_code(&self) -> LastErrorCode {
+        self.data_phase_last_error_code
+    }
 }
@@ -0,0 +1,150 @@
+//!
+//! Acceptance filter configuration for a CAN module
+//!
+use defmt::Format;
+
+use crate::can::{
+    memory::{
+        filter::{ExtendedFilter, StandardFilter},
+        module_ram::NodeMemory,
+    },
+    CanModule, CanModuleRAM,
+};
+
+use super::{registers::NodeRegisters, CanNode, InConfiguration};
+
+/// Extended id filter elements that can be changed while the node is running, see
+/// [CanNode::set_extended_filter_table]
+///
+/// The list size is fixed in configuration, unused elements are disabled.
+pub struct ExtendedFilterTable<'mem, M: CanModuleRAM> {
+    memory: NodeMemory<'mem, ExtendedFilter, M>,
+}
+
+impl<'mem, M: CanModuleRAM> ExtendedFilterTable<'mem, M> {
+    /// Number of filter elements
+    pub fn elements(&self) -> u8 {
+        self.memory.elements()
+    }
+
+    /// Replace the filter element at the given index, it applies to the frames
+    /// received afterwards
+    pub fn set(&mut self, index: u8, filter: ExtendedFilter) {
+        defmt::trace!("Extended filter {}: {}", index, filter);
+        // # Safety
+        // The memory is owned by the table, the hardware only reads it
+        let dst = unsafe { self.memory.get(index) }.expect("Filter index out of range");
+        unsafe { filter.write_volatile(dst) };
+    }
+
+    /// Replace all filter elements, the elements after the given filters are disabled
+    pub fn set_all(&mut self, filters: &[ExtendedFilter]) {
+        defmt::assert!(
+            filters.len() <= self.elements() as usize,
+            "Not enough memory for {} filters",
+            filters.len()
+        );
+
+        for index in 0..self.elements() {
+            let filter = filters.get(index as usize).copied().unwrap_or_default();
+            self.set(index, filter);
+        }
+    }
+}
+
+/// What happens to frames that do not match any filter element (`GFC.ANFS`/`GFC.ANFE`)
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum NonMatchingFrames {
+    AcceptFifo0,
+    AcceptFifo1,
+    Reject,
+}
+
+impl<'r, AnyConnection, AnyTx, AnyRx, M: CanModule>
+    CanNode<'r, AnyConnection, InConfiguration, AnyTx, AnyRx, M>
+{
+    /// Copy the standard id filters into the given memory and enable them
+    pub fn set_standard_filters(
+        self,
+        memory: NodeMemory<'_, StandardFilter, M::RAM>,
+        filters: &[StandardFilter],
+    ) -> Self {
+        defmt::assert!(
+            filters.len() <= memory.elements() as usize,
+            "Not enough memory for {} filters",
+            filters.len()
+        );
+
+        for (index, filter) in filters.iter().enumerate() {
+            defmt::trace!("Standard filter {}: {}", index, filter);
+            // # Safety
+            // Index is in range and the memory is not referenced anywhere else
+            let dst = unsafe { memory.get(index as u8) }.unwrap();
+            unsafe { core::ptr::write_volatile(dst as *mut StandardFilter, *filter) };
+        }
+
+        self.node
+            .set_standard_filter_list(memory.in_module_offset() as u16, filters.len() as u8);
+        self
+    }
+
+    /// Copy the extended id filters into the given memory and enable them
+    pub fn set_extended_filters(
+        self,
+        memory: NodeMemory<'_, ExtendedFilter, M::RAM>,
+        filters: &[ExtendedFilter],
+    ) -> Self {
+        defmt::assert!(
+            filters.len() <= memory.elements() as usize,
+            "Not enough memory for {} filters",
+            filters.len()
+        );
+
+        for (index, filter) in filters.iter().enumerate() {
+            defmt::trace!("Extended filter {}: {}", index, filter);
+            // # Safety
+            // Index is in range and the memory is not referenced anywhere else
+            let dst = unsafe { memory.get(index as u8) }.unwrap();
+            unsafe { filter.write_volatile(dst) };
+        }
+
+        self.node
+            .set_extended_filter_list(memory.in_module_offset() as u16, filters.len() as u8);
+        self
+    }
+
+    /// Reserve the memory for extended id filters that are set while the node is
+    /// running, all elements start disabled
+    pub fn set_extended_filter_table<'mem>(
+        self,
+        memory: NodeMemory<'mem, ExtendedFilter, M::RAM>,
+    ) -> (Self, ExtendedFilterTable<'mem, M::RAM>) {
+        let mut table = ExtendedFilterTable { memory };
+        table.set_all(&[]);
+
+        self.node.set_extended_filter_list(
+            table.memory.in_module_offset() as u16,
+            table.memory.elements(),
+        );
+        (self, table)
+    }
+
+    /// Mask applied to extended ids before range filtering (`XIDAM`), defaults to all ones
+    pub fn set_extended_id_mask(self, mask: u32) -> Self {
+        self.node.set_extended_id_mask(mask);
+        self
+    }
+
+    /// Decide what happens to frames that did not match any filter element
+    ///
+    /// Note that [set_rx_fifo0](CanNode::set_rx_fifo0) accepts all standard frames in
+    /// FIFO 0, so this must be called afterwards
+    pub fn set_non_matching_frames(
+        self,
+        standard: NonMatchingFrames,
+        extended: NonMatchingFrames,
+    ) -> Self {
+        self.node.set_non_matching_frames(standard, extended);
+        self
+    }
+}
@@ -1,15 +1,31 @@
 use core::marker::PhantomData;
 
+#[cfg(not(feature = "sim"))]
 use tc37x_pac::{can0, CAN0};
-use tc37x_rt::block_while_nops;
 
-use self::connection::{Connected, DefaultDisconnected};
+use self::{
+    connection::{CanPin, Connected, DefaultDisconnected},
+    registers::NodeRegisters,
+};
 
-use super::{timing::CanBitrate, CanModule};
+use super::{
+    timing::{CanBitrate, CanDataBitrate},
+    CanModule,
+};
 
+pub mod bitrate_detection;
 pub mod connection;
+pub mod ecc;
 pub mod error;
+pub mod filter;
+pub mod options;
+pub mod priority;
 pub mod receive;
+pub mod registers;
+#[cfg(not(feature = "sim"))]
+pub mod sleep;
+pub mod split;
+pub mod statistics;
 pub mod transceive;
 
 /// Generalized node over supported implementations based on [`NodeInstance`], with basic
@@ -20,7 +36,7 @@ pub mod transceive;
 /// when we have async, so we can use our own HAL and only support that
 pub struct CanNode<'r, Connection, S, TxConfig, RxConfig, M: CanModule> {
     /// The node of the can module
-    node: &'r can0::NODE,
+    node: &'r M::Node,
     /// Configuration related to transceiving
     tx_dedicated_config: TxConfig,
     /// Configuration related to receiving
@@ -29,7 +45,29 @@ pub struct CanNode<'r, Connection, S, TxConfig, RxConfig, M: CanModule> {
     marker: PhantomData<(Connection, S, M)>,
 }
 
-impl<'r, M: CanModule>
+impl<'r, P: CanPin, M: CanModule>
+    CanNode<'r, DefaultDisconnected<P>, InConfiguration, transceive::NoTx, receive::NoRx, M>
+{
+    /// Take over the given node registers and put the node into configuration mode
+    pub(crate) fn from_registers(node: &'r M::Node) -> Self {
+        if node.is_in_init() {
+            defmt::warn!("Node appears to be in configuration mode already, resetting node");
+            node.disable_init();
+        }
+
+        node.enable_init();
+
+        Self {
+            node,
+            tx_dedicated_config: transceive::NoTx,
+            rx_fifo0_config: receive::NoRx,
+            marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(not(feature = "sim"))]
+impl<'r, M: CanModule<Node = can0::NODE>>
     CanNode<
         'r,
         DefaultDisconnected<connection::Node0Pin>,
@@ -40,23 +78,12 @@ impl<'r, M: CanModule>
     >
 {
     pub(super) fn node0(can0: &'r CAN0) -> Self {
-        if can0.node0().cccr.read().init().bit_is_set() {
-            defmt::warn!("Node 0 appears to be in configuration mode already, resetting node");
-            can0.node0().disable_init();
-        }
-
-        can0.node0().enable_init();
-
-        Self {
-            node: can0.node0(),
-            tx_dedicated_config: transceive::NoTx,
-            rx_fifo0_config: receive::NoRx,
-            marker: PhantomData,
-        }
+        Self::from_registers(can0.node0())
     }
 }
 
-impl<'r, M: CanModule>
+#[cfg(not(feature = "sim"))]
+impl<'r, M: CanModule<Node = can0::NODE>>
     CanNode<
         'r,
         DefaultDisconnected<connection::Node1Pin>,
@@ -67,19 +94,7 @@ impl<'r, M: CanModule>
     >
 {
     pub(super) fn node1(can0: &'r CAN0) -> Self {
-        if can0.node1.cccr.read().init().bit_is_set() {
-            defmt::warn!("Node 1 appears to be in configuration mode already, resetting node");
-            can0.node1.disable_init();
-        }
-
-        can0.node1.enable_init();
-
-        Self {
-            node: &can0.node1,
-            tx_dedicated_config: transceive::NoTx,
-            rx_fifo0_config: receive::NoRx,
-            marker: PhantomData,
-        }
+        Self::from_registers(&can0.node1)
     }
 }
 
@@ -100,22 +115,83 @@ impl<'r, C: Connected, AnyRx, AnyTx, M: CanModule>
     }
 }
 
+impl<'r, C: Connected, AnyRx, AnyTx, M: CanModule> CanNode<'r, C, Running, AnyRx, AnyTx, M> {
+    /// Go back to configuration mode (set INIT & CCE), e.g. to change the bitrate.
+    /// Pending transmissions and unread frames are dropped, the memory setup is kept.
+    pub fn reconfigure(self) -> CanNode<'r, C, InConfiguration, AnyRx, AnyTx, M> {
+        self.node.enable_init();
+
+        CanNode {
+            marker: PhantomData,
+            ..self
+        }
+    }
+
+    /// Request power-down by stopping the clock (`CCCR.CSR`)
+    ///
+    /// The node finishes pending transmissions and waits for the bus to become idle
+    /// before it stops, check [CanNode::is_powered_down] before switching off the
+    /// module clock.
+    pub fn power_down(self) -> CanNode<'r, C, PowerDown, AnyRx, AnyTx, M> {
+        self.node.set_clock_stop_request(true);
+
+        CanNode {
+            marker: PhantomData,
+            ..self
+        }
+    }
+}
+
+impl<'r, C: Connected, AnyRx, AnyTx, M: CanModule> CanNode<'r, C, PowerDown, AnyRx, AnyTx, M> {
+    /// Whether the node stopped (`CCCR.CSA`), it is in configuration mode then
+    pub fn is_powered_down(&self) -> bool {
+        self.node.is_clock_stop_acknowledged()
+    }
+
+    /// Release the clock stop and take part in bus traffic again
+    pub fn wake_up(self) -> CanNode<'r, C, Running, AnyRx, AnyTx, M> {
+        self.node.set_clock_stop_request(false);
+        while self.node.is_clock_stop_acknowledged() {}
+        // The node stays in configuration mode after the clock stop
+        self.node.disable_init();
+
+        CanNode {
+            marker: PhantomData,
+            ..self
+        }
+    }
+}
+
 impl<'r, AnyConnection, AnyRx, AnyTx, M: CanModule>
     CanNode<'r, AnyConnection, InConfiguration, AnyRx, AnyTx, M>
 {
     /// Set already correctly computed bitrate
     pub fn set_bitrate(self, cfg: &CanBitrate) -> Self {
         defmt::trace!("Using bitrate configuration {}", cfg);
-        self.node.nbtp.modify(|_, w| {
-            w.nsjw()
-                .variant(cfg.sync_jump_width() - 1)
-                .ntseg1()
-                .variant(cfg.tseg1() - 1)
-                .ntseg2()
-                .variant(cfg.tseg2() - 1)
-                .nbrp()
-                .variant(cfg.pre_scaler() - 1)
-        });
+        self.node.set_nominal_bit_timing(cfg);
+        self
+    }
+
+    /// Set already correctly computed data phase bitrate, this enables CAN FD operation
+    /// with bitrate switching and, from 1 Mbit/s on, the transmitter delay compensation
+    /// (see [CanDataBitrate::delay_compensation_offset])
+    pub fn set_data_bitrate(self, cfg: &CanDataBitrate) -> Self {
+        defmt::trace!("Using data bitrate configuration {}", cfg);
+        self.node.set_data_bit_timing(cfg);
+        self.node.set_fd_operation(true);
+        self
+    }
+
+    /// Go back to classic CAN operation (disables CAN FD and bitrate switching)
+    pub fn disable_fd(self) -> Self {
+        self.node.set_fd_operation(false);
+        self
+    }
+
+    /// Enable or disable bus monitoring mode: the node only listens to the bus, it
+    /// neither acknowledges frames nor sends error frames
+    pub fn set_bus_monitoring(self, enabled: bool) -> Self {
+        self.node.set_bus_monitoring(enabled);
         self
     }
 }
@@ -126,41 +202,5 @@ pub struct InConfiguration;
 /// Running state
 pub struct Running;
 
-pub trait NodeExt {
-    /// Helper method to put the node into configuration mode (set INIT & CCE flags)
-    fn enable_init(&self);
-
-    /// Helper method to enable the node operation (clear INIT & CCE flags)
-    fn disable_init(&self);
-}
-
-impl NodeExt for can0::NODE {
-    fn enable_init(&self) {
-        // Clear Init
-        while self.cccr.read().init().bit_is_clear() {
-            self.cccr.modify(|_, w| w.init().set_bit());
-        }
-        // Clear CCE
-        while self.cccr.read().cce().bit_is_clear() {
-            self.cccr.modify(|_, w| w.cce().set_bit());
-        }
-    }
-
-    fn disable_init(&self) {
-        // Clear CCE
-        block_while_nops!(
-            {
-                self.cccr.modify(|_, w| w.cce().clear_bit());
-                self.cccr.read().cce().bit_is_clear()
-            },
-            "Cannot clear cce flag"
-        );
-        block_while_nops!(
-            {
-                self.cccr.modify(|_, w| w.init().clear_bit());
-                self.cccr.read().init().bit_is_clear()
-            },
-            "Cannot clear init flag"
-        );
-    }
-}
+/// Power-down state, see [CanNode::power_down]
+pub struct PowerDown;
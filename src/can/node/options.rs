@@ -0,0 +1,62 @@
+//!
+//! Protocol options of a CAN node (`CCCR`)
+//!
+use defmt::Format;
+
+use crate::can::CanModule;
+
+use super::{registers::NodeRegisters, CanNode, InConfiguration};
+
+/// Protocol related options of a node, applied through [CanNode::set_protocol_options]
+///
+/// [Default] yields the reset configuration of the hardware
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub struct NodeProtocolOptions {
+    /// Retransmit frames that lost arbitration or were disturbed by an error (`CCCR.DAR`
+    /// cleared). Disabling this yields single-shot transmission, see
+    /// [TransmitStatus](super::transceive::TransmitStatus) for the outcome of a frame.
+    pub automatic_retransmission: bool,
+    /// Pause for two bit times after each successful transmission (`CCCR.TXP`)
+    pub transmit_pause: bool,
+    /// Require two consecutive dominant time quanta to detect an edge for hard
+    /// synchronization (`CCCR.EFBI`)
+    pub edge_filtering: bool,
+    /// Enter bus integration state when a protocol exception event is detected on a
+    /// CAN FD frame (`CCCR.PXHD` cleared)
+    pub protocol_exception_handling: bool,
+    /// Use 16 bit message markers instead of 8 bit ones (`CCCR.WMM`)
+    pub wide_message_markers: bool,
+}
+
+impl Default for NodeProtocolOptions {
+    fn default() -> Self {
+        NodeProtocolOptions {
+            automatic_retransmission: true,
+            transmit_pause: false,
+            edge_filtering: false,
+            protocol_exception_handling: true,
+            wide_message_markers: false,
+        }
+    }
+}
+
+impl NodeProtocolOptions {
+    /// The reset configuration, but without automatic retransmission
+    pub fn single_shot() -> Self {
+        NodeProtocolOptions {
+            automatic_retransmission: false,
+            ..Default::default()
+        }
+    }
+}
+
+impl<'r, AnyConnection, AnyTx, AnyRx, M: CanModule>
+    CanNode<'r, AnyConnection, InConfiguration, AnyTx, AnyRx, M>
+{
+    /// Apply protocol options, these are only writable while the node is in configuration
+    pub fn set_protocol_options(self, options: &NodeProtocolOptions) -> Self {
+        defmt::trace!("Using protocol options {}", options);
+        self.node.set_protocol_options(options);
+        self
+    }
+}
@@ -0,0 +1,79 @@
+//!
+//! High priority message handling for a CAN module
+//!
+//! Frames matching a filter element with one of the "set priority" actions (see
+//! [FilterAction](crate::can::memory::filter::FilterAction)) are recorded in `HPMS`
+//! and flagged through `IR.HPM`, so they can be serviced before the ordinary traffic.
+//!
+use defmt::Format;
+
+use crate::can::CanModule;
+
+use super::{
+    registers::{Interrupt, NodeRegisters},
+    CanNode, InConfiguration, Running,
+};
+
+/// Where a high priority message was stored (`HPMS.MSI`)
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum MessageStorage {
+    /// The filter only set the priority, the frame was not stored
+    NoFifo,
+    /// The frame was to be stored, but the FIFO was full
+    FifoMessageLost,
+    Fifo0,
+    Fifo1,
+}
+
+/// The filter list that matched a high priority message (`HPMS.FLST`)
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum FilterList {
+    Standard,
+    Extended,
+}
+
+/// Status of the latest high priority message (`HPMS`)
+#[derive(Format, Clone, Copy)]
+pub struct HighPriorityMessage {
+    /// Index of the FIFO element the frame was stored in, only valid for
+    /// [MessageStorage::Fifo0] and [MessageStorage::Fifo1]
+    pub buffer_index: u8,
+    pub storage: MessageStorage,
+    /// Index of the matching filter element within [HighPriorityMessage::filter_list]
+    pub filter_index: u8,
+    pub filter_list: FilterList,
+}
+
+impl<'r, AnyConnection, AnyTx, AnyRx, M: CanModule>
+    CanNode<'r, AnyConnection, InConfiguration, AnyTx, AnyRx, M>
+{
+    /// Enable the high priority message interrupt (`IE.HPME`)
+    pub fn enable_high_priority_interrupt(self) -> Self {
+        self.node.enable_interrupt(Interrupt::HighPriorityMessage);
+        self
+    }
+}
+
+impl<'r, AnyConnection, AnyTx, AnyRx, M: CanModule>
+    CanNode<'r, AnyConnection, Running, AnyTx, AnyRx, M>
+{
+    /// Return the status of the latest high priority message, if one was flagged since
+    /// the last call. Only the latest message is reported by the hardware.
+    pub fn take_high_priority_message(&self) -> Option<HighPriorityMessage> {
+        if !self
+            .node
+            .is_interrupt_pending(Interrupt::HighPriorityMessage)
+        {
+            return None;
+        }
+
+        // Clear the flag before reading the status, a message arriving in between
+        // raises it again instead of being lost
+        self.node.clear_interrupt(Interrupt::HighPriorityMessage);
+
+        let message = self.node.high_priority_message_status();
+
+        defmt::trace!("High priority message {}", message);
+        Some(message)
+    }
+}
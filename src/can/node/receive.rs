@@ -1,17 +1,24 @@
 //!
 //! Receive side for a CAN module
 //!
-use tc37x_pac::can0::node::gfc;
+use core::marker::PhantomData;
+
+use defmt::Format;
 
 use crate::can::{
     memory::{
         module_ram::{CanBuffer, NodeMemory},
-        rx::CanRxFrame,
+        rx::{CanRxFrame, RxFrameHeader},
     },
-    CanModule,
+    CanModule, CanModuleRAM,
 };
 
-use super::{connection::Connected, CanNode, InConfiguration, Running};
+use super::{
+    connection::Connected,
+    filter::NonMatchingFrames,
+    registers::{FifoBehavior, Interrupt, NodeRegisters},
+    CanNode, InConfiguration, Running,
+};
 
 mod states {
     use crate::can::{
@@ -39,16 +46,17 @@ impl<'r, 'mem, AnyConnection, AnyTx, M: CanModule>
         self,
         memory: NodeMemory<'mem, CanRxFrame<B>, M::RAM>,
     ) -> CanNode<'r, AnyConnection, InConfiguration, AnyTx, RxFifo0<'mem, B, M::RAM>, M> {
-        self.node.set_buffer_dimension::<B>(
+        self.node.set_rx_fifo0::<B>(
             memory.in_module_offset() as u16,
             memory.elements(),
             FifoBehavior::Blocking,
         );
 
         // When implementing fifo1 we need to refactor this to somewhere else
-        self.node
-            .gfc
-            .modify(|_, w| w.anfs().variant(gfc::ANFS_A::ACCEPT_FIFO0));
+        self.node.set_non_matching_frames(
+            NonMatchingFrames::AcceptFifo0,
+            NonMatchingFrames::AcceptFifo0,
+        );
 
         CanNode {
             rx_fifo0_config: RxFifo0 { memory },
@@ -57,25 +65,138 @@ impl<'r, 'mem, AnyConnection, AnyTx, M: CanModule>
     }
 }
 
+impl<'r, 'mem, AnyConnection, B: CanBuffer, AnyTx, M: CanModule>
+    CanNode<'r, AnyConnection, InConfiguration, AnyTx, RxFifo0<'mem, B, M::RAM>, M>
+{
+    /// Enable the FIFO 0 new message interrupt (`IE.RF0NE`)
+    pub fn enable_rx_fifo0_interrupt(self) -> Self {
+        self.node.enable_interrupt(Interrupt::RxFifo0NewMessage);
+        self
+    }
+}
+
 impl<'r, 'mem, C: Connected, B: CanBuffer, AnyTx, M: CanModule>
     CanNode<'r, C, Running, AnyTx, RxFifo0<'mem, B, M::RAM>, M>
 {
+    /// Clear the FIFO 0 new message flag, returning whether it was set. Clear it before
+    /// draining the fifo, a frame arriving in between raises it again.
+    pub fn take_rx_fifo0_interrupt(&self) -> bool {
+        take_rx_fifo0_interrupt(self.node)
+    }
+
     /// This will try to fetch a packet from the FIFO_0, returning None if no
     /// packets have been received
     pub fn try_receive_fifo0(&mut self) -> Option<CanRxFrame<B>> {
+        self.rx_fifo0_config.pop(self.node)
+    }
+
+    /// Iterate over all frames currently available in FIFO 0, acknowledging them with a
+    /// single register write once the iterator is dropped
+    ///
+    /// Frames that are not consumed from the iterator are still acknowledged if a later
+    /// frame was consumed
+    pub fn drain_fifo0(&mut self) -> Fifo0Drain<'_, 'mem, B, M> {
+        self.rx_fifo0_config.drain(self.node)
+    }
+
+    /// Borrow the oldest frame in FIFO 0 in place, returning None if no packets have
+    /// been received. The frame is acknowledged when the lease is dropped.
+    ///
+    /// As the lease mutably borrows the node, only one lease can exist at a time and
+    /// frames are released in order.
+    pub fn lease_fifo0(&mut self) -> Option<RxFrameLease<'_, B, M>> {
+        self.rx_fifo0_config.lease(self.node)
+    }
+
+    /// Copy as many available frames as fit into `frames`, returning the number of
+    /// frames copied. All of them are acknowledged at once.
+    pub fn receive_fifo0_into(&mut self, frames: &mut [CanRxFrame<B>]) -> usize {
+        self.rx_fifo0_config.receive_into::<M>(self.node, frames)
+    }
+}
+
+/// Clear the FIFO 0 new message flag, returning whether it was set
+pub(super) fn take_rx_fifo0_interrupt<N: NodeRegisters>(node: &N) -> bool {
+    if !node.is_interrupt_pending(Interrupt::RxFifo0NewMessage) {
+        return false;
+    }
+    node.clear_interrupt(Interrupt::RxFifo0NewMessage);
+    true
+}
+
+impl<'a, B: CanBuffer, M: CanModuleRAM> RxFifo0<'a, B, M> {
+    /// Copy the oldest frame out of the fifo and acknowledge it, returning None if
+    /// the fifo is empty
+    pub(super) fn pop<N: NodeRegisters>(&mut self, node: &N) -> Option<CanRxFrame<B>> {
         // Do we have a message to read?
-        if self.node.rx_fifo0_fill_level() == 0 {
+        if node.rx_fifo0_fill_level() == 0 {
             return None;
         }
 
         // Get the buffer index to read (shall be < 32, else we panic.. we asserted that
         // during configuration)... temporary to avoid working with 2 registers
-        let index = self.node.rx_fifo0_index();
+        let index = node.rx_fifo0_index();
+
+        let frame = self.read(index);
+
+        // Ack the data and return frame
+        node.rx_fifo0_ack_index(index);
+        Some(frame)
+    }
+
+    pub(super) fn drain<'f, Module: CanModule<RAM = M>>(
+        &'f self,
+        node: &'f Module::Node,
+    ) -> Fifo0Drain<'f, 'a, B, Module> {
+        Fifo0Drain {
+            node,
+            fifo: self,
+            remaining: node.rx_fifo0_fill_level(),
+            next_index: node.rx_fifo0_index(),
+            last_index: None,
+        }
+    }
 
-        // Buffer slot & read the frame
-        let rx_buffer = &mut self.rx_fifo0_config.memory;
+    pub(super) fn lease<'f, Module: CanModule<RAM = M>>(
+        &'f mut self,
+        node: &'f Module::Node,
+    ) -> Option<RxFrameLease<'f, B, Module>> {
+        if node.rx_fifo0_fill_level() == 0 {
+            return None;
+        }
 
-        let src = unsafe { rx_buffer.get(index) }
+        let index = node.rx_fifo0_index();
+        let element = unsafe { self.memory.get(index) }
+            .expect("Buffer out of range (again, shall not happen with proper configuration)");
+
+        Some(RxFrameLease {
+            node,
+            element: element as *const CanRxFrame<B> as *const u32,
+            index,
+            marker: PhantomData,
+        })
+    }
+
+    pub(super) fn receive_into<Module: CanModule<RAM = M>>(
+        &mut self,
+        node: &Module::Node,
+        frames: &mut [CanRxFrame<B>],
+    ) -> usize {
+        let mut drain = self.drain::<Module>(node);
+        let mut count = 0;
+
+        // `frames` goes first, so no frame is taken from the fifo without a slot for it
+        for (dst, frame) in frames.iter_mut().zip(&mut drain) {
+            *dst = frame;
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Copy the frame at the given index out of the module ram
+    fn read(&self, index: u8) -> CanRxFrame<B> {
+        let src = unsafe { self.memory.get(index) }
             .expect("Buffer out of range (again, shall not happen with proper configuration)");
 
         // BUG: We are having trouble to make 64 bit reads from can0, to avoid that we
@@ -86,91 +207,138 @@ impl<'r, 'mem, C: Connected, B: CanBuffer, AnyTx, M: CanModule>
             "Received message {} at index {} in {}",
             frame,
             index,
-            rx_buffer
+            self.memory
         );
 
-        // Ack the data and return frame
-        self.node.rx_fifo0_ack_index(index);
-        Some(frame)
+        frame
     }
 }
 
-mod fif0_helpers {
-    use crate::can::memory::module_ram::CanBuffer;
-
-    pub trait FifoState {
-        /// Obtain the numbers of available can messages in the fifo
-        fn rx_fifo0_fill_level(&self) -> u8;
-
-        /// Obtain the index of the next to be fetched can frame
-        ///
-        /// This should usually be paired with a call to `rx_fifo0_fill_level`
-        /// before actually reading the memory in the module ram from this address
-        fn rx_fifo0_index(&self) -> u8;
-
-        /// Acknowledge all frames up until the given level
-        fn rx_fifo0_ack_index(&self, index: u8);
-
-        /// Sets up fifo0.
-        ///
-        /// This will
-        fn set_buffer_dimension<B: CanBuffer>(
-            &self,
-            in_module_offset: u16,
-            buffer_count: u8,
-            buffer_full_behavior: FifoBehavior,
-        );
+/// Iterator over the frames that were available in FIFO 0 when it was created, see
+/// [CanNode::drain_fifo0]
+///
+/// Frames are acknowledged all at once when the iterator is dropped, so the hardware
+/// does not reuse their slots while the iterator is alive
+pub struct Fifo0Drain<'a, 'mem, B: CanBuffer, M: CanModule> {
+    node: &'a M::Node,
+    fifo: &'a RxFifo0<'mem, B, M::RAM>,
+    /// Index of the next frame to read
+    next_index: u8,
+    /// Number of frames still to be read
+    remaining: u8,
+    /// Index of the last frame read, if any
+    last_index: Option<u8>,
+}
+
+impl<'a, 'mem, B: CanBuffer, M: CanModule> Iterator for Fifo0Drain<'a, 'mem, B, M> {
+    type Item = CanRxFrame<B>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let index = self.next_index;
+        let frame = self.fifo.read(index);
+
+        self.last_index = Some(index);
+        self.next_index = (index + 1) % self.fifo.memory.elements();
+        self.remaining -= 1;
+
+        Some(frame)
     }
 
-    /// Sets up the behavior what happens when the Fifo is full.
-    ///
-    /// # CAUTION
-    /// Operating the fifo in overwrite mode is not implemented, since we might
-    /// create race conditions between accessing the buffer and the cpu writing
-    pub enum FifoBehavior {
-        Blocking,
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
     }
+}
 
-    impl FifoState for &tc37x_pac::can0::node::NODE {
-        fn rx_fifo0_fill_level(&self) -> u8 {
-            self.rxf0s.read().f0fl().bits()
+impl<'a, 'mem, B: CanBuffer, M: CanModule> Drop for Fifo0Drain<'a, 'mem, B, M> {
+    fn drop(&mut self) {
+        if let Some(index) = self.last_index {
+            // Acknowledging the last index releases all frames read before it
+            self.node.rx_fifo0_ack_index(index);
         }
+    }
+}
 
-        fn rx_fifo0_index(&self) -> u8 {
-            self.rxf0s.read().f0gi().bits()
-        }
-        /// FIFO-0 Ack frame in index
-        fn rx_fifo0_ack_index(&self, index: u8) {
-            // This automatically update the other FIFO registers since we ack
-            self.rxf0a.modify(|_, w| w.f0ai().variant(index))
+/// A received frame borrowed in place from the module ram, see [CanNode::lease_fifo0]
+///
+/// All accesses are 32 bit volatile reads, the fifo slot is acknowledged on drop
+pub struct RxFrameLease<'a, B: CanBuffer, M: CanModule> {
+    node: &'a M::Node,
+    /// First word of the fifo element, the element stays valid until acknowledged
+    element: *const u32,
+    index: u8,
+    marker: PhantomData<&'a RxFifo0<'a, B, M::RAM>>,
+}
+
+/// Number of header words in front of the payload of a fifo element
+const HEADER_WORDS: usize = 2;
+
+impl<'a, B: CanBuffer, M: CanModule> RxFrameLease<'a, B, M> {
+    /// Read the header (id & flags) of the frame
+    pub fn header(&self) -> RxFrameHeader {
+        // # Safety
+        // The element is part of the fifo memory and not acknowledged yet, hence the
+        // hardware does not write to it
+        let (t0, t1) = unsafe {
+            (
+                core::ptr::read_volatile(self.element),
+                core::ptr::read_volatile(self.element.add(1)),
+            )
+        };
+        RxFrameHeader::from_words(t0, t1)
+    }
+
+    /// Number of valid payload bytes (truncated to the fifo element size)
+    pub fn data_length(&self) -> usize {
+        self.header().data_length().min(B::BUFFER_SIZE)
+    }
+
+    /// Read one 32 bit word of the payload, None if out of the element
+    pub fn data_word(&self, word: usize) -> Option<u32> {
+        if word >= B::BUFFER_SIZE / 4 {
+            return None;
         }
+        // # Safety
+        // See [RxFrameLease::header], index is checked against the element size
+        Some(unsafe { core::ptr::read_volatile(self.element.add(HEADER_WORDS + word)) })
+    }
+
+    /// Copy (at most `dst.len()` bytes of) the payload, returning the number of bytes copied
+    pub fn copy_data(&self, dst: &mut [u8]) -> usize {
+        let length = self.data_length().min(dst.len());
 
-        fn set_buffer_dimension<B: CanBuffer>(
-            &self,
-            in_module_offset: u16,
-            buffer_count: u8,
-            buffer_full_behavior: FifoBehavior,
-        ) {
-            self.rxf0c.modify(|_, w| {
-                w.f0sa()
-                    .variant(in_module_offset >> 2)
-                    .f0s()
-                    .variant(buffer_count)
-            });
-
-            self.rxesc
-                .modify(|_, w| w.f0ds().variant(B::buffer_size().into()));
-
-            match buffer_full_behavior {
-                FifoBehavior::Blocking => {
-                    self.rxf0c.modify(|_, w| w.f0om().clear_bit());
-                }
-            }
-
-            self.rxf0c.modify(|_, w| {
-                w.f0wm().variant(0) // no watermark
-            })
+        for (word, chunk) in dst[..length].chunks_mut(4).enumerate() {
+            // Cannot fail, length is limited by the element size
+            let bytes = self.data_word(word).unwrap().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
         }
+
+        length
+    }
+
+    /// Copy the whole frame out of the module ram
+    pub fn to_frame(&self) -> CanRxFrame<B> {
+        // BUG: Same workaround as in [RxFifo0::read]
+        unsafe { core::ptr::read_volatile(self.element as *const CanRxFrame<B>) }
+    }
+}
+
+impl<'a, B: CanBuffer, M: CanModule> Drop for RxFrameLease<'a, B, M> {
+    fn drop(&mut self) {
+        self.node.rx_fifo0_ack_index(self.index);
+    }
+}
+
+impl<'a, B: CanBuffer, M: CanModule> Format for RxFrameLease<'a, B, M> {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "RxFrameLease {{ index: {}, header: {} }}",
+            self.index,
+            self.header()
+        )
     }
 }
-use fif0_helpers::*;
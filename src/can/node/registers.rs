@@ -0,0 +1,472 @@
+//!
+//! Register access for a CAN node
+//!
+//! The driver never touches the node registers directly but goes through
+//! [NodeRegisters], which is implemented for the register block of the PAC and, with
+//! the `sim` feature, for the host-side model in [crate::can::sim].
+//!
+use defmt::Format;
+#[cfg(not(feature = "sim"))]
+use tc37x_pac::can0::{self, node::gfc};
+#[cfg(not(feature = "sim"))]
+use tc37x_rt::block_while_nops;
+
+use crate::can::{
+    memory::module_ram::CanBuffer,
+    timing::{CanBitrate, CanDataBitrate},
+};
+
+#[cfg(not(feature = "sim"))]
+use super::priority::{FilterList, MessageStorage};
+use super::{
+    error::ProtocolStatus, filter::NonMatchingFrames, options::NodeProtocolOptions,
+    priority::HighPriorityMessage,
+};
+
+/// Interrupt flags (`IR`) used by the driver
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    /// A new message was stored in FIFO 0 (`IR.RF0N`)
+    RxFifo0NewMessage,
+    /// A message was discarded as FIFO 0 was full (`IR.RF0L`)
+    RxFifo0MessageLost,
+    /// A high priority message was received (`IR.HPM`)
+    HighPriorityMessage,
+    /// A single bit error in the message RAM was corrected (`IR.BEC`)
+    BitErrorCorrected,
+    /// An uncorrectable bit error in the message RAM was detected, the node entered
+    /// configuration mode (`IR.BEU`)
+    BitErrorUncorrected,
+}
+
+/// Sets up the behavior what happens when the Fifo is full.
+///
+/// # CAUTION
+/// Operating the fifo in overwrite mode is not implemented, since we might
+/// create race conditions between accessing the buffer and the cpu writing
+pub enum FifoBehavior {
+    Blocking,
+}
+
+/// All register operations the driver performs on a node
+pub trait NodeRegisters {
+    /// Helper method to put the node into configuration mode (set INIT & CCE flags)
+    fn enable_init(&self);
+
+    /// Helper method to enable the node operation (clear INIT & CCE flags)
+    fn disable_init(&self);
+
+    /// Whether the node is in configuration mode (`CCCR.INIT`)
+    fn is_in_init(&self) -> bool;
+
+    /// Request or release the clock stop for power-down (`CCCR.CSR`)
+    fn set_clock_stop_request(&self, enabled: bool);
+
+    /// Whether the node acknowledged the clock stop request (`CCCR.CSA`)
+    fn is_clock_stop_acknowledged(&self) -> bool;
+
+    /// Nominal bit timing (`NBTP`)
+    fn set_nominal_bit_timing(&self, cfg: &CanBitrate);
+
+    /// Data phase bit timing (`DBTP`) and transmitter delay compensation (`TDCR`)
+    fn set_data_bit_timing(&self, cfg: &CanDataBitrate);
+
+    /// Enable or disable CAN FD operation with bitrate switching (`CCCR.FDOE`/`CCCR.BRSE`)
+    fn set_fd_operation(&self, enabled: bool);
+
+    /// Bus monitoring mode (`CCCR.MON`)
+    fn set_bus_monitoring(&self, enabled: bool);
+
+    fn is_bus_monitoring(&self) -> bool;
+
+    /// Protocol options (`CCCR.DAR/TXP/EFBI/PXHD/WMM`)
+    fn set_protocol_options(&self, options: &NodeProtocolOptions);
+
+    /// Connect the node to the module internal loop-back bus (`NPCR.LBM`)
+    fn connect_internal_loopback(&self);
+
+    /// Select the receive input (`NPCR.RXSEL`)
+    fn select_rx_pin(&self, rxsel: u8);
+
+    /// Read (and thereby reset the last error codes of) the `PSR` register
+    fn protocol_status(&self) -> ProtocolStatus;
+
+    /// Transmit and receive error counters (`ECR.TEC`, `ECR.REC`)
+    fn error_counters(&self) -> (u8, u8);
+
+    /// Enable the given interrupt (`IE`)
+    fn enable_interrupt(&self, interrupt: Interrupt);
+
+    /// Check the given interrupt flag (`IR`)
+    fn is_interrupt_pending(&self, interrupt: Interrupt) -> bool;
+
+    /// Clear the given interrupt flag (`IR`)
+    fn clear_interrupt(&self, interrupt: Interrupt);
+
+    /// Decoded high priority message status (`HPMS`)
+    fn high_priority_message_status(&self) -> HighPriorityMessage;
+
+    /// Standard id filter list location and size (`SIDFC`)
+    fn set_standard_filter_list(&self, in_module_offset: u16, count: u8);
+
+    /// Extended id filter list location and size (`XIDFC`)
+    fn set_extended_filter_list(&self, in_module_offset: u16, count: u8);
+
+    /// Mask applied to extended ids before range filtering (`XIDAM`)
+    fn set_extended_id_mask(&self, mask: u32);
+
+    /// Handling of frames that do not match any filter (`GFC`)
+    fn set_non_matching_frames(&self, standard: NonMatchingFrames, extended: NonMatchingFrames);
+
+    /// Sets up fifo0 (`RXF0C`, `RXESC`)
+    fn set_rx_fifo0<B: CanBuffer>(
+        &self,
+        in_module_offset: u16,
+        buffer_count: u8,
+        buffer_full_behavior: FifoBehavior,
+    );
+
+    /// Obtain the numbers of available can messages in the fifo
+    fn rx_fifo0_fill_level(&self) -> u8;
+
+    /// Obtain the index of the next to be fetched can frame
+    ///
+    /// This should usually be paired with a call to `rx_fifo0_fill_level`
+    /// before actually reading the memory in the module ram from this address
+    fn rx_fifo0_index(&self) -> u8;
+
+    /// Acknowledge all frames up until the given level
+    fn rx_fifo0_ack_index(&self, index: u8);
+
+    /// Transmit buffers location, `dedicated` buffers followed by a FIFO of `fifo`
+    /// buffers (`TXBC`, `TXESC`)
+    fn set_tx_buffers<B: CanBuffer>(&self, in_module_offset: u16, dedicated: u8, fifo: u8);
+
+    /// Buffer index of the next free element of the transmit FIFO (`TXFQS.TFQPI`), None
+    /// while the FIFO is full (`TXFQS.TFQF`)
+    fn tx_fifo_put_index(&self) -> Option<u8>;
+
+    /// Whether a transmission is pending for the given buffer (`TXBRP`)
+    fn is_transmission_pending(&self, index: u8) -> bool;
+
+    /// Request the transmission of the given buffer (`TXBAR`)
+    fn request_transmission(&self, index: u8);
+
+    /// Request the cancellation of a pending transmission (`TXBCR`)
+    fn request_cancellation(&self, index: u8);
+
+    /// Whether the last transmission of the given buffer succeeded (`TXBTO`)
+    fn has_transmission_occurred(&self, index: u8) -> bool;
+
+    /// Whether the last transmission of the given buffer was cancelled (`TXBCF`)
+    fn has_cancellation_finished(&self, index: u8) -> bool;
+}
+
+#[cfg(not(feature = "sim"))]
+impl NodeRegisters for can0::NODE {
+    fn enable_init(&self) {
+        // Clear Init
+        while self.cccr.read().init().bit_is_clear() {
+            self.cccr.modify(|_, w| w.init().set_bit());
+        }
+        // Clear CCE
+        while self.cccr.read().cce().bit_is_clear() {
+            self.cccr.modify(|_, w| w.cce().set_bit());
+        }
+    }
+
+    fn disable_init(&self) {
+        // Clear CCE
+        block_while_nops!(
+            {
+                self.cccr.modify(|_, w| w.cce().clear_bit());
+                self.cccr.read().cce().bit_is_clear()
+            },
+            "Cannot clear cce flag"
+        );
+        block_while_nops!(
+            {
+                self.cccr.modify(|_, w| w.init().clear_bit());
+                self.cccr.read().init().bit_is_clear()
+            },
+            "Cannot clear init flag"
+        );
+    }
+
+    fn is_in_init(&self) -> bool {
+        self.cccr.read().init().bit_is_set()
+    }
+
+    fn set_clock_stop_request(&self, enabled: bool) {
+        self.cccr.modify(|_, w| w.csr().bit(enabled));
+    }
+
+    fn is_clock_stop_acknowledged(&self) -> bool {
+        self.cccr.read().csa().bit_is_set()
+    }
+
+    fn set_nominal_bit_timing(&self, cfg: &CanBitrate) {
+        self.nbtp.modify(|_, w| {
+            w.nsjw()
+                .variant(cfg.sync_jump_width() - 1)
+                .ntseg1()
+                .variant(cfg.tseg1() - 1)
+                .ntseg2()
+                .variant(cfg.tseg2() - 1)
+                .nbrp()
+                .variant(cfg.pre_scaler() - 1)
+        });
+    }
+
+    fn set_data_bit_timing(&self, cfg: &CanDataBitrate) {
+        self.dbtp.modify(|_, w| {
+            w.dsjw()
+                .variant(cfg.sync_jump_width() - 1)
+                .dtseg1()
+                .variant(cfg.tseg1() - 1)
+                .dtseg2()
+                .variant(cfg.tseg2() - 1)
+                .dbrp()
+                .variant(cfg.pre_scaler() - 1)
+                .tdc()
+                .bit(cfg.delay_compensation_offset().is_some())
+        });
+
+        if let Some(offset) = cfg.delay_compensation_offset() {
+            self.tdcr.modify(|_, w| w.tdco().variant(offset));
+        }
+    }
+
+    fn set_fd_operation(&self, enabled: bool) {
+        self.cccr
+            .modify(|_, w| w.fdoe().bit(enabled).brse().bit(enabled));
+    }
+
+    fn set_bus_monitoring(&self, enabled: bool) {
+        self.cccr.modify(|_, w| w.mon().bit(enabled));
+    }
+
+    fn is_bus_monitoring(&self) -> bool {
+        self.cccr.read().mon().bit()
+    }
+
+    fn set_protocol_options(&self, options: &NodeProtocolOptions) {
+        self.cccr.modify(|_, w| {
+            w.dar()
+                .bit(!options.automatic_retransmission)
+                .txp()
+                .bit(options.transmit_pause)
+                .efbi()
+                .bit(options.edge_filtering)
+                .pxhd()
+                .bit(!options.protocol_exception_handling)
+                .wmm()
+                .bit(options.wide_message_markers)
+        });
+    }
+
+    fn connect_internal_loopback(&self) {
+        self.npcr.modify(|_, w| w.lbm().bit(true));
+    }
+
+    fn select_rx_pin(&self, rxsel: u8) {
+        self.npcr.modify(|_, w| w.rxsel().variant(rxsel));
+    }
+
+    fn protocol_status(&self) -> ProtocolStatus {
+        let psr_value = self.psr.read();
+
+        ProtocolStatus::from_register_values(
+            psr_value.act().bits(),
+            psr_value.ew().bit(),
+            psr_value.ep().bit(),
+            psr_value.bo().bit(),
+            psr_value.lec().bits(),
+            psr_value.dlec().bits(),
+        )
+    }
+
+    fn error_counters(&self) -> (u8, u8) {
+        let ecr_value = self.ecr.read();
+        (ecr_value.tec().bits(), ecr_value.rec().bits())
+    }
+
+    fn enable_interrupt(&self, interrupt: Interrupt) {
+        self.ie.modify(|_, w| match interrupt {
+            Interrupt::RxFifo0NewMessage => w.rf0ne().set_bit(),
+            Interrupt::RxFifo0MessageLost => w.rf0le().set_bit(),
+            Interrupt::HighPriorityMessage => w.hpme().set_bit(),
+            Interrupt::BitErrorCorrected => w.bece().set_bit(),
+            Interrupt::BitErrorUncorrected => w.beue().set_bit(),
+        });
+    }
+
+    fn is_interrupt_pending(&self, interrupt: Interrupt) -> bool {
+        let ir_value = self.ir.read();
+        match interrupt {
+            Interrupt::RxFifo0NewMessage => ir_value.rf0n().bit_is_set(),
+            Interrupt::RxFifo0MessageLost => ir_value.rf0l().bit_is_set(),
+            Interrupt::HighPriorityMessage => ir_value.hpm().bit_is_set(),
+            Interrupt::BitErrorCorrected => ir_value.bec().bit_is_set(),
+            Interrupt::BitErrorUncorrected => ir_value.beu().bit_is_set(),
+        }
+    }
+
+    fn clear_interrupt(&self, interrupt: Interrupt) {
+        // Flags are cleared by writing 1, hence no read-modify-write here
+        self.ir.write(|w| match interrupt {
+            Interrupt::RxFifo0NewMessage => w.rf0n().set_bit(),
+            Interrupt::RxFifo0MessageLost => w.rf0l().set_bit(),
+            Interrupt::HighPriorityMessage => w.hpm().set_bit(),
+            Interrupt::BitErrorCorrected => w.bec().set_bit(),
+            Interrupt::BitErrorUncorrected => w.beu().set_bit(),
+        });
+    }
+
+    fn high_priority_message_status(&self) -> HighPriorityMessage {
+        let hpms = self.hpms.read();
+
+        HighPriorityMessage {
+            buffer_index: hpms.bidx().bits(),
+            storage: match hpms.msi().bits() {
+                0b00 => MessageStorage::NoFifo,
+                0b01 => MessageStorage::FifoMessageLost,
+                0b10 => MessageStorage::Fifo0,
+                _ => MessageStorage::Fifo1,
+            },
+            filter_index: hpms.fidx().bits(),
+            filter_list: if hpms.flst().bit_is_set() {
+                FilterList::Extended
+            } else {
+                FilterList::Standard
+            },
+        }
+    }
+
+    fn set_standard_filter_list(&self, in_module_offset: u16, count: u8) {
+        self.sidfc.modify(|_, w| {
+            w.flssa()
+                .variant(in_module_offset >> 2)
+                .lss()
+                .variant(count)
+        });
+    }
+
+    fn set_extended_filter_list(&self, in_module_offset: u16, count: u8) {
+        self.xidfc.modify(|_, w| {
+            w.flesa()
+                .variant(in_module_offset >> 2)
+                .lse()
+                .variant(count)
+        });
+    }
+
+    fn set_extended_id_mask(&self, mask: u32) {
+        self.xidam.modify(|_, w| w.eidm().variant(mask));
+    }
+
+    fn set_non_matching_frames(&self, standard: NonMatchingFrames, extended: NonMatchingFrames) {
+        self.gfc.modify(|_, w| {
+            w.anfs()
+                .variant(match standard {
+                    NonMatchingFrames::AcceptFifo0 => gfc::ANFS_A::ACCEPT_FIFO0,
+                    NonMatchingFrames::AcceptFifo1 => gfc::ANFS_A::ACCEPT_FIFO1,
+                    NonMatchingFrames::Reject => gfc::ANFS_A::REJECT,
+                })
+                .anfe()
+                .variant(match extended {
+                    NonMatchingFrames::AcceptFifo0 => gfc::ANFE_A::ACCEPT_FIFO0,
+                    NonMatchingFrames::AcceptFifo1 => gfc::ANFE_A::ACCEPT_FIFO1,
+                    NonMatchingFrames::Reject => gfc::ANFE_A::REJECT,
+                })
+        });
+    }
+
+    fn set_rx_fifo0<B: CanBuffer>(
+        &self,
+        in_module_offset: u16,
+        buffer_count: u8,
+        buffer_full_behavior: FifoBehavior,
+    ) {
+        self.rxf0c.modify(|_, w| {
+            w.f0sa()
+                .variant(in_module_offset >> 2)
+                .f0s()
+                .variant(buffer_count)
+        });
+
+        self.rxesc
+            .modify(|_, w| w.f0ds().variant(B::buffer_size().into()));
+
+        match buffer_full_behavior {
+            FifoBehavior::Blocking => {
+                self.rxf0c.modify(|_, w| w.f0om().clear_bit());
+            }
+        }
+
+        self.rxf0c.modify(|_, w| {
+            w.f0wm().variant(0) // no watermark
+        })
+    }
+
+    fn rx_fifo0_fill_level(&self) -> u8 {
+        self.rxf0s.read().f0fl().bits()
+    }
+
+    fn rx_fifo0_index(&self) -> u8 {
+        self.rxf0s.read().f0gi().bits()
+    }
+
+    /// FIFO-0 Ack frame in index
+    fn rx_fifo0_ack_index(&self, index: u8) {
+        // This automatically update the other FIFO registers since we ack
+        self.rxf0a.modify(|_, w| w.f0ai().variant(index))
+    }
+
+    fn set_tx_buffers<B: CanBuffer>(&self, in_module_offset: u16, dedicated: u8, fifo: u8) {
+        self.txesc
+            .modify(|_, w| w.tbds().variant(B::buffer_size().into()));
+
+        self.txbc.modify(|_, w| {
+            w.tbsa()
+                .variant(in_module_offset >> 2)
+                .ndtb()
+                .variant(dedicated)
+                .tfqs()
+                .variant(fifo)
+                // FIFO, not queue mode
+                .tfqm()
+                .clear_bit()
+        });
+    }
+
+    fn tx_fifo_put_index(&self) -> Option<u8> {
+        let status = self.txfqs.read();
+        if status.tfqf().bit_is_set() {
+            None
+        } else {
+            Some(status.tfqpi().bits())
+        }
+    }
+
+    fn is_transmission_pending(&self, index: u8) -> bool {
+        unsafe { self.txbrp.read().trp(index).bit_is_set() }
+    }
+
+    fn request_transmission(&self, index: u8) {
+        // Set the bit of the corresponding buffer index
+        self.txbar.write(|w| unsafe { w.bits(1 << index) });
+    }
+
+    fn request_cancellation(&self, index: u8) {
+        self.txbcr.write(|w| unsafe { w.bits(1 << index) });
+    }
+
+    fn has_transmission_occurred(&self, index: u8) -> bool {
+        unsafe { self.txbto.read().to(index).bit_is_set() }
+    }
+
+    fn has_cancellation_finished(&self, index: u8) -> bool {
+        unsafe { self.txbcf.read().cf(index).bit_is_set() }
+    }
+}
@@ -0,0 +1,154 @@
+//!
+//! Power-down with wake-up on bus activity through the SCU ERU
+//!
+//! [CanNode::sleep] stops the node like [CanNode::power_down] and lets a falling edge
+//! on the receive pin, i.e. the first dominant bit of a frame, trigger an ERU (event
+//! request unit) output. The service request of that output (`SRC_SCUERUx`) wakes
+//! the CPU from idle or sleep mode once it is enabled.
+//!
+use defmt::Format;
+use tc37x_pac::{can0, Peripherals};
+use tc37x_rt::call_without_endinit;
+
+use crate::can::CanModule;
+
+use super::{connection::Connected, CanNode, PowerDown, Running};
+
+/// `EICRx` fields of a channel, the odd channel of a register uses the upper half word
+const EICR_EXIS_SHIFT: u32 = 4;
+const EICR_FEN: u32 = 1 << 8;
+const EICR_EIEN: u32 = 1 << 11;
+const EICR_INP_SHIFT: u32 = 12;
+const EICR_CHANNEL_MASK: u32 = 0xFFFF;
+
+/// `IGCRx.IGP` of an output gating unit, the odd unit uses the upper half word
+const IGCR_IGP_SHIFT: u32 = 14;
+/// Activate the output on every trigger event, independent of the pattern
+const IGCR_IGP_TRIGGER: u32 = 0b01;
+const IGCR_UNIT_MASK: u32 = 0xFFFF;
+
+/// `FMR.FCx`, clears the flag of a channel
+const FMR_CLEAR_SHIFT: u32 = 16;
+
+/// ERU channel and output used for the wake-up
+///
+/// Which channel and input the receive pin is connected to is listed in the ERU input
+/// table of the data sheet, the output selects the service request.
+#[derive(Format, Clone, Copy)]
+pub struct WakeUpSource {
+    /// External request channel (`ERSx`), 0 to 7
+    pub channel: u8,
+    /// Input of the channel the receive pin is connected to (`EICRx.EXISy`), 0 to 3
+    pub input: u8,
+    /// Output gating unit triggered by the channel (`EICRx.INPy`), 0 to 7
+    pub output: u8,
+}
+
+/// A powered-down node waiting for bus activity, see [CanNode::sleep]
+pub struct SleepingNode<'r, C, Tx, Rx, M: CanModule> {
+    node: CanNode<'r, C, PowerDown, Tx, Rx, M>,
+    source: WakeUpSource,
+}
+
+impl<'r, C: Connected, Tx, Rx, M: CanModule<Node = can0::NODE>> CanNode<'r, C, Running, Tx, Rx, M> {
+    /// Request power-down and configure the receive pin as wake-up source
+    pub fn sleep(self, source: WakeUpSource) -> SleepingNode<'r, C, Tx, Rx, M> {
+        defmt::assert!(
+            source.channel < 8 && source.input < 4 && source.output < 8,
+            "Invalid ERU wake-up source {}",
+            source
+        );
+
+        let node = self.power_down();
+        let p = unsafe { Peripherals::steal() };
+
+        // Falling edges of the receive pin trigger the output gating unit, which
+        // activates its service request on every trigger
+        let channel = (source.input as u32) << EICR_EXIS_SHIFT
+            | EICR_FEN
+            | EICR_EIEN
+            | (source.output as u32) << EICR_INP_SHIFT;
+        let channel_shift = 16 * (source.channel as u32 % 2);
+        let unit_shift = 16 * (source.output as u32 % 2);
+
+        call_without_endinit(|| {
+            macro_rules! set_half_word {
+                ($register:expr, $mask:expr, $shift:expr, $value:expr) => {
+                    $register.modify(|r, w| unsafe {
+                        w.bits((r.bits() & !($mask << $shift)) | ($value << $shift))
+                    })
+                };
+            }
+
+            match source.channel / 2 {
+                0 => set_half_word!(p.SCU.eicr0, EICR_CHANNEL_MASK, channel_shift, channel),
+                1 => set_half_word!(p.SCU.eicr1, EICR_CHANNEL_MASK, channel_shift, channel),
+                2 => set_half_word!(p.SCU.eicr2, EICR_CHANNEL_MASK, channel_shift, channel),
+                _ => set_half_word!(p.SCU.eicr3, EICR_CHANNEL_MASK, channel_shift, channel),
+            }
+
+            let unit = IGCR_IGP_TRIGGER << IGCR_IGP_SHIFT;
+            match source.output / 2 {
+                0 => set_half_word!(p.SCU.igcr0, IGCR_UNIT_MASK, unit_shift, unit),
+                1 => set_half_word!(p.SCU.igcr1, IGCR_UNIT_MASK, unit_shift, unit),
+                2 => set_half_word!(p.SCU.igcr2, IGCR_UNIT_MASK, unit_shift, unit),
+                _ => set_half_word!(p.SCU.igcr3, IGCR_UNIT_MASK, unit_shift, unit),
+            }
+        });
+
+        // Only edges from now on count
+        clear_flag(&p, source.channel);
+
+        SleepingNode { node, source }
+    }
+}
+
+impl<'r, C: Connected, Tx, Rx, M: CanModule<Node = can0::NODE>> SleepingNode<'r, C, Tx, Rx, M> {
+    /// Whether the node stopped, see [CanNode::is_powered_down]
+    pub fn is_powered_down(&self) -> bool {
+        self.node.is_powered_down()
+    }
+
+    /// Whether a falling edge was seen on the receive pin (`EIFR.INTFx`)
+    pub fn has_bus_activity(&self) -> bool {
+        let p = unsafe { Peripherals::steal() };
+        p.SCU.eifr.read().bits() & (1 << self.source.channel) != 0
+    }
+
+    pub fn wake_up_source(&self) -> &WakeUpSource {
+        &self.source
+    }
+
+    /// Disable the wake-up source and take part in bus traffic again. The frame that
+    /// caused the wake-up is lost, the sender repeats it when it was not acknowledged.
+    pub fn wake_up(self) -> CanNode<'r, C, Running, Tx, Rx, M> {
+        let p = unsafe { Peripherals::steal() };
+        let channel_shift = 16 * (self.source.channel as u32 % 2);
+
+        call_without_endinit(|| {
+            macro_rules! disable_trigger {
+                ($register:expr) => {
+                    $register
+                        .modify(|r, w| unsafe { w.bits(r.bits() & !(EICR_EIEN << channel_shift)) })
+                };
+            }
+
+            match self.source.channel / 2 {
+                0 => disable_trigger!(p.SCU.eicr0),
+                1 => disable_trigger!(p.SCU.eicr1),
+                2 => disable_trigger!(p.SCU.eicr2),
+                _ => disable_trigger!(p.SCU.eicr3),
+            }
+        });
+        clear_flag(&p, self.source.channel);
+
+        self.node.wake_up()
+    }
+}
+
+/// Clear the flag of an ERU channel (`FMR.FCx`)
+fn clear_flag(p: &Peripherals, channel: u8) {
+    p.SCU
+        .fmr
+        .write(|w| unsafe { w.bits(1 << (FMR_CLEAR_SHIFT + channel as u32)) });
+}
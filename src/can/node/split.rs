@@ -0,0 +1,183 @@
+//!
+//! Independent transmit and receive halves of a running node
+//!
+//! [CanNode::split] hands the transmit buffers and FIFO 0 to separate owners, e.g.
+//! the receive interrupt and a transmitting task on another CPU. Each half only
+//! touches its own registers and its own part of the message RAM, so neither needs a
+//! lock:
+//!
+//! - [CanTx]: `TXBRP`, `TXBAR`, `TXBCR`, `TXBTO`, `TXBCF` and the transmit buffers
+//! - [CanRx]: `RXF0S`, `RXF0A`, the `RF0N` flag of `IR` and the FIFO 0 elements
+//!
+//! `IR` is shared, but its flags are cleared by writing 1, which leaves all other
+//! flags untouched.
+//!
+use core::{marker::PhantomData, ptr};
+
+#[cfg(feature = "sim")]
+use crate::can::sim::node::SimNode;
+#[cfg(not(feature = "sim"))]
+use tc37x_pac::can0;
+
+use crate::{
+    can::{
+        memory::{module_ram::CanBuffer, rx::CanRxFrame},
+        CanModule,
+    },
+    time::Instant,
+};
+
+use super::{
+    connection::Connected,
+    receive::{self, Fifo0Drain, RxFifo0, RxFrameLease},
+    transceive::{TransmitBuffer, TransmitStatus, TransmitToken, TxDedicated, Uninitialized},
+    CanNode, Running,
+};
+
+/// Transmit half of a node, see [CanNode::split]
+pub struct CanTx<'r, 'mem, C, B: CanBuffer, M: CanModule> {
+    node: &'r M::Node,
+    config: TxDedicated<'mem, B, M::RAM>,
+    marker: PhantomData<(C, M)>,
+}
+
+/// Receive half of a node, see [CanNode::split]
+pub struct CanRx<'r, 'mem, C, B: CanBuffer, M: CanModule> {
+    node: &'r M::Node,
+    config: RxFifo0<'mem, B, M::RAM>,
+    marker: PhantomData<(C, M)>,
+}
+
+/// Node registers that the transmit and the receive half may access from different
+/// contexts at the same time, see the [module](self) documentation
+///
+/// # Safety
+/// Accesses to the registers of one half must not interfere with those of the other
+pub unsafe trait SplitRegisters {}
+
+// # Safety
+// Each register is accessed with a single load or store
+#[cfg(not(feature = "sim"))]
+unsafe impl SplitRegisters for can0::NODE {}
+
+// # Safety
+// The state of the model is locked for every access
+#[cfg(feature = "sim")]
+unsafe impl SplitRegisters for SimNode {}
+
+// # Safety
+// The halves access disjoint registers (see the module documentation) and disjoint
+// parts of the message RAM, which is owned by the respective half
+unsafe impl<'r, 'mem, C, B: CanBuffer, M: CanModule> Send for CanTx<'r, 'mem, C, B, M> where
+    M::Node: SplitRegisters
+{
+}
+
+// # Safety
+// See [CanTx]
+unsafe impl<'r, 'mem, C, B: CanBuffer, M: CanModule> Send for CanRx<'r, 'mem, C, B, M> where
+    M::Node: SplitRegisters
+{
+}
+
+impl<'r, 'mem, C: Connected, TB: CanBuffer, RB: CanBuffer, M: CanModule>
+    CanNode<'r, C, Running, TxDedicated<'mem, TB, M::RAM>, RxFifo0<'mem, RB, M::RAM>, M>
+{
+    /// Split the node into a transmit and a receive half that can be used
+    /// independently, see the [module](self) documentation
+    pub fn split(self) -> (CanTx<'r, 'mem, C, TB, M>, CanRx<'r, 'mem, C, RB, M>) {
+        let tx = CanTx {
+            node: self.node,
+            config: self.tx_dedicated_config,
+            marker: PhantomData,
+        };
+        let rx = CanRx {
+            node: self.node,
+            config: self.rx_fifo0_config,
+            marker: PhantomData,
+        };
+        (tx, rx)
+    }
+
+    /// Put the halves of a node back together, e.g. to reconfigure it
+    pub fn join(tx: CanTx<'r, 'mem, C, TB, M>, rx: CanRx<'r, 'mem, C, RB, M>) -> Self {
+        defmt::assert!(
+            ptr::eq(tx.node, rx.node),
+            "Halves of different nodes cannot be joined"
+        );
+
+        CanNode {
+            node: tx.node,
+            tx_dedicated_config: tx.config,
+            rx_fifo0_config: rx.config,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'r, 'mem, C: Connected, B: CanBuffer, M: CanModule> CanTx<'r, 'mem, C, B, M> {
+    /// See [CanNode::transmit_status]
+    pub fn transmit_status(&self, token: &TransmitToken) -> TransmitStatus {
+        self.config.status(self.node, token)
+    }
+
+    /// See [CanNode::cancel_timed_out_transmissions]
+    pub fn cancel_timed_out_transmissions(&self, now: &Instant) -> u8 {
+        self.config.cancel_timed_out(self.node, now)
+    }
+
+    /// See [CanNode::cancel_transmission]
+    pub fn cancel_transmission(&self, token: &TransmitToken) {
+        self.config.cancel(self.node, token)
+    }
+
+    /// See [CanNode::acquire_transmit_buffer]
+    pub fn acquire_transmit_buffer<'a>(
+        &'a mut self,
+    ) -> Option<TransmitBuffer<'a, B, Uninitialized, M>>
+    where
+        'r: 'a,
+        'mem: 'a,
+    {
+        self.config.acquire(self.node)
+    }
+
+    /// See [CanNode::dedicated_transmit_buffer]
+    pub fn dedicated_transmit_buffer<'a>(
+        &'a mut self,
+        index: u8,
+    ) -> Option<TransmitBuffer<'a, B, Uninitialized, M>>
+    where
+        'r: 'a,
+        'mem: 'a,
+    {
+        self.config.dedicated(self.node, index)
+    }
+}
+
+impl<'r, 'mem, C: Connected, B: CanBuffer, M: CanModule> CanRx<'r, 'mem, C, B, M> {
+    /// See [CanNode::take_rx_fifo0_interrupt]
+    pub fn take_rx_fifo0_interrupt(&self) -> bool {
+        receive::take_rx_fifo0_interrupt(self.node)
+    }
+
+    /// See [CanNode::try_receive_fifo0]
+    pub fn try_receive_fifo0(&mut self) -> Option<CanRxFrame<B>> {
+        self.config.pop(self.node)
+    }
+
+    /// See [CanNode::drain_fifo0]
+    pub fn drain_fifo0(&mut self) -> Fifo0Drain<'_, 'mem, B, M> {
+        self.config.drain(self.node)
+    }
+
+    /// See [CanNode::lease_fifo0]
+    pub fn lease_fifo0(&mut self) -> Option<RxFrameLease<'_, B, M>> {
+        self.config.lease(self.node)
+    }
+
+    /// See [CanNode::receive_fifo0_into]
+    pub fn receive_fifo0_into(&mut self, frames: &mut [CanRxFrame<B>]) -> usize {
+        self.config.receive_into::<M>(self.node, frames)
+    }
+}
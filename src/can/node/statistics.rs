@@ -0,0 +1,408 @@
+//!
+//! Traffic, error and bus load statistics of a node
+//!
+//! [NodeStatistics] is fed with the frames the application receives and sends and
+//! samples the node with [CanNode::update_statistics]. Frames are counted per id
+//! bucket, the 4 most significant bits of the identifier, i.e. by priority.
+//!
+//! The bus load is estimated from the frames seen by this node: the length of each
+//! frame including the worst case number of stuff bits at the nominal and data
+//! bitrate, summed up over a sliding window.
+//!
+use core::time::Duration;
+
+use defmt::Format;
+
+use crate::{
+    can::{memory::module_ram::CanBuffer, CanID, CanModule, CanRxFrame, CanTxFrame},
+    time::Instant,
+};
+
+use super::{
+    bitrate_detection::BitrateCandidate,
+    connection::Connected,
+    error::LastErrorCode,
+    receive::RxFifo0,
+    registers::{Interrupt, NodeRegisters},
+    CanNode, Running,
+};
+
+/// Frames are counted per bucket of ids
+pub const ID_BUCKETS: usize = 16;
+
+/// The window is moved in steps of a tenth of its length
+const WINDOW_SLOTS: usize = 10;
+
+/// CRC delimiter, ACK slot and delimiter, end of frame and intermission
+const FRAME_TRAILER_BITS: u32 = 13;
+
+/// Frame bits from the start of frame to the end of the CRC, without data and stuff
+/// bits, of classic frames
+const CLASSIC_STANDARD_BITS: u32 = 34;
+const CLASSIC_EXTENDED_BITS: u32 = 54;
+
+/// Arbitration phase bits from the start of frame to the BRS bit of CAN FD frames
+const FD_STANDARD_ARBITRATION_BITS: u32 = 17;
+const FD_EXTENDED_ARBITRATION_BITS: u32 = 36;
+/// ESI and DLC
+const FD_CONTROL_BITS: u32 = 5;
+/// Stuff count, CRC and their fixed stuff bits, for up to 16 and for more data bytes
+const FD_SHORT_CRC_BITS: u32 = 4 + 17 + 6;
+const FD_LONG_CRC_BITS: u32 = 4 + 21 + 7;
+
+/// Protocol errors seen in `PSR.LEC` and `PSR.DLEC`, each read counts at most one
+#[derive(Format, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ErrorCounts {
+    pub stuff: u32,
+    pub form: u32,
+    pub ack: u32,
+    pub bit1: u32,
+    pub bit0: u32,
+    pub crc: u32,
+}
+
+impl ErrorCounts {
+    /// Sum of all counts, wrapping like the counts themselves
+    pub fn total(&self) -> u32 {
+        [
+            self.stuff, self.form, self.ack, self.bit1, self.bit0, self.crc,
+        ]
+        .into_iter()
+        .fold(0, u32::wrapping_add)
+    }
+
+    fn count(&mut self, code: LastErrorCode) {
+        match code {
+            LastErrorCode::StuffError => self.stuff = self.stuff.wrapping_add(1),
+            LastErrorCode::FormError => self.form = self.form.wrapping_add(1),
+            LastErrorCode::AckError => self.ack = self.ack.wrapping_add(1),
+            LastErrorCode::Bit1Error => self.bit1 = self.bit1.wrapping_add(1),
+            LastErrorCode::Bit0Error => self.bit0 = self.bit0.wrapping_add(1),
+            LastErrorCode::CrcError => self.crc = self.crc.wrapping_add(1),
+            LastErrorCode::NoError | LastErrorCode::NoChange => {}
+        }
+    }
+}
+
+/// The statistics at one point in time, see [NodeStatistics::snapshot]
+#[derive(Format, Clone, Copy)]
+pub struct StatisticsSnapshot {
+    pub received: u32,
+    pub transmitted: u32,
+    /// Frames per id bucket, see the [module](self) documentation
+    pub received_by_id: [u32; ID_BUCKETS],
+    pub transmitted_by_id: [u32; ID_BUCKETS],
+    pub errors: ErrorCounts,
+    /// Times `IR.RF0L` was found set, at least one frame was lost each time
+    pub lost: u32,
+    /// Highest FIFO 0 fill level seen
+    pub fifo0_high_water_mark: u8,
+    /// Estimated bus load over the window in per mille
+    pub bus_load: u16,
+}
+
+/// Statistics of a node, see the [module](self) documentation
+pub struct NodeStatistics {
+    bitrate: BitrateCandidate,
+    received: u32,
+    transmitted: u32,
+    received_by_id: [u32; ID_BUCKETS],
+    transmitted_by_id: [u32; ID_BUCKETS],
+    errors: ErrorCounts,
+    lost: u32,
+    fifo0_high_water_mark: u8,
+    /// Bus time in nanoseconds per slot of the window
+    slots: [u64; WINDOW_SLOTS],
+    slot: usize,
+    slot_length: Duration,
+    /// End of the current slot, None before the first frame
+    slot_end: Option<Instant>,
+}
+
+impl NodeStatistics {
+    /// Statistics for a node running at the given bitrate, the bus load is averaged
+    /// over `window`
+    pub fn new(bitrate: BitrateCandidate, window: Duration) -> Self {
+        defmt::assert!(
+            bitrate.nominal.value() > 0 && !window.is_zero(),
+            "Invalid statistics configuration"
+        );
+
+        NodeStatistics {
+            bitrate,
+            received: 0,
+            transmitted: 0,
+            received_by_id: [0; ID_BUCKETS],
+            transmitted_by_id: [0; ID_BUCKETS],
+            errors: ErrorCounts::default(),
+            lost: 0,
+            fifo0_high_water_mark: 0,
+            slots: [0; WINDOW_SLOTS],
+            slot: 0,
+            slot_length: window / WINDOW_SLOTS as u32,
+            slot_end: None,
+        }
+    }
+
+    /// Count a received frame
+    pub fn on_received<B: CanBuffer>(&mut self, frame: &CanRxFrame<B>, now: &Instant) {
+        let header = frame.header();
+        let data_length = if header.rtr() {
+            0
+        } else {
+            header.data_length()
+        };
+
+        self.received = self.received.wrapping_add(1);
+        let bucket = &mut self.received_by_id[id_bucket(frame.get_id())];
+        *bucket = bucket.wrapping_add(1);
+        self.add_bus_time(
+            frame.get_id(),
+            data_length,
+            frame.is_fd_format(),
+            frame.bitrate_switching(),
+            now,
+        );
+    }
+
+    /// Count a frame handed to the node for transmission
+    pub fn on_transmitted<B: CanBuffer>(&mut self, frame: &CanTxFrame<B>, now: &Instant) {
+        let data_length = if frame.is_remote_request() {
+            0
+        } else {
+            frame.data().len()
+        };
+
+        self.transmitted = self.transmitted.wrapping_add(1);
+        let bucket = &mut self.transmitted_by_id[id_bucket(frame.get_id())];
+        *bucket = bucket.wrapping_add(1);
+        self.add_bus_time(
+            frame.get_id(),
+            data_length,
+            frame.is_fd_format(),
+            frame.bitrate_switching(),
+            now,
+        );
+    }
+
+    /// Count the protocol errors reported by a read of `PSR`
+    pub fn on_error_codes(&mut self, last: LastErrorCode, data_phase_last: LastErrorCode) {
+        self.errors.count(last);
+        self.errors.count(data_phase_last);
+    }
+
+    /// Count a lost message flag (`IR.RF0L`)
+    pub fn on_message_lost(&mut self) {
+        self.lost = self.lost.wrapping_add(1);
+    }
+
+    /// Track the FIFO 0 high-water mark
+    pub fn on_fifo0_fill_level(&mut self, fill_level: u8) {
+        self.fifo0_high_water_mark = self.fifo0_high_water_mark.max(fill_level);
+    }
+
+    /// The bus load estimated over the window in per mille
+    pub fn bus_load(&mut self, now: &Instant) -> u16 {
+        self.advance(now);
+        let busy: u64 = self.slots.iter().sum();
+        let window = self.slot_length.as_nanos() as u64 * WINDOW_SLOTS as u64;
+        (busy * 1000 / window).min(1000) as u16
+    }
+
+    pub fn snapshot(&mut self, now: &Instant) -> StatisticsSnapshot {
+        StatisticsSnapshot {
+            received: self.received,
+            transmitted: self.transmitted,
+            received_by_id: self.received_by_id,
+            transmitted_by_id: self.transmitted_by_id,
+            errors: self.errors,
+            lost: self.lost,
+            fifo0_high_water_mark: self.fifo0_high_water_mark,
+            bus_load: self.bus_load(now),
+        }
+    }
+
+    /// Reset all counters, e.g. after the bitrate changed
+    pub fn reset(&mut self, bitrate: BitrateCandidate) {
+        *self = NodeStatistics::new(bitrate, self.slot_length * WINDOW_SLOTS as u32);
+    }
+
+    fn add_bus_time(
+        &mut self,
+        id: CanID,
+        data_length: usize,
+        fd_format: bool,
+        bitrate_switching: bool,
+        now: &Instant,
+    ) {
+        self.advance(now);
+        let time = frame_time(&self.bitrate, id, data_length, fd_format, bitrate_switching);
+        self.slots[self.slot] += time;
+    }
+
+    /// Move the window to contain `now`
+    fn advance(&mut self, now: &Instant) {
+        let Some(slot_end) = self.slot_end else {
+            self.slot_end = Some(now + self.slot_length);
+            return;
+        };
+        if *now < slot_end {
+            return;
+        }
+
+        let window = self.slot_length * WINDOW_SLOTS as u32;
+        if *now - slot_end >= window {
+            self.slots = [0; WINDOW_SLOTS];
+            self.slot_end = Some(now + self.slot_length);
+            return;
+        }
+
+        let mut slot_end = slot_end;
+        while *now >= slot_end {
+            self.slot = (self.slot + 1) % WINDOW_SLOTS;
+            self.slots[self.slot] = 0;
+            slot_end = &slot_end + self.slot_length;
+        }
+        self.slot_end = Some(slot_end);
+    }
+}
+
+impl<'r, 'mem, C: Connected, B: CanBuffer, AnyTx, M: CanModule>
+    CanNode<'r, C, Running, AnyTx, RxFifo0<'mem, B, M::RAM>, M>
+{
+    /// Sample the protocol errors, the lost message flag and the FIFO 0 fill level.
+    /// Call this regularly and before draining FIFO 0.
+    ///
+    /// Reading `PSR` resets the last error codes, they are no longer reported by
+    /// [CanNode::clear_error] afterwards.
+    pub fn update_statistics(&self, statistics: &mut NodeStatistics) {
+        let status = self.node.protocol_status();
+        statistics.on_error_codes(
+            status.last_error_code(),
+            status.data_phase_last_error_code(),
+        );
+
+        if self
+            .node
+            .is_interrupt_pending(Interrupt::RxFifo0MessageLost)
+        {
+            self.node.clear_interrupt(Interrupt::RxFifo0MessageLost);
+            statistics.on_message_lost();
+        }
+
+        statistics.on_fifo0_fill_level(self.node.rx_fifo0_fill_level());
+    }
+}
+
+/// The 4 most significant bits of the identifier
+fn id_bucket(id: CanID) -> usize {
+    match id {
+        CanID::Standard(id) => (id as usize >> 7) % ID_BUCKETS,
+        CanID::Extended(id) => (id as usize >> 25) % ID_BUCKETS,
+    }
+}
+
+/// Time on the bus in nanoseconds, with the worst case number of stuff bits
+fn frame_time(
+    bitrate: &BitrateCandidate,
+    id: CanID,
+    data_length: usize,
+    fd_format: bool,
+    bitrate_switching: bool,
+) -> u64 {
+    let extended = matches!(id, CanID::Extended(_));
+    let data_bits = 8 * data_length as u32;
+    let nominal_ns = 1_000_000 / bitrate.nominal.value() as u64;
+
+    if !fd_format {
+        let header = if extended {
+            CLASSIC_EXTENDED_BITS
+        } else {
+            CLASSIC_STANDARD_BITS
+        };
+        let stuffed = header + data_bits;
+        let bits = stuffed + (stuffed - 1) / 4 + FRAME_TRAILER_BITS;
+        return bits as u64 * nominal_ns;
+    }
+
+    let arbitration = if extended {
+        FD_EXTENDED_ARBITRATION_BITS
+    } else {
+        FD_STANDARD_ARBITRATION_BITS
+    };
+    let crc = if data_length > 16 {
+        FD_LONG_CRC_BITS
+    } else {
+        FD_SHORT_CRC_BITS
+    };
+    let data_phase = FD_CONTROL_BITS + data_bits;
+    let nominal_bits = arbitration + (arbitration - 1) / 4 + FRAME_TRAILER_BITS;
+    let data_phase_bits = data_phase + data_phase / 4 + crc;
+
+    let data_ns = match bitrate.data {
+        Some(data) if bitrate_switching => 1_000_000 / data.value() as u64,
+        _ => nominal_ns,
+    };
+    nominal_bits as u64 * nominal_ns + data_phase_bits as u64 * data_ns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::timing::Kbps;
+
+    fn at(millis: u64) -> Instant {
+        Instant::from_time_since_boot(Duration::from_millis(millis))
+    }
+
+    #[test]
+    fn error_total_wraps() {
+        let errors = ErrorCounts {
+            stuff: u32::MAX,
+            ack: 2,
+            ..Default::default()
+        };
+        assert_eq!(errors.total(), 1);
+    }
+
+    #[test]
+    fn frame_times() {
+        let classic = BitrateCandidate::classic(Kbps::new(500));
+        let fd = BitrateCandidate::fd(Kbps::new(500), Kbps::new(2000));
+        let standard = CanID::Standard(0x123);
+
+        // 34 + 64 bits, 24 stuff bits and the trailer: 135 bits of 2 µs
+        assert_eq!(frame_time(&classic, standard, 8, false, false), 270_000);
+        // 54 bits, 13 stuff bits and the trailer
+        assert_eq!(
+            frame_time(&classic, CanID::Extended(0x123), 0, false, false),
+            160_000
+        );
+
+        // 34 bits in the arbitration phase and 678 bits (517 with 129 stuff bits and
+        // the long CRC) in the data phase of 0.5 µs
+        assert_eq!(frame_time(&fd, standard, 64, true, true), 407_000);
+        assert_eq!(frame_time(&fd, standard, 64, true, false), 1_424_000);
+        assert_eq!(frame_time(&classic, standard, 64, true, true), 1_424_000);
+    }
+
+    #[test]
+    fn bus_load_over_the_window() {
+        let mut statistics = NodeStatistics::new(
+            BitrateCandidate::classic(Kbps::new(500)),
+            Duration::from_millis(10),
+        );
+        let id = CanID::Standard(0x123);
+
+        // One frame of 270 µs per millisecond
+        for millis in 0..10 {
+            statistics.add_bus_time(id, 8, false, false, &at(millis));
+        }
+        assert_eq!(statistics.bus_load(&at(9)), 270);
+
+        // The slot of the first frame leaves the window
+        assert_eq!(statistics.bus_load(&at(10)), 243);
+        assert_eq!(statistics.bus_load(&at(14)), 135);
+        assert_eq!(statistics.bus_load(&at(30)), 0);
+    }
+}
@@ -1,9 +1,9 @@
 //!
 //! Transmit side for a CAN module
 //!
-use core::marker::PhantomData;
+use core::{cell::Cell, marker::PhantomData, time::Duration};
 
-use tc37x_pac::can0;
+use defmt::Format;
 
 use crate::can::{
     memory::{
@@ -12,10 +12,13 @@ use crate::can::{
     },
     CanModule, CanModuleRAM,
 };
+use crate::time::Instant;
 
-use super::{connection::Connected, CanNode, InConfiguration, Running};
+use super::{connection::Connected, registers::NodeRegisters, CanNode, InConfiguration, Running};
 
 mod states {
+    use core::{cell::Cell, time::Duration};
+
     use crate::can::{
         memory::{
             module_ram::{CanBuffer, NodeMemory},
@@ -23,11 +26,22 @@ mod states {
         },
         CanModuleRAM,
     };
+    use crate::time::Instant;
 
     pub struct NoTx;
 
     pub struct TxDedicated<'a, B: CanBuffer, M: CanModuleRAM> {
         pub(super) memory: NodeMemory<'a, CanTxFrame<B>, M>,
+        /// The first buffers are only used through `dedicated_transmit_buffer`
+        pub(super) reserved: u8,
+        /// The buffers that are not reserved form the transmit FIFO
+        pub(super) fifo: bool,
+        /// Applied to every transmission requested with `send`
+        pub(super) timeout: Option<Duration>,
+        /// Deadline of the pending transmission per buffer
+        pub(super) deadlines: [Cell<Option<Instant>>; 32],
+        /// Buffers whose last transmission was cancelled after its deadline
+        pub(super) timed_out: Cell<u32>,
     }
 }
 
@@ -44,44 +58,90 @@ impl<'r, C: Connected, R, M: CanModule> CanNode<'r, C, InConfiguration, NoTx, R,
             "Cannot support more than 32 buffers for now"
         );
 
-        self.node
-            .txesc
-            .modify(|_, w| w.tbds().variant(B::buffer_size().into()));
-
         let addr = memory.in_module_offset() as u16;
         let num = memory.elements();
 
-        self.node
-            .txbc
-            .modify(|_, w| w.tbsa().variant(addr >> 2).ndtb().variant(num));
+        self.node.set_tx_buffers::<B>(addr, num, 0);
 
         CanNode {
-            tx_dedicated_config: TxDedicated { memory },
+            tx_dedicated_config: TxDedicated {
+                memory,
+                reserved: 0,
+                fifo: false,
+                timeout: None,
+                deadlines: Default::default(),
+                timed_out: Cell::new(0),
+            },
             ..self
         }
     }
 }
 
+impl<'r, 'mem, C: Connected, B: CanBuffer, R, M: CanModule>
+    CanNode<'r, C, InConfiguration, TxDedicated<'mem, B, M::RAM>, R, M>
+{
+    /// Keep the first `count` buffers for messages that always use the same buffer,
+    /// see [CanNode::dedicated_transmit_buffer]. They are never handed out by
+    /// [CanNode::acquire_transmit_buffer].
+    pub fn reserve_tx_buffers(mut self, count: u8) -> Self {
+        defmt::assert!(
+            count <= self.tx_dedicated_config.memory.elements(),
+            "Cannot reserve {} transmit buffers",
+            count
+        );
+        self.tx_dedicated_config.reserved = count;
+        self.apply_tx_buffers();
+        self
+    }
+
+    /// Send the buffers that are not reserved as a FIFO (`TXBC.TFQS`, `TXBC.TFQM = 0`):
+    /// [CanNode::acquire_transmit_buffer] hands out the put index (`TXFQS.TFQPI`) and
+    /// frames go on the bus in the order they were sent, regardless of their ids. The
+    /// reserved buffers still take part in the arbitration with the oldest FIFO
+    /// element.
+    pub fn set_tx_fifo(mut self) -> Self {
+        self.tx_dedicated_config.fifo = true;
+        self.apply_tx_buffers();
+        self
+    }
+
+    fn apply_tx_buffers(&self) {
+        let config = &self.tx_dedicated_config;
+        let addr = config.memory.in_module_offset() as u16;
+        let (dedicated, fifo) = if config.fifo {
+            (config.reserved, config.memory.elements() - config.reserved)
+        } else {
+            (config.memory.elements(), 0)
+        };
+        self.node.set_tx_buffers::<B>(addr, dedicated, fifo);
+    }
+
+    /// Cancel transmissions requested with [TransmitBuffer::send] that are still
+    /// pending after `timeout`, see [CanNode::cancel_timed_out_transmissions]
+    pub fn set_transmit_timeout(mut self, timeout: Duration) -> Self {
+        self.tx_dedicated_config.timeout = Some(timeout);
+        self
+    }
+}
+
 pub struct Uninitialized;
 
 pub struct Initialized;
 
-pub struct TransmitBuffer<'a, B: CanBuffer, S, M: CanModuleRAM> {
-    node: &'a can0::NODE,
-    buffer: &'a TxDedicated<'a, B, M>,
+pub struct TransmitBuffer<'a, B: CanBuffer, S, M: CanModule> {
+    node: &'a M::Node,
+    buffer: &'a TxDedicated<'a, B, M::RAM>,
     in_buffer_index: u8,
     marker: PhantomData<S>,
 }
 
-impl<'a, B: CanBuffer, M: CanModuleRAM> TransmitBuffer<'a, B, Uninitialized, M> {
+impl<'a, B: CanBuffer, M: CanModule> TransmitBuffer<'a, B, Uninitialized, M> {
     pub fn set_frame(self, frame: CanTxFrame<B>) -> TransmitBuffer<'a, B, Initialized, M> {
         let dst = unsafe { self.buffer.memory.get(self.in_buffer_index) }.unwrap();
         // In theory we should not need unsafe here, but IFX crashes if we *dst = frame because of some
         // parallel access (we figure Rust's tries to write 16 bytes at once)
-        unsafe {
-            core::ptr::write_volatile(dst as *mut CanTxFrame<B>, frame);
-            core::arch::asm!("dsync");
-        }
+        unsafe { core::ptr::write_volatile(dst as *mut CanTxFrame<B>, frame) };
+        M::RAM::data_sync();
         TransmitBuffer {
             marker: PhantomData,
             ..self
@@ -89,22 +149,67 @@ impl<'a, B: CanBuffer, M: CanModuleRAM> TransmitBuffer<'a, B, Uninitialized, M>
     }
 }
 
-impl<'a, B: CanBuffer, M: CanModuleRAM> TransmitBuffer<'a, B, Initialized, M> {
-    pub fn send(self) {
+impl<'a, B: CanBuffer, M: CanModule> TransmitBuffer<'a, B, Initialized, M> {
+    /// Request the transmission, the returned token can be used to query the outcome
+    /// through [CanNode::transmit_status]
+    ///
+    /// With a transmit timeout configured the deadline is taken from [Instant::now].
+    pub fn send(self) -> TransmitToken {
+        let deadline = self.buffer.timeout.map(|timeout| &Instant::now() + timeout);
+        self.request(deadline)
+    }
+
+    /// Request the transmission, it is cancelled if still pending at `deadline`, see
+    /// [CanNode::cancel_timed_out_transmissions]
+    pub fn send_before(self, deadline: &Instant) -> TransmitToken {
+        self.request(Some(*deadline))
+    }
+
+    fn request(self, deadline: Option<Instant>) -> TransmitToken {
+        let index = self.in_buffer_index;
+        self.buffer.deadlines[index as usize].set(deadline);
+        let timed_out = &self.buffer.timed_out;
+        timed_out.set(timed_out.get() & !(1 << index));
+
         defmt::trace!("Request sending of buffer index {:?}", self.in_buffer_index);
-        // Set the bit of the corresponding buffer index
-        self.node
-            .txbar
-            .write(|w| unsafe { w.bits(1 << self.in_buffer_index) });
+        self.node.request_transmission(self.in_buffer_index);
+
+        TransmitToken {
+            in_buffer_index: self.in_buffer_index,
+        }
     }
 }
 
+/// Identifies a requested transmission
+///
+/// The token is only meaningful until the same buffer is acquired again, since a new
+/// request resets the transmission state of the buffer
+#[derive(Format)]
+pub struct TransmitToken {
+    in_buffer_index: u8,
+}
+
+/// Outcome of a transmission request
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum TransmitStatus {
+    /// The frame is still waiting for (re-)transmission
+    Pending,
+    /// The frame was transmitted successfully (`TXBTO`)
+    Sent,
+    /// The frame lost arbitration or was disturbed by an error and was not retried, as
+    /// automatic retransmission is disabled (`TXBCF`)
+    NotSent,
+    /// The frame was still pending at its deadline and the transmission was cancelled
+    /// by [CanNode::cancel_timed_out_transmissions]
+    TimedOut,
+}
+
 pub enum TransmitError {}
 
 impl<'r, 'mem, C: Connected, B: CanBuffer, R, M: CanModule>
     CanNode<'r, C, Running, TxDedicated<'mem, B, M::RAM>, R, M>
 {
-    pub fn with_transmit_buffer<S, F: FnOnce(TransmitBuffer<'_, B, Uninitialized, M::RAM>) -> S>(
+    pub fn with_transmit_buffer<S, F: FnOnce(TransmitBuffer<'_, B, Uninitialized, M>) -> S>(
         &mut self,
         buffer_consume: F,
     ) -> Option<S> {
@@ -112,26 +217,141 @@ impl<'r, 'mem, C: Connected, B: CanBuffer, R, M: CanModule>
         Some(buffer_consume(buffer))
     }
 
+    /// Query the outcome of a previously requested transmission
+    pub fn transmit_status(&self, token: &TransmitToken) -> TransmitStatus {
+        self.tx_dedicated_config.status(self.node, token)
+    }
+
+    /// Cancel all transmissions still pending after their deadline (`TXBCR`), call this
+    /// regularly. Returns the number of cancelled buffers.
+    ///
+    /// A frame that is already on the bus is finished by the node, its status becomes
+    /// [TransmitStatus::Sent] then instead of [TransmitStatus::TimedOut].
+    pub fn cancel_timed_out_transmissions(&self, now: &Instant) -> u8 {
+        self.tx_dedicated_config.cancel_timed_out(self.node, now)
+    }
+
+    /// Cancel a pending transmission (`TXBCR`), e.g. once a protocol gave up waiting
+    /// for it. The status stays [TransmitStatus::Pending] until the cancellation
+    /// finished (`TXBCF`) and becomes [TransmitStatus::NotSent] then, or
+    /// [TransmitStatus::Sent] if the frame was already on the bus.
+    pub fn cancel_transmission(&self, token: &TransmitToken) {
+        self.tx_dedicated_config.cancel(self.node, token)
+    }
+
     pub fn acquire_transmit_buffer<'a>(
         &'a mut self,
-    ) -> Option<TransmitBuffer<'a, B, Uninitialized, M::RAM>>
+    ) -> Option<TransmitBuffer<'a, B, Uninitialized, M>>
+    where
+        'r: 'a,
+        'mem: 'a,
+    {
+        self.tx_dedicated_config.acquire(self.node)
+    }
+
+    /// The reserved buffer with the given index, unless its previous transmission is
+    /// still pending. Rewriting the whole buffer keeps each update of the message
+    /// atomic.
+    pub fn dedicated_transmit_buffer<'a>(
+        &'a mut self,
+        index: u8,
+    ) -> Option<TransmitBuffer<'a, B, Uninitialized, M>>
     where
         'r: 'a,
         'mem: 'a,
     {
-        for buffer_index in 0..self.tx_dedicated_config.memory.elements() {
-            let buffer_is_free = unsafe { self.node.txbrp.read().trp(buffer_index).bit_is_clear() };
-
-            if buffer_is_free {
-                return Some(TransmitBuffer {
-                    node: self.node,
-                    buffer: &self.tx_dedicated_config,
-                    in_buffer_index: buffer_index,
-                    marker: PhantomData,
-                });
+        self.tx_dedicated_config.dedicated(self.node, index)
+    }
+}
+
+impl<'mem, B: CanBuffer, R: CanModuleRAM> TxDedicated<'mem, B, R> {
+    pub(super) fn status<N: NodeRegisters>(
+        &self,
+        node: &N,
+        token: &TransmitToken,
+    ) -> TransmitStatus {
+        let index = token.in_buffer_index;
+
+        if node.is_transmission_pending(index) {
+            TransmitStatus::Pending
+        } else if node.has_transmission_occurred(index) {
+            TransmitStatus::Sent
+        } else if self.timed_out.get() & (1 << index) != 0 {
+            TransmitStatus::TimedOut
+        } else {
+            defmt::debug_assert!(node.has_cancellation_finished(index));
+            TransmitStatus::NotSent
+        }
+    }
+
+    pub(super) fn cancel_timed_out<N: NodeRegisters>(&self, node: &N, now: &Instant) -> u8 {
+        let mut cancelled = 0;
+
+        for index in 0..self.memory.elements() {
+            let deadline = &self.deadlines[index as usize];
+            let expired = deadline.get().is_some_and(|deadline| deadline <= *now);
+            if !expired || !node.is_transmission_pending(index) {
+                continue;
             }
+
+            defmt::warn!("Transmission of buffer {} timed out, cancelling", index);
+            node.request_cancellation(index);
+            deadline.set(None);
+            self.timed_out.set(self.timed_out.get() | (1 << index));
+            cancelled += 1;
+        }
+
+        cancelled
+    }
+
+    pub(super) fn cancel<N: NodeRegisters>(&self, node: &N, token: &TransmitToken) {
+        let index = token.in_buffer_index;
+        if node.is_transmission_pending(index) {
+            node.request_cancellation(index);
+            self.deadlines[index as usize].set(None);
+        }
+    }
+
+    /// The put index of the FIFO, otherwise the first free buffer that is not reserved
+    pub(super) fn acquire<'a, M: CanModule<RAM = R>>(
+        &'a self,
+        node: &'a M::Node,
+    ) -> Option<TransmitBuffer<'a, B, Uninitialized, M>> {
+        let buffer_index = if self.fifo {
+            node.tx_fifo_put_index()?
+        } else {
+            (self.reserved..self.memory.elements())
+                .find(|&index| !node.is_transmission_pending(index))?
+        };
+
+        Some(TransmitBuffer {
+            node,
+            buffer: self,
+            in_buffer_index: buffer_index,
+            marker: PhantomData,
+        })
+    }
+
+    pub(super) fn dedicated<'a, M: CanModule<RAM = R>>(
+        &'a self,
+        node: &'a M::Node,
+        index: u8,
+    ) -> Option<TransmitBuffer<'a, B, Uninitialized, M>> {
+        defmt::assert!(
+            index < self.reserved,
+            "Transmit buffer {} is not reserved",
+            index
+        );
+
+        if node.is_transmission_pending(index) {
+            return None;
         }
 
-        None
+        Some(TransmitBuffer {
+            node,
+            buffer: self,
+            in_buffer_index: index,
+            marker: PhantomData,
+        })
     }
 }
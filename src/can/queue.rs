@@ -0,0 +1,77 @@
+//!
+//! Frames of the protocol stacks waiting for transmission
+//!
+use crate::can::{memory::module_ram::CanBuffer, CanID, CanTxFrame};
+
+/// A classic CAN frame waiting for transmission
+#[derive(Clone, Copy)]
+pub(crate) struct QueuedFrame {
+    pub id: CanID,
+    data: [u8; 8],
+    length: u8,
+}
+
+impl QueuedFrame {
+    pub fn new(id: CanID, data: &[u8]) -> Self {
+        defmt::assert!(data.len() <= 8);
+        let mut frame = QueuedFrame {
+            id,
+            data: [0; 8],
+            length: data.len() as u8,
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        frame
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.length as usize]
+    }
+
+    pub fn to_tx_frame<B: CanBuffer>(self) -> CanTxFrame<B> {
+        let mut frame = CanTxFrame::<B>::default();
+        frame.set_id(self.id);
+        frame.set_data(self.data());
+        frame
+    }
+}
+
+/// First in first out queue of at most `N` frames
+pub(crate) struct FrameQueue<const N: usize> {
+    frames: [Option<QueuedFrame>; N],
+    head: usize,
+    length: usize,
+}
+
+impl<const N: usize> FrameQueue<N> {
+    pub const fn new() -> Self {
+        FrameQueue {
+            frames: [None; N],
+            head: 0,
+            length: 0,
+        }
+    }
+
+    /// Err if the queue is full
+    pub fn push(&mut self, frame: QueuedFrame) -> Result<(), ()> {
+        if self.length == N {
+            return Err(());
+        }
+        self.frames[(self.head + self.length) % N] = Some(frame);
+        self.length += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<QueuedFrame> {
+        if self.length == 0 {
+            return None;
+        }
+        let frame = self.frames[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.length -= 1;
+        frame
+    }
+
+    pub fn clear(&mut self) {
+        *self = FrameQueue::new();
+    }
+}
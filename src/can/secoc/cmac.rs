@@ -0,0 +1,206 @@
+//!
+//! AES-128 CMAC (RFC 4493) behind a trait, with a software implementation
+//!
+
+/// Length of a full MAC, before truncation
+pub const MAC_LENGTH: usize = 16;
+
+/// Computes the AES-128 CMAC with the key of a secured message, e.g. in the HSM
+pub trait Cmac {
+    /// The full 16 byte MAC of `message`, None if the backend is not available
+    fn cmac(&mut self, message: &[u8]) -> Option<[u8; MAC_LENGTH]>;
+}
+
+/// CMAC computed on the CPU, the key is kept in RAM
+pub struct SoftwareCmac {
+    round_keys: [[u8; 16]; 11],
+    /// Subkeys for complete and padded last blocks
+    k1: [u8; 16],
+    k2: [u8; 16],
+}
+
+impl SoftwareCmac {
+    pub fn new(key: &[u8; 16]) -> Self {
+        let round_keys = expand_key(key);
+        let l = encrypt(&round_keys, &[0; 16]);
+        let k1 = double(&l);
+        let k2 = double(&k1);
+        SoftwareCmac { round_keys, k1, k2 }
+    }
+}
+
+impl Cmac for SoftwareCmac {
+    fn cmac(&mut self, message: &[u8]) -> Option<[u8; MAC_LENGTH]> {
+        // The last block is complete only for non-empty messages of a multiple of 16
+        let blocks = message.len().div_ceil(16).max(1);
+        let (head, last) = message.split_at((blocks - 1) * 16);
+
+        let mut state = [0; 16];
+        for block in head.chunks(16) {
+            xor(&mut state, block);
+            state = encrypt(&self.round_keys, &state);
+        }
+
+        let mut block = [0; 16];
+        block[..last.len()].copy_from_slice(last);
+        if last.len() == 16 {
+            xor(&mut block, &self.k1);
+        } else {
+            block[last.len()] = 0x80;
+            xor(&mut block, &self.k2);
+        }
+        xor(&mut state, &block);
+        Some(encrypt(&self.round_keys, &state))
+    }
+}
+
+impl Drop for SoftwareCmac {
+    fn drop(&mut self) {
+        // Do not leave the key schedule behind on the stack
+        for key in self
+            .round_keys
+            .iter_mut()
+            .chain([&mut self.k1, &mut self.k2])
+        {
+            unsafe { core::ptr::write_volatile(key, [0; 16]) };
+        }
+    }
+}
+
+fn xor(block: &mut [u8; 16], other: &[u8]) {
+    for (byte, other) in block.iter_mut().zip(other) {
+        *byte ^= other;
+    }
+}
+
+/// Multiplication by x in GF(2^128), for the subkeys
+fn double(block: &[u8; 16]) -> [u8; 16] {
+    let value = u128::from_be_bytes(*block);
+    let doubled = (value << 1) ^ if value >> 127 != 0 { 0x87 } else { 0 };
+    doubled.to_be_bytes()
+}
+
+#[rustfmt::skip]
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+fn expand_key(key: &[u8; 16]) -> [[u8; 16]; 11] {
+    let mut round_keys = [[0; 16]; 11];
+    round_keys[0] = *key;
+    let mut rcon = 0x01u8;
+
+    for round in 1..11 {
+        let previous = round_keys[round - 1];
+        let mut word = [previous[13], previous[14], previous[15], previous[12]];
+        for byte in word.iter_mut() {
+            *byte = SBOX[*byte as usize];
+        }
+        word[0] ^= rcon;
+        rcon = xtime(rcon);
+
+        // Each word is the previous word xor the same word of the previous round key
+        for (index, byte) in round_keys[round].iter_mut().enumerate() {
+            word[index % 4] ^= previous[index];
+            *byte = word[index % 4];
+        }
+    }
+    round_keys
+}
+
+/// AES-128 encryption of a single block, the state is column major like the block
+fn encrypt(round_keys: &[[u8; 16]; 11], block: &[u8; 16]) -> [u8; 16] {
+    let mut state = *block;
+    xor(&mut state, &round_keys[0]);
+
+    for (round, key) in round_keys.iter().enumerate().skip(1) {
+        // SubBytes and ShiftRows: row r is rotated left by r columns
+        let mut shifted = [0; 16];
+        for column in 0..4 {
+            for row in 0..4 {
+                shifted[4 * column + row] = SBOX[state[4 * ((column + row) % 4) + row] as usize];
+            }
+        }
+        state = shifted;
+
+        if round != 10 {
+            for column in state.chunks_mut(4) {
+                let [a, b, c, d] = [column[0], column[1], column[2], column[3]];
+                let all = a ^ b ^ c ^ d;
+                column[0] ^= all ^ xtime(a ^ b);
+                column[1] ^= all ^ xtime(b ^ c);
+                column[2] ^= all ^ xtime(c ^ d);
+                column[3] ^= all ^ xtime(d ^ a);
+            }
+        }
+        xor(&mut state, key);
+    }
+    state
+}
+
+/// Multiplication by x in GF(2^8)
+fn xtime(value: u8) -> u8 {
+    (value << 1) ^ if value & 0x80 != 0 { 0x1B } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Key and message of the examples of RFC 4493
+    const KEY: u128 = 0x2b7e1516_28aed2a6_abf71588_09cf4f3c;
+    const MESSAGE: [u128; 4] = [
+        0x6bc1bee2_2e409f96_e93d7e11_7393172a,
+        0xae2d8a57_1e03ac9c_9eb76fac_45af8e51,
+        0x30c81c46_a35ce411_e5fbc119_1a0a52ef,
+        0xf69f2445_df4f9b17_ad2b417b_e66c3710,
+    ];
+
+    #[test]
+    fn subkeys() {
+        let cmac = SoftwareCmac::new(&KEY.to_be_bytes());
+        let l = encrypt(&cmac.round_keys, &[0; 16]);
+        assert_eq!(l, 0x7df76b0c_1ab899b3_3e42f047_b91b546f_u128.to_be_bytes());
+        assert_eq!(
+            cmac.k1,
+            0xfbeed618_35713366_7c85e08f_7236a8de_u128.to_be_bytes()
+        );
+        assert_eq!(
+            cmac.k2,
+            0xf7ddac30_6ae266cc_f90bc11e_e46d513b_u128.to_be_bytes()
+        );
+    }
+
+    #[test]
+    fn rfc4493_examples() {
+        let mut cmac = SoftwareCmac::new(&KEY.to_be_bytes());
+        let message: Vec<u8> = MESSAGE
+            .iter()
+            .flat_map(|block| block.to_be_bytes())
+            .collect();
+
+        for (length, mac) in [
+            (0, 0xbb1d6929_e9593728_7fa37d12_9b756746_u128),
+            (16, 0x070a16b4_6b4d4144_f79bdd9d_d04a287c),
+            (40, 0xdfa66747_de9ae630_30ca3261_1497c827),
+            (64, 0x51f0bebf_7e3b9d92_fc497417_79363cfe),
+        ] {
+            assert_eq!(cmac.cmac(&message[..length]), Some(mac.to_be_bytes()));
+        }
+    }
+}
@@ -0,0 +1,501 @@
+//!
+//! SecOC style authentication of CAN frames
+//!
+//! A secured payload is the authentic payload followed by the truncated freshness
+//! value and the truncated MAC:
+//!
+//! ```text
+//! | payload (payload_length) | freshness (freshness_length) | MAC (mac_length) |
+//! ```
+//!
+//! The MAC is the AES-128 CMAC over the data id (2 bytes), the payload and the full
+//! 64 bit freshness value (8 bytes), all big endian. The [Cmac] is computed by a
+//! backend, e.g. the HSM or [SoftwareCmac]. Payloads longer than 8 bytes make the frame
+//! a CAN FD frame, see [CanTxFrame::set_data].
+//!
+//! The freshness value is either a counter that the sender increments per message or
+//! a timestamp from [Instant]. The receiver reconstructs the full value from the
+//! transmitted bits and accepts it if it lies within the acceptance window.
+//!
+//! ```ignore
+//! let config = SecOcConfig::new(0x0123, 4).with_truncation(1, 3);
+//! let mut sender = SecOcSender::new(config);
+//! sender.secure_frame(&mut hsm, &[1, 2, 3, 4], &Instant::now(), &mut frame)?;
+//!
+//! // On the receiver
+//! let payload = receiver.verify_frame(&mut hsm, &rx_frame, &Instant::now())?;
+//! ```
+//!
+use core::time::Duration;
+
+use defmt::Format;
+
+use crate::{
+    can::{memory::module_ram::CanBuffer, CanRxFrame, CanTxFrame},
+    time::Instant,
+};
+
+mod cmac;
+
+pub use cmac::{Cmac, SoftwareCmac, MAC_LENGTH};
+
+/// Largest secured payload, a CAN FD frame
+const MAX_SECURED_LENGTH: usize = 64;
+
+/// Length of the full freshness value in the authenticated data
+const FRESHNESS_LENGTH: usize = 8;
+
+/// Data id, payload and freshness value at most
+const MAX_AUTHENTICATED_LENGTH: usize = 2 + MAX_SECURED_LENGTH + FRESHNESS_LENGTH;
+
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    /// Incremented by the sender for every message. The receiver accepts values at
+    /// most `acceptance_window` above the last accepted one.
+    Counter,
+    /// Time since boot in units of `tick`. The receiver accepts values at most
+    /// `acceptance_window` ticks away from its own time, and newer than the last
+    /// accepted one.
+    Timestamp { tick: Duration },
+}
+
+#[derive(Format, Clone, Copy)]
+pub struct SecOcConfig {
+    pub data_id: u16,
+    /// Length of the authentic payload in bytes
+    pub payload_length: usize,
+    pub freshness: Freshness,
+    /// Transmitted least significant bytes of the freshness value, 0 to 8
+    pub freshness_length: usize,
+    /// Transmitted most significant bytes of the MAC, 1 to 16
+    pub mac_length: usize,
+    pub acceptance_window: u64,
+}
+
+impl SecOcConfig {
+    /// A counter as freshness value, 1 byte of it and 3 bytes of the MAC are sent
+    pub const fn new(data_id: u16, payload_length: usize) -> Self {
+        SecOcConfig {
+            data_id,
+            payload_length,
+            freshness: Freshness::Counter,
+            freshness_length: 1,
+            mac_length: 3,
+            acceptance_window: 16,
+        }
+    }
+
+    pub const fn with_freshness(self, freshness: Freshness, acceptance_window: u64) -> Self {
+        SecOcConfig {
+            freshness,
+            acceptance_window,
+            ..self
+        }
+    }
+
+    pub const fn with_truncation(self, freshness_length: usize, mac_length: usize) -> Self {
+        SecOcConfig {
+            freshness_length,
+            mac_length,
+            ..self
+        }
+    }
+
+    /// Length of the secured payload
+    pub const fn secured_length(&self) -> usize {
+        self.payload_length + self.freshness_length + self.mac_length
+    }
+
+    fn assert_valid(&self) {
+        defmt::assert!(
+            self.freshness_length <= FRESHNESS_LENGTH,
+            "Freshness value longer than 8 bytes"
+        );
+        defmt::assert!(
+            (1..=MAC_LENGTH).contains(&self.mac_length),
+            "MAC length must be 1 to 16 bytes"
+        );
+        defmt::assert!(
+            self.secured_length() <= MAX_SECURED_LENGTH,
+            "Secured payload longer than {} bytes",
+            MAX_SECURED_LENGTH
+        );
+    }
+
+    /// Full freshness value of a timestamp
+    fn timestamp(tick: Duration, now: &Instant) -> u64 {
+        (now.time_since_boot().as_nanos() / tick.as_nanos().max(1)) as u64
+    }
+
+    /// Mask of the transmitted freshness bits
+    fn freshness_mask(&self) -> u64 {
+        match self.freshness_length {
+            FRESHNESS_LENGTH => u64::MAX,
+            length => (1 << (8 * length)) - 1,
+        }
+    }
+
+    fn mac<C: Cmac>(
+        &self,
+        cmac: &mut C,
+        payload: &[u8],
+        freshness: u64,
+    ) -> Result<[u8; MAC_LENGTH], SecOcError> {
+        let mut message = [0; MAX_AUTHENTICATED_LENGTH];
+        let length = 2 + payload.len() + FRESHNESS_LENGTH;
+        message[..2].copy_from_slice(&self.data_id.to_be_bytes());
+        message[2..2 + payload.len()].copy_from_slice(payload);
+        message[2 + payload.len()..length].copy_from_slice(&freshness.to_be_bytes());
+        cmac.cmac(&message[..length]).ok_or(SecOcError::Backend)
+    }
+}
+
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum SecOcError {
+    /// The payload does not have the configured length
+    PayloadLength,
+    /// The freshness value is outside of the acceptance window
+    Freshness,
+    /// The MAC does not match
+    Mac,
+    /// The CMAC backend failed
+    Backend,
+    /// The counter reached its maximum, the key must be renewed
+    CounterExhausted,
+}
+
+/// Secures the messages with one data id
+pub struct SecOcSender {
+    config: SecOcConfig,
+    counter: u64,
+}
+
+impl SecOcSender {
+    pub fn new(config: SecOcConfig) -> Self {
+        config.assert_valid();
+        SecOcSender { config, counter: 0 }
+    }
+
+    pub fn config(&self) -> &SecOcConfig {
+        &self.config
+    }
+
+    /// Continue with the counter stored before a reset, it must never repeat
+    pub fn set_counter(&mut self, counter: u64) {
+        self.counter = counter;
+    }
+
+    /// The freshness counter of the next message
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    /// Write the secured payload into `secured`, which must hold
+    /// [SecOcConfig::secured_length] bytes. Returns the secured length.
+    pub fn secure<C: Cmac>(
+        &mut self,
+        cmac: &mut C,
+        payload: &[u8],
+        now: &Instant,
+        secured: &mut [u8],
+    ) -> Result<usize, SecOcError> {
+        let config = &self.config;
+        if payload.len() != config.payload_length {
+            return Err(SecOcError::PayloadLength);
+        }
+        let freshness = match config.freshness {
+            Freshness::Counter if self.counter == u64::MAX => {
+                return Err(SecOcError::CounterExhausted)
+            }
+            Freshness::Counter => self.counter,
+            Freshness::Timestamp { tick } => SecOcConfig::timestamp(tick, now),
+        };
+        let mac = config.mac(cmac, payload, freshness)?;
+
+        let (data, rest) = secured[..config.secured_length()].split_at_mut(payload.len());
+        let (truncated_freshness, truncated_mac) = rest.split_at_mut(config.freshness_length);
+        data.copy_from_slice(payload);
+        truncated_freshness.copy_from_slice(
+            &freshness.to_be_bytes()[FRESHNESS_LENGTH - config.freshness_length..],
+        );
+        truncated_mac.copy_from_slice(&mac[..config.mac_length]);
+
+        if config.freshness == Freshness::Counter {
+            self.counter += 1;
+        }
+        Ok(config.secured_length())
+    }
+
+    /// Secure the payload and set it as data of the frame
+    pub fn secure_frame<C: Cmac, B: CanBuffer>(
+        &mut self,
+        cmac: &mut C,
+        payload: &[u8],
+        now: &Instant,
+        frame: &mut CanTxFrame<B>,
+    ) -> Result<(), SecOcError> {
+        let mut secured = [0; MAX_SECURED_LENGTH];
+        let length = self.secure(cmac, payload, now, &mut secured)?;
+        frame.set_data(&secured[..length]);
+        Ok(())
+    }
+}
+
+/// Verifies the messages with one data id
+pub struct SecOcReceiver {
+    config: SecOcConfig,
+    /// Full freshness value of the last verified message
+    last_freshness: Option<u64>,
+    /// Accept the next counter value beyond the acceptance window
+    synchronizing: bool,
+}
+
+impl SecOcReceiver {
+    pub fn new(config: SecOcConfig) -> Self {
+        config.assert_valid();
+        SecOcReceiver {
+            config,
+            last_freshness: None,
+            synchronizing: false,
+        }
+    }
+
+    pub fn config(&self) -> &SecOcConfig {
+        &self.config
+    }
+
+    /// Continue after the freshness value stored before a reset
+    pub fn set_last_freshness(&mut self, freshness: u64) {
+        self.last_freshness = Some(freshness);
+    }
+
+    pub fn last_freshness(&self) -> Option<u64> {
+        self.last_freshness
+    }
+
+    /// Accept the next authentic counter value even if it is beyond the acceptance
+    /// window, e.g. when the sender ran ahead while this receiver was off. It still has
+    /// to be above the last freshness value and reconstructable from the transmitted
+    /// bits, so without a last freshness value this only works with the full counter
+    /// transmitted. Any recorded newer message is accepted as well, so only synchronize
+    /// when the freshness is rejected.
+    pub fn synchronize(&mut self) {
+        self.synchronizing = true;
+    }
+
+    /// Verify a secured payload, returning the authentic payload. Padding after the
+    /// secured payload (e.g. up to the next CAN FD length) is ignored.
+    pub fn verify<'d, C: Cmac>(
+        &mut self,
+        cmac: &mut C,
+        secured: &'d [u8],
+        now: &Instant,
+    ) -> Result<&'d [u8], SecOcError> {
+        let config = &self.config;
+        let secured = secured
+            .get(..config.secured_length())
+            .ok_or(SecOcError::PayloadLength)?;
+        let (payload, rest) = secured.split_at(config.payload_length);
+        let (truncated_freshness, truncated_mac) = rest.split_at(config.freshness_length);
+
+        let received = truncated_freshness
+            .iter()
+            .fold(0u64, |value, byte| (value << 8) | *byte as u64);
+        let freshness = self.reconstruct(received, now)?;
+
+        let mac = config.mac(cmac, payload, freshness)?;
+        // Compare all bytes, the time must not tell how many matched
+        let difference = mac
+            .iter()
+            .zip(truncated_mac)
+            .fold(0, |difference, (a, b)| difference | (a ^ b));
+        if difference != 0 {
+            return Err(SecOcError::Mac);
+        }
+
+        self.last_freshness = Some(freshness);
+        self.synchronizing = false;
+        Ok(payload)
+    }
+
+    /// Verify the data of a frame, returning the authentic payload
+    pub fn verify_frame<'f, C: Cmac, B: CanBuffer>(
+        &mut self,
+        cmac: &mut C,
+        frame: &'f CanRxFrame<B>,
+        now: &Instant,
+    ) -> Result<&'f [u8], SecOcError> {
+        self.verify(cmac, frame.data(), now)
+    }
+
+    /// The full freshness value closest to the expected one with the received bits
+    fn reconstruct(&self, received: u64, now: &Instant) -> Result<u64, SecOcError> {
+        let config = &self.config;
+        let mask = config.freshness_mask();
+        let window = config.acceptance_window;
+
+        match config.freshness {
+            Freshness::Counter => {
+                // The counter must have moved on since the last accepted message
+                let last = self.last_freshness;
+                let base = last.map_or(0, |last| last.wrapping_add(1));
+                let delta = received.wrapping_sub(base) & mask;
+                let freshness = base.checked_add(delta).ok_or(SecOcError::Freshness)?;
+                let accepted = match last {
+                    _ if self.synchronizing => true,
+                    Some(last) => freshness - last <= window,
+                    None => freshness <= window,
+                };
+                accepted.then_some(freshness).ok_or(SecOcError::Freshness)
+            }
+            Freshness::Timestamp { tick } => {
+                let expected = SecOcConfig::timestamp(tick, now);
+                // Signed distance from the expected value within the transmitted bits
+                let delta = received.wrapping_sub(expected) & mask;
+                let freshness = if mask != u64::MAX && delta > mask / 2 {
+                    expected.wrapping_sub((mask - delta).wrapping_add(1))
+                } else {
+                    expected.wrapping_add(delta)
+                };
+                let within_window = freshness.abs_diff(expected) <= window;
+                let not_older = self.last_freshness.is_none_or(|last| freshness > last);
+                (within_window && not_older)
+                    .then_some(freshness)
+                    .ok_or(SecOcError::Freshness)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAYLOAD: [u8; 4] = [1, 2, 3, 4];
+
+    fn cmac() -> SoftwareCmac {
+        SoftwareCmac::new(&[0x5A; 16])
+    }
+
+    fn at(millis: u64) -> Instant {
+        Instant::from_time_since_boot(Duration::from_millis(millis))
+    }
+
+    /// The payload secured with 1 byte of freshness and 3 bytes of MAC
+    fn secure(sender: &mut SecOcSender, millis: u64) -> [u8; 8] {
+        let mut secured = [0; 8];
+        let length = sender.secure(&mut cmac(), &PAYLOAD, &at(millis), &mut secured);
+        assert!(length == Ok(8));
+        secured
+    }
+
+    fn verify(receiver: &mut SecOcReceiver, secured: &[u8], millis: u64) -> Result<(), SecOcError> {
+        receiver
+            .verify(&mut cmac(), secured, &at(millis))
+            .map(|payload| assert_eq!(payload, PAYLOAD))
+    }
+
+    #[test]
+    fn counter_across_the_truncation_wrap() {
+        let config = SecOcConfig::new(0x0123, 4);
+        let mut sender = SecOcSender::new(config);
+        let mut receiver = SecOcReceiver::new(config);
+        sender.set_counter(0xFE);
+        receiver.set_last_freshness(0xFD);
+
+        for counter in 0xFE..0x102 {
+            let secured = secure(&mut sender, 0);
+            assert_eq!(secured[4], counter as u8);
+            assert!(verify(&mut receiver, &secured, 0) == Ok(()));
+            assert_eq!(receiver.last_freshness(), Some(counter));
+        }
+
+        // Skipped messages within the window
+        sender.set_counter(0x110);
+        let secured = secure(&mut sender, 0);
+        assert!(verify(&mut receiver, &secured, 0) == Ok(()));
+        assert_eq!(receiver.last_freshness(), Some(0x110));
+    }
+
+    #[test]
+    fn replay_and_forgery() {
+        let config = SecOcConfig::new(0x0123, 4);
+        let mut sender = SecOcSender::new(config);
+        let mut receiver = SecOcReceiver::new(config);
+
+        let first = secure(&mut sender, 0);
+        let second = secure(&mut sender, 0);
+        assert!(verify(&mut receiver, &first, 0) == Ok(()));
+        assert!(verify(&mut receiver, &second, 0) == Ok(()));
+
+        // Replayed messages are taken for a counter a whole wrap ahead, beyond the window
+        assert!(verify(&mut receiver, &second, 0) == Err(SecOcError::Freshness));
+        assert!(verify(&mut receiver, &first, 0) == Err(SecOcError::Freshness));
+
+        let mut forged = secure(&mut sender, 0);
+        forged[0] ^= 1;
+        assert!(verify(&mut receiver, &forged, 0) == Err(SecOcError::Mac));
+        let mut forged = secure(&mut sender, 0);
+        forged[7] ^= 1;
+        assert!(verify(&mut receiver, &forged, 0) == Err(SecOcError::Mac));
+        assert!(verify(&mut receiver, &forged[..7], 0) == Err(SecOcError::PayloadLength));
+        assert_eq!(receiver.last_freshness(), Some(1));
+
+        // Another data id has another MAC
+        let mut other = SecOcReceiver::new(SecOcConfig::new(0x0124, 4));
+        assert!(verify(&mut other, &first, 0) == Err(SecOcError::Mac));
+    }
+
+    #[test]
+    fn synchronize() {
+        let config = SecOcConfig::new(0x0123, 4);
+        let mut sender = SecOcSender::new(config);
+        let mut receiver = SecOcReceiver::new(config);
+        receiver.set_last_freshness(10);
+
+        // The sender ran ahead by more than the window
+        sender.set_counter(10 + 17);
+        let secured = secure(&mut sender, 0);
+        assert!(verify(&mut receiver, &secured, 0) == Err(SecOcError::Freshness));
+
+        receiver.synchronize();
+        let mut forged = secured;
+        forged[7] ^= 1;
+        assert!(verify(&mut receiver, &forged, 0) == Err(SecOcError::Mac));
+        assert!(verify(&mut receiver, &secured, 0) == Ok(()));
+        assert_eq!(receiver.last_freshness(), Some(27));
+
+        // Only the first authentic message is accepted beyond the window
+        sender.set_counter(27 + 17);
+        let secured = secure(&mut sender, 0);
+        assert!(verify(&mut receiver, &secured, 0) == Err(SecOcError::Freshness));
+    }
+
+    #[test]
+    fn timestamp_window() {
+        let config = SecOcConfig::new(0x0123, 4).with_freshness(
+            Freshness::Timestamp {
+                tick: Duration::from_millis(1),
+            },
+            5,
+        );
+        let mut sender = SecOcSender::new(config);
+        let mut receiver = SecOcReceiver::new(config);
+
+        let secured = secure(&mut sender, 1000);
+        assert!(verify(&mut receiver, &secured, 1006) == Err(SecOcError::Freshness));
+        assert!(verify(&mut receiver, &secured, 995) == Ok(()));
+        assert_eq!(receiver.last_freshness(), Some(1000));
+
+        // Only the low byte 0xFF is sent, the receiver already counts 0x401
+        let secured = secure(&mut sender, 0x3FF);
+        assert_eq!(secured[4], 0xFF);
+        assert!(verify(&mut receiver, &secured, 0x401) == Ok(()));
+        assert_eq!(receiver.last_freshness(), Some(0x3FF));
+
+        // Within the window, but not newer than the last one
+        assert!(verify(&mut receiver, &secured, 0x401) == Err(SecOcError::Freshness));
+        let secured = secure(&mut sender, 0x3FE);
+        assert!(verify(&mut receiver, &secured, 0x401) == Err(SecOcError::Freshness));
+    }
+}
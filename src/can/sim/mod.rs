@@ -0,0 +1,285 @@
+//!
+//! Host-side model of the MCMCAN module, to run the CAN driver without a board
+//!
+//! Enabled through the `sim` feature, which builds the crate for the host without the
+//! peripherals. A [SimulatedModule] provides [NODE_COUNT] nodes sharing one message RAM
+//! ([SimulatedRAM]), all nodes that are connected (through pins or the internal
+//! loop-back bus) share one virtual bus. Nothing happens on the bus until
+//! [SimulatedModule::transfer] is called, so tests decide when frames move (see
+//! `tests/sim.rs`, run by `cargo test --features sim`):
+//!
+//! ```ignore
+//! let module = SimulatedModule::take();
+//! let mut ram = unsafe { NodeMemoryBuilder::<SimulatedRAM>::steal_module_mem() };
+//!
+//! let mut tx = module.node(0).connect_internal_loopback().set_tx(ram.take_expect(2)).finalize();
+//! let mut rx = module.node(1).connect_internal_loopback().set_rx_fifo0(ram.take_expect(4)).finalize();
+//!
+//! tx.acquire_transmit_buffer().unwrap().set_frame(frame).send();
+//! assert_eq!(module.transfer(), 1);
+//! assert!(rx.try_receive_fifo0().is_some());
+//! ```
+//!
+//! The model covers what the driver uses: acceptance filtering, FIFO 0 get/put
+//! indices, the `TXBAR` → `TXBRP` → `TXBTO`/`TXBCF` transitions, the transmit FIFO,
+//! arbitration by id and acknowledgement by other (non-monitoring) nodes. Bit timing
+//! is only compared by the resulting nominal bitrate, the data phase is not modeled.
+//!
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::can::{
+    memory::module_ram::length_to_dlc,
+    node::{
+        connection::{CanPin, DefaultDisconnected},
+        ecc::{EccErrorKind, EccTracking},
+        receive::NoRx,
+        registers::NodeRegisters,
+        transceive::NoTx,
+        CanNode, InConfiguration,
+    },
+    CanID, CanModule, CanModuleRAM,
+};
+
+pub mod node;
+
+use node::SimNode;
+
+/// Number of nodes of the simulated module
+pub const NODE_COUNT: usize = 4;
+
+/// Same size as the RAM of CAN0
+const RAM_SIZE: usize = 0x8000;
+
+#[repr(align(8))]
+struct MessageRAM(UnsafeCell<[u8; RAM_SIZE]>);
+
+// # Safety
+// Only accessed while the [SimulatedModule] is taken, which is exclusive
+unsafe impl Sync for MessageRAM {}
+
+static MESSAGE_RAM: MessageRAM = MessageRAM(UnsafeCell::new([0; RAM_SIZE]));
+
+/// Guards the message RAM, see [SimulatedModule::take]
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Type defining the RAM for the simulated module
+pub struct SimulatedRAM;
+
+// # Safety
+// Points to a static buffer of the given size
+unsafe impl CanModuleRAM for SimulatedRAM {
+    const RAM_SIZE: usize = RAM_SIZE;
+
+    fn ram_location() -> *mut u8 {
+        MESSAGE_RAM.0.get() as *mut u8
+    }
+
+    fn data_sync() {}
+}
+
+/// Read a word from the message RAM at the given in module offset
+fn read_word(in_module_offset: usize) -> u32 {
+    assert!(in_module_offset + 4 <= RAM_SIZE);
+    unsafe {
+        core::ptr::read_volatile(SimulatedRAM::ram_location().add(in_module_offset) as *const u32)
+    }
+}
+
+/// Write a word to the message RAM at the given in module offset
+fn write_word(in_module_offset: usize, value: u32) {
+    assert!(in_module_offset + 4 <= RAM_SIZE);
+    unsafe {
+        core::ptr::write_volatile(
+            SimulatedRAM::ram_location().add(in_module_offset) as *mut u32,
+            value,
+        )
+    }
+}
+
+/// A frame on the virtual bus, in the layout of the TX buffer element
+pub(crate) struct BusFrame {
+    t0: u32,
+    t1: u32,
+    data: [u8; 64],
+}
+
+/// Pin of a simulated node, every pin leads to the virtual bus
+#[derive(Default, Clone, Copy)]
+pub struct SimPin;
+
+impl CanPin for SimPin {
+    type PeripheralPort = ();
+
+    fn setup_with(&self, _port: &()) {}
+
+    fn rxsel(&self) -> u8 {
+        0
+    }
+}
+
+/// The simulated CAN module, see the module documentation
+pub struct SimulatedModule {
+    nodes: [SimNode; NODE_COUNT],
+}
+
+impl CanModule for SimulatedModule {
+    type RAM = SimulatedRAM;
+    type Node = SimNode;
+}
+
+impl SimulatedModule {
+    /// Take the simulated module with a cleared message RAM
+    ///
+    /// There is only one message RAM, if the module is already taken (e.g. by a test
+    /// running in parallel) this waits until it is dropped.
+    pub fn take() -> Self {
+        while TAKEN
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+
+        for offset in (0..RAM_SIZE).step_by(4) {
+            write_word(offset, 0);
+        }
+
+        SimulatedModule {
+            nodes: Default::default(),
+        }
+    }
+
+    /// Take a node of the module, note that (unlike for the hardware modules) nothing
+    /// prevents taking a node twice
+    pub fn node(
+        &self,
+        index: usize,
+    ) -> CanNode<'_, DefaultDisconnected<SimPin>, InConfiguration, NoTx, NoRx, SimulatedModule>
+    {
+        CanNode::from_registers(&self.nodes[index])
+    }
+
+    /// Whether any enabled interrupt of the given node is pending
+    pub fn interrupt_requested(&self, index: usize) -> bool {
+        self.nodes[index].interrupt_requested()
+    }
+
+    /// Error tracking of a node, errors injected through it are flagged right away at
+    /// in module offset 0
+    pub fn ecc_tracking(&self, index: usize) -> SimEccTracking<'_> {
+        SimEccTracking {
+            node: &self.nodes[index],
+        }
+    }
+
+    /// Flag a message RAM error of a node at the given in module offset
+    pub fn inject_ram_error(&self, index: usize, kind: EccErrorKind, in_module_offset: u32) {
+        self.nodes[index].inject_ram_error(kind, in_module_offset);
+    }
+
+    /// Let the pending frame with the highest priority go over the bus, returning
+    /// false if no frame made it onto the bus
+    pub fn step(&self) -> bool {
+        self.transmit().is_some()
+    }
+
+    /// Run the bus until no more frames can be transferred, returning the number of
+    /// frames that went over the bus
+    ///
+    /// A frame that is not acknowledged stays pending (unless automatic retransmission
+    /// is disabled) and would be retried forever, this stops after the first attempt.
+    pub fn transfer(&self) -> usize {
+        let mut transferred = 0;
+
+        while let Some((sender, index)) = self.transmit() {
+            transferred += 1;
+
+            if self.nodes[sender].is_transmission_pending(index) {
+                break;
+            }
+        }
+
+        transferred
+    }
+
+    /// Arbitrate and transmit one frame, returning the sending node and buffer index
+    fn transmit(&self) -> Option<(usize, u8)> {
+        let (sender, index, frame) = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(sender, node)| {
+                node.next_pending()
+                    .map(|(index, frame)| (sender, index, frame))
+            })
+            .min_by_key(|(_, _, frame)| frame.arbitration_key())?;
+
+        let sender_bitrate = self.nodes[sender].nominal_bitrate();
+        let mut acknowledged = false;
+
+        for (receiver, node) in self.nodes.iter().enumerate() {
+            if receiver == sender || !node.is_active() {
+                continue;
+            }
+
+            let matching_bitrate = match (node.nominal_bitrate(), sender_bitrate) {
+                (Some(own), Some(sender)) => own == sender,
+                _ => true,
+            };
+            acknowledged |= node.acknowledges() && matching_bitrate;
+
+            node.receive(&frame, sender_bitrate);
+        }
+
+        self.nodes[sender].finish_transmission(index, acknowledged);
+        Some((sender, index))
+    }
+
+    /// Put a frame from an external node (e.g. a tester) onto the bus, it is received
+    /// by all active nodes right away
+    pub fn inject(&self, id: CanID, data: &[u8]) {
+        let t0 = match id {
+            CanID::Standard(id) => (id as u32 & 0x7FF) << 18,
+            CanID::Extended(id) => (id & 0x1FFF_FFFF) | (1 << 30),
+        };
+
+        let dlc = length_to_dlc(data.len()).expect("Payload exceeds 64 bytes");
+        let fd_format = data.len() > 8;
+
+        let mut frame = BusFrame {
+            t0,
+            t1: ((dlc as u32) << 16) | ((fd_format as u32) << 21),
+            data: [0; 64],
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+
+        for node in self.nodes.iter().filter(|node| node.is_active()) {
+            node.receive(&frame, None);
+        }
+    }
+}
+
+impl Drop for SimulatedModule {
+    fn drop(&mut self) {
+        TAKEN.store(false, Ordering::Release);
+    }
+}
+
+/// [EccTracking] of a simulated node, see [SimulatedModule::ecc_tracking]
+pub struct SimEccTracking<'a> {
+    node: &'a SimNode,
+}
+
+impl<'a> EccTracking for SimEccTracking<'a> {
+    fn take_error_address(&mut self) -> Option<u32> {
+        self.node.take_ram_error_address()
+    }
+
+    fn inject_error(&mut self, kind: EccErrorKind) -> bool {
+        self.node.inject_ram_error(kind, 0);
+        true
+    }
+}
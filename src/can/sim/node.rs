@@ -0,0 +1,708 @@
+//!
+//! Software model of a single MCMCAN node
+//!
+use std::sync::{Mutex, MutexGuard};
+
+use crate::can::{
+    memory::module_ram::{dlc_to_length, CanBuffer},
+    node::{
+        ecc::EccErrorKind,
+        error::ProtocolStatus,
+        filter::NonMatchingFrames,
+        options::NodeProtocolOptions,
+        priority::{FilterList, HighPriorityMessage, MessageStorage},
+        registers::{FifoBehavior, Interrupt, NodeRegisters},
+    },
+    timing::{CanBitrate, CanDataBitrate},
+};
+
+use super::{read_word, write_word, BusFrame};
+
+/// Size of the header (T0/R0 and T1/R1 words) of a frame element
+const HEADER_SIZE: usize = 8;
+
+/// `PSR.LEC` values used by the model
+const LEC_FORM_ERROR: u8 = 2;
+const LEC_ACK_ERROR: u8 = 3;
+const LEC_STUFF_ERROR: u8 = 1;
+const LEC_NO_CHANGE: u8 = 7;
+
+/// A node of the [SimulatedModule](super::SimulatedModule), implementing the register
+/// accesses of the driver on plain state
+///
+/// The state is locked for every access, so the halves of a
+/// [split](crate::can::node::split) node can run on different threads.
+pub struct SimNode {
+    state: Mutex<NodeState>,
+}
+
+/// Location and layout of a list of elements in the message RAM
+#[derive(Default, Clone, Copy)]
+struct ElementList {
+    in_module_offset: usize,
+    count: u8,
+    element_size: usize,
+}
+
+impl ElementList {
+    fn element_offset(&self, index: u8) -> usize {
+        self.in_module_offset + index as usize * self.element_size
+    }
+}
+
+struct NodeState {
+    init: bool,
+    /// `CCCR.CSR`
+    clock_stop_requested: bool,
+    /// `CCCR.CSA`
+    clock_stopped: bool,
+    connected: bool,
+    monitoring: bool,
+    fd_operation: bool,
+    options: NodeProtocolOptions,
+    /// Nominal bitrate in bits per second, None if never configured
+    nominal_bitrate: Option<u32>,
+    last_error_code: u8,
+    transmit_error_counter: u8,
+    interrupts: u32,
+    enabled_interrupts: u32,
+    high_priority_message: HighPriorityMessage,
+    standard_filters: ElementList,
+    extended_filters: ElementList,
+    extended_id_mask: u32,
+    non_matching: (NonMatchingFrames, NonMatchingFrames),
+    rx_fifo0: ElementList,
+    rx_fifo0_get_index: u8,
+    rx_fifo0_fill_level: u8,
+    /// Dedicated buffers and FIFO elements
+    tx_buffers: ElementList,
+    /// `TXBC.NDTB`, the FIFO takes the remaining buffers
+    tx_dedicated: u8,
+    /// `TXFQS.TFQPI`
+    tx_fifo_put_index: u8,
+    /// `TXBRP`
+    tx_pending: u32,
+    /// `TXBTO`
+    tx_occurred: u32,
+    /// `TXBCF`
+    tx_cancellation_finished: u32,
+    /// Address of the last injected message RAM error, see [SimNode::inject_ram_error]
+    ram_error_address: Option<u32>,
+}
+
+impl Default for SimNode {
+    fn default() -> Self {
+        SimNode {
+            state: Mutex::new(NodeState {
+                init: false,
+                clock_stop_requested: false,
+                clock_stopped: false,
+                connected: false,
+                monitoring: false,
+                fd_operation: false,
+                options: NodeProtocolOptions::default(),
+                nominal_bitrate: None,
+                last_error_code: LEC_NO_CHANGE,
+                transmit_error_counter: 0,
+                interrupts: 0,
+                enabled_interrupts: 0,
+                high_priority_message: HighPriorityMessage {
+                    buffer_index: 0,
+                    storage: MessageStorage::NoFifo,
+                    filter_index: 0,
+                    filter_list: FilterList::Standard,
+                },
+                standard_filters: ElementList::default(),
+                extended_filters: ElementList::default(),
+                extended_id_mask: 0x1FFF_FFFF,
+                non_matching: (
+                    NonMatchingFrames::AcceptFifo0,
+                    NonMatchingFrames::AcceptFifo0,
+                ),
+                rx_fifo0: ElementList::default(),
+                rx_fifo0_get_index: 0,
+                rx_fifo0_fill_level: 0,
+                tx_buffers: ElementList::default(),
+                tx_dedicated: 0,
+                tx_fifo_put_index: 0,
+                tx_pending: 0,
+                tx_occurred: 0,
+                tx_cancellation_finished: 0,
+                ram_error_address: None,
+            }),
+        }
+    }
+}
+
+/// Outcome of the acceptance filtering for a frame
+struct FilterMatch {
+    action: u8,
+    filter_index: u8,
+    filter_list: FilterList,
+}
+
+/// `SFEC`/`EFEC` values, all others (reject, FIFO 1) drop the frame
+const ACTION_DISABLE: u8 = 0b000;
+const ACTION_STORE_FIFO0: u8 = 0b001;
+const ACTION_SET_PRIORITY: u8 = 0b100;
+const ACTION_SET_PRIORITY_STORE_FIFO0: u8 = 0b101;
+
+impl NodeState {
+    /// The FIFO elements from the oldest to the newest request, starting at the put
+    /// index which follows the newest one
+    fn tx_fifo_order(&self) -> impl Iterator<Item = u8> {
+        let dedicated = self.tx_dedicated;
+        let size = self.tx_buffers.count - dedicated;
+        let put = self.tx_fifo_put_index;
+        (0..size).map(move |offset| dedicated + (put - dedicated + offset) % size)
+    }
+
+    /// Stop the clock once a requested clock stop no longer waits for transmissions
+    fn acknowledge_clock_stop(&mut self) {
+        if self.clock_stop_requested && self.tx_pending == 0 {
+            self.init = true;
+            self.clock_stopped = true;
+        }
+    }
+}
+
+impl SimNode {
+    fn state(&self) -> MutexGuard<'_, NodeState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Whether the node takes part in bus traffic
+    pub(super) fn is_active(&self) -> bool {
+        let state = self.state();
+        !state.init && state.connected
+    }
+
+    /// Whether the node acknowledges frames
+    pub(super) fn acknowledges(&self) -> bool {
+        self.is_active() && !self.state().monitoring
+    }
+
+    /// Whether any enabled interrupt is pending, i.e. the interrupt line is raised
+    pub(super) fn interrupt_requested(&self) -> bool {
+        let state = self.state();
+        state.interrupts & state.enabled_interrupts != 0
+    }
+
+    /// Flag a message RAM error at the given in module offset as if the node had read
+    /// a faulty word, an uncorrectable error stops the node like on the hardware
+    pub(super) fn inject_ram_error(&self, kind: EccErrorKind, in_module_offset: u32) {
+        let mut state = self.state();
+        state.ram_error_address = Some(in_module_offset);
+        match kind {
+            EccErrorKind::Corrected => {
+                state.interrupts |= interrupt_bit(Interrupt::BitErrorCorrected);
+            }
+            EccErrorKind::Uncorrected => {
+                state.interrupts |= interrupt_bit(Interrupt::BitErrorUncorrected);
+                state.init = true;
+            }
+        }
+    }
+
+    pub(super) fn take_ram_error_address(&self) -> Option<u32> {
+        self.state().ram_error_address.take()
+    }
+
+    pub(super) fn nominal_bitrate(&self) -> Option<u32> {
+        self.state().nominal_bitrate
+    }
+
+    /// The pending transmit buffer that would enter arbitration first, together with
+    /// its frame
+    pub(super) fn next_pending(&self) -> Option<(u8, BusFrame)> {
+        let state = self.state();
+        if state.init || !state.connected || state.monitoring {
+            return None;
+        }
+
+        // Only the oldest element of the FIFO takes part in the arbitration
+        let fifo_head = state
+            .tx_fifo_order()
+            .find(|index| state.tx_pending & (1 << index) != 0);
+
+        (0..state.tx_dedicated)
+            .chain(fifo_head)
+            .filter(|index| state.tx_pending & (1 << index) != 0)
+            .map(|index| (index, self.read_tx_buffer(&state, index)))
+            .min_by_key(|(_, frame)| frame.arbitration_key())
+    }
+
+    fn read_tx_buffer(&self, state: &NodeState, index: u8) -> BusFrame {
+        let offset = state.tx_buffers.element_offset(index);
+        let t0 = read_word(offset);
+        let t1 = read_word(offset + 4);
+
+        let mut frame = BusFrame {
+            t0,
+            t1,
+            data: [0; 64],
+        };
+
+        let length = frame
+            .length()
+            .min(state.tx_buffers.element_size - HEADER_SIZE);
+        for (word, chunk) in frame.data[..length].chunks_mut(4).enumerate() {
+            let bytes = read_word(offset + HEADER_SIZE + 4 * word).to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+
+        frame
+    }
+
+    /// Transmission of the given buffer finished, `acknowledged` tells whether any
+    /// other node acknowledged the frame
+    pub(super) fn finish_transmission(&self, index: u8, acknowledged: bool) {
+        let mut state = self.state();
+        let bit = 1 << index;
+
+        if acknowledged {
+            state.tx_pending &= !bit;
+            state.tx_occurred |= bit;
+            state.transmit_error_counter = state.transmit_error_counter.saturating_sub(1);
+        } else {
+            state.last_error_code = LEC_ACK_ERROR;
+            state.transmit_error_counter = state.transmit_error_counter.saturating_add(8);
+
+            if !state.options.automatic_retransmission {
+                state.tx_pending &= !bit;
+                state.tx_cancellation_finished |= bit;
+            }
+        }
+        state.acknowledge_clock_stop();
+    }
+
+    /// A frame appeared on the bus, run it through filtering and store it
+    pub(super) fn receive(&self, frame: &BusFrame, sender_bitrate: Option<u32>) {
+        let mut state = self.state();
+
+        if let (Some(own), Some(sender)) = (state.nominal_bitrate, sender_bitrate) {
+            if own != sender {
+                state.last_error_code = LEC_STUFF_ERROR;
+                return;
+            }
+        }
+
+        if frame.is_fd_format() && !state.fd_operation {
+            state.last_error_code = LEC_FORM_ERROR;
+            return;
+        }
+
+        let matched = if frame.is_extended() {
+            Self::filter_extended(&state, frame.id())
+        } else {
+            Self::filter_standard(&state, frame.id())
+        };
+
+        let (action, filter_index, non_matching) = match &matched {
+            Some(matched) => (matched.action, matched.filter_index, false),
+            None => {
+                let handling = if frame.is_extended() {
+                    state.non_matching.1
+                } else {
+                    state.non_matching.0
+                };
+                match handling {
+                    NonMatchingFrames::AcceptFifo0 => (ACTION_STORE_FIFO0, 0, true),
+                    // FIFO 1 is not modeled (nor implemented in the driver)
+                    NonMatchingFrames::AcceptFifo1 | NonMatchingFrames::Reject => return,
+                }
+            }
+        };
+
+        let stored = match action {
+            ACTION_STORE_FIFO0 | ACTION_SET_PRIORITY_STORE_FIFO0 => Some(Self::store_fifo0(
+                &mut state,
+                frame,
+                filter_index,
+                non_matching,
+            )),
+            _ => None,
+        };
+
+        if let (Some(matched), ACTION_SET_PRIORITY | ACTION_SET_PRIORITY_STORE_FIFO0) =
+            (&matched, action)
+        {
+            state.high_priority_message = HighPriorityMessage {
+                buffer_index: stored.flatten().unwrap_or(0),
+                storage: match stored {
+                    None => MessageStorage::NoFifo,
+                    Some(None) => MessageStorage::FifoMessageLost,
+                    Some(Some(_)) => MessageStorage::Fifo0,
+                },
+                filter_index: matched.filter_index,
+                filter_list: matched.filter_list,
+            };
+            state.interrupts |= interrupt_bit(Interrupt::HighPriorityMessage);
+        }
+    }
+
+    /// Store the frame in FIFO 0, returning the index or None if the frame was lost
+    fn store_fifo0(
+        state: &mut NodeState,
+        frame: &BusFrame,
+        filter_index: u8,
+        non_matching: bool,
+    ) -> Option<u8> {
+        let fifo = state.rx_fifo0;
+
+        if fifo.count == 0 || state.rx_fifo0_fill_level == fifo.count {
+            // Blocking mode, the new frame is discarded
+            state.interrupts |= interrupt_bit(Interrupt::RxFifo0MessageLost);
+            return None;
+        }
+
+        let index = (state.rx_fifo0_get_index + state.rx_fifo0_fill_level) % fifo.count;
+        let offset = fifo.element_offset(index);
+
+        // R1: keep DLC, BRS and FDF, add filter information
+        let r1 = (frame.t1 & 0x003F_0000)
+            | ((filter_index as u32 & 0x7F) << 24)
+            | ((non_matching as u32) << 31);
+
+        write_word(offset, frame.t0);
+        write_word(offset + 4, r1);
+
+        // Frames longer than the element are truncated
+        let length = frame.length().min(fifo.element_size - HEADER_SIZE);
+        for (word, chunk) in frame.data[..length].chunks(4).enumerate() {
+            let mut bytes = [0; 4];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            write_word(offset + HEADER_SIZE + 4 * word, u32::from_le_bytes(bytes));
+        }
+
+        state.rx_fifo0_fill_level += 1;
+        state.interrupts |= interrupt_bit(Interrupt::RxFifo0NewMessage);
+        Some(index)
+    }
+
+    fn filter_standard(state: &NodeState, id: u32) -> Option<FilterMatch> {
+        let list = state.standard_filters;
+
+        (0..list.count).find_map(|index| {
+            let element = read_word(list.in_module_offset + 4 * index as usize);
+            let id2 = element & 0x7FF;
+            let id1 = (element >> 16) & 0x7FF;
+            let action = ((element >> 27) & 0b111) as u8;
+            let filter_type = element >> 30;
+
+            let matches = match filter_type {
+                0b00 => (id1..=id2).contains(&id),
+                0b01 => id == id1 || id == id2,
+                0b10 => id & id2 == id1 & id2,
+                _ => false,
+            };
+
+            (matches && action != ACTION_DISABLE).then_some(FilterMatch {
+                action,
+                filter_index: index,
+                filter_list: FilterList::Standard,
+            })
+        })
+    }
+
+    fn filter_extended(state: &NodeState, id: u32) -> Option<FilterMatch> {
+        let list = state.extended_filters;
+
+        (0..list.count).find_map(|index| {
+            let offset = list.in_module_offset + 8 * index as usize;
+            let f0 = read_word(offset);
+            let f1 = read_word(offset + 4);
+            let id1 = f0 & 0x1FFF_FFFF;
+            let id2 = f1 & 0x1FFF_FFFF;
+            let action = (f0 >> 29) as u8;
+            let filter_type = f1 >> 30;
+
+            let matches = match filter_type {
+                0b00 => (id1..=id2).contains(&(id & state.extended_id_mask)),
+                0b01 => id == id1 || id == id2,
+                0b10 => id & id2 == id1 & id2,
+                _ => (id1..=id2).contains(&id),
+            };
+
+            (matches && action != ACTION_DISABLE).then_some(FilterMatch {
+                action,
+                filter_index: index,
+                filter_list: FilterList::Extended,
+            })
+        })
+    }
+}
+
+fn interrupt_bit(interrupt: Interrupt) -> u32 {
+    1 << interrupt as u32
+}
+
+/// Bitrate in bits per second for the 80Mhz CAN clock the timings are computed for
+fn bitrate(pre_scaler: u32, segments: u32) -> u32 {
+    80_000_000 / (pre_scaler * segments)
+}
+
+impl NodeRegisters for SimNode {
+    fn enable_init(&self) {
+        let mut state = self.state();
+        state.init = true;
+        // Setting CCE resets the transmit requests and the FIFO status
+        state.tx_pending = 0;
+        state.rx_fifo0_get_index = 0;
+        state.rx_fifo0_fill_level = 0;
+    }
+
+    fn disable_init(&self) {
+        self.state().init = false;
+    }
+
+    fn is_in_init(&self) -> bool {
+        self.state().init
+    }
+
+    fn set_clock_stop_request(&self, enabled: bool) {
+        let mut state = self.state();
+        state.clock_stop_requested = enabled;
+        if enabled {
+            state.acknowledge_clock_stop();
+        } else {
+            // INIT stays set until the driver clears it
+            state.clock_stopped = false;
+        }
+    }
+
+    fn is_clock_stop_acknowledged(&self) -> bool {
+        self.state().clock_stopped
+    }
+
+    fn set_nominal_bit_timing(&self, cfg: &CanBitrate) {
+        // The synchronization segment is a single time quantum
+        let segments = 1 + cfg.tseg1() as u32 + cfg.tseg2() as u32;
+        self.state().nominal_bitrate = Some(bitrate(cfg.pre_scaler() as u32, segments));
+    }
+
+    fn set_data_bit_timing(&self, _cfg: &CanDataBitrate) {
+        // The data phase is not modeled, frames are exchanged as a whole
+    }
+
+    fn set_fd_operation(&self, enabled: bool) {
+        self.state().fd_operation = enabled;
+    }
+
+    fn set_bus_monitoring(&self, enabled: bool) {
+        self.state().monitoring = enabled;
+    }
+
+    fn is_bus_monitoring(&self) -> bool {
+        self.state().monitoring
+    }
+
+    fn set_protocol_options(&self, options: &NodeProtocolOptions) {
+        self.state().options = *options;
+    }
+
+    fn connect_internal_loopback(&self) {
+        self.state().connected = true;
+    }
+
+    fn select_rx_pin(&self, _rxsel: u8) {
+        // All pins lead to the one virtual bus
+        self.state().connected = true;
+    }
+
+    fn protocol_status(&self) -> ProtocolStatus {
+        let mut state = self.state();
+        let last_error_code = core::mem::replace(&mut state.last_error_code, LEC_NO_CHANGE);
+        let tec = state.transmit_error_counter;
+
+        ProtocolStatus::from_register_values(
+            if state.init { 0b00 } else { 0b01 },
+            tec >= 96,
+            tec >= 128,
+            false,
+            last_error_code,
+            LEC_NO_CHANGE,
+        )
+    }
+
+    fn error_counters(&self) -> (u8, u8) {
+        (self.state().transmit_error_counter, 0)
+    }
+
+    fn enable_interrupt(&self, interrupt: Interrupt) {
+        self.state().enabled_interrupts |= interrupt_bit(interrupt);
+    }
+
+    fn is_interrupt_pending(&self, interrupt: Interrupt) -> bool {
+        self.state().interrupts & interrupt_bit(interrupt) != 0
+    }
+
+    fn clear_interrupt(&self, interrupt: Interrupt) {
+        self.state().interrupts &= !interrupt_bit(interrupt);
+    }
+
+    fn high_priority_message_status(&self) -> HighPriorityMessage {
+        self.state().high_priority_message
+    }
+
+    fn set_standard_filter_list(&self, in_module_offset: u16, count: u8) {
+        self.state().standard_filters = ElementList {
+            in_module_offset: in_module_offset as usize,
+            count,
+            element_size: 4,
+        };
+    }
+
+    fn set_extended_filter_list(&self, in_module_offset: u16, count: u8) {
+        self.state().extended_filters = ElementList {
+            in_module_offset: in_module_offset as usize,
+            count,
+            element_size: 8,
+        };
+    }
+
+    fn set_extended_id_mask(&self, mask: u32) {
+        self.state().extended_id_mask = mask & 0x1FFF_FFFF;
+    }
+
+    fn set_non_matching_frames(&self, standard: NonMatchingFrames, extended: NonMatchingFrames) {
+        self.state().non_matching = (standard, extended);
+    }
+
+    fn set_rx_fifo0<B: CanBuffer>(
+        &self,
+        in_module_offset: u16,
+        buffer_count: u8,
+        buffer_full_behavior: FifoBehavior,
+    ) {
+        match buffer_full_behavior {
+            FifoBehavior::Blocking => {}
+        }
+
+        let mut state = self.state();
+        state.rx_fifo0 = ElementList {
+            in_module_offset: in_module_offset as usize,
+            count: buffer_count,
+            element_size: HEADER_SIZE + B::BUFFER_SIZE,
+        };
+        state.rx_fifo0_get_index = 0;
+        state.rx_fifo0_fill_level = 0;
+    }
+
+    fn rx_fifo0_fill_level(&self) -> u8 {
+        self.state().rx_fifo0_fill_level
+    }
+
+    fn rx_fifo0_index(&self) -> u8 {
+        self.state().rx_fifo0_get_index
+    }
+
+    fn rx_fifo0_ack_index(&self, index: u8) {
+        let mut state = self.state();
+        let count = state.rx_fifo0.count;
+
+        if count == 0 || index >= count {
+            return;
+        }
+
+        // Acknowledging an index releases all frames up to and including it
+        let released = (index + count - state.rx_fifo0_get_index) % count + 1;
+        if released <= state.rx_fifo0_fill_level {
+            state.rx_fifo0_fill_level -= released;
+            state.rx_fifo0_get_index = (index + 1) % count;
+        }
+    }
+
+    fn set_tx_buffers<B: CanBuffer>(&self, in_module_offset: u16, dedicated: u8, fifo: u8) {
+        let mut state = self.state();
+        state.tx_buffers = ElementList {
+            in_module_offset: in_module_offset as usize,
+            count: dedicated + fifo,
+            element_size: HEADER_SIZE + B::BUFFER_SIZE,
+        };
+        state.tx_dedicated = dedicated;
+        state.tx_fifo_put_index = dedicated;
+    }
+
+    fn tx_fifo_put_index(&self) -> Option<u8> {
+        let state = self.state();
+        let put = state.tx_fifo_put_index;
+        let full = put >= state.tx_buffers.count || state.tx_pending & (1 << put) != 0;
+        (!full).then_some(put)
+    }
+
+    fn is_transmission_pending(&self, index: u8) -> bool {
+        self.state().tx_pending & (1 << index) != 0
+    }
+
+    fn request_transmission(&self, index: u8) {
+        let mut state = self.state();
+        let bit = 1 << index;
+
+        if index >= state.tx_dedicated {
+            assert_eq!(index, state.tx_fifo_put_index, "Not the FIFO put index");
+            let size = state.tx_buffers.count - state.tx_dedicated;
+            state.tx_fifo_put_index = state.tx_dedicated + (index - state.tx_dedicated + 1) % size;
+        }
+
+        // A new request resets the outcome of the previous one
+        state.tx_pending |= bit;
+        state.tx_occurred &= !bit;
+        state.tx_cancellation_finished &= !bit;
+    }
+
+    fn request_cancellation(&self, index: u8) {
+        let mut state = self.state();
+        let bit = 1 << index;
+
+        // Frames are exchanged as a whole, a pending one is never on the bus yet
+        if state.tx_pending & bit != 0 {
+            state.tx_pending &= !bit;
+            state.tx_cancellation_finished |= bit;
+        }
+        state.acknowledge_clock_stop();
+    }
+
+    fn has_transmission_occurred(&self, index: u8) -> bool {
+        self.state().tx_occurred & (1 << index) != 0
+    }
+
+    fn has_cancellation_finished(&self, index: u8) -> bool {
+        self.state().tx_cancellation_finished & (1 << index) != 0
+    }
+}
+
+impl BusFrame {
+    pub(super) fn is_extended(&self) -> bool {
+        self.t0 & (1 << 30) != 0
+    }
+
+    pub(super) fn is_fd_format(&self) -> bool {
+        self.t1 & (1 << 21) != 0
+    }
+
+    /// The 11 or 29 bit identifier
+    pub(super) fn id(&self) -> u32 {
+        if self.is_extended() {
+            self.t0 & 0x1FFF_FFFF
+        } else {
+            (self.t0 >> 18) & 0x7FF
+        }
+    }
+
+    pub(super) fn length(&self) -> usize {
+        dlc_to_length(((self.t1 >> 16) & 0xF) as u8)
+    }
+
+    /// Lower keys win the arbitration: the base id is compared first, a standard frame
+    /// wins against an extended one with the same base id
+    pub(super) fn arbitration_key(&self) -> (u32, bool, u32) {
+        let base_id = (self.t0 >> 18) & 0x7FF;
+        let extension = if self.is_extended() {
+            self.t0 & 0x3_FFFF
+        } else {
+            0
+        };
+        (base_id, self.is_extended(), extension)
+    }
+}
@@ -0,0 +1,138 @@
+//!
+//! Bridges [Slcan] to a [CanNode]
+//!
+use crate::{
+    can::{
+        memory::module_ram::CanBuffer,
+        node::{
+            connection::Connected, receive::RxFifo0, transceive::TxDedicated, CanNode,
+            InConfiguration, Running,
+        },
+        timing::{CanBitrate, CanDataBitrate},
+        CanModule,
+    },
+    time::Instant,
+};
+
+use super::{ByteStream, Slcan, SlcanCommand, SlcanFrame};
+
+type SlcanCanNode<'r, 'mem, C, S, TB, RB, M> = CanNode<
+    'r,
+    C,
+    S,
+    TxDedicated<'mem, TB, <M as CanModule>::RAM>,
+    RxFifo0<'mem, RB, <M as CanModule>::RAM>,
+    M,
+>;
+
+/// The node of an [SlcanChannel], in configuration mode while the channel is closed
+pub enum SlcanNode<'r, 'mem, C, TB: CanBuffer, RB: CanBuffer, M: CanModule> {
+    Closed(SlcanCanNode<'r, 'mem, C, InConfiguration, TB, RB, M>),
+    Open(SlcanCanNode<'r, 'mem, C, Running, TB, RB, M>),
+}
+
+/// An SLCAN interface on a byte stream, see [SlcanChannel::process]
+pub struct SlcanChannel<S: ByteStream> {
+    slcan: Slcan,
+    stream: S,
+}
+
+impl<S: ByteStream> SlcanChannel<S> {
+    pub fn new(slcan: Slcan, stream: S) -> Self {
+        SlcanChannel { slcan, stream }
+    }
+
+    pub fn slcan(&self) -> &Slcan {
+        &self.slcan
+    }
+
+    pub fn stream_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Pass received frames to the host and execute its commands, the node changes
+    /// between configuration and running mode as the host opens and closes the
+    /// channel. Call this regularly with the node returned by the previous call.
+    pub fn process<'r, 'mem, C: Connected, TB: CanBuffer, RB: CanBuffer, M: CanModule>(
+        &mut self,
+        mut node: SlcanNode<'r, 'mem, C, TB, RB, M>,
+        now: &Instant,
+    ) -> SlcanNode<'r, 'mem, C, TB, RB, M> {
+        if let SlcanNode::Open(running) = &mut node {
+            while let Some(frame) = running.try_receive_fifo0() {
+                let header = frame.header();
+                let slcan_frame = if header.rtr() {
+                    SlcanFrame::remote(frame.get_id(), header.data_length().min(8))
+                } else {
+                    SlcanFrame::new(
+                        frame.get_id(),
+                        frame.data(),
+                        frame.is_fd_format(),
+                        frame.bitrate_switching(),
+                    )
+                };
+                self.slcan.on_frame(&slcan_frame, now);
+            }
+        }
+
+        while let Some(command) = self.slcan.poll(&mut self.stream) {
+            let (next, success) = execute(node, command);
+            node = next;
+            self.slcan.complete(success);
+        }
+
+        self.slcan.flush(&mut self.stream);
+        node
+    }
+}
+
+/// Execute a command on the node, returns the node and whether the command succeeded
+fn execute<'r, 'mem, C: Connected, TB: CanBuffer, RB: CanBuffer, M: CanModule>(
+    node: SlcanNode<'r, 'mem, C, TB, RB, M>,
+    command: SlcanCommand,
+) -> (SlcanNode<'r, 'mem, C, TB, RB, M>, bool) {
+    match (node, command) {
+        (
+            SlcanNode::Closed(node),
+            SlcanCommand::Open {
+                bitrate,
+                nominal_timing,
+                listen_only,
+            },
+        ) => {
+            let nominal =
+                nominal_timing.map_or_else(|| CanBitrate::from_frequency(bitrate.nominal), Ok);
+            let Ok(nominal) = nominal else {
+                defmt::warn!("SLCAN bitrate {} not supported", bitrate);
+                return (SlcanNode::Closed(node), false);
+            };
+            let node = match bitrate.data.map(CanDataBitrate::from_frequency) {
+                None => node.disable_fd(),
+                Some(Ok(data)) => node.set_data_bitrate(&data),
+                Some(Err(())) => {
+                    defmt::warn!("SLCAN bitrate {} not supported", bitrate);
+                    return (SlcanNode::Closed(node), false);
+                }
+            };
+            let node = node
+                .set_bitrate(&nominal)
+                .set_bus_monitoring(listen_only)
+                .finalize();
+            (SlcanNode::Open(node), true)
+        }
+        (SlcanNode::Open(node), SlcanCommand::Close) => {
+            (SlcanNode::Closed(node.reconfigure()), true)
+        }
+        (SlcanNode::Open(mut node), SlcanCommand::Transmit(frame)) => {
+            let sent = match (frame.to_tx_frame::<TB>(), node.acquire_transmit_buffer()) {
+                (Some(tx_frame), Some(buffer)) => {
+                    buffer.set_frame(tx_frame).send();
+                    true
+                }
+                _ => false,
+            };
+            (SlcanNode::Open(node), sent)
+        }
+        (node, _) => (node, false),
+    }
+}
@@ -0,0 +1,514 @@
+//!
+//! Lawicel SLCAN protocol, to use the board as CAN interface for `slcand`
+//!
+//! [Slcan] parses the ASCII commands of the host and formats received frames:
+//!
+//! - `O`, `L` and `C` open, open listen-only and close the channel
+//! - `S0` to `S8` select the nominal bitrate, `sXXYY` the nominal bit timing given as
+//!   SJA1000 bus timing registers `BTR0`/`BTR1`, see [CanBitrate::from_sja1000]
+//! - `Y1`, `Y2`, `Y4`, `Y5` and `Y8` select the data bitrate of CAN FD frames in Mbit/s
+//! - `t`/`T` send standard/extended frames, `r`/`R` remote frames and `d`/`D` CAN FD
+//!   frames, `b`/`B` CAN FD frames with bitrate switching
+//! - `Z0`/`Z1` disable/enable millisecond timestamps on received frames
+//! - `F`, `V` and `N` report the status flags, version and serial number
+//!
+//! Everything else is refused.
+//!
+//! Like the other protocols it never touches the hardware: bytes are passed in with
+//! [Slcan::on_byte] or read from a [ByteStream] with [Slcan::poll], so recorded
+//! sessions can be replayed on the host. [SlcanChannel] bridges it to a
+//! [CanNode](crate::can::node::CanNode).
+//!
+use defmt::Format;
+
+use crate::{
+    can::{
+        memory::module_ram::{dlc_to_length, length_to_dlc, CanBuffer},
+        node::bitrate_detection::BitrateCandidate,
+        timing::{CanBitrate, Kbps},
+        CanID, CanTxFrame,
+    },
+    time::Instant,
+};
+
+mod channel;
+
+pub use channel::{SlcanChannel, SlcanNode};
+
+/// Responses to commands
+const OK: u8 = b'\r';
+const ERROR: u8 = 0x07;
+
+/// Longest command: `B`, 8 id digits, the length code and 64 data bytes
+const MAX_LINE: usize = 1 + 8 + 1 + 2 * 64;
+/// Longest received frame: a command and a timestamp
+const MAX_FRAME_LINE: usize = MAX_LINE + 4 + 1;
+/// Formatted frames waiting for the host at most
+const OUTPUT_LENGTH: usize = 1024;
+
+/// Timestamps count milliseconds and wrap after a minute
+const TIMESTAMP_PERIOD: u64 = 60_000;
+
+/// `F` status flag for frames dropped as the host did not keep up
+const STATUS_DATA_OVERRUN: u8 = 0x08;
+
+const VERSION: &[u8] = b"V1013";
+
+/// `S0` to `S8`
+const NOMINAL_BITRATES: [u32; 9] = [10, 20, 50, 100, 125, 250, 500, 800, 1_000];
+
+/// A byte oriented connection to the host, e.g. a UART or USB CDC serial port
+pub trait ByteStream {
+    /// The next received byte, if any
+    fn read(&mut self) -> Option<u8>;
+
+    /// Write as many bytes as possible without blocking, returns the number written
+    fn write(&mut self, bytes: &[u8]) -> usize;
+}
+
+/// A frame as exchanged with the host
+#[derive(Clone, Copy)]
+pub struct SlcanFrame {
+    pub id: CanID,
+    data: [u8; 64],
+    length: u8,
+    /// Remote frames have a length but no data
+    pub remote: bool,
+    pub fd_format: bool,
+    pub bitrate_switching: bool,
+}
+
+impl SlcanFrame {
+    /// A classic or CAN FD data frame, more than 8 bytes need `fd_format`
+    pub fn new(id: CanID, data: &[u8], fd_format: bool, bitrate_switching: bool) -> Self {
+        defmt::assert!(data.len() <= 64 && (fd_format || data.len() <= 8));
+        let mut frame = SlcanFrame {
+            id,
+            data: [0; 64],
+            length: data.len() as u8,
+            remote: false,
+            fd_format,
+            bitrate_switching: fd_format && bitrate_switching,
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        frame
+    }
+
+    /// A remote frame requesting `length` bytes
+    pub fn remote(id: CanID, length: usize) -> Self {
+        defmt::assert!(length <= 8);
+        SlcanFrame {
+            id,
+            data: [0; 64],
+            length: length as u8,
+            remote: true,
+            fd_format: false,
+            bitrate_switching: false,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        if self.remote {
+            return &[];
+        }
+        &self.data[..self.length as usize]
+    }
+
+    pub fn length(&self) -> usize {
+        self.length as usize
+    }
+
+    /// The frame to transmit, frames longer than the buffer cannot be sent
+    pub fn to_tx_frame<B: CanBuffer>(&self) -> Option<CanTxFrame<B>> {
+        if self.data().len() > B::BUFFER_SIZE {
+            return None;
+        }
+        let mut frame = CanTxFrame::<B>::default();
+        frame.set_id(self.id);
+        if self.remote {
+            frame.set_remote_request(self.length as usize);
+        } else {
+            frame.set_data(self.data());
+            if self.fd_format {
+                frame.set_fd_format(self.bitrate_switching);
+            }
+        }
+        Some(frame)
+    }
+}
+
+/// A command that needs the node, answer it with [Slcan::complete]
+#[derive(Clone, Copy)]
+pub enum SlcanCommand {
+    /// Apply the bitrate and start taking part in bus traffic, without acknowledging
+    /// or sending frames when `listen_only`
+    Open {
+        bitrate: BitrateCandidate,
+        /// The bit timing of an `s` command, applied instead of the timing for
+        /// `bitrate.nominal`
+        nominal_timing: Option<CanBitrate>,
+        listen_only: bool,
+    },
+    /// Leave the bus
+    Close,
+    Transmit(SlcanFrame),
+}
+
+/// Response owed for a returned command
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    Open { listen_only: bool },
+    Close,
+    Transmit { extended: bool },
+}
+
+/// Bytes waiting for the host
+struct OutputQueue {
+    bytes: [u8; OUTPUT_LENGTH],
+    head: usize,
+    length: usize,
+}
+
+impl OutputQueue {
+    const fn new() -> Self {
+        OutputQueue {
+            bytes: [0; OUTPUT_LENGTH],
+            head: 0,
+            length: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        if OUTPUT_LENGTH - self.length < bytes.len() {
+            return Err(());
+        }
+        for byte in bytes {
+            self.bytes[(self.head + self.length) % OUTPUT_LENGTH] = *byte;
+            self.length += 1;
+        }
+        Ok(())
+    }
+
+    /// The longest contiguous run of queued bytes
+    fn front(&self) -> &[u8] {
+        let end = (self.head + self.length).min(OUTPUT_LENGTH);
+        &self.bytes[self.head..end]
+    }
+
+    fn consume(&mut self, count: usize) {
+        self.head = (self.head + count) % OUTPUT_LENGTH;
+        self.length -= count;
+    }
+}
+
+/// SLCAN protocol engine, see the [module](self) documentation
+pub struct Slcan {
+    serial_number: u16,
+    line: [u8; MAX_LINE],
+    line_length: usize,
+    /// The current line exceeds [MAX_LINE] and is refused at its end
+    overlong: bool,
+    /// Some while open, whether listen-only
+    open: Option<bool>,
+    nominal_bitrate: Option<Kbps>,
+    /// Set by `s`, `nominal_bitrate` is the bitrate it yields
+    nominal_timing: Option<CanBitrate>,
+    data_bitrate: Option<Kbps>,
+    timestamps: bool,
+    pending: Option<Pending>,
+    status: u8,
+    output: OutputQueue,
+}
+
+impl Slcan {
+    /// A closed channel without bitrate, the serial number is reported by `N`
+    pub fn new(serial_number: u16) -> Self {
+        Slcan {
+            serial_number,
+            line: [0; MAX_LINE],
+            line_length: 0,
+            overlong: false,
+            open: None,
+            nominal_bitrate: None,
+            nominal_timing: None,
+            data_bitrate: None,
+            timestamps: false,
+            pending: None,
+            status: 0,
+            output: OutputQueue::new(),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open.is_some()
+    }
+
+    /// Write pending output and read bytes until a command needs the node
+    pub fn poll<S: ByteStream>(&mut self, stream: &mut S) -> Option<SlcanCommand> {
+        self.flush(stream);
+        while self.pending.is_none() {
+            let command = self.on_byte(stream.read()?);
+            if command.is_some() {
+                return command;
+            }
+        }
+        None
+    }
+
+    /// Write as much of the pending output as the stream takes
+    pub fn flush<S: ByteStream>(&mut self, stream: &mut S) {
+        while self.output.length > 0 {
+            let written = stream.write(self.output.front());
+            if written == 0 {
+                break;
+            }
+            self.output.consume(written);
+        }
+    }
+
+    /// Process a byte from the host, returns the command once a line is complete and
+    /// needs the node. Answer it with [Slcan::complete] before passing further bytes.
+    pub fn on_byte(&mut self, byte: u8) -> Option<SlcanCommand> {
+        if byte != b'\r' {
+            if self.line_length == MAX_LINE {
+                self.overlong = true;
+            } else {
+                self.line[self.line_length] = byte;
+                self.line_length += 1;
+            }
+            return None;
+        }
+
+        let length = core::mem::take(&mut self.line_length);
+        if core::mem::take(&mut self.overlong) {
+            self.respond(&[ERROR]);
+            return None;
+        }
+        // `slcand` sends empty lines to flush the buffer of the adapter
+        if length == 0 {
+            return None;
+        }
+
+        let line = self.line;
+        let result = self.on_line(&line[..length]);
+        if result.is_err() {
+            self.respond(&[ERROR]);
+        }
+        result.ok().flatten()
+    }
+
+    /// Answer the last returned command
+    pub fn complete(&mut self, success: bool) {
+        let Some(pending) = self.pending.take() else {
+            defmt::warn!("SLCAN completion without command");
+            return;
+        };
+
+        match (pending, success) {
+            (Pending::Open { listen_only }, true) => {
+                self.open = Some(listen_only);
+                self.respond(&[OK]);
+            }
+            (Pending::Close, _) => {
+                self.open = None;
+                self.respond(&[OK]);
+            }
+            (Pending::Transmit { extended }, true) => {
+                self.respond(if extended { b"Z\r" } else { b"z\r" })
+            }
+            (_, false) => self.respond(&[ERROR]),
+        }
+    }
+
+    /// Pass a frame received while open to the host
+    pub fn on_frame(&mut self, frame: &SlcanFrame, now: &Instant) {
+        if self.open.is_none() {
+            return;
+        }
+
+        let mut line = [0; MAX_FRAME_LINE];
+        let (command, id_digits, id) = match (frame.id, frame.remote, frame.fd_format) {
+            (CanID::Standard(id), true, _) => (b'r', 3, id as u32),
+            (CanID::Extended(id), true, _) => (b'R', 8, id),
+            (CanID::Standard(id), false, false) => (b't', 3, id as u32),
+            (CanID::Extended(id), false, false) => (b'T', 8, id),
+            (CanID::Standard(id), false, true) => (
+                if frame.bitrate_switching { b'b' } else { b'd' },
+                3,
+                id as u32,
+            ),
+            (CanID::Extended(id), false, true) => {
+                (if frame.bitrate_switching { b'B' } else { b'D' }, 8, id)
+            }
+        };
+        line[0] = command;
+        let mut length = 1;
+        length += write_hex(&mut line[length..], id, id_digits);
+
+        // Cannot fail, lengths are at most 64 bytes
+        let dlc = length_to_dlc(frame.length()).unwrap();
+        length += write_hex(&mut line[length..], dlc as u32, 1);
+        for byte in frame.data() {
+            length += write_hex(&mut line[length..], *byte as u32, 2);
+        }
+
+        if self.timestamps {
+            let millis = now.time_since_boot().as_millis() as u64 % TIMESTAMP_PERIOD;
+            length += write_hex(&mut line[length..], millis as u32, 4);
+        }
+        line[length] = b'\r';
+        length += 1;
+
+        if self.output.push(&line[..length]).is_err() {
+            defmt::warn!("SLCAN output full, dropping frame");
+            self.status |= STATUS_DATA_OVERRUN;
+        }
+    }
+
+    /// Handle a complete line, Err is answered with an error
+    fn on_line(&mut self, line: &[u8]) -> Result<Option<SlcanCommand>, ()> {
+        let (&command, arguments) = line.split_first().ok_or(())?;
+
+        match (command, arguments) {
+            (b'O' | b'L', []) => {
+                if self.open.is_some() {
+                    return Err(());
+                }
+                let bitrate = BitrateCandidate {
+                    nominal: self.nominal_bitrate.ok_or(())?,
+                    data: self.data_bitrate,
+                };
+                let listen_only = command == b'L';
+                self.pending = Some(Pending::Open { listen_only });
+                Ok(Some(SlcanCommand::Open {
+                    bitrate,
+                    nominal_timing: self.nominal_timing,
+                    listen_only,
+                }))
+            }
+            (b'C', []) => {
+                if self.open.is_none() {
+                    self.respond(&[OK]);
+                    return Ok(None);
+                }
+                self.pending = Some(Pending::Close);
+                Ok(Some(SlcanCommand::Close))
+            }
+            (b'S', [index]) if self.open.is_none() => {
+                let index = (*index as char).to_digit(10).ok_or(())? as usize;
+                let kbps = *NOMINAL_BITRATES.get(index).ok_or(())?;
+                self.nominal_bitrate = Some(Kbps::new(kbps));
+                self.nominal_timing = None;
+                self.respond(&[OK]);
+                Ok(None)
+            }
+            (b's', registers @ [_, _, _, _]) if self.open.is_none() => {
+                let registers = parse_hex(registers).ok_or(())?;
+                let timing = CanBitrate::from_sja1000((registers >> 8) as u8, registers as u8)?;
+                self.nominal_bitrate = Some(timing.kbps());
+                self.nominal_timing = Some(timing);
+                self.respond(&[OK]);
+                Ok(None)
+            }
+            (b'Y', [mbps @ (b'1' | b'2' | b'4' | b'5' | b'8')]) if self.open.is_none() => {
+                self.data_bitrate = Some(Kbps::new((mbps - b'0') as u32 * 1_000));
+                self.respond(&[OK]);
+                Ok(None)
+            }
+            (b'Z', [enabled @ (b'0' | b'1')]) => {
+                self.timestamps = *enabled == b'1';
+                self.respond(&[OK]);
+                Ok(None)
+            }
+            (b'F', []) => {
+                let mut response = [b'F', 0, 0, b'\r'];
+                write_hex(
+                    &mut response[1..3],
+                    core::mem::take(&mut self.status) as u32,
+                    2,
+                );
+                self.respond(&response);
+                Ok(None)
+            }
+            (b'V', []) => {
+                self.respond(VERSION);
+                self.respond(&[OK]);
+                Ok(None)
+            }
+            (b'N', []) => {
+                let mut response = [b'N', 0, 0, 0, 0, b'\r'];
+                write_hex(&mut response[1..5], self.serial_number as u32, 4);
+                self.respond(&response);
+                Ok(None)
+            }
+            (b't' | b'T' | b'r' | b'R' | b'd' | b'D' | b'b' | b'B', _) => {
+                // Listen-only channels never send
+                if self.open != Some(false) {
+                    return Err(());
+                }
+                let frame = parse_frame(command, arguments).ok_or(())?;
+                self.pending = Some(Pending::Transmit {
+                    extended: matches!(frame.id, CanID::Extended(_)),
+                });
+                Ok(Some(SlcanCommand::Transmit(frame)))
+            }
+            _ => Err(()),
+        }
+    }
+
+    fn respond(&mut self, bytes: &[u8]) {
+        if self.output.push(bytes).is_err() {
+            defmt::warn!("SLCAN output full, dropping response");
+        }
+    }
+}
+
+/// Parse the frame of a transmit command, `arguments` are the bytes after the command
+fn parse_frame(command: u8, arguments: &[u8]) -> Option<SlcanFrame> {
+    let extended = command.is_ascii_uppercase();
+    let id_digits = if extended { 8 } else { 3 };
+    let id = parse_hex(arguments.get(..id_digits)?)?;
+    let id = if extended {
+        (id <= 0x1FFF_FFFF).then_some(CanID::Extended(id))?
+    } else {
+        (id <= 0x7FF).then_some(CanID::Standard(id as u16))?
+    };
+
+    let dlc = parse_hex(arguments.get(id_digits..id_digits + 1)?)? as u8;
+    let data = &arguments[id_digits + 1..];
+
+    match command.to_ascii_lowercase() {
+        b'r' => (dlc <= 8 && data.is_empty()).then(|| SlcanFrame::remote(id, dlc as usize)),
+        fd @ (b't' | b'd' | b'b') => {
+            let fd_format = fd != b't';
+            if !fd_format && dlc > 8 {
+                return None;
+            }
+            let length = dlc_to_length(dlc);
+            if data.len() != 2 * length {
+                return None;
+            }
+            let mut bytes = [0; 64];
+            for (byte, digits) in bytes.iter_mut().zip(data.chunks(2)) {
+                *byte = parse_hex(digits)? as u8;
+            }
+            Some(SlcanFrame::new(id, &bytes[..length], fd_format, fd == b'b'))
+        }
+        _ => None,
+    }
+}
+
+fn parse_hex(digits: &[u8]) -> Option<u32> {
+    digits.iter().try_fold(0, |value, digit| {
+        Some(value << 4 | (*digit as char).to_digit(16)?)
+    })
+}
+
+/// Write the lowest `digits` nibbles of the value as upper case hex, returns `digits`
+fn write_hex(buffer: &mut [u8], value: u32, digits: usize) -> usize {
+    for (index, byte) in buffer[..digits].iter_mut().enumerate() {
+        let nibble = (value >> (4 * (digits - 1 - index))) & 0xF;
+        *byte = b"0123456789ABCDEF"[nibble as usize];
+    }
+    digits
+}
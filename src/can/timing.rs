@@ -3,6 +3,7 @@ use defmt::Format;
 /// A helper structure to configure nominal bit timing for can
 ///
 /// See https://www.infineon.com/dgdl/Infineon-AURIX_TC3xx_Part2-UserManual-v02_00-EN.pdf?fileId=5546d462712ef9b701717d35f8541d94
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct CanBitrate {
     sync_jump_width: u8,
     pre_scaler: u16,
@@ -18,6 +19,22 @@ const BITRATE_50KHZ: CanBitrate = CanBitrate {
     time_segment2: 1,
 };
 
+/// Manually crunched numbers to yield a 125khz bitrate for a 80Mhz CAN clock
+const BITRATE_125KHZ: CanBitrate = CanBitrate {
+    sync_jump_width: 1,
+    pre_scaler: 40,
+    time_segment1: 13,
+    time_segment2: 2,
+};
+
+/// Manually crunched numbers to yield a 250khz bitrate for a 80Mhz CAN clock
+const BITRATE_250KHZ: CanBitrate = CanBitrate {
+    sync_jump_width: 1,
+    pre_scaler: 20,
+    time_segment1: 13,
+    time_segment2: 2,
+};
+
 /// Manually crunched numbers to yield a 500khz bitrate for a 80Mhz CAN clock
 const BITRATE_500KHZ: CanBitrate = CanBitrate {
     sync_jump_width: 1,
@@ -26,10 +43,36 @@ const BITRATE_500KHZ: CanBitrate = CanBitrate {
     time_segment2: 2,
 };
 
+/// Manually crunched numbers to yield a 1Mhz bitrate for a 80Mhz CAN clock
+const BITRATE_1MHZ: CanBitrate = CanBitrate {
+    sync_jump_width: 1,
+    pre_scaler: 5,
+    time_segment1: 13,
+    time_segment2: 2,
+};
+
+/// 80Mhz CAN clock over the 16Mhz reference clock of the SJA1000
+const SJA1000_CLOCK_RATIO: u16 = 5;
+
+/// Largest nominal pre-scaler (`NBTP.NBRP` + 1)
+const MAX_PRE_SCALER: u16 = 512;
+
 /// Zero-cost abstraction to express the unit "kilobits per second" through the type
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Clone, Copy, Format)]
 pub struct Kbps(u32);
 
+impl Kbps {
+    /// A new bitrate in kilobits per second
+    pub const fn new(kbps: u32) -> Self {
+        Kbps(kbps)
+    }
+
+    /// The raw value in kilobits per second
+    pub const fn value(&self) -> u32 {
+        self.0
+    }
+}
+
 pub trait U32Ext {
     /// Interpret the given value as kilobits per seconds
     fn kbps(&self) -> Kbps;
@@ -44,16 +87,48 @@ impl U32Ext for u32 {
 impl CanBitrate {
     /// Infer can bitrate timings for the given frequency. This assumes an 80Mhz
     /// f_async CAN clock.
+    #[allow(clippy::result_unit_err)]
     pub fn from_frequency(bitrate: Kbps) -> Result<Self, ()> {
         // Here a more sophisticated algorithm may be implemented, but for now
-        // we only have hardcoded values for 50khz and 500khz
-        if bitrate == 50u32.kbps() {
-            Ok(BITRATE_50KHZ)
-        } else if bitrate == 500u32.kbps() {
-            Ok(BITRATE_500KHZ)
-        } else {
-            Err(())
+        // we only have hardcoded values for the usual bitrates
+        match bitrate.value() {
+            50 => Ok(BITRATE_50KHZ),
+            125 => Ok(BITRATE_125KHZ),
+            250 => Ok(BITRATE_250KHZ),
+            500 => Ok(BITRATE_500KHZ),
+            1_000 => Ok(BITRATE_1MHZ),
+            _ => Err(()),
+        }
+    }
+
+    /// The bit timing of the SJA1000 bus timing registers `BTR0`/`BTR1` at its 16Mhz
+    /// reference clock, as used by SLCAN adapters. The time quanta are scaled to the
+    /// 80Mhz CAN clock, triple sampling (`BTR1.SAM`) is not supported and ignored.
+    ///
+    /// Err if the pre-scaler exceeds the range of the node or the first time segment
+    /// is a single time quantum.
+    #[allow(clippy::result_unit_err)]
+    pub fn from_sja1000(btr0: u8, btr1: u8) -> Result<Self, ()> {
+        // A time quantum of the SJA1000 is 2 * (BRP + 1) periods of its 16Mhz clock
+        let pre_scaler = SJA1000_CLOCK_RATIO * 2 * ((btr0 & 0x3F) as u16 + 1);
+        let time_segment1 = (btr1 & 0x0F) + 1;
+        if pre_scaler > MAX_PRE_SCALER || time_segment1 < 2 {
+            return Err(());
         }
+
+        Ok(CanBitrate {
+            sync_jump_width: (btr0 >> 6) + 1,
+            pre_scaler,
+            time_segment1,
+            time_segment2: ((btr1 >> 4) & 0x07) + 1,
+        })
+    }
+
+    /// The bitrate of the timing, rounded down to whole kilobits per second
+    pub fn kbps(&self) -> Kbps {
+        let bit_time =
+            self.pre_scaler as u32 * (1 + self.time_segment1 as u32 + self.time_segment2 as u32);
+        Kbps(80_000 / bit_time)
     }
 
     pub fn sync_jump_width(&self) -> u8 {
@@ -82,11 +157,10 @@ impl Format for CanBitrate {
         let time_quanta = clock_period * self.pre_scaler as f32;
 
         let total_bit_time =
-            (self.sync_jump_width as f32 + self.time_segment1 as f32 + self.time_segment2 as f32)
-                * time_quanta;
+            (1.0 + self.time_segment1 as f32 + self.time_segment2 as f32) * time_quanta;
 
-        let sample_point = (self.sync_jump_width as f32 + self.time_segment1 as f32)
-            / (self.sync_jump_width as f32 + self.time_segment1 as f32 + self.time_segment2 as f32);
+        let sample_point = (1.0 + self.time_segment1 as f32)
+            / (1.0 + self.time_segment1 as f32 + self.time_segment2 as f32);
 
         defmt::write!(
             fmt,
@@ -96,3 +170,182 @@ impl Format for CanBitrate {
         );
     }
 }
+
+/// A helper structure to configure the data phase bit timing (CAN FD with bitrate switching)
+///
+/// Limits are smaller than for the nominal bit timing, see `DBTP` in the reference manual
+pub struct CanDataBitrate {
+    sync_jump_width: u8,
+    pre_scaler: u8,
+    time_segment1: u8,
+    time_segment2: u8,
+    /// Transmitter delay compensation, see [CanDataBitrate::delay_compensation_offset]
+    delay_compensation: bool,
+}
+
+/// Data bitrates from which the transmitter delay compensation is used
+const DELAY_COMPENSATION_MIN_KBPS: u32 = 1_000;
+
+/// Manually crunched numbers to yield a 1Mhz data bitrate for a 80Mhz CAN clock
+const DATA_BITRATE_1MHZ: CanDataBitrate = CanDataBitrate {
+    sync_jump_width: 1,
+    pre_scaler: 4,
+    time_segment1: 15,
+    time_segment2: 4,
+    delay_compensation: true,
+};
+
+/// Manually crunched numbers to yield a 2Mhz data bitrate for a 80Mhz CAN clock
+const DATA_BITRATE_2MHZ: CanDataBitrate = CanDataBitrate {
+    sync_jump_width: 1,
+    pre_scaler: 2,
+    time_segment1: 15,
+    time_segment2: 4,
+    delay_compensation: true,
+};
+
+/// Manually crunched numbers to yield a 4Mhz data bitrate for a 80Mhz CAN clock
+const DATA_BITRATE_4MHZ: CanDataBitrate = CanDataBitrate {
+    sync_jump_width: 1,
+    pre_scaler: 1,
+    time_segment1: 15,
+    time_segment2: 4,
+    delay_compensation: true,
+};
+
+/// Manually crunched numbers to yield a 5Mhz data bitrate for a 80Mhz CAN clock
+const DATA_BITRATE_5MHZ: CanDataBitrate = CanDataBitrate {
+    sync_jump_width: 1,
+    pre_scaler: 1,
+    time_segment1: 11,
+    time_segment2: 4,
+    delay_compensation: true,
+};
+
+impl CanDataBitrate {
+    /// Infer can data phase timings for the given frequency. This assumes an 80Mhz
+    /// f_async CAN clock.
+    #[allow(clippy::result_unit_err)]
+    pub fn from_frequency(bitrate: Kbps) -> Result<Self, ()> {
+        match bitrate.value() {
+            1_000 => Ok(DATA_BITRATE_1MHZ),
+            2_000 => Ok(DATA_BITRATE_2MHZ),
+            4_000 => Ok(DATA_BITRATE_4MHZ),
+            5_000 => Ok(DATA_BITRATE_5MHZ),
+            _ => Err(()),
+        }
+    }
+
+    pub fn sync_jump_width(&self) -> u8 {
+        self.sync_jump_width
+    }
+
+    pub fn pre_scaler(&self) -> u8 {
+        self.pre_scaler
+    }
+
+    pub fn tseg1(&self) -> u8 {
+        self.time_segment1
+    }
+
+    pub fn tseg2(&self) -> u8 {
+        self.time_segment2
+    }
+
+    /// Use the transmitter delay compensation or not, it is on by default for data
+    /// bitrates from 1 Mbit/s on
+    pub fn with_delay_compensation(self, enabled: bool) -> Self {
+        CanDataBitrate {
+            delay_compensation: enabled,
+            ..self
+        }
+    }
+
+    /// Offset of the secondary sample point (`TDCR.TDCO`) in CAN clock periods, None if
+    /// the transmitter delay compensation is not used (`DBTP.TDC`)
+    ///
+    /// The offset is the data phase sample point, the node adds the measured
+    /// transmitter delay to it. The delay would exceed the short data bit times
+    /// otherwise and the node would see bit errors on its own frames.
+    pub fn delay_compensation_offset(&self) -> Option<u8> {
+        let bit_time =
+            self.pre_scaler as u32 * (1 + self.time_segment1 as u32 + self.time_segment2 as u32);
+        let kbps = 80_000 / bit_time;
+
+        (self.delay_compensation && kbps >= DELAY_COMPENSATION_MIN_KBPS)
+            .then(|| self.pre_scaler * (1 + self.time_segment1))
+    }
+}
+
+impl Format for CanDataBitrate {
+    fn format(&self, fmt: defmt::Formatter) {
+        let ccu_can_frequency = 80_000_000.0f32;
+
+        let time_quanta = self.pre_scaler as f32 / ccu_can_frequency;
+
+        let total_bit_time =
+            (1.0 + self.time_segment1 as f32 + self.time_segment2 as f32) * time_quanta;
+
+        defmt::write!(
+            fmt,
+            "DataBitRate {{ frequency: {}hz }}",
+            1.0 / total_bit_time
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_compensation_at_the_sample_point() {
+        let offset = |kbps| {
+            CanDataBitrate::from_frequency(Kbps::new(kbps))
+                .unwrap()
+                .delay_compensation_offset()
+        };
+
+        // 4 clock periods per time quantum, sample point after 16 of 20 time quanta
+        assert_eq!(offset(1_000), Some(64));
+        assert_eq!(offset(2_000), Some(32));
+        assert_eq!(offset(4_000), Some(16));
+        assert_eq!(offset(5_000), Some(12));
+
+        let data = DATA_BITRATE_2MHZ.with_delay_compensation(false);
+        assert_eq!(data.delay_compensation_offset(), None);
+
+        let slow = CanDataBitrate {
+            pre_scaler: 8,
+            ..DATA_BITRATE_1MHZ
+        };
+        assert_eq!(slow.delay_compensation_offset(), None);
+    }
+
+    #[test]
+    fn sja1000_bus_timing() {
+        // 500 kbit/s, time quanta of 2 SJA1000 clock periods
+        let timing = CanBitrate::from_sja1000(0x00, 0x1C).unwrap();
+        assert_eq!(
+            (timing.pre_scaler(), timing.tseg1(), timing.tseg2()),
+            (10, 13, 2)
+        );
+        assert!(timing.kbps() == Kbps::new(500));
+
+        // SJW 4, triple sampling ignored, 111.1 kbit/s rounded down
+        let timing = CanBitrate::from_sja1000(0xC5, 0xA7).unwrap();
+        assert_eq!(
+            (
+                timing.sync_jump_width(),
+                timing.pre_scaler(),
+                timing.tseg1(),
+                timing.tseg2()
+            ),
+            (4, 60, 8, 3)
+        );
+        assert!(timing.kbps() == Kbps::new(111));
+
+        assert!(CanBitrate::from_sja1000(0x33, 0x1C).is_err());
+        assert!(CanBitrate::from_sja1000(0x00, 0x10).is_err());
+    }
+}
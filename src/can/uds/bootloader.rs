@@ -0,0 +1,724 @@
+//!
+//! Flash download over UDS: request download (0x34), transfer data (0x36) and request
+//! transfer exit (0x37)
+//!
+//! A [Bootloader] is added to the server with [UdsServer::with_bootloader]. The usual
+//! programming sequence of a tester is:
+//!
+//! 1. programming session and security access
+//! 2. request download of a segment, which erases the sectors of the segment
+//! 3. transfer data, in blocks of the length announced in the download response
+//! 4. request transfer exit
+//! 5. routine [CHECK_MEMORY] with the CRC32 of the segment
+//! 6. steps 2 to 5 for further segments
+//! 7. routine [CHECK_PROGRAMMING_DEPENDENCIES], which activates the new image
+//! 8. ECU reset
+//!
+//! Flash is accessed through [Flash], which only starts erase and program operations.
+//! The bootloader never waits for the flash, the server answers with response pending
+//! while an operation is running and checks it again on every [UdsServer::poll]. The
+//! PFlash cannot be read while a sector of the same bank is erased or programmed, so
+//! everything running meanwhile (CAN driver, server and [Flash] implementation) has to
+//! run from RAM or another bank. Downloads into sectors the [Flash] cannot modify
+//! while the program runs ([Flash::can_modify]) are refused, the PFlash backend
+//! (`PFlash`) refuses the banks of the running code.
+//!
+use defmt::Format;
+
+use crate::can::crc::crc32;
+
+use super::{DiagnosticSession, Nrc, SeedKey, UdsServer, SUPPRESS_POSITIVE_RESPONSE};
+
+/// Routine verifying the CRC32 of the last downloaded segment, the option record is
+/// the expected CRC (4 bytes, big endian)
+pub const CHECK_MEMORY: u16 = 0x0202;
+/// Routine activating the downloaded image once all segments have been checked
+pub const CHECK_PROGRAMMING_DEPENDENCIES: u16 = 0xFF01;
+
+/// Largest supported [Flash::PAGE_SIZE]
+pub const MAX_PAGE_SIZE: usize = 256;
+/// Bytes checked per call of the check memory routine
+const CHECK_CHUNK: u32 = 4096;
+
+/// Routine status records
+const ROUTINE_CORRECT: u8 = 0x00;
+const ROUTINE_INCORRECT: u8 = 0x01;
+
+/// State of the last erase or program operation
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum FlashStatus {
+    Busy,
+    /// The operation succeeded, also reported while idle
+    Done,
+    Failed,
+}
+
+/// Program flash as used by the [Bootloader], usually the PFlash of the DMU
+///
+/// Addresses are those of the [Region]s of the [ImagePolicy].
+pub trait Flash {
+    /// Size of the smallest erasable unit
+    const SECTOR_SIZE: u32;
+    /// Size of the programming unit, at most [MAX_PAGE_SIZE]
+    const PAGE_SIZE: usize;
+    /// Value of erased bytes, the last page of a segment is padded with it
+    const ERASED: u8 = 0x00;
+
+    /// Start erasing the sector at `address`, which is aligned to [Flash::SECTOR_SIZE]
+    fn start_erase(&mut self, address: u32) -> bool;
+
+    /// Start programming a page at `address`, which is aligned to [Flash::PAGE_SIZE]
+    fn start_program(&mut self, address: u32, page: &[u8]) -> bool;
+
+    fn status(&mut self) -> FlashStatus;
+
+    fn read(&mut self, address: u32, buffer: &mut [u8]);
+
+    /// The bank of an [ImagePolicy::AB] the application runs from
+    fn active_bank(&mut self) -> u8 {
+        0
+    }
+
+    /// Run the application from `bank` after the next reset, e.g. by updating the
+    /// swap configuration
+    fn activate_bank(&mut self, _bank: u8) -> bool {
+        false
+    }
+
+    /// Whether `region` can be erased and programmed while the program runs, false
+    /// e.g. if it shares a PFlash bank with the code running during the download
+    fn can_modify(&mut self, _region: Region) -> bool {
+        true
+    }
+}
+
+/// Flash of a server without bootloader
+pub enum NoFlash {}
+
+impl Flash for NoFlash {
+    const SECTOR_SIZE: u32 = 1;
+    const PAGE_SIZE: usize = 1;
+
+    fn start_erase(&mut self, _address: u32) -> bool {
+        match *self {}
+    }
+
+    fn start_program(&mut self, _address: u32, _page: &[u8]) -> bool {
+        match *self {}
+    }
+
+    fn status(&mut self) -> FlashStatus {
+        match *self {}
+    }
+
+    fn read(&mut self, _address: u32, _buffer: &mut [u8]) {
+        match *self {}
+    }
+}
+
+/// Flash area holding an application image
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: u32,
+    pub size: u32,
+}
+
+impl Region {
+    pub const fn new(start: u32, size: u32) -> Self {
+        Region { start, size }
+    }
+
+    fn end(&self) -> u32 {
+        self.start + self.size
+    }
+}
+
+/// Where a new image is programmed to
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum ImagePolicy {
+    /// The image is replaced in place
+    Single(Region),
+    /// Two banks of the same size: the image goes to the inactive bank, which becomes
+    /// active with [CHECK_PROGRAMMING_DEPENDENCIES]. Download addresses are those of
+    /// the first bank and are moved to the inactive one.
+    AB([Region; 2]),
+}
+
+impl ImagePolicy {
+    /// The region of the image as the tester addresses it
+    fn image(&self) -> Region {
+        match self {
+            ImagePolicy::Single(region) => *region,
+            ImagePolicy::AB(banks) => banks[0],
+        }
+    }
+}
+
+/// Flash operation continued on every call of its service
+#[derive(Clone, Copy)]
+enum Operation {
+    Erase {
+        start: u32,
+        next: u32,
+        end: u32,
+    },
+    /// Offset of the next page in the data of the transfer data request
+    Program {
+        offset: usize,
+    },
+    Check {
+        next: u32,
+        crc: u32,
+    },
+}
+
+/// A download between request download and request transfer exit
+#[derive(Clone, Copy)]
+struct Transfer {
+    start: u32,
+    next: u32,
+    end: u32,
+    /// Block sequence counter of the next transfer data request
+    sequence: u8,
+}
+
+/// Flash download state of an [UdsServer], see the module documentation
+pub struct Bootloader<F: Flash> {
+    flash: F,
+    policy: ImagePolicy,
+    security_level: Option<u8>,
+    /// Length of transfer data requests, set by [UdsServer::with_bootloader]
+    max_block_length: usize,
+    operation: Option<Operation>,
+    transfer: Option<Transfer>,
+    /// The last segment whose transfer was completed
+    downloaded: Option<(u32, u32)>,
+    /// Something was erased in this session
+    modified: bool,
+    /// The last requested segment has not been checked yet, no other download is
+    /// accepted until it is
+    unchecked: bool,
+    /// A check or flash operation failed in this session
+    failed: bool,
+    page: [u8; MAX_PAGE_SIZE],
+}
+
+impl<F: Flash> Bootloader<F> {
+    pub fn new(flash: F, policy: ImagePolicy) -> Self {
+        defmt::assert!(F::PAGE_SIZE <= MAX_PAGE_SIZE, "Flash page too large");
+        defmt::assert!(
+            (F::SECTOR_SIZE as usize).is_multiple_of(F::PAGE_SIZE),
+            "Flash sectors have to consist of pages"
+        );
+
+        Bootloader {
+            flash,
+            policy,
+            security_level: None,
+            max_block_length: 0,
+            operation: None,
+            transfer: None,
+            downloaded: None,
+            modified: false,
+            unchecked: false,
+            failed: false,
+            page: [F::ERASED; MAX_PAGE_SIZE],
+        }
+    }
+
+    /// Security level that has to be unlocked to download
+    pub fn with_security_level(self, level: u8) -> Self {
+        Bootloader {
+            security_level: Some(level),
+            ..self
+        }
+    }
+
+    pub fn flash(&mut self) -> &mut F {
+        &mut self.flash
+    }
+
+    /// Forget downloads of the previous session
+    fn reset(&mut self) {
+        self.operation = None;
+        self.transfer = None;
+        self.downloaded = None;
+        self.modified = false;
+        self.unchecked = false;
+        self.failed = false;
+    }
+
+    /// The bank the download goes to
+    fn target_bank(&mut self) -> u8 {
+        match self.policy {
+            ImagePolicy::Single(_) => 0,
+            ImagePolicy::AB(_) => 1 - self.flash.active_bank().min(1),
+        }
+    }
+
+    /// Move an image address range to the target bank
+    fn target(&mut self, address: u32, size: u32) -> Option<u32> {
+        let image = self.policy.image();
+        let end = address.checked_add(size)?;
+        if size == 0 || address < image.start || end > image.end() {
+            return None;
+        }
+        if !(address - image.start).is_multiple_of(F::SECTOR_SIZE) {
+            return None;
+        }
+
+        let start = match self.policy {
+            ImagePolicy::Single(_) => address,
+            ImagePolicy::AB(banks) => {
+                let bank = banks[self.target_bank() as usize];
+                bank.start + (address - image.start)
+            }
+        };
+        if !self.flash.can_modify(Region::new(start, size)) {
+            defmt::warn!("0x{:X}..0x{:X} cannot be modified", start, start + size);
+            return None;
+        }
+        Some(start)
+    }
+
+    /// Fail with response pending while the flash is busy
+    fn flash_ready(&mut self) -> Result<(), Nrc> {
+        match self.flash.status() {
+            FlashStatus::Busy => Err(Nrc::ResponsePending),
+            FlashStatus::Done => Ok(()),
+            FlashStatus::Failed => Err(self.abort()),
+        }
+    }
+
+    /// Abandon the download after a flash error
+    fn abort(&mut self) -> Nrc {
+        defmt::warn!("Flash operation failed, download aborted");
+        self.operation = None;
+        self.transfer = None;
+        self.failed = true;
+        Nrc::GeneralProgrammingFailure
+    }
+
+    fn request_download(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, Nrc> {
+        if self.operation.is_none() {
+            let &[_, data_format, format, ref parameters @ ..] = request else {
+                return Err(Nrc::IncorrectMessageLengthOrInvalidFormat);
+            };
+            let address_length = (format & 0xF) as usize;
+            let size_length = (format >> 4) as usize;
+            if !(1..=4).contains(&address_length)
+                || !(1..=4).contains(&size_length)
+                || parameters.len() != address_length + size_length
+            {
+                return Err(Nrc::IncorrectMessageLengthOrInvalidFormat);
+            }
+            // Neither compression nor encryption
+            if data_format != 0 {
+                return Err(Nrc::RequestOutOfRange);
+            }
+            if self.transfer.is_some() {
+                return Err(Nrc::ConditionsNotCorrect);
+            }
+            // Every segment has to be checked before the next one is downloaded
+            if self.unchecked {
+                return Err(Nrc::RequestSequenceError);
+            }
+
+            let address = be_value(&parameters[..address_length]);
+            let size = be_value(&parameters[address_length..]);
+            let start = self.target(address, size).ok_or(Nrc::RequestOutOfRange)?;
+
+            defmt::debug!("Download of {} bytes to 0x{:X}", size, start);
+            self.modified = true;
+            self.unchecked = true;
+            self.downloaded = None;
+            self.operation = Some(Operation::Erase {
+                start,
+                next: start,
+                end: start + size,
+            });
+        }
+
+        let (start, end) = loop {
+            self.flash_ready()?;
+            let Some(Operation::Erase { start, next, end }) = self.operation else {
+                return Err(Nrc::RequestSequenceError);
+            };
+            if next >= end {
+                break (start, end);
+            }
+            if !self.flash.start_erase(next) {
+                return Err(self.abort());
+            }
+            self.operation = Some(Operation::Erase {
+                start,
+                next: next + F::SECTOR_SIZE,
+                end,
+            });
+        };
+
+        self.operation = None;
+        self.transfer = Some(Transfer {
+            start,
+            next: start,
+            end,
+            sequence: 1,
+        });
+
+        // Length format: 2 bytes of maximum block length
+        response[1] = 0x20;
+        response[2..4].copy_from_slice(&(self.max_block_length as u16).to_be_bytes());
+        Ok(4)
+    }
+
+    fn transfer_data(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, Nrc> {
+        let &[_, sequence, ref data @ ..] = request else {
+            return Err(Nrc::IncorrectMessageLengthOrInvalidFormat);
+        };
+        let transfer = self.transfer.ok_or(Nrc::RequestSequenceError)?;
+
+        if self.operation.is_none() {
+            // A repeated block whose response got lost is only acknowledged again
+            let repeated =
+                transfer.next != transfer.start && sequence == transfer.sequence.wrapping_sub(1);
+            if repeated {
+                response[1] = sequence;
+                return Ok(2);
+            }
+            if sequence != transfer.sequence {
+                return Err(Nrc::WrongBlockSequenceCounter);
+            }
+            if data.is_empty() || data.len() > self.max_block_length - 2 {
+                return Err(Nrc::IncorrectMessageLengthOrInvalidFormat);
+            }
+            let remaining = (transfer.end - transfer.next) as usize;
+            if data.len() > remaining {
+                return Err(Nrc::TransferDataSuspended);
+            }
+            // Only the last block may end within a page
+            if !data.len().is_multiple_of(F::PAGE_SIZE) && data.len() != remaining {
+                return Err(Nrc::IncorrectMessageLengthOrInvalidFormat);
+            }
+            self.operation = Some(Operation::Program { offset: 0 });
+        }
+
+        let address = transfer.next;
+        loop {
+            self.flash_ready()?;
+            let Some(Operation::Program { offset }) = self.operation else {
+                return Err(Nrc::RequestSequenceError);
+            };
+            if offset >= data.len() {
+                break;
+            }
+
+            let page = &mut self.page[..F::PAGE_SIZE];
+            let chunk = &data[offset..data.len().min(offset + F::PAGE_SIZE)];
+            page[..chunk.len()].copy_from_slice(chunk);
+            page[chunk.len()..].fill(F::ERASED);
+            if !self.flash.start_program(address + offset as u32, page) {
+                return Err(self.abort());
+            }
+            self.operation = Some(Operation::Program {
+                offset: offset + F::PAGE_SIZE,
+            });
+        }
+
+        self.operation = None;
+        if let Some(transfer) = &mut self.transfer {
+            transfer.next += data.len() as u32;
+            transfer.sequence = sequence.wrapping_add(1);
+        }
+
+        response[1] = sequence;
+        Ok(2)
+    }
+
+    fn request_transfer_exit(&mut self) -> Result<usize, Nrc> {
+        match &self.transfer {
+            Some(transfer) if transfer.next == transfer.end => {
+                self.downloaded = Some((transfer.start, transfer.end));
+                self.transfer = None;
+                Ok(1)
+            }
+            _ => Err(Nrc::RequestSequenceError),
+        }
+    }
+
+    /// Compare the CRC32 of the last downloaded segment, in chunks between which the
+    /// server answers with response pending
+    fn check_memory(&mut self, options: &[u8], status: &mut [u8]) -> Result<usize, Nrc> {
+        let &[a, b, c, d] = options else {
+            return Err(Nrc::IncorrectMessageLengthOrInvalidFormat);
+        };
+        let (start, end) = self.downloaded.ok_or(Nrc::RequestSequenceError)?;
+
+        let (mut next, mut crc) = match self.operation {
+            Some(Operation::Check { next, crc }) => (next, crc),
+            _ => (start, !0),
+        };
+
+        let chunk_end = end.min(next + CHECK_CHUNK);
+        while next < chunk_end {
+            let length = (chunk_end - next).min(MAX_PAGE_SIZE as u32);
+            let buffer = &mut self.page[..length as usize];
+            self.flash.read(next, buffer);
+            crc = crc32(crc, buffer);
+            next += length;
+        }
+
+        if next < end {
+            self.operation = Some(Operation::Check { next, crc });
+            return Err(Nrc::ResponsePending);
+        }
+
+        self.operation = None;
+        self.unchecked = false;
+        if !crc == u32::from_be_bytes([a, b, c, d]) {
+            status[0] = ROUTINE_CORRECT;
+        } else {
+            defmt::warn!("CRC mismatch of segment 0x{:X}..0x{:X}", start, end);
+            self.failed = true;
+            status[0] = ROUTINE_INCORRECT;
+        }
+        Ok(1)
+    }
+
+    /// Activate the target bank of an [ImagePolicy::AB] once every segment is checked
+    fn check_programming_dependencies(&mut self, status: &mut [u8]) -> Result<usize, Nrc> {
+        if !self.modified || self.unchecked || self.failed || self.transfer.is_some() {
+            status[0] = ROUTINE_INCORRECT;
+            return Ok(1);
+        }
+
+        if let ImagePolicy::AB(_) = self.policy {
+            let bank = self.target_bank();
+            if !self.flash.activate_bank(bank) {
+                return Err(Nrc::GeneralProgrammingFailure);
+            }
+            defmt::info!("Bank {} activated", bank);
+        }
+
+        status[0] = ROUTINE_CORRECT;
+        Ok(1)
+    }
+}
+
+/// Value of a big endian address or size of 1 to 4 bytes
+fn be_value(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |value, &byte| (value << 8) | byte as u32)
+}
+
+impl<'a, K: SeedKey> UdsServer<'a, K> {
+    /// Add the flash download services and routines
+    ///
+    /// The maximum block length of transfer data is taken from the request buffer,
+    /// which has to hold at least one page.
+    pub fn with_bootloader<F: Flash>(self, mut bootloader: Bootloader<F>) -> UdsServer<'a, K, F> {
+        defmt::assert!(F::PAGE_SIZE > 0, "Flash pages must not be empty");
+        // Service id and block sequence counter in front of the data
+        let data = self.request.len().min(u16::MAX as usize).saturating_sub(2);
+        let data = data / F::PAGE_SIZE * F::PAGE_SIZE;
+        defmt::assert!(data > 0, "UDS request buffer too short for a flash page");
+        bootloader.max_block_length = 2 + data;
+
+        UdsServer {
+            config: self.config,
+            data_identifiers: self.data_identifiers,
+            routines: self.routines,
+            security: self.security,
+            session: self.session,
+            session_deadline: self.session_deadline,
+            request: self.request,
+            request_length: self.request_length,
+            response: self.response,
+            pending: self.pending,
+            reset: self.reset,
+            bootloader: Some(bootloader),
+        }
+    }
+}
+
+impl<'a, K: SeedKey, F: Flash> UdsServer<'a, K, F> {
+    pub fn bootloader(&mut self) -> Option<&mut Bootloader<F>> {
+        self.bootloader.as_mut()
+    }
+
+    /// Request download, transfer data and request transfer exit
+    pub(super) fn download(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, Nrc> {
+        let bootloader = self.programming_access(Nrc::ServiceNotSupportedInActiveSession)?;
+        match request[0] {
+            super::SERVICE_REQUEST_DOWNLOAD => bootloader.request_download(request, response),
+            super::SERVICE_TRANSFER_DATA => bootloader.transfer_data(request, response),
+            _ => bootloader.request_transfer_exit(),
+        }
+    }
+
+    /// Run a routine of the bootloader, None for other routines
+    pub(super) fn bootloader_routine(
+        &mut self,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Option<Result<usize, Nrc>> {
+        let id = u16::from_be_bytes([request[2], request[3]]);
+        if self.bootloader.is_none()
+            || ![CHECK_MEMORY, CHECK_PROGRAMMING_DEPENDENCIES].contains(&id)
+        {
+            return None;
+        }
+
+        // Like the routines of the table, which are out of range in other sessions
+        let result = self
+            .programming_access(Nrc::RequestOutOfRange)
+            .and_then(|bootloader| {
+                if request[1] & !SUPPRESS_POSITIVE_RESPONSE != 0x01 {
+                    return Err(Nrc::SubFunctionNotSupported);
+                }
+                let status = &mut response[4..];
+                match id {
+                    CHECK_MEMORY => bootloader.check_memory(&request[4..], status),
+                    _ => bootloader.check_programming_dependencies(status),
+                }
+            });
+
+        Some(result.map(|length| {
+            response[1] = 0x01;
+            response[2..4].copy_from_slice(&request[2..4]);
+            4 + length
+        }))
+    }
+
+    /// Forget downloads when the session changes
+    pub(super) fn reset_bootloader(&mut self) {
+        if let Some(bootloader) = &mut self.bootloader {
+            bootloader.reset();
+        }
+    }
+
+    /// The bootloader, if downloads are possible in the current state. Outside of the
+    /// programming session `wrong_session` is returned.
+    fn programming_access(&mut self, wrong_session: Nrc) -> Result<&mut Bootloader<F>, Nrc> {
+        let unlocked = self.security.unlocked();
+        let bootloader = self.bootloader.as_mut().ok_or(Nrc::ServiceNotSupported)?;
+
+        if self.session != DiagnosticSession::Programming {
+            return Err(wrong_session);
+        }
+        match bootloader.security_level {
+            Some(level) if unlocked != Some(level) => Err(Nrc::SecurityAccessDenied),
+            _ => Ok(bootloader),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flash of 4 sectors at 0x1000, the first one holds the running code
+    struct TestFlash {
+        memory: [u8; 0x400],
+    }
+
+    impl Flash for TestFlash {
+        const SECTOR_SIZE: u32 = 0x100;
+        const PAGE_SIZE: usize = 4;
+
+        fn start_erase(&mut self, address: u32) -> bool {
+            let start = (address - 0x1000) as usize;
+            self.memory[start..start + 0x100].fill(Self::ERASED);
+            true
+        }
+
+        fn start_program(&mut self, address: u32, page: &[u8]) -> bool {
+            let start = (address - 0x1000) as usize;
+            self.memory[start..start + page.len()].copy_from_slice(page);
+            true
+        }
+
+        fn status(&mut self) -> FlashStatus {
+            FlashStatus::Done
+        }
+
+        fn read(&mut self, address: u32, buffer: &mut [u8]) {
+            let start = (address - 0x1000) as usize;
+            buffer.copy_from_slice(&self.memory[start..start + buffer.len()]);
+        }
+
+        fn can_modify(&mut self, region: Region) -> bool {
+            region.start >= 0x1100
+        }
+    }
+
+    fn bootloader() -> Bootloader<TestFlash> {
+        let flash = TestFlash {
+            memory: [0xFF; 0x400],
+        };
+        let mut bootloader =
+            Bootloader::new(flash, ImagePolicy::Single(Region::new(0x1000, 0x400)));
+        bootloader.max_block_length = 2 + 8;
+        bootloader
+    }
+
+    fn request_download(address: u32, size: u32) -> [u8; 11] {
+        let mut request = [0x34, 0x00, 0x44, 0, 0, 0, 0, 0, 0, 0, 0];
+        request[3..7].copy_from_slice(&address.to_be_bytes());
+        request[7..].copy_from_slice(&size.to_be_bytes());
+        request
+    }
+
+    #[test]
+    fn running_code_is_not_erased() {
+        let mut bootloader = bootloader();
+        let mut response = [0; 8];
+
+        let result = bootloader.request_download(&request_download(0x1000, 0x200), &mut response);
+        assert!(result == Err(Nrc::RequestOutOfRange));
+        assert_eq!(bootloader.flash().memory[0], 0xFF);
+
+        let result = bootloader.request_download(&request_download(0x1100, 0x200), &mut response);
+        assert!(result == Ok(4));
+        assert_eq!(&response[1..4], &[0x20, 0x00, 0x0A]);
+        assert_eq!(bootloader.flash().memory[0x100], 0x00);
+        assert_eq!(bootloader.flash().memory[0], 0xFF);
+    }
+
+    #[test]
+    fn download_and_check() {
+        let mut bootloader = bootloader();
+        let mut response = [0; 8];
+        let data: Vec<u8> = (0..10).collect();
+
+        assert!(bootloader
+            .request_download(&request_download(0x1200, 10), &mut response)
+            .is_ok());
+        let mut request = vec![0x36, 1];
+        request.extend_from_slice(&data[..8]);
+        assert!(bootloader.transfer_data(&request, &mut response) == Ok(2));
+        let mut request = vec![0x36, 2];
+        request.extend_from_slice(&data[8..]);
+        assert!(bootloader.transfer_data(&request, &mut response) == Ok(2));
+        assert!(bootloader.request_transfer_exit() == Ok(1));
+
+        // The last page is padded
+        assert_eq!(
+            &bootloader.flash().memory[0x200..0x20C],
+            &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0]
+        );
+
+        // A new download needs the check first
+        let request = request_download(0x1300, 4);
+        assert!(
+            bootloader.request_download(&request, &mut response) == Err(Nrc::RequestSequenceError)
+        );
+
+        let crc = !crc32(!0, &data);
+        let mut status = [0xFF];
+        assert!(bootloader.check_memory(&crc.to_be_bytes(), &mut status) == Ok(1));
+        assert_eq!(status, [ROUTINE_CORRECT]);
+        assert!(bootloader.check_programming_dependencies(&mut status) == Ok(1));
+        assert_eq!(status, [ROUTINE_CORRECT]);
+    }
+}
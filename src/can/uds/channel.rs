@@ -0,0 +1,58 @@
+//!
+//! Serves an [UdsServer] over an [IsoTpChannel]
+//!
+use crate::{
+    can::{
+        isotp::{IsoTpChannel, IsoTpEvent},
+        memory::module_ram::CanBuffer,
+        node::{
+            connection::Connected, receive::RxFifo0, transceive::TxDedicated, CanNode, Running,
+        },
+        CanModule,
+    },
+    time::Instant,
+};
+
+use super::{Flash, SeedKey, UdsServer};
+
+impl<'a, K: SeedKey, F: Flash> UdsServer<'a, K, F> {
+    /// Receive requests from the channel and send the responses, call this regularly
+    /// (in place of [IsoTpChannel::process]).
+    ///
+    /// A reset requested through ECU reset is performed once the response is sent.
+    #[allow(clippy::type_complexity)]
+    pub fn serve<'r, 'mem, C: Connected, TB: CanBuffer, RB: CanBuffer, M: CanModule>(
+        &mut self,
+        channel: &mut IsoTpChannel<'_>,
+        node: &mut CanNode<
+            'r,
+            C,
+            Running,
+            TxDedicated<'mem, TB, M::RAM>,
+            RxFifo0<'mem, RB, M::RAM>,
+            M,
+        >,
+        now: &Instant,
+    ) {
+        let response = match channel.process(node, now) {
+            Some(IsoTpEvent::Received { .. }) => self.handle_request(channel.received(), now),
+            Some(IsoTpEvent::TransmitFailed(error)) => {
+                defmt::warn!("Failed to send UDS response: {}", error);
+                self.poll(now)
+            }
+            _ => self.poll(now),
+        };
+
+        if let Some(response) = response {
+            if let Err(error) = channel.send(response) {
+                defmt::warn!("Dropping UDS response: {}", error);
+            }
+        }
+
+        if !channel.transport().is_transmitting() {
+            if let Some(reset) = self.take_reset() {
+                reset.perform();
+            }
+        }
+    }
+}
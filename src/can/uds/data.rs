@@ -0,0 +1,152 @@
+//!
+//! Registration tables for data identifiers and routines
+//!
+use super::{DiagnosticSession, Nrc};
+
+/// Read the value of a data identifier into the buffer, returning its length.
+/// Values that do not fit the buffer are answered with [Nrc::ResponseTooLong].
+pub type ReadData = fn(&mut [u8]) -> Result<usize, Nrc>;
+
+/// Write the value of a data identifier
+pub type WriteData = fn(&[u8]) -> Result<(), Nrc>;
+
+/// Start, stop or get the results of a routine: gets the option record of the request
+/// and writes the status record of the response, returning its length
+pub type RoutineHandler = fn(&[u8], &mut [u8]) -> Result<usize, Nrc>;
+
+/// Set of diagnostic sessions an identifier or routine is accessible in
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Sessions(u8);
+
+impl Sessions {
+    pub const ALL: Sessions = Sessions(0b111);
+    pub const NON_DEFAULT: Sessions = Sessions(0b110);
+
+    pub const fn only(session: DiagnosticSession) -> Self {
+        Sessions(Self::bit(session))
+    }
+
+    pub const fn with(self, session: DiagnosticSession) -> Self {
+        Sessions(self.0 | Self::bit(session))
+    }
+
+    pub const fn contains(&self, session: DiagnosticSession) -> bool {
+        self.0 & Self::bit(session) != 0
+    }
+
+    const fn bit(session: DiagnosticSession) -> u8 {
+        1 << (session as u8 - 1)
+    }
+}
+
+/// Entry of the data identifier table, e.g.
+///
+/// ```ignore
+/// static DATA_IDENTIFIERS: [DataIdentifier; 1] =
+///     [DataIdentifier::new(0xF190).readable(read_vin).with_security_level(1)];
+/// ```
+#[derive(Clone, Copy)]
+pub struct DataIdentifier {
+    pub id: u16,
+    pub read: Option<ReadData>,
+    pub write: Option<WriteData>,
+    pub sessions: Sessions,
+    /// Security level that has to be unlocked for any access
+    pub security_level: Option<u8>,
+}
+
+impl DataIdentifier {
+    /// An identifier that can neither be read nor written (yet), in all sessions
+    pub const fn new(id: u16) -> Self {
+        DataIdentifier {
+            id,
+            read: None,
+            write: None,
+            sessions: Sessions::ALL,
+            security_level: None,
+        }
+    }
+
+    pub const fn readable(self, read: ReadData) -> Self {
+        DataIdentifier {
+            read: Some(read),
+            ..self
+        }
+    }
+
+    pub const fn writable(self, write: WriteData) -> Self {
+        DataIdentifier {
+            write: Some(write),
+            ..self
+        }
+    }
+
+    pub const fn in_sessions(self, sessions: Sessions) -> Self {
+        DataIdentifier { sessions, ..self }
+    }
+
+    pub const fn with_security_level(self, level: u8) -> Self {
+        DataIdentifier {
+            security_level: Some(level),
+            ..self
+        }
+    }
+}
+
+/// Entry of the routine table, built like a [DataIdentifier]
+#[derive(Clone, Copy)]
+pub struct Routine {
+    pub id: u16,
+    pub start: Option<RoutineHandler>,
+    pub stop: Option<RoutineHandler>,
+    pub results: Option<RoutineHandler>,
+    pub sessions: Sessions,
+    /// Security level that has to be unlocked to control the routine
+    pub security_level: Option<u8>,
+}
+
+impl Routine {
+    /// A routine without handlers, in non default sessions
+    pub const fn new(id: u16) -> Self {
+        Routine {
+            id,
+            start: None,
+            stop: None,
+            results: None,
+            sessions: Sessions::NON_DEFAULT,
+            security_level: None,
+        }
+    }
+
+    pub const fn on_start(self, start: RoutineHandler) -> Self {
+        Routine {
+            start: Some(start),
+            ..self
+        }
+    }
+
+    pub const fn on_stop(self, stop: RoutineHandler) -> Self {
+        Routine {
+            stop: Some(stop),
+            ..self
+        }
+    }
+
+    pub const fn on_results(self, results: RoutineHandler) -> Self {
+        Routine {
+            results: Some(results),
+            ..self
+        }
+    }
+
+    pub const fn in_sessions(self, sessions: Sessions) -> Self {
+        Routine { sessions, ..self }
+    }
+
+    pub const fn with_security_level(self, level: u8) -> Self {
+        Routine {
+            security_level: Some(level),
+            ..self
+        }
+    }
+}
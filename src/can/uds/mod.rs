@@ -0,0 +1,770 @@
+//!
+//! UDS (ISO 14229) diagnostic server
+//!
+//! [UdsServer] handles complete requests and produces complete responses, it is not
+//! bound to a transport: [UdsServer::handle_request] takes a request and
+//! [UdsServer::poll] has to be called regularly for timing. [UdsServer::serve] runs the
+//! server on an [IsoTpChannel](super::isotp::IsoTpChannel).
+//!
+//! Supported services: diagnostic session control (0x10), ECU reset (0x11), read/write
+//! data by identifier (0x22/0x2E), security access (0x27), routine control (0x31) and
+//! tester present (0x3E). Data identifiers and routines are registered through static
+//! tables (see [DataIdentifier] and [Routine]), seed & key are provided by a [SeedKey].
+//! With a [Bootloader] the server also downloads images into flash (0x34, 0x36, 0x37).
+//!
+//! Handlers that cannot answer right away return [Nrc::ResponsePending], they are called
+//! again on every [UdsServer::poll]. The server keeps P2/P2* by sending negative
+//! responses with code 0x78 (response pending) while the handler is not done.
+//!
+use core::time::Duration;
+
+use defmt::Format;
+
+use crate::{reset::ResetType, time::Instant};
+
+mod bootloader;
+mod channel;
+mod data;
+#[cfg(not(feature = "sim"))]
+mod pflash;
+mod security;
+
+pub use bootloader::{
+    Bootloader, Flash, FlashStatus, ImagePolicy, NoFlash, Region, CHECK_MEMORY,
+    CHECK_PROGRAMMING_DEPENDENCIES, MAX_PAGE_SIZE,
+};
+pub use data::{DataIdentifier, ReadData, Routine, RoutineHandler, Sessions, WriteData};
+#[cfg(not(feature = "sim"))]
+pub use pflash::PFlash;
+pub use security::SeedKey;
+
+use security::SecurityState;
+
+const SERVICE_SESSION_CONTROL: u8 = 0x10;
+const SERVICE_ECU_RESET: u8 = 0x11;
+const SERVICE_READ_DATA_BY_IDENTIFIER: u8 = 0x22;
+const SERVICE_SECURITY_ACCESS: u8 = 0x27;
+const SERVICE_WRITE_DATA_BY_IDENTIFIER: u8 = 0x2E;
+const SERVICE_ROUTINE_CONTROL: u8 = 0x31;
+const SERVICE_REQUEST_DOWNLOAD: u8 = 0x34;
+const SERVICE_TRANSFER_DATA: u8 = 0x36;
+const SERVICE_REQUEST_TRANSFER_EXIT: u8 = 0x37;
+const SERVICE_TESTER_PRESENT: u8 = 0x3E;
+
+/// Service id of negative responses
+const NEGATIVE_RESPONSE: u8 = 0x7F;
+/// Added to the service id in positive responses
+const POSITIVE_RESPONSE_OFFSET: u8 = 0x40;
+/// Set in the sub-function byte to suppress the positive response
+const SUPPRESS_POSITIVE_RESPONSE: u8 = 0x80;
+
+/// Negative response codes
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Nrc {
+    GeneralReject = 0x10,
+    ServiceNotSupported = 0x11,
+    SubFunctionNotSupported = 0x12,
+    IncorrectMessageLengthOrInvalidFormat = 0x13,
+    ResponseTooLong = 0x14,
+    BusyRepeatRequest = 0x21,
+    ConditionsNotCorrect = 0x22,
+    RequestSequenceError = 0x24,
+    RequestOutOfRange = 0x31,
+    SecurityAccessDenied = 0x33,
+    InvalidKey = 0x35,
+    ExceededNumberOfAttempts = 0x36,
+    RequiredTimeDelayNotExpired = 0x37,
+    UploadDownloadNotAccepted = 0x70,
+    TransferDataSuspended = 0x71,
+    GeneralProgrammingFailure = 0x72,
+    WrongBlockSequenceCounter = 0x73,
+    /// Returned by handlers that are not done yet, they are called again later
+    ResponsePending = 0x78,
+    SubFunctionNotSupportedInActiveSession = 0x7E,
+    ServiceNotSupportedInActiveSession = 0x7F,
+}
+
+/// Diagnostic sessions, see [UdsServer::session]
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DiagnosticSession {
+    Default = 0x01,
+    Programming = 0x02,
+    Extended = 0x03,
+}
+
+impl DiagnosticSession {
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0x01 => Some(DiagnosticSession::Default),
+            0x02 => Some(DiagnosticSession::Programming),
+            0x03 => Some(DiagnosticSession::Extended),
+            _ => None,
+        }
+    }
+}
+
+/// Reset requested through the ECU reset service
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+pub enum EcuReset {
+    Hard,
+    Soft,
+}
+
+impl EcuReset {
+    /// Perform the reset through the SCU: a hard reset is a system reset, a soft reset
+    /// an application reset
+    pub fn perform(self) -> ! {
+        crate::reset::software_reset(match self {
+            EcuReset::Hard => ResetType::System,
+            EcuReset::Soft => ResetType::Application,
+        })
+    }
+}
+
+/// Timing and security parameters of the server
+#[derive(Clone, Copy)]
+pub struct UdsConfig {
+    /// Time until the (first) response has to be sent (P2server)
+    pub p2: Duration,
+    /// Time until the response has to be sent after a response pending (P2*server)
+    pub p2_extended: Duration,
+    /// Time without requests after which a non default session ends (S3server)
+    pub s3: Duration,
+    /// Number of invalid keys after which security access is delayed
+    pub max_key_attempts: u8,
+    /// Time security access is refused for after too many invalid keys
+    pub key_attempt_delay: Duration,
+}
+
+impl Default for UdsConfig {
+    fn default() -> Self {
+        UdsConfig {
+            p2: Duration::from_millis(50),
+            p2_extended: Duration::from_millis(5000),
+            s3: Duration::from_millis(5000),
+            max_key_attempts: 3,
+            key_attempt_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+/// A request whose handler returned [Nrc::ResponsePending]
+struct PendingRequest {
+    /// Time at which a response pending has to be sent
+    response_pending_due: Instant,
+    /// Whether a response pending was sent, a positive response is not suppressed then
+    response_pending_sent: bool,
+}
+
+/// UDS server, see the module documentation
+pub struct UdsServer<'a, K: SeedKey, F: Flash = NoFlash> {
+    config: UdsConfig,
+    data_identifiers: &'a [DataIdentifier],
+    routines: &'a [Routine],
+    security: SecurityState<K>,
+    session: DiagnosticSession,
+    /// End of the current non default session (S3)
+    session_deadline: Option<Instant>,
+    request: &'a mut [u8],
+    request_length: usize,
+    response: &'a mut [u8],
+    pending: Option<PendingRequest>,
+    reset: Option<EcuReset>,
+    bootloader: Option<Bootloader<F>>,
+}
+
+impl<'a, K: SeedKey> UdsServer<'a, K> {
+    /// Create a server, the buffers limit the length of requests and responses
+    pub fn new(
+        config: UdsConfig,
+        data_identifiers: &'a [DataIdentifier],
+        routines: &'a [Routine],
+        seed_key: K,
+        request: &'a mut [u8],
+        response: &'a mut [u8],
+    ) -> Self {
+        defmt::assert!(
+            response.len() >= 8,
+            "UDS response buffer needs at least 8 bytes"
+        );
+
+        UdsServer {
+            config,
+            data_identifiers,
+            routines,
+            security: SecurityState::new(seed_key),
+            session: DiagnosticSession::Default,
+            session_deadline: None,
+            request,
+            request_length: 0,
+            response,
+            pending: None,
+            reset: None,
+            bootloader: None,
+        }
+    }
+}
+
+impl<'a, K: SeedKey, F: Flash> UdsServer<'a, K, F> {
+    pub fn session(&self) -> DiagnosticSession {
+        self.session
+    }
+
+    /// The unlocked security level, if any
+    pub fn security_level(&self) -> Option<u8> {
+        self.security.unlocked()
+    }
+
+    /// Take the reset requested through the ECU reset service, to be performed once
+    /// the response is sent
+    pub fn take_reset(&mut self) -> Option<EcuReset> {
+        self.reset.take()
+    }
+
+    /// Handle a request, returning the response to send (if any)
+    pub fn handle_request(&mut self, request: &[u8], now: &Instant) -> Option<&[u8]> {
+        let &service = request.first()?;
+
+        if self.session_deadline.is_some() {
+            self.session_deadline = Some(now + self.config.s3);
+        }
+
+        if self.pending.is_some() {
+            return self.negative_response(service, Nrc::BusyRepeatRequest);
+        }
+
+        if request.len() > self.request.len() {
+            return self.negative_response(service, Nrc::IncorrectMessageLengthOrInvalidFormat);
+        }
+
+        self.request[..request.len()].copy_from_slice(request);
+        self.request_length = request.len();
+        self.pending = Some(PendingRequest {
+            response_pending_due: now + self.config.p2 / 2,
+            response_pending_sent: false,
+        });
+
+        self.process_pending(now)
+    }
+
+    /// Check the session timeout and retry a pending request, returning the response to
+    /// send (if any)
+    pub fn poll(&mut self, now: &Instant) -> Option<&[u8]> {
+        if let Some(deadline) = self.session_deadline {
+            if *now >= deadline && self.pending.is_none() {
+                defmt::debug!("Diagnostic session timed out");
+                self.enter_session(DiagnosticSession::Default, now);
+            }
+        }
+
+        if self.pending.is_some() {
+            self.process_pending(now)
+        } else {
+            None
+        }
+    }
+
+    fn process_pending(&mut self, now: &Instant) -> Option<&[u8]> {
+        let service = self.request[0];
+
+        // Take the buffers out to hand them to the services next to `self`
+        let request = core::mem::take(&mut self.request);
+        let response = core::mem::take(&mut self.response);
+        let result = self.service(&request[..self.request_length], response, now);
+        let suppress = suppresses_positive_response(&request[..self.request_length]);
+        self.request = request;
+        self.response = response;
+
+        let pending = self.pending.as_mut()?;
+        match result {
+            Err(Nrc::ResponsePending) => {
+                if *now < pending.response_pending_due {
+                    return None;
+                }
+                // Sending half way through the budget leaves time for the transport
+                pending.response_pending_due = now + self.config.p2_extended / 2;
+                pending.response_pending_sent = true;
+                self.negative_response(service, Nrc::ResponsePending)
+            }
+            Err(nrc) => {
+                self.pending = None;
+                self.negative_response(service, nrc)
+            }
+            Ok(length) => {
+                let response_pending_sent = pending.response_pending_sent;
+                self.pending = None;
+                if suppress && !response_pending_sent {
+                    None
+                } else {
+                    Some(&self.response[..length])
+                }
+            }
+        }
+    }
+
+    fn negative_response(&mut self, service: u8, nrc: Nrc) -> Option<&[u8]> {
+        defmt::debug!("Negative response to service 0x{:X}: {}", service, nrc);
+        self.response[..3].copy_from_slice(&[NEGATIVE_RESPONSE, service, nrc as u8]);
+        Some(&self.response[..3])
+    }
+
+    /// Run the service of the request, returning the length of the positive response
+    fn service(
+        &mut self,
+        request: &[u8],
+        response: &mut [u8],
+        now: &Instant,
+    ) -> Result<usize, Nrc> {
+        let service = request[0];
+        // Identifiers from 0xC0 on are no requests, their response would not fit in a byte
+        response[0] = service
+            .checked_add(POSITIVE_RESPONSE_OFFSET)
+            .ok_or(Nrc::ServiceNotSupported)?;
+
+        match service {
+            SERVICE_SESSION_CONTROL => self.session_control(request, response, now),
+            SERVICE_ECU_RESET => self.ecu_reset(request, response),
+            SERVICE_READ_DATA_BY_IDENTIFIER => self.read_data_by_identifier(request, response),
+            SERVICE_SECURITY_ACCESS => self.security_access(request, response, now),
+            SERVICE_WRITE_DATA_BY_IDENTIFIER => self.write_data_by_identifier(request, response),
+            SERVICE_ROUTINE_CONTROL => self.routine_control(request, response),
+            SERVICE_REQUEST_DOWNLOAD | SERVICE_TRANSFER_DATA | SERVICE_REQUEST_TRANSFER_EXIT => {
+                self.download(request, response)
+            }
+            SERVICE_TESTER_PRESENT => tester_present(request, response),
+            _ => Err(Nrc::ServiceNotSupported),
+        }
+    }
+
+    fn session_control(
+        &mut self,
+        request: &[u8],
+        response: &mut [u8],
+        now: &Instant,
+    ) -> Result<usize, Nrc> {
+        let &[_, sub_function] = request else {
+            return Err(Nrc::IncorrectMessageLengthOrInvalidFormat);
+        };
+        let session = DiagnosticSession::from_id(sub_function & !SUPPRESS_POSITIVE_RESPONSE)
+            .ok_or(Nrc::SubFunctionNotSupported)?;
+
+        self.enter_session(session, now);
+
+        let p2 = self.config.p2.as_millis() as u16;
+        let p2_extended = (self.config.p2_extended.as_millis() / 10) as u16;
+
+        response[1] = session as u8;
+        response[2..4].copy_from_slice(&p2.to_be_bytes());
+        response[4..6].copy_from_slice(&p2_extended.to_be_bytes());
+        Ok(6)
+    }
+
+    /// Every session transition locks security access again
+    fn enter_session(&mut self, session: DiagnosticSession, now: &Instant) {
+        defmt::debug!("Entering diagnostic session {}", session);
+        self.session = session;
+        self.security.lock();
+        self.reset_bootloader();
+        self.session_deadline = match session {
+            DiagnosticSession::Default => None,
+            _ => Some(now + self.config.s3),
+        };
+    }
+
+    fn ecu_reset(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, Nrc> {
+        let &[_, sub_function] = request else {
+            return Err(Nrc::IncorrectMessageLengthOrInvalidFormat);
+        };
+        let sub_function = sub_function & !SUPPRESS_POSITIVE_RESPONSE;
+
+        self.reset = Some(match sub_function {
+            0x01 => EcuReset::Hard,
+            0x03 => EcuReset::Soft,
+            _ => return Err(Nrc::SubFunctionNotSupported),
+        });
+
+        response[1] = sub_function;
+        Ok(2)
+    }
+
+    fn read_data_by_identifier(&self, request: &[u8], response: &mut [u8]) -> Result<usize, Nrc> {
+        if request.len() < 3 || request.len().is_multiple_of(2) {
+            return Err(Nrc::IncorrectMessageLengthOrInvalidFormat);
+        }
+
+        let mut length = 1;
+        for id in request[1..].chunks(2) {
+            let entry = self.data_identifier(u16::from_be_bytes([id[0], id[1]]))?;
+            let read = entry.read.ok_or(Nrc::RequestOutOfRange)?;
+
+            let record = response
+                .get_mut(length..length + 2)
+                .ok_or(Nrc::ResponseTooLong)?;
+            record.copy_from_slice(id);
+            length += 2;
+
+            length += read(&mut response[length..])?;
+        }
+
+        Ok(length)
+    }
+
+    fn write_data_by_identifier(&self, request: &[u8], response: &mut [u8]) -> Result<usize, Nrc> {
+        if request.len() < 4 {
+            return Err(Nrc::IncorrectMessageLengthOrInvalidFormat);
+        }
+
+        let entry = self.data_identifier(u16::from_be_bytes([request[1], request[2]]))?;
+        let write = entry.write.ok_or(Nrc::RequestOutOfRange)?;
+        write(&request[3..])?;
+
+        response[1..3].copy_from_slice(&request[1..3]);
+        Ok(3)
+    }
+
+    fn routine_control(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, Nrc> {
+        if request.len() < 4 {
+            return Err(Nrc::IncorrectMessageLengthOrInvalidFormat);
+        }
+        if let Some(result) = self.bootloader_routine(request, response) {
+            return result;
+        }
+
+        let sub_function = request[1] & !SUPPRESS_POSITIVE_RESPONSE;
+        let id = u16::from_be_bytes([request[2], request[3]]);
+
+        let routine = self
+            .routines
+            .iter()
+            .find(|routine| routine.id == id)
+            .ok_or(Nrc::RequestOutOfRange)?;
+        self.check_access(routine.sessions, routine.security_level)?;
+
+        let handler = match sub_function {
+            0x01 => routine.start,
+            0x02 => routine.stop,
+            0x03 => routine.results,
+            _ => None,
+        }
+        .ok_or(Nrc::SubFunctionNotSupported)?;
+
+        response[1] = sub_function;
+        response[2..4].copy_from_slice(&request[2..4]);
+        Ok(4 + handler(&request[4..], &mut response[4..])?)
+    }
+
+    /// Look up a data identifier accessible in the current state
+    fn data_identifier(&self, id: u16) -> Result<&'a DataIdentifier, Nrc> {
+        let entry = self
+            .data_identifiers
+            .iter()
+            .find(|entry| entry.id == id)
+            .ok_or(Nrc::RequestOutOfRange)?;
+        self.check_access(entry.sessions, entry.security_level)?;
+        Ok(entry)
+    }
+
+    fn check_access(&self, sessions: Sessions, security_level: Option<u8>) -> Result<(), Nrc> {
+        if !sessions.contains(self.session) {
+            return Err(Nrc::RequestOutOfRange);
+        }
+
+        match security_level {
+            Some(level) if self.security.unlocked() != Some(level) => {
+                Err(Nrc::SecurityAccessDenied)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn tester_present(request: &[u8], response: &mut [u8]) -> Result<usize, Nrc> {
+    let &[_, sub_function] = request else {
+        return Err(Nrc::IncorrectMessageLengthOrInvalidFormat);
+    };
+    if sub_function & !SUPPRESS_POSITIVE_RESPONSE != 0 {
+        return Err(Nrc::SubFunctionNotSupported);
+    }
+
+    response[1] = 0;
+    Ok(2)
+}
+
+/// Whether the request has a sub-function with the suppress positive response bit set
+fn suppresses_positive_response(request: &[u8]) -> bool {
+    let has_sub_function = matches!(
+        request[0],
+        SERVICE_SESSION_CONTROL
+            | SERVICE_ECU_RESET
+            | SERVICE_SECURITY_ACCESS
+            | SERVICE_ROUTINE_CONTROL
+            | SERVICE_TESTER_PRESENT
+    );
+
+    has_sub_function
+        && request
+            .get(1)
+            .is_some_and(|sub| sub & SUPPRESS_POSITIVE_RESPONSE != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU8, Ordering};
+
+    use super::*;
+
+    /// Security level 1 with a fixed seed, the key is the inverted seed
+    struct TestKeys;
+
+    impl SeedKey for TestKeys {
+        fn seed(&mut self, level: u8, seed: &mut [u8]) -> Result<usize, Nrc> {
+            if level != 1 {
+                return Err(Nrc::SubFunctionNotSupported);
+            }
+            seed[..2].copy_from_slice(&[0x12, 0x34]);
+            Ok(2)
+        }
+
+        fn verify_key(&mut self, _level: u8, seed: &[u8], key: &[u8]) -> bool {
+            seed.iter().zip(key).all(|(seed, key)| *key == !seed) && seed.len() == key.len()
+        }
+    }
+
+    fn read_serial(value: &mut [u8]) -> Result<usize, Nrc> {
+        let value = value.get_mut(..2).ok_or(Nrc::ResponseTooLong)?;
+        value.copy_from_slice(&[0xAB, 0xCD]);
+        Ok(2)
+    }
+
+    /// Calls of [slow_routine], only used by the response pending test
+    static SLOW_ROUTINE_CALLS: AtomicU8 = AtomicU8::new(0);
+
+    /// Done on the sixth call
+    fn slow_routine(_options: &[u8], _status: &mut [u8]) -> Result<usize, Nrc> {
+        if SLOW_ROUTINE_CALLS.fetch_add(1, Ordering::Relaxed) < 5 {
+            Err(Nrc::ResponsePending)
+        } else {
+            Ok(0)
+        }
+    }
+
+    static DATA_IDENTIFIERS: [DataIdentifier; 2] = [
+        DataIdentifier::new(0xF18C)
+            .readable(read_serial)
+            .in_sessions(Sessions::NON_DEFAULT),
+        DataIdentifier::new(0xF1A0)
+            .readable(read_serial)
+            .with_security_level(1),
+    ];
+
+    static ROUTINES: [Routine; 1] = [Routine::new(0x1234).on_start(slow_routine)];
+
+    fn server<'a>(request: &'a mut [u8], response: &'a mut [u8]) -> UdsServer<'a, TestKeys> {
+        UdsServer::new(
+            UdsConfig::default(),
+            &DATA_IDENTIFIERS,
+            &ROUTINES,
+            TestKeys,
+            request,
+            response,
+        )
+    }
+
+    fn at(millis: u64) -> Instant {
+        Instant::from_time_since_boot(Duration::from_millis(millis))
+    }
+
+    fn ask(server: &mut UdsServer<TestKeys>, request: &[u8], millis: u64) -> Option<Vec<u8>> {
+        server
+            .handle_request(request, &at(millis))
+            .map(<[u8]>::to_vec)
+    }
+
+    fn poll(server: &mut UdsServer<TestKeys>, millis: u64) -> Option<Vec<u8>> {
+        server.poll(&at(millis)).map(<[u8]>::to_vec)
+    }
+
+    fn negative(service: u8, nrc: Nrc) -> Option<Vec<u8>> {
+        Some(vec![NEGATIVE_RESPONSE, service, nrc as u8])
+    }
+
+    #[test]
+    fn negative_response_codes() {
+        let (mut request, mut response) = ([0; 16], [0; 16]);
+        let mut server = server(&mut request, &mut response);
+
+        assert_eq!(
+            ask(&mut server, &[0x19, 0x02], 0),
+            negative(0x19, Nrc::ServiceNotSupported)
+        );
+        assert_eq!(
+            ask(&mut server, &[0xC0], 0),
+            negative(0xC0, Nrc::ServiceNotSupported)
+        );
+        assert_eq!(
+            ask(&mut server, &[0x10], 0),
+            negative(0x10, Nrc::IncorrectMessageLengthOrInvalidFormat)
+        );
+        assert_eq!(
+            ask(&mut server, &[0x10, 0x05], 0),
+            negative(0x10, Nrc::SubFunctionNotSupported)
+        );
+        assert_eq!(
+            ask(&mut server, &[0x22; 17], 0),
+            negative(0x22, Nrc::IncorrectMessageLengthOrInvalidFormat)
+        );
+
+        // Not accessible in the default session, locked
+        assert_eq!(
+            ask(&mut server, &[0x22, 0xF1, 0x8C], 0),
+            negative(0x22, Nrc::RequestOutOfRange)
+        );
+        assert_eq!(
+            ask(&mut server, &[0x31, 0x01, 0x12, 0x34], 0),
+            negative(0x31, Nrc::RequestOutOfRange)
+        );
+        assert_eq!(
+            ask(&mut server, &[0x22, 0xF1, 0xA0], 0),
+            negative(0x22, Nrc::SecurityAccessDenied)
+        );
+        assert_eq!(
+            ask(&mut server, &[0x2E, 0xF1, 0x8C, 0x00], 0),
+            negative(0x2E, Nrc::RequestOutOfRange)
+        );
+
+        assert_eq!(
+            ask(&mut server, &[0x27, 0x03], 0),
+            negative(0x27, Nrc::SubFunctionNotSupported)
+        );
+        assert_eq!(
+            ask(&mut server, &[0x27, 0x02, 0xED, 0xCB], 0),
+            negative(0x27, Nrc::RequestSequenceError)
+        );
+
+        assert_eq!(ask(&mut server, &[0x3E, 0x00], 0), Some(vec![0x7E, 0x00]));
+        assert_eq!(ask(&mut server, &[0x3E, 0x80], 0), None);
+    }
+
+    #[test]
+    fn session_timeout() {
+        let (mut request, mut response) = ([0; 16], [0; 16]);
+        let mut server = server(&mut request, &mut response);
+
+        // P2 of 50 ms and P2* of 5000 ms in units of 10 ms
+        assert_eq!(
+            ask(&mut server, &[0x10, 0x03], 0),
+            Some(vec![0x50, 0x03, 0x00, 0x32, 0x01, 0xF4])
+        );
+        assert_eq!(poll(&mut server, 4999), None);
+        assert!(server.session() == DiagnosticSession::Extended);
+
+        // Every request restarts S3
+        assert_eq!(ask(&mut server, &[0x3E, 0x80], 4000), None);
+        assert_eq!(poll(&mut server, 8999), None);
+        assert!(server.session() == DiagnosticSession::Extended);
+        assert_eq!(
+            ask(&mut server, &[0x22, 0xF1, 0x8C], 8999),
+            Some(vec![0x62, 0xF1, 0x8C, 0xAB, 0xCD])
+        );
+
+        poll(&mut server, 13_998);
+        assert!(server.session() == DiagnosticSession::Extended);
+        poll(&mut server, 13_999);
+        assert!(server.session() == DiagnosticSession::Default);
+        assert_eq!(
+            ask(&mut server, &[0x22, 0xF1, 0x8C], 14_000),
+            negative(0x22, Nrc::RequestOutOfRange)
+        );
+
+        // No timeout in the default session
+        poll(&mut server, 100_000);
+        assert!(server.session() == DiagnosticSession::Default);
+    }
+
+    #[test]
+    fn response_pending() {
+        let (mut request, mut response) = ([0; 16], [0; 16]);
+        let mut server = server(&mut request, &mut response);
+        assert!(ask(&mut server, &[0x10, 0x83], 0).is_none());
+
+        // Suppressed positive response, handler called on every poll
+        assert_eq!(ask(&mut server, &[0x31, 0x81, 0x12, 0x34], 0), None);
+        assert_eq!(poll(&mut server, 24), None);
+
+        // First one half way through P2, then half way through P2*
+        assert_eq!(poll(&mut server, 25), negative(0x31, Nrc::ResponsePending));
+        assert_eq!(
+            ask(&mut server, &[0x3E, 0x00], 100),
+            negative(0x3E, Nrc::BusyRepeatRequest)
+        );
+        assert_eq!(poll(&mut server, 2524), None);
+        assert_eq!(
+            poll(&mut server, 2525),
+            negative(0x31, Nrc::ResponsePending)
+        );
+        assert_eq!(SLOW_ROUTINE_CALLS.load(Ordering::Relaxed), 5);
+
+        // The positive response is sent after a response pending
+        assert_eq!(poll(&mut server, 2600), Some(vec![0x71, 0x01, 0x12, 0x34]));
+        assert_eq!(poll(&mut server, 2700), None);
+        assert_eq!(
+            ask(&mut server, &[0x3E, 0x00], 2700),
+            Some(vec![0x7E, 0x00])
+        );
+    }
+
+    #[test]
+    fn security_access_delay() {
+        let (mut request, mut response) = ([0; 16], [0; 16]);
+        let mut server = server(&mut request, &mut response);
+
+        for nrc in [
+            Nrc::InvalidKey,
+            Nrc::InvalidKey,
+            Nrc::ExceededNumberOfAttempts,
+        ] {
+            assert_eq!(
+                ask(&mut server, &[0x27, 0x01], 0),
+                Some(vec![0x67, 0x01, 0x12, 0x34])
+            );
+            assert_eq!(
+                ask(&mut server, &[0x27, 0x02, 0x12, 0x34], 0),
+                negative(0x27, nrc)
+            );
+        }
+
+        assert_eq!(
+            ask(&mut server, &[0x27, 0x01], 9999),
+            negative(0x27, Nrc::RequiredTimeDelayNotExpired)
+        );
+        assert_eq!(
+            ask(&mut server, &[0x27, 0x01], 10_000),
+            Some(vec![0x67, 0x01, 0x12, 0x34])
+        );
+        assert_eq!(
+            ask(&mut server, &[0x27, 0x02, 0xED, 0xCB], 10_000),
+            Some(vec![0x67, 0x02])
+        );
+        assert!(server.security_level() == Some(1));
+        assert_eq!(
+            ask(&mut server, &[0x22, 0xF1, 0xA0], 10_000),
+            Some(vec![0x62, 0xF1, 0xA0, 0xAB, 0xCD])
+        );
+
+        // Unlocked levels get a zero seed, a seed is used once
+        assert_eq!(
+            ask(&mut server, &[0x27, 0x01], 10_000),
+            Some(vec![0x67, 0x01, 0x00, 0x00])
+        );
+        assert_eq!(
+            ask(&mut server, &[0x27, 0x02, 0xED, 0xCB], 10_000),
+            negative(0x27, Nrc::RequestSequenceError)
+        );
+
+        // Locked again by a session transition
+        ask(&mut server, &[0x10, 0x01], 10_000);
+        assert!(server.security_level().is_none());
+    }
+}
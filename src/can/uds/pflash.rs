@@ -0,0 +1,182 @@
+//!
+//! Program flash of the DMU as [Flash] of the [Bootloader](super::Bootloader)
+//!
+//! Erase and program operations are started with the command sequences of the DMU and
+//! polled through `HF_STATUS`/`HF_ERRSR`. A PFlash bank cannot be read while one of
+//! its sectors is erased or programmed, so:
+//!
+//! - the functions issuing commands and polling the DMU are placed in the `.ramcode`
+//!   section, which the linker script has to locate in the PSPR and the startup code
+//!   has to copy there
+//! - sectors in a bank that holds code running during the download (the CAN driver,
+//!   the UDS server, the interrupt handlers) cannot be modified, [PFlash] reports
+//!   them through [Flash::can_modify] and the bootloader refuses such downloads, e.g.
+//!   an [ImagePolicy::Single](super::ImagePolicy::Single) image in the bank the
+//!   program runs from
+//!
+//! Addresses may be given cached (`0x8...`) or non-cached (`0xA...`), flash is read
+//! non-cached so freshly programmed data is seen. Bank swapping (`UCB_SWAP`) is not
+//! implemented, an [ImagePolicy::AB](super::ImagePolicy::AB) needs a [Flash] wrapping
+//! [PFlash] that provides [Flash::active_bank] and [Flash::activate_bank].
+//!
+use super::{Flash, FlashStatus, Region};
+
+/// Base of the command sequence interpreter
+const COMMAND_BASE: usize = 0xAF00_0000;
+const COMMAND_5554: usize = 0x5554;
+const COMMAND_55F0: usize = 0x55F0;
+const COMMAND_55F4: usize = 0x55F4;
+const COMMAND_AA50: usize = 0xAA50;
+const COMMAND_AA58: usize = 0xAA58;
+const COMMAND_AAA8: usize = 0xAAA8;
+
+/// Enter page mode for the PFlash
+const ENTER_PAGE_MODE_PFLASH: u32 = 0x50;
+const CLEAR_STATUS: u32 = 0xFA;
+
+/// Flash status (`DMU_HF_STATUS`), `PxBUSY` from bit 2
+const HF_STATUS: usize = 0xF804_0010;
+const HF_STATUS_P0BUSY_SHIFT: u32 = 2;
+/// Errors of the last command (`DMU_HF_ERRSR`)
+const HF_ERRSR: usize = 0xF804_0034;
+/// `OPER`, `SQER`, `PROER`, `EVER` and `PVER`
+const HF_ERRSR_FAILED: u32 = 0b111 | (1 << 8) | (1 << 9);
+
+/// PFlash banks of the TC37x
+const BANK_COUNT: u32 = 2;
+const BANK_SIZE: u32 = 0x30_0000;
+/// Offset of an address within the cached and non-cached segments
+const SEGMENT_MASK: u32 = 0x0FFF_FFFF;
+const NON_CACHED: u32 = 0xA000_0000;
+
+/// The PFlash of the DMU, see the [module](self) documentation
+pub struct PFlash {
+    /// Bank the last operation was started in
+    bank: u32,
+    /// Banks holding code that runs during a download, one bit per bank
+    running_banks: u32,
+}
+
+impl PFlash {
+    /// `running_code` is the flash the program running during the download executes
+    /// from, e.g. taken from linker symbols. Its banks are never modified. An empty
+    /// region if everything was copied to RAM.
+    pub fn new(running_code: Region) -> Self {
+        let running_banks = if running_code.size == 0 {
+            0
+        } else {
+            let (first, last) = defmt::unwrap!(banks(running_code), "Not in the PFlash");
+            (first..=last).fold(0, |banks, bank| banks | (1 << bank))
+        };
+
+        PFlash {
+            bank: 0,
+            running_banks,
+        }
+    }
+}
+
+/// The bank holding `address`, None outside of the PFlash
+fn bank(address: u32) -> Option<u32> {
+    let offset = address & SEGMENT_MASK;
+    (offset < BANK_COUNT * BANK_SIZE).then_some(offset / BANK_SIZE)
+}
+
+/// The first and last bank of a region
+fn banks(region: Region) -> Option<(u32, u32)> {
+    let last = region.start.checked_add(region.size.checked_sub(1)?)?;
+    Some((bank(region.start)?, bank(last)?))
+}
+
+fn non_cached(address: u32) -> u32 {
+    (address & SEGMENT_MASK) | NON_CACHED
+}
+
+#[inline(always)]
+fn command(offset: usize, value: u32) {
+    unsafe { core::ptr::write_volatile((COMMAND_BASE + offset) as *mut u32, value) };
+}
+
+impl Flash for PFlash {
+    /// Logical sector
+    const SECTOR_SIZE: u32 = 0x4000;
+    const PAGE_SIZE: usize = 32;
+
+    #[inline(never)]
+    #[link_section = ".ramcode"]
+    fn start_erase(&mut self, address: u32) -> bool {
+        let Some(bank) = bank(address) else {
+            return false;
+        };
+        self.bank = bank;
+        command(COMMAND_5554, CLEAR_STATUS);
+
+        // Erase logical sector range, one sector
+        command(COMMAND_AA50, non_cached(address));
+        command(COMMAND_AA58, 1);
+        command(COMMAND_AAA8, 0x80);
+        command(COMMAND_AAA8, 0x50);
+        true
+    }
+
+    #[inline(never)]
+    #[link_section = ".ramcode"]
+    fn start_program(&mut self, address: u32, page: &[u8]) -> bool {
+        let Some(bank) = bank(address) else {
+            return false;
+        };
+        self.bank = bank;
+        command(COMMAND_5554, CLEAR_STATUS);
+        command(COMMAND_5554, ENTER_PAGE_MODE_PFLASH);
+
+        // Load page, 64 bits at a time
+        for words in page.chunks_exact(8) {
+            command(
+                COMMAND_55F0,
+                u32::from_le_bytes(words[..4].try_into().unwrap()),
+            );
+            command(
+                COMMAND_55F4,
+                u32::from_le_bytes(words[4..].try_into().unwrap()),
+            );
+        }
+
+        // Write page
+        command(COMMAND_AA50, non_cached(address));
+        command(COMMAND_AA58, 0x00);
+        command(COMMAND_AAA8, 0xA0);
+        command(COMMAND_AAA8, 0xAA);
+        true
+    }
+
+    #[inline(never)]
+    #[link_section = ".ramcode"]
+    fn status(&mut self) -> FlashStatus {
+        let status = unsafe { core::ptr::read_volatile(HF_STATUS as *const u32) };
+        if status & (1 << (HF_STATUS_P0BUSY_SHIFT + self.bank)) != 0 {
+            return FlashStatus::Busy;
+        }
+
+        let errors = unsafe { core::ptr::read_volatile(HF_ERRSR as *const u32) };
+        if errors & HF_ERRSR_FAILED != 0 {
+            defmt::warn!("PFlash operation failed, HF_ERRSR 0x{:X}", errors);
+            FlashStatus::Failed
+        } else {
+            FlashStatus::Done
+        }
+    }
+
+    fn read(&mut self, address: u32, buffer: &mut [u8]) {
+        let source = non_cached(address) as usize;
+        for (offset, byte) in buffer.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile((source + offset) as *const u8) };
+        }
+    }
+
+    fn can_modify(&mut self, region: Region) -> bool {
+        let Some((first, last)) = banks(region) else {
+            return false;
+        };
+        (first..=last).all(|bank| self.running_banks & (1 << bank) == 0)
+    }
+}
@@ -0,0 +1,144 @@
+//!
+//! Security access (0x27) with pluggable seed & key
+//!
+use crate::time::Instant;
+
+use super::{Flash, Nrc, UdsServer, SUPPRESS_POSITIVE_RESPONSE};
+
+/// Maximum length of a seed
+const MAX_SEED_LENGTH: usize = 32;
+
+/// Seed generation and key verification of the security levels. Level `n` is
+/// requested with sub-functions `2n - 1` (seed) and `2n` (key).
+pub trait SeedKey {
+    /// Write a fresh seed for the given level, returning its length (at most 32 bytes).
+    /// Unsupported levels are answered with [Nrc::SubFunctionNotSupported].
+    fn seed(&mut self, level: u8, seed: &mut [u8]) -> Result<usize, Nrc>;
+
+    /// Whether the key matches the seed handed out for the level
+    fn verify_key(&mut self, level: u8, seed: &[u8], key: &[u8]) -> bool;
+}
+
+/// The seed handed out last
+struct Seed {
+    level: u8,
+    value: [u8; MAX_SEED_LENGTH],
+    length: usize,
+}
+
+pub(super) struct SecurityState<K: SeedKey> {
+    seed_key: K,
+    unlocked: Option<u8>,
+    seed: Option<Seed>,
+    failed_attempts: u8,
+    /// Seeds are refused until then after too many invalid keys
+    delayed_until: Option<Instant>,
+}
+
+impl<K: SeedKey> SecurityState<K> {
+    pub(super) fn new(seed_key: K) -> Self {
+        SecurityState {
+            seed_key,
+            unlocked: None,
+            seed: None,
+            failed_attempts: 0,
+            delayed_until: None,
+        }
+    }
+
+    pub(super) fn unlocked(&self) -> Option<u8> {
+        self.unlocked
+    }
+
+    pub(super) fn lock(&mut self) {
+        self.unlocked = None;
+        self.seed = None;
+    }
+}
+
+impl<'a, K: SeedKey, F: Flash> UdsServer<'a, K, F> {
+    pub(super) fn security_access(
+        &mut self,
+        request: &[u8],
+        response: &mut [u8],
+        now: &Instant,
+    ) -> Result<usize, Nrc> {
+        let Some(&sub_function) = request.get(1) else {
+            return Err(Nrc::IncorrectMessageLengthOrInvalidFormat);
+        };
+        let sub_function = sub_function & !SUPPRESS_POSITIVE_RESPONSE;
+        if !(0x01..=0x7E).contains(&sub_function) {
+            return Err(Nrc::SubFunctionNotSupported);
+        }
+
+        let level = sub_function.div_ceil(2);
+        response[1] = sub_function;
+
+        if sub_function % 2 == 1 {
+            self.request_seed(level, &mut response[2..], now)
+                .map(|length| 2 + length)
+        } else {
+            self.send_key(level, &request[2..], now).map(|_| 2)
+        }
+    }
+
+    fn request_seed(
+        &mut self,
+        level: u8,
+        response: &mut [u8],
+        now: &Instant,
+    ) -> Result<usize, Nrc> {
+        let security = &mut self.security;
+
+        if security.delayed_until.is_some_and(|until| *now < until) {
+            return Err(Nrc::RequiredTimeDelayNotExpired);
+        }
+
+        let mut value = [0; MAX_SEED_LENGTH];
+        let length = security.seed_key.seed(level, &mut value)?;
+        let seed = response.get_mut(..length).ok_or(Nrc::ResponseTooLong)?;
+
+        if security.unlocked == Some(level) {
+            // Already unlocked, answered with a zero seed
+            seed.fill(0);
+            return Ok(length);
+        }
+
+        seed.copy_from_slice(&value[..length]);
+        security.seed = Some(Seed {
+            level,
+            value,
+            length,
+        });
+        Ok(length)
+    }
+
+    fn send_key(&mut self, level: u8, key: &[u8], now: &Instant) -> Result<(), Nrc> {
+        let security = &mut self.security;
+
+        // A seed can only be used once
+        let seed = match security.seed.take() {
+            Some(seed) if seed.level == level => seed,
+            _ => return Err(Nrc::RequestSequenceError),
+        };
+
+        if security
+            .seed_key
+            .verify_key(level, &seed.value[..seed.length], key)
+        {
+            defmt::debug!("Security level {} unlocked", level);
+            security.unlocked = Some(level);
+            security.failed_attempts = 0;
+            return Ok(());
+        }
+
+        security.failed_attempts += 1;
+        if security.failed_attempts < self.config.max_key_attempts {
+            return Err(Nrc::InvalidKey);
+        }
+
+        security.failed_attempts = 0;
+        security.delayed_until = Some(now + self.config.key_attempt_delay);
+        Err(Nrc::ExceededNumberOfAttempts)
+    }
+}
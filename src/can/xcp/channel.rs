@@ -0,0 +1,75 @@
+//!
+//! Drives an [Xcp] slave with a [CanNode]
+//!
+use crate::{
+    can::{
+        memory::module_ram::CanBuffer,
+        node::{
+            connection::Connected, receive::RxFifo0, transceive::TxDedicated, CanNode, Running,
+        },
+        CanModule,
+    },
+    time::Instant,
+};
+
+use super::{Xcp, XcpMemory};
+
+/// An XCP slave on a running node, see [XcpChannel::process]
+///
+/// Frames other than the master's commands are ignored, configure the acceptance
+/// filters to keep them out of FIFO 0.
+pub struct XcpChannel<M: XcpMemory> {
+    slave: Xcp<M>,
+}
+
+impl<M: XcpMemory> XcpChannel<M> {
+    pub fn new(slave: Xcp<M>) -> Self {
+        XcpChannel { slave }
+    }
+
+    pub fn slave(&self) -> &Xcp<M> {
+        &self.slave
+    }
+
+    pub fn slave_mut(&mut self) -> &mut Xcp<M> {
+        &mut self.slave
+    }
+
+    /// Answer received commands and transmit the responses and queued DAQ packets.
+    /// Call this regularly and after [Xcp::event] to keep the DAQ queue short.
+    #[allow(clippy::type_complexity)]
+    pub fn process<'r, 'mem, C: Connected, TB: CanBuffer, RB: CanBuffer, N: CanModule>(
+        &mut self,
+        node: &mut CanNode<
+            'r,
+            C,
+            Running,
+            TxDedicated<'mem, TB, N::RAM>,
+            RxFifo0<'mem, RB, N::RAM>,
+            N,
+        >,
+        now: &Instant,
+    ) {
+        while let Some(frame) = node.try_receive_fifo0() {
+            self.slave.on_frame(frame.get_id(), frame.data(), now);
+
+            // Each command gets its response before the next one is handled, DAQ
+            // packets wait for the buffers left afterwards
+            if let Some(frame) = self.slave.next_response::<TB>() {
+                match node.acquire_transmit_buffer() {
+                    Some(buffer) => {
+                        buffer.set_frame(frame).send();
+                    }
+                    None => defmt::warn!("XCP response dropped, no free transmit buffer"),
+                }
+            }
+        }
+
+        while let Some(buffer) = node.acquire_transmit_buffer() {
+            let Some(frame) = self.slave.next_frame::<TB>() else {
+                break;
+            };
+            buffer.set_frame(frame).send();
+        }
+    }
+}
@@ -0,0 +1,735 @@
+//!
+//! Dynamic DAQ lists: configuration commands and sampling on events
+//!
+use crate::time::Instant;
+
+use super::{read_u16, read_u32, Xcp, XcpError, XcpMemory, MAX_DTO};
+
+const CMD_SET_DAQ_PTR: u8 = 0xE2;
+const CMD_WRITE_DAQ: u8 = 0xE1;
+const CMD_SET_DAQ_LIST_MODE: u8 = 0xE0;
+const CMD_START_STOP_DAQ_LIST: u8 = 0xDE;
+const CMD_START_STOP_SYNCH: u8 = 0xDD;
+const CMD_GET_DAQ_CLOCK: u8 = 0xDC;
+const CMD_GET_DAQ_PROCESSOR_INFO: u8 = 0xDA;
+const CMD_GET_DAQ_RESOLUTION_INFO: u8 = 0xD9;
+const CMD_FREE_DAQ: u8 = 0xD6;
+const CMD_ALLOC_DAQ: u8 = 0xD5;
+const CMD_ALLOC_ODT: u8 = 0xD4;
+const CMD_ALLOC_ODT_ENTRY: u8 = 0xD3;
+
+/// DAQ list mode: timestamp in the first ODT, the only supported mode bit
+const MODE_TIMESTAMP: u8 = 0x10;
+
+/// GET_DAQ_PROCESSOR_INFO: dynamic configuration, prescaler and timestamps
+const DAQ_PROPERTIES: u8 = 0x01 | 0x02 | 0x10;
+/// 4 byte timestamps in units of 1us
+const TIMESTAMP_MODE: u8 = 0x04 | (0x3 << 4);
+const TIMESTAMP_LENGTH: usize = 4;
+
+/// WRITE_DAQ bit offset of whole bytes
+const NO_BIT_OFFSET: u8 = 0xFF;
+
+/// DAQ lists, ODTs and ODT entries that can be allocated, shared by all lists
+pub const MAX_DAQ_LISTS: usize = 4;
+pub const MAX_ODTS: usize = 16;
+pub const MAX_ODT_ENTRIES: usize = 64;
+
+#[derive(Clone, Copy)]
+struct DaqList {
+    first_odt: u8,
+    odt_count: u8,
+    mode: u8,
+    event_channel: u16,
+    prescaler: u8,
+    /// Events since the last sample
+    cycle: u8,
+    selected: bool,
+    running: bool,
+}
+
+impl DaqList {
+    const fn new() -> Self {
+        DaqList {
+            first_odt: 0,
+            odt_count: 0,
+            mode: 0,
+            event_channel: 0,
+            prescaler: 1,
+            cycle: 0,
+            selected: false,
+            running: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Odt {
+    first_entry: u8,
+    entry_count: u8,
+}
+
+#[derive(Clone, Copy)]
+struct OdtEntry {
+    address: u32,
+    extension: u8,
+    size: u8,
+}
+
+/// Dynamic allocation has to follow FREE_DAQ, ALLOC_DAQ, ALLOC_ODT, ALLOC_ODT_ENTRY
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Allocation {
+    Free,
+    Daq,
+    Odt,
+    OdtEntry,
+}
+
+pub(super) struct DaqState {
+    lists: [DaqList; MAX_DAQ_LISTS],
+    list_count: u8,
+    odts: [Odt; MAX_ODTS],
+    odt_count: u8,
+    entries: [OdtEntry; MAX_ODT_ENTRIES],
+    entry_count: u8,
+    allocation: Allocation,
+    /// List, absolute ODT and entry within the ODT written next by WRITE_DAQ
+    pointer: Option<(u16, u8, u8)>,
+}
+
+impl DaqState {
+    pub(super) const fn new() -> Self {
+        DaqState {
+            lists: [DaqList::new(); MAX_DAQ_LISTS],
+            list_count: 0,
+            odts: [Odt {
+                first_entry: 0,
+                entry_count: 0,
+            }; MAX_ODTS],
+            odt_count: 0,
+            entries: [OdtEntry {
+                address: 0,
+                extension: 0,
+                size: 0,
+            }; MAX_ODT_ENTRIES],
+            entry_count: 0,
+            allocation: Allocation::Free,
+            pointer: None,
+        }
+    }
+
+    pub(super) fn is_running(&self) -> bool {
+        self.lists().iter().any(|list| list.running)
+    }
+
+    pub(super) fn stop_all(&mut self) {
+        for list in self.lists.iter_mut() {
+            list.running = false;
+            list.selected = false;
+        }
+    }
+
+    fn lists(&self) -> &[DaqList] {
+        &self.lists[..self.list_count as usize]
+    }
+
+    fn list_mut(&mut self, daq: u16) -> Result<&mut DaqList, XcpError> {
+        self.lists[..self.list_count as usize]
+            .get_mut(daq as usize)
+            .ok_or(XcpError::OutOfRange)
+    }
+
+    /// Absolute index of an ODT of a list
+    fn odt_index(&self, daq: u16, odt: u8) -> Result<u8, XcpError> {
+        let list = self.lists().get(daq as usize).ok_or(XcpError::OutOfRange)?;
+        if odt >= list.odt_count {
+            return Err(XcpError::OutOfRange);
+        }
+        Ok(list.first_odt + odt)
+    }
+
+    fn entries(&self, odt: &Odt) -> &[OdtEntry] {
+        &self.entries[odt.first_entry as usize..][..odt.entry_count as usize]
+    }
+
+    /// Lists cannot be changed while selected for or running the sampling
+    fn check_inactive(&self, daq: u16) -> Result<(), XcpError> {
+        let list = self.lists().get(daq as usize).ok_or(XcpError::OutOfRange)?;
+        if list.running || list.selected {
+            return Err(XcpError::DaqActive);
+        }
+        Ok(())
+    }
+
+    /// Length of the DAQ packet of an ODT of a list
+    fn odt_length(&self, list: &DaqList, odt: u8) -> usize {
+        let size: usize = self
+            .entries(&self.odts[(list.first_odt + odt) as usize])
+            .iter()
+            .map(|entry| entry.size as usize)
+            .sum();
+        if odt == 0 && list.mode & MODE_TIMESTAMP != 0 {
+            1 + TIMESTAMP_LENGTH + size
+        } else {
+            1 + size
+        }
+    }
+
+    /// Whether all ODTs of the list fit into a DAQ packet
+    fn check_list(&self, list: &DaqList) -> Result<(), XcpError> {
+        if list.odt_count == 0 {
+            return Err(XcpError::DaqConfig);
+        }
+        if (0..list.odt_count).any(|odt| self.odt_length(list, odt) > MAX_DTO) {
+            return Err(XcpError::DaqConfig);
+        }
+        Ok(())
+    }
+}
+
+impl<M: XcpMemory> Xcp<M> {
+    /// Sample the DAQ lists running on the given event channel, call this where the
+    /// measured values are consistent (e.g. at the end of a cyclic task)
+    pub fn event(&mut self, channel: u16, now: &Instant) {
+        let timestamp = timestamp(now);
+
+        for index in 0..self.daq.list_count as usize {
+            let list = &mut self.daq.lists[index];
+            if !list.running || list.event_channel != channel {
+                continue;
+            }
+            list.cycle += 1;
+            if list.cycle < list.prescaler {
+                continue;
+            }
+            list.cycle = 0;
+
+            let list = *list;
+            for odt in 0..list.odt_count {
+                let pid = list.first_odt + odt;
+                let mut packet = [0; MAX_DTO];
+                packet[0] = pid;
+                let mut length = 1;
+                if odt == 0 && list.mode & MODE_TIMESTAMP != 0 {
+                    packet[1..1 + TIMESTAMP_LENGTH].copy_from_slice(&timestamp.to_le_bytes());
+                    length += TIMESTAMP_LENGTH;
+                }
+
+                // Checked when selecting or starting the list, which cannot change
+                // until it is stopped
+                let odt = self.daq.odts[pid as usize];
+                for entry in self.daq.entries(&odt) {
+                    let data = &mut packet[length..length + entry.size as usize];
+                    let read = self.memory.read(entry.address, entry.extension, data);
+                    // Checked by WRITE_DAQ, zeros should the memory become unavailable
+                    if read.is_err() {
+                        data.fill(0);
+                    }
+                    length += entry.size as usize;
+                }
+
+                let packet = self.packet(&packet[..length]);
+                if self.dtos.push(packet).is_err() {
+                    defmt::warn!("XCP DAQ queue full, dropping ODT {}", pid);
+                }
+            }
+        }
+    }
+
+    pub(super) fn handle_daq_command(
+        &mut self,
+        command: &[u8],
+        response: &mut [u8],
+        now: &Instant,
+    ) -> Result<usize, XcpError> {
+        match command[0] {
+            CMD_GET_DAQ_PROCESSOR_INFO => {
+                let [max_daq_low, max_daq_high] = (MAX_DAQ_LISTS as u16).to_le_bytes();
+                // Unknown number of event channels, no minimum DAQ lists, ODT numbers
+                // as absolute packet identifiers
+                response[..7].copy_from_slice(&[
+                    DAQ_PROPERTIES,
+                    max_daq_low,
+                    max_daq_high,
+                    0,
+                    0,
+                    0,
+                    0,
+                ]);
+                Ok(7)
+            }
+            CMD_GET_DAQ_RESOLUTION_INFO => {
+                // Byte granularity, no STIM, one timestamp tick per unit
+                response[..7].copy_from_slice(&[
+                    1,
+                    (MAX_DTO - 1) as u8,
+                    1,
+                    0,
+                    TIMESTAMP_MODE,
+                    1,
+                    0,
+                ]);
+                Ok(7)
+            }
+            CMD_GET_DAQ_CLOCK => {
+                response[..3].fill(0);
+                response[3..7].copy_from_slice(&timestamp(now).to_le_bytes());
+                Ok(7)
+            }
+            CMD_FREE_DAQ => {
+                self.daq = DaqState::new();
+                self.daq.allocation = Allocation::Daq;
+                self.dtos.clear();
+                Ok(0)
+            }
+            CMD_ALLOC_DAQ => {
+                let command = command.get(..4).ok_or(XcpError::CmdSyntax)?;
+                self.alloc_daq(read_u16(&command[2..4]))
+            }
+            CMD_ALLOC_ODT => {
+                let command = command.get(..5).ok_or(XcpError::CmdSyntax)?;
+                self.alloc_odt(read_u16(&command[2..4]), command[4])
+            }
+            CMD_ALLOC_ODT_ENTRY => {
+                let command = command.get(..6).ok_or(XcpError::CmdSyntax)?;
+                self.alloc_odt_entry(read_u16(&command[2..4]), command[4], command[5])
+            }
+            CMD_SET_DAQ_PTR => {
+                let command = command.get(..6).ok_or(XcpError::CmdSyntax)?;
+                let daq = read_u16(&command[2..4]);
+                let odt = self.daq.odt_index(daq, command[4])?;
+                self.daq.check_inactive(daq)?;
+                if command[5] >= self.daq.odts[odt as usize].entry_count {
+                    return Err(XcpError::OutOfRange);
+                }
+                self.daq.pointer = Some((daq, odt, command[5]));
+                Ok(0)
+            }
+            CMD_WRITE_DAQ => {
+                let command = command.get(..8).ok_or(XcpError::CmdSyntax)?;
+                self.write_daq(command[1], command[2], command[3], read_u32(&command[4..8]))
+            }
+            CMD_SET_DAQ_LIST_MODE => {
+                let command = command.get(..8).ok_or(XcpError::CmdSyntax)?;
+                let (mode, prescaler) = (command[1], command[6]);
+                if mode & !MODE_TIMESTAMP != 0 || prescaler == 0 {
+                    return Err(XcpError::OutOfRange);
+                }
+                let daq = read_u16(&command[2..4]);
+                self.daq.check_inactive(daq)?;
+                let list = self.daq.list_mut(daq)?;
+                list.mode = mode;
+                list.event_channel = read_u16(&command[4..6]);
+                list.prescaler = prescaler;
+                Ok(0)
+            }
+            CMD_START_STOP_DAQ_LIST => {
+                let command = command.get(..4).ok_or(XcpError::CmdSyntax)?;
+                self.start_stop_daq_list(command[1], read_u16(&command[2..4]), response)
+            }
+            CMD_START_STOP_SYNCH => {
+                let mode = *command.get(1).ok_or(XcpError::CmdSyntax)?;
+                self.start_stop_synch(mode)
+            }
+            _ => Err(XcpError::CmdUnknown),
+        }
+    }
+
+    fn alloc_daq(&mut self, count: u16) -> Result<usize, XcpError> {
+        let daq = &mut self.daq;
+        if daq.allocation != Allocation::Daq {
+            return Err(XcpError::Sequence);
+        }
+        if count as usize > MAX_DAQ_LISTS {
+            return Err(XcpError::MemoryOverflow);
+        }
+        daq.list_count = count as u8;
+        daq.allocation = Allocation::Odt;
+        Ok(0)
+    }
+
+    fn alloc_odt(&mut self, daq: u16, count: u8) -> Result<usize, XcpError> {
+        let state = &mut self.daq;
+        if state.allocation != Allocation::Odt {
+            return Err(XcpError::Sequence);
+        }
+        let first_odt = state.odt_count;
+        if first_odt as usize + count as usize > MAX_ODTS {
+            return Err(XcpError::MemoryOverflow);
+        }
+        let list = state.list_mut(daq)?;
+        // The ODTs of a list have to be contiguous
+        if list.odt_count != 0 {
+            return Err(XcpError::Sequence);
+        }
+        list.first_odt = first_odt;
+        list.odt_count = count;
+        state.odt_count += count;
+        Ok(0)
+    }
+
+    fn alloc_odt_entry(&mut self, daq: u16, odt: u8, count: u8) -> Result<usize, XcpError> {
+        let state = &mut self.daq;
+        if !matches!(state.allocation, Allocation::Odt | Allocation::OdtEntry) {
+            return Err(XcpError::Sequence);
+        }
+        let odt = state.odt_index(daq, odt)? as usize;
+        let first_entry = state.entry_count;
+        if first_entry as usize + count as usize > MAX_ODT_ENTRIES {
+            return Err(XcpError::MemoryOverflow);
+        }
+        if state.odts[odt].entry_count != 0 {
+            return Err(XcpError::Sequence);
+        }
+        state.odts[odt] = Odt {
+            first_entry,
+            entry_count: count,
+        };
+        state.entry_count += count;
+        state.allocation = Allocation::OdtEntry;
+        Ok(0)
+    }
+
+    fn write_daq(
+        &mut self,
+        bit_offset: u8,
+        size: u8,
+        extension: u8,
+        address: u32,
+    ) -> Result<usize, XcpError> {
+        let (daq, odt, entry) = self.daq.pointer.ok_or(XcpError::Sequence)?;
+        self.daq.check_inactive(daq)?;
+        let Odt {
+            first_entry,
+            entry_count,
+        } = self.daq.odts[odt as usize];
+        if entry >= entry_count {
+            return Err(XcpError::OutOfRange);
+        }
+        if bit_offset != NO_BIT_OFFSET || size == 0 || size as usize > MAX_DTO - 1 {
+            return Err(XcpError::OutOfRange);
+        }
+
+        // Refuse what cannot be sampled now rather than sending zeros later
+        let mut scratch = [0; MAX_DTO];
+        self.memory
+            .read(address, extension, &mut scratch[..size as usize])?;
+
+        // The whole ODT has to fit into a DAQ packet, keeping the old entry otherwise
+        let index = (first_entry + entry) as usize;
+        let previous = self.daq.entries[index];
+        self.daq.entries[index] = OdtEntry {
+            address,
+            extension,
+            size,
+        };
+        let list = self.daq.lists[daq as usize];
+        if self.daq.odt_length(&list, odt - list.first_odt) > MAX_DTO {
+            self.daq.entries[index] = previous;
+            return Err(XcpError::DaqConfig);
+        }
+        self.daq.pointer = Some((daq, odt, entry + 1));
+        Ok(0)
+    }
+
+    fn start_stop_daq_list(
+        &mut self,
+        mode: u8,
+        daq: u16,
+        response: &mut [u8],
+    ) -> Result<usize, XcpError> {
+        let list = *self.daq.list_mut(daq)?;
+        if mode != 0 {
+            self.daq.check_list(&list)?;
+        }
+
+        let list = self.daq.list_mut(daq)?;
+        match mode {
+            0 => list.running = false,
+            1 => {
+                list.running = true;
+                list.cycle = 0;
+            }
+            2 => list.selected = true,
+            _ => return Err(XcpError::OutOfRange),
+        }
+        response[0] = list.first_odt;
+        Ok(1)
+    }
+
+    fn start_stop_synch(&mut self, mode: u8) -> Result<usize, XcpError> {
+        if mode > 2 {
+            return Err(XcpError::OutOfRange);
+        }
+        let count = self.daq.list_count as usize;
+        for list in self.daq.lists[..count].iter_mut() {
+            match mode {
+                0 => list.running = false,
+                1 if list.selected => {
+                    list.running = true;
+                    list.cycle = 0;
+                }
+                2 if list.selected => list.running = false,
+                _ => {}
+            }
+            list.selected = false;
+        }
+        if mode == 0 {
+            self.dtos.clear();
+        }
+        Ok(0)
+    }
+}
+
+/// DAQ timestamp in microseconds
+fn timestamp(now: &Instant) -> u32 {
+    now.time_since_boot().as_micros() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::*;
+    use crate::can::{
+        memory::module_ram::BufferSize8,
+        xcp::{XcpConfig, PID_ERROR, PID_RESPONSE},
+        CanID,
+    };
+
+    const BASE: u32 = 0x1000;
+
+    /// 16 bytes at [BASE] holding their offset
+    struct TestMemory([u8; 16]);
+
+    impl XcpMemory for TestMemory {
+        fn read(&mut self, address: u32, extension: u8, data: &mut [u8]) -> Result<(), XcpError> {
+            let start = address.wrapping_sub(BASE) as usize;
+            let memory = self.0.get(start..start + data.len());
+            data.copy_from_slice(
+                memory
+                    .filter(|_| extension == 0)
+                    .ok_or(XcpError::AccessDenied)?,
+            );
+            Ok(())
+        }
+
+        fn write(&mut self, _: u32, _: u8, _: &[u8]) -> Result<(), XcpError> {
+            Err(XcpError::WriteProtected)
+        }
+    }
+
+    fn connected() -> Xcp<TestMemory> {
+        let config = XcpConfig::new(CanID::Standard(0x7E0), CanID::Standard(0x7E1));
+        let mut xcp = Xcp::new(config, TestMemory(core::array::from_fn(|i| i as u8)));
+        assert_eq!(command(&mut xcp, &[0xFF, 0x00])[0], PID_RESPONSE);
+        xcp
+    }
+
+    /// The response to a command
+    fn command(xcp: &mut Xcp<TestMemory>, command: &[u8]) -> Vec<u8> {
+        xcp.on_frame(
+            CanID::Standard(0x7E0),
+            command,
+            &Instant::from_time_since_boot(Duration::ZERO),
+        );
+        xcp.response.take().unwrap().data().to_vec()
+    }
+
+    fn ok() -> Vec<u8> {
+        vec![PID_RESPONSE]
+    }
+
+    fn error(error: XcpError) -> Vec<u8> {
+        vec![PID_ERROR, error as u8]
+    }
+
+    /// The queued DAQ packets
+    fn packets(xcp: &mut Xcp<TestMemory>) -> Vec<Vec<u8>> {
+        core::iter::from_fn(|| xcp.dtos.pop())
+            .map(|packet| packet.data().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn dynamic_allocation_sequence() {
+        let mut xcp = connected();
+
+        // FREE_DAQ comes first
+        assert_eq!(
+            command(&mut xcp, &[0xD5, 0, 2, 0]),
+            error(XcpError::Sequence)
+        );
+        assert_eq!(command(&mut xcp, &[0xD6]), ok());
+        assert_eq!(
+            command(&mut xcp, &[0xD5, 0, 5, 0]),
+            error(XcpError::MemoryOverflow)
+        );
+        assert_eq!(command(&mut xcp, &[0xD5, 0, 2, 0]), ok());
+        assert_eq!(
+            command(&mut xcp, &[0xD5, 0, 2, 0]),
+            error(XcpError::Sequence)
+        );
+
+        assert_eq!(command(&mut xcp, &[0xD4, 0, 0, 0, 2]), ok());
+        // Once per list, within the allocated lists and ODTs
+        assert_eq!(
+            command(&mut xcp, &[0xD4, 0, 0, 0, 1]),
+            error(XcpError::Sequence)
+        );
+        assert_eq!(
+            command(&mut xcp, &[0xD4, 0, 2, 0, 1]),
+            error(XcpError::OutOfRange)
+        );
+        assert_eq!(
+            command(&mut xcp, &[0xD4, 0, 1, 0, 15]),
+            error(XcpError::MemoryOverflow)
+        );
+        assert_eq!(command(&mut xcp, &[0xD4, 0, 1, 0, 3]), ok());
+
+        assert_eq!(
+            command(&mut xcp, &[0xD3, 0, 1, 0, 3, 2]),
+            error(XcpError::OutOfRange)
+        );
+        assert_eq!(command(&mut xcp, &[0xD3, 0, 1, 0, 2, 2]), ok());
+        assert_eq!(
+            command(&mut xcp, &[0xD3, 0, 1, 0, 2, 1]),
+            error(XcpError::Sequence)
+        );
+        assert_eq!(
+            command(&mut xcp, &[0xD3, 0, 0, 0, 0, 63]),
+            error(XcpError::MemoryOverflow)
+        );
+        assert_eq!(command(&mut xcp, &[0xD3, 0, 0, 0, 0, 1]), ok());
+        // No more ODTs once entries are allocated
+        assert_eq!(
+            command(&mut xcp, &[0xD4, 0, 0, 0, 1]),
+            error(XcpError::Sequence)
+        );
+
+        // ODT numbers are absolute packet identifiers, the second list starts at 2
+        assert_eq!(command(&mut xcp, &[0xDE, 2, 1, 0]), vec![PID_RESPONSE, 2]);
+        assert_eq!(
+            command(&mut xcp, &[0xE2, 0, 1, 0, 2, 0]),
+            error(XcpError::DaqActive)
+        );
+
+        // FREE_DAQ releases everything
+        assert_eq!(command(&mut xcp, &[0xD6]), ok());
+        assert_eq!(
+            command(&mut xcp, &[0xD4, 0, 0, 0, 1]),
+            error(XcpError::Sequence)
+        );
+        assert_eq!(command(&mut xcp, &[0xD5, 0, 1, 0]), ok());
+        assert_eq!(command(&mut xcp, &[0xD4, 0, 0, 0, 16]), ok());
+    }
+
+    #[test]
+    fn write_daq_fits_the_odt_into_a_packet() {
+        let mut xcp = connected();
+        for allocation in [
+            &[0xD6][..],
+            &[0xD5, 0, 1, 0],
+            &[0xD4, 0, 0, 0, 1],
+            &[0xD3, 0, 0, 0, 0, 3],
+        ] {
+            assert_eq!(command(&mut xcp, allocation), ok());
+        }
+
+        assert_eq!(
+            command(&mut xcp, &[0xE1, 0xFF, 4, 0, 0x00, 0x10, 0, 0]),
+            error(XcpError::Sequence)
+        );
+        assert_eq!(
+            command(&mut xcp, &[0xE2, 0, 0, 0, 0, 3]),
+            error(XcpError::OutOfRange)
+        );
+        assert_eq!(command(&mut xcp, &[0xE2, 0, 0, 0, 0, 0]), ok());
+
+        // Whole bytes, at most a packet without PID, readable
+        assert_eq!(
+            command(&mut xcp, &[0xE1, 0, 4, 0, 0x00, 0x10, 0, 0]),
+            error(XcpError::OutOfRange)
+        );
+        assert_eq!(
+            command(&mut xcp, &[0xE1, 0xFF, 8, 0, 0x00, 0x10, 0, 0]),
+            error(XcpError::OutOfRange)
+        );
+        assert_eq!(
+            command(&mut xcp, &[0xE1, 0xFF, 4, 0, 0x0E, 0x10, 0, 0]),
+            error(XcpError::AccessDenied)
+        );
+
+        assert_eq!(
+            command(&mut xcp, &[0xE1, 0xFF, 4, 0, 0x00, 0x10, 0, 0]),
+            ok()
+        );
+        assert_eq!(
+            command(&mut xcp, &[0xE1, 0xFF, 3, 0, 0x04, 0x10, 0, 0]),
+            ok()
+        );
+        // The PID and 7 bytes fill the packet
+        assert_eq!(
+            command(&mut xcp, &[0xE1, 0xFF, 1, 0, 0x07, 0x10, 0, 0]),
+            error(XcpError::DaqConfig)
+        );
+
+        // The refused entry is kept empty and the pointer stays, a smaller second
+        // entry makes room
+        assert_eq!(command(&mut xcp, &[0xE2, 0, 0, 0, 0, 1]), ok());
+        assert_eq!(
+            command(&mut xcp, &[0xE1, 0xFF, 2, 0, 0x04, 0x10, 0, 0]),
+            ok()
+        );
+        assert_eq!(
+            command(&mut xcp, &[0xE1, 0xFF, 1, 0, 0x0F, 0x10, 0, 0]),
+            ok()
+        );
+
+        // The timestamp does not fit in addition
+        assert_eq!(command(&mut xcp, &[0xE0, 0x10, 0, 0, 1, 0, 1, 0]), ok());
+        assert_eq!(
+            command(&mut xcp, &[0xDE, 1, 0, 0]),
+            error(XcpError::DaqConfig)
+        );
+        assert_eq!(command(&mut xcp, &[0xE0, 0x00, 0, 0, 1, 0, 1, 0]), ok());
+        assert_eq!(command(&mut xcp, &[0xDE, 1, 0, 0]), vec![PID_RESPONSE, 0]);
+
+        xcp.event(1, &Instant::from_time_since_boot(Duration::ZERO));
+        assert_eq!(packets(&mut xcp), [vec![0, 0, 1, 2, 3, 4, 5, 15]]);
+    }
+
+    #[test]
+    fn timestamp_in_the_first_odt() {
+        let mut xcp = connected();
+        for allocation in [
+            &[0xD6][..],
+            &[0xD5, 0, 1, 0],
+            &[0xD4, 0, 0, 0, 2],
+            &[0xD3, 0, 0, 0, 0, 1],
+            &[0xD3, 0, 0, 0, 1, 1],
+            &[0xE2, 0, 0, 0, 0, 0],
+            &[0xE1, 0xFF, 2, 0, 0x00, 0x10, 0, 0],
+            &[0xE2, 0, 0, 0, 1, 0],
+            &[0xE1, 0xFF, 4, 0, 0x04, 0x10, 0, 0],
+            // Timestamped, event channel 3, every second event
+            &[0xE0, 0x10, 0, 0, 3, 0, 2, 0],
+        ] {
+            assert_eq!(command(&mut xcp, allocation), ok());
+        }
+        assert_eq!(command(&mut xcp, &[0xDE, 1, 0, 0]), vec![PID_RESPONSE, 0]);
+
+        let at = |micros| Instant::from_time_since_boot(Duration::from_micros(micros));
+        xcp.event(3, &at(1_000));
+        xcp.event(4, &at(1_500));
+        assert!(packets(&mut xcp).is_empty());
+
+        xcp.event(3, &at(2_500));
+        // Responses alone leave the DAQ packets queued
+        assert!(xcp.next_response::<BufferSize8>().is_none());
+        assert_eq!(
+            packets(&mut xcp),
+            [vec![0, 0xC4, 0x09, 0, 0, 0, 1], vec![1, 4, 5, 6, 7]]
+        );
+    }
+}
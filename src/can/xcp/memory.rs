@@ -0,0 +1,98 @@
+//!
+//! Memory access of the XCP slave, restricted to configured regions
+//!
+use super::XcpError;
+
+/// Memory the master may read (upload, DAQ) and write (download)
+pub trait XcpMemory {
+    fn read(&mut self, address: u32, extension: u8, data: &mut [u8]) -> Result<(), XcpError>;
+
+    fn write(&mut self, address: u32, extension: u8, data: &[u8]) -> Result<(), XcpError>;
+}
+
+/// A range of RAM open to the master
+#[derive(Clone, Copy)]
+pub struct RamRegion {
+    pub start: u32,
+    pub length: u32,
+    /// Whether the master may calibrate (write) the region
+    pub writable: bool,
+}
+
+impl RamRegion {
+    pub const fn read_only(start: u32, length: u32) -> Self {
+        RamRegion {
+            start,
+            length,
+            writable: false,
+        }
+    }
+
+    pub const fn writable(start: u32, length: u32) -> Self {
+        RamRegion {
+            start,
+            length,
+            writable: true,
+        }
+    }
+
+    fn contains(&self, address: u32, length: usize) -> bool {
+        let offset = address.wrapping_sub(self.start) as u64;
+        address >= self.start && offset + length as u64 <= self.length as u64
+    }
+}
+
+/// Direct access to the memory of the controller, limited to the given regions.
+/// Only address extension 0 is supported.
+pub struct RamRegions<'a> {
+    regions: &'a [RamRegion],
+}
+
+impl<'a> RamRegions<'a> {
+    /// # Safety
+    /// All regions must be RAM that may be read at any time, writable regions must hold
+    /// data for which every bit pattern is valid (e.g. calibration parameters). The
+    /// application reads them concurrently, accesses are byte wise.
+    pub unsafe fn new(regions: &'a [RamRegion]) -> Self {
+        RamRegions { regions }
+    }
+
+    fn region(&self, address: u32, extension: u8, length: usize) -> Option<&RamRegion> {
+        if extension != 0 {
+            return None;
+        }
+        self.regions
+            .iter()
+            .find(|region| region.contains(address, length))
+    }
+}
+
+impl<'a> XcpMemory for RamRegions<'a> {
+    fn read(&mut self, address: u32, extension: u8, data: &mut [u8]) -> Result<(), XcpError> {
+        self.region(address, extension, data.len())
+            .ok_or(XcpError::AccessDenied)?;
+
+        for (offset, byte) in data.iter_mut().enumerate() {
+            // # Safety
+            // Within a region, which is readable by contract of [RamRegions::new]
+            *byte = unsafe { core::ptr::read_volatile((address as usize + offset) as *const u8) };
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, address: u32, extension: u8, data: &[u8]) -> Result<(), XcpError> {
+        let region = self
+            .region(address, extension, data.len())
+            .ok_or(XcpError::AccessDenied)?;
+        if !region.writable {
+            return Err(XcpError::WriteProtected);
+        }
+
+        for (offset, byte) in data.iter().enumerate() {
+            // # Safety
+            // Within a writable region, see [RamRegions::new]
+            unsafe { core::ptr::write_volatile((address as usize + offset) as *mut u8, *byte) };
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+//!
+//! XCP on CAN measurement and calibration slave
+//!
+//! [Xcp] answers the commands of an XCP master: CONNECT, DISCONNECT, GET_STATUS,
+//! SYNCH, SET_MTA, UPLOAD, SHORT_UPLOAD and DOWNLOAD for polling and calibration, and
+//! the dynamic DAQ configuration (FREE_DAQ, ALLOC_DAQ, ALLOC_ODT, ALLOC_ODT_ENTRY,
+//! SET_DAQ_PTR, WRITE_DAQ, SET_DAQ_LIST_MODE, START_STOP_DAQ_LIST, START_STOP_SYNCH,
+//! GET_DAQ_CLOCK and the info commands). All memory accesses go through an
+//! [XcpMemory], e.g. [RamRegions] which only opens the configured regions.
+//!
+//! DAQ lists are sampled when the application triggers their event channel with
+//! [Xcp::event], timestamps are taken from [Instant] in microseconds.
+//!
+//! Like the other protocols it does not touch the hardware: commands are passed in with
+//! [Xcp::on_frame] and responses and DAQ packets are pulled out with
+//! [Xcp::next_frame], responses alone with [Xcp::next_response]. [XcpChannel] drives it with a
+//! [CanNode](crate::can::node::CanNode).
+//!
+//! The slave uses Intel byte order, byte address granularity and 8 byte packets.
+//!
+use defmt::Format;
+
+use crate::{
+    can::{
+        memory::module_ram::CanBuffer,
+        queue::{FrameQueue, QueuedFrame},
+        CanID, CanTxFrame,
+    },
+    time::Instant,
+};
+
+mod channel;
+mod daq;
+mod memory;
+
+pub use channel::XcpChannel;
+pub use daq::{MAX_DAQ_LISTS, MAX_ODTS, MAX_ODT_ENTRIES};
+pub use memory::{RamRegion, RamRegions, XcpMemory};
+
+const CMD_CONNECT: u8 = 0xFF;
+const CMD_DISCONNECT: u8 = 0xFE;
+const CMD_GET_STATUS: u8 = 0xFD;
+const CMD_SYNCH: u8 = 0xFC;
+const CMD_SET_MTA: u8 = 0xF6;
+const CMD_UPLOAD: u8 = 0xF5;
+const CMD_SHORT_UPLOAD: u8 = 0xF4;
+const CMD_DOWNLOAD: u8 = 0xF0;
+
+/// Packet identifiers of responses
+const PID_RESPONSE: u8 = 0xFF;
+const PID_ERROR: u8 = 0xFE;
+
+/// Packets are at most a classic CAN frame
+const MAX_CTO: usize = 8;
+const MAX_DTO: usize = 8;
+
+/// CONNECT response: calibration and DAQ resources
+const RESOURCE_CAL_PAG: u8 = 0x01;
+const RESOURCE_DAQ: u8 = 0x04;
+/// Protocol and transport layer version
+const XCP_VERSION: u8 = 0x01;
+
+/// GET_STATUS session status bits
+const SESSION_DAQ_RUNNING: u8 = 0x40;
+
+/// DAQ packets queued for transmission at most
+const QUEUE_LENGTH: usize = 16;
+
+/// Error codes of negative responses
+#[derive(Format, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum XcpError {
+    /// Answer to SYNCH
+    CmdSynch = 0x00,
+    CmdBusy = 0x10,
+    DaqActive = 0x11,
+    CmdUnknown = 0x20,
+    CmdSyntax = 0x21,
+    OutOfRange = 0x22,
+    WriteProtected = 0x23,
+    AccessDenied = 0x24,
+    Sequence = 0x29,
+    DaqConfig = 0x2A,
+    MemoryOverflow = 0x30,
+    Generic = 0x31,
+}
+
+#[derive(Format, Clone, Copy)]
+pub struct XcpConfig {
+    /// Id of the commands from the master
+    pub command_id: CanID,
+    /// Id of the responses and DAQ packets
+    pub response_id: CanID,
+}
+
+impl XcpConfig {
+    pub const fn new(command_id: CanID, response_id: CanID) -> Self {
+        XcpConfig {
+            command_id,
+            response_id,
+        }
+    }
+}
+
+/// An XCP slave, see the [module](self) documentation
+pub struct Xcp<M: XcpMemory> {
+    config: XcpConfig,
+    memory: M,
+    connected: bool,
+    /// Memory transfer address and extension
+    mta: (u32, u8),
+    /// Response to the last command, sent before any DAQ packet
+    response: Option<QueuedFrame>,
+    dtos: FrameQueue<QUEUE_LENGTH>,
+    daq: daq::DaqState,
+}
+
+impl<M: XcpMemory> Xcp<M> {
+    pub fn new(config: XcpConfig, memory: M) -> Self {
+        Xcp {
+            config,
+            memory,
+            connected: false,
+            mta: (0, 0),
+            response: None,
+            dtos: FrameQueue::new(),
+            daq: daq::DaqState::new(),
+        }
+    }
+
+    pub fn config(&self) -> &XcpConfig {
+        &self.config
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    pub fn memory_mut(&mut self) -> &mut M {
+        &mut self.memory
+    }
+
+    /// Process a received frame, frames other than commands are ignored
+    pub fn on_frame(&mut self, id: CanID, data: &[u8], now: &Instant) {
+        if id != self.config.command_id || data.is_empty() {
+            return;
+        }
+        // Nothing but CONNECT is answered while disconnected
+        if !self.connected && data[0] != CMD_CONNECT {
+            return;
+        }
+
+        let mut response = [0; MAX_CTO];
+        response[0] = PID_RESPONSE;
+        match self.handle_command(data, &mut response[1..], now) {
+            Ok(length) => self.response = Some(self.packet(&response[..1 + length])),
+            Err(error) => {
+                defmt::debug!("XCP command 0x{:02X} failed: {}", data[0], error);
+                self.response = Some(self.packet(&[PID_ERROR, error as u8]));
+            }
+        }
+    }
+
+    /// The next frame to send, responses go before DAQ packets
+    pub fn next_frame<B: CanBuffer>(&mut self) -> Option<CanTxFrame<B>> {
+        let packet = self.response.take().or_else(|| self.dtos.pop())?;
+        Some(packet.to_tx_frame())
+    }
+
+    /// The response to the last command, if not sent yet. DAQ packets stay queued.
+    pub fn next_response<B: CanBuffer>(&mut self) -> Option<CanTxFrame<B>> {
+        Some(self.response.take()?.to_tx_frame())
+    }
+
+    /// A response or DAQ packet to the master
+    fn packet(&self, data: &[u8]) -> QueuedFrame {
+        QueuedFrame::new(self.config.response_id, data)
+    }
+
+    /// Handle a command, writing the response after the PID. Returns the response length.
+    fn handle_command(
+        &mut self,
+        command: &[u8],
+        response: &mut [u8],
+        now: &Instant,
+    ) -> Result<usize, XcpError> {
+        match command[0] {
+            CMD_CONNECT => self.connect(response),
+            CMD_DISCONNECT => {
+                self.connected = false;
+                self.daq.stop_all();
+                self.dtos.clear();
+                Ok(0)
+            }
+            CMD_GET_STATUS => {
+                let session = if self.daq.is_running() {
+                    SESSION_DAQ_RUNNING
+                } else {
+                    0
+                };
+                // No resource is protected, session configuration id 0
+                response[..5].copy_from_slice(&[session, 0, 0, 0, 0]);
+                Ok(5)
+            }
+            CMD_SYNCH => Err(XcpError::CmdSynch),
+            CMD_SET_MTA => {
+                let command = command.get(..8).ok_or(XcpError::CmdSyntax)?;
+                self.mta = (read_u32(&command[4..8]), command[3]);
+                Ok(0)
+            }
+            CMD_UPLOAD => {
+                let length = *command.get(1).ok_or(XcpError::CmdSyntax)? as usize;
+                let (address, extension) = self.mta;
+                self.upload(address, extension, length, response)
+            }
+            CMD_SHORT_UPLOAD => {
+                let command = command.get(..8).ok_or(XcpError::CmdSyntax)?;
+                let address = read_u32(&command[4..8]);
+                self.upload(address, command[3], command[1] as usize, response)
+            }
+            CMD_DOWNLOAD => {
+                let length = *command.get(1).ok_or(XcpError::CmdSyntax)? as usize;
+                let data = command.get(2..2 + length).ok_or(XcpError::CmdSyntax)?;
+                let (address, extension) = self.mta;
+                self.memory.write(address, extension, data)?;
+                self.mta = (address.wrapping_add(length as u32), extension);
+                Ok(0)
+            }
+            _ => self.handle_daq_command(command, response, now),
+        }
+    }
+
+    fn connect(&mut self, response: &mut [u8]) -> Result<usize, XcpError> {
+        if !self.connected {
+            defmt::debug!("XCP master connected");
+        }
+        self.connected = true;
+
+        let [max_dto_low, max_dto_high] = (MAX_DTO as u16).to_le_bytes();
+        response[..7].copy_from_slice(&[
+            RESOURCE_CAL_PAG | RESOURCE_DAQ,
+            // Intel byte order, byte granularity, no block mode
+            0x00,
+            MAX_CTO as u8,
+            max_dto_low,
+            max_dto_high,
+            XCP_VERSION,
+            XCP_VERSION,
+        ]);
+        Ok(7)
+    }
+
+    /// Read `length` bytes into the response and move the MTA behind them
+    fn upload(
+        &mut self,
+        address: u32,
+        extension: u8,
+        length: usize,
+        response: &mut [u8],
+    ) -> Result<usize, XcpError> {
+        // No block mode, the data has to fit a single response
+        if length == 0 || length > MAX_CTO - 1 {
+            return Err(XcpError::OutOfRange);
+        }
+        self.memory
+            .read(address, extension, &mut response[..length])?;
+        self.mta = (address.wrapping_add(length as u32), extension);
+        Ok(length)
+    }
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
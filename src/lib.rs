@@ -4,11 +4,26 @@
 //! - [can]: a basic CAN driver implementation to send/receive frames in non-blocking manner
 //! - [clocks]: for PLL & CCU configuration (i.e., to setup the peripheral and system clock)
 //!
-#![no_std]
+//! With the `sim` feature the crate builds for the host: the peripherals are left out
+//! and the CAN driver runs on the model in [can::sim].
+//!
+#![cfg_attr(not(test), no_std)]
 #![feature(type_changing_struct_update)]
 
+// The host model of the CAN module uses the locks of std
+#[cfg(all(feature = "sim", not(test)))]
+extern crate std;
+
 pub mod can;
+#[cfg(not(feature = "sim"))]
 pub mod clocks;
+#[cfg(not(feature = "sim"))]
 pub mod delay;
 pub mod frequency;
+pub mod reset;
 pub mod time;
+
+/// defmt logger and panic handler of the unit tests, shared with the integration tests
+#[cfg(test)]
+#[path = "../tests/support/logger.rs"]
+mod logger;
@@ -0,0 +1,45 @@
+//!
+//! Software triggered resets through the SCU
+//!
+#[cfg(not(feature = "sim"))]
+use tc37x_pac::Peripherals;
+#[cfg(not(feature = "sim"))]
+use tc37x_rt::call_without_endinit;
+
+/// Kind of reset triggered by [software_reset]
+#[derive(defmt::Format, Clone, Copy, PartialEq, Eq)]
+pub enum ResetType {
+    /// Resets the whole system, except debug and power management
+    System,
+    /// Resets the CPUs and peripherals, the SCU keeps its state
+    Application,
+}
+
+/// Request a software reset (`SWRSTCON.SWRSTREQ`), the kind of reset is selected in
+/// `RSTCON.SW` right before
+#[cfg(not(feature = "sim"))]
+pub fn software_reset(reset: ResetType) -> ! {
+    let p = unsafe { Peripherals::steal() };
+
+    defmt::info!("Requesting {} reset", reset);
+    defmt::flush();
+
+    call_without_endinit(|| {
+        p.SCU.rstcon.modify(|_, w| match reset {
+            ResetType::System => w.sw().system(),
+            ResetType::Application => w.sw().application(),
+        });
+        p.SCU.swrstcon.write(|w| w.swrstreq().set_bit());
+    });
+
+    // The reset takes a few cycles to kick in
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// There is nothing to reset on the host, the request panics so tests can expect it
+#[cfg(feature = "sim")]
+pub fn software_reset(reset: ResetType) -> ! {
+    defmt::panic!("Requesting {} reset", reset)
+}
@@ -1,38 +1,123 @@
 //!
 //! Basic time utilities for testing
 //!
+#[cfg(feature = "sim")]
+use core::sync::atomic::{AtomicU64, Ordering};
 use core::{
     ops::{Add, Sub},
     time::Duration,
 };
+#[cfg(not(feature = "sim"))]
 use tc37x_pac::Peripherals;
 
+#[cfg(not(feature = "sim"))]
+const HZ_FREQUENCY_STM0: u64 = 100_000_000;
+
+/// Bit of STM0 where `TIM4` starts, i.e. `TIM4` counts in units of `1 << 16` ticks
+#[cfg(not(feature = "sim"))]
+const TIM4_SHIFT: u32 = 16;
+
+/// Simulated time since boot in microseconds, see [advance_time]
+#[cfg(feature = "sim")]
+static SIMULATED_TIME: AtomicU64 = AtomicU64::new(0);
+
 /// Basic instant implementation based on [STM0]
 ///
 /// At the moment, this gives no guarantees about the Instant quality
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub struct Instant {
     time_since_boot: Duration,
 }
 
 impl Instant {
     /// Return a new instant
+    #[cfg(not(feature = "sim"))]
     pub fn now() -> Self {
         let p = unsafe { Peripherals::steal() };
         let time = p.STM0.tim4.read().bits() as u64;
 
-        const HZ_FREQUENCY_STM0: u64 = 100_000_000;
-
         // We are not reading tim0, but tim4, so our time is actually multiplied with 1 << 16
-        let millis = (time * 1_000 * (1 << 16)) / (HZ_FREQUENCY_STM0);
+        let millis = (time * 1_000 * (1 << TIM4_SHIFT)) / (HZ_FREQUENCY_STM0);
 
         Instant {
             time_since_boot: Duration::from_millis(millis),
         }
     }
+
+    /// The simulated time, which only moves through [advance_time]
+    #[cfg(feature = "sim")]
+    pub fn now() -> Self {
+        let micros = SIMULATED_TIME.load(Ordering::Acquire);
+        Instant {
+            time_since_boot: Duration::from_micros(micros),
+        }
+    }
+
+    /// Create an instant from the time since boot, e.g. to drive time based state
+    /// machines on the host
+    pub const fn from_time_since_boot(time_since_boot: Duration) -> Self {
+        Instant { time_since_boot }
+    }
+
+    /// The time since boot of this instant
+    pub const fn time_since_boot(&self) -> Duration {
+        self.time_since_boot
+    }
+}
+
+/// Raise the STM0 compare 0 interrupt (`SRC_STM0SR0`) once the given instant is reached,
+/// e.g. to wake up for the next due message instead of polling.
+///
+/// The compare uses the same 32 bits of the timer as [Instant::now] and only matches
+/// the exact value. Returns false if the instant is already reached: the alarm is
+/// not armed, the caller has to act right away (e.g. poll the scheduler again)
+/// instead of waiting for an interrupt that would only come after the timer wrapped
+/// around.
+#[cfg(not(feature = "sim"))]
+#[must_use]
+pub fn set_alarm(at: &Instant) -> bool {
+    let p = unsafe { Peripherals::steal() };
+
+    // Round up, the interrupt must not come early
+    let ticks = (at.time_since_boot.as_nanos() * HZ_FREQUENCY_STM0 as u128).div_ceil(1_000_000_000);
+    let compare = ticks.div_ceil(1 << TIM4_SHIFT) as u32;
+
+    // Compare bits 16 to 47, like TIM4
+    p.STM0
+        .cmcon
+        .modify(|_, w| unsafe { w.msize0().bits(31).mstart0().bits(TIM4_SHIFT as u8) });
+    p.STM0.cmp0.write(|w| unsafe { w.cmpval().bits(compare) });
+    p.STM0.iscr.write(|w| w.cmp0irr().set_bit());
+    p.STM0
+        .icr
+        .modify(|_, w| w.cmp0en().set_bit().cmp0os().clear_bit());
+
+    // Checked after arming, a compare that matches in between still raises the
+    // interrupt and the caller runs once more than needed
+    let now = p.STM0.tim4.read().bits();
+    if (now.wrapping_sub(compare) as i32) >= 0 {
+        clear_alarm();
+        return false;
+    }
+    true
+}
+
+/// Stop the alarm and clear its pending request
+#[cfg(not(feature = "sim"))]
+pub fn clear_alarm() {
+    let p = unsafe { Peripherals::steal() };
+    p.STM0.icr.modify(|_, w| w.cmp0en().clear_bit());
+    p.STM0.iscr.write(|w| w.cmp0irr().set_bit());
+}
+
+/// Move the simulated time of [Instant::now] forward, e.g. to let transmit timeouts
+/// expire in a test
+#[cfg(feature = "sim")]
+pub fn advance_time(by: Duration) {
+    SIMULATED_TIME.fetch_add(by.as_micros() as u64, Ordering::AcqRel);
 }
 
-impl<'a> Add<Duration> for &'a Instant {
+impl Add<Duration> for &Instant {
     type Output = Instant;
 
     fn add(self, rhs: Duration) -> Self::Output {
@@ -0,0 +1,102 @@
+//!
+//! Message types generated from `dbc-codegen/tests/sample.dbc`
+//!
+#![cfg(feature = "sim")]
+
+mod support;
+
+mod sample {
+    include!("../dbc-codegen/tests/generated/sample.rs");
+}
+
+use sample::{BodyStatus, BodyStatusLights, EngineData, EngineDataGearSelector, GatewayInfo};
+use tc37x_hal::can::{
+    dbc::{DbcError, DbcMessage},
+    memory::module_ram::{BufferSize64, BufferSize8},
+    CanID,
+};
+
+#[test]
+fn signals_round_trip() {
+    let mut engine = EngineData::new();
+    assert!(engine.set_engine_speed(3000.25).is_ok());
+    assert!(engine.set_coolant_temp(-40).is_ok());
+    assert!(engine.set_torque(-250.5).is_ok());
+    assert!(engine
+        .set_gear_selector(EngineDataGearSelector::Drive)
+        .is_ok());
+    assert!(engine.set_counter(9).is_ok());
+
+    assert_eq!(engine.engine_speed(), 3000.25);
+    assert_eq!(engine.coolant_temp(), -40);
+    assert_eq!(engine.torque(), -250.5);
+    assert_eq!(engine.torque_raw(), -501);
+    assert_eq!(engine.gear_selector(), EngineDataGearSelector::Drive);
+    assert_eq!(engine.counter(), 9);
+    assert_eq!(
+        engine.as_bytes(),
+        [0xE1, 0x2E, 0x00, 0x0B, 0x3E, 0x00, 0x00, 0x90]
+    );
+}
+
+#[test]
+fn integer_values_are_rounded() {
+    let mut gateway = GatewayInfo::new();
+
+    assert!(gateway.set_odometer(14).is_ok());
+    assert_eq!(gateway.odometer(), 10);
+    assert!(gateway.set_odometer(15).is_ok());
+    assert_eq!(gateway.odometer(), 20);
+    assert!(gateway.set_odometer(599_999).is_ok());
+    assert_eq!(gateway.odometer_raw(), 60_000);
+}
+
+#[test]
+fn out_of_range() {
+    let mut engine = EngineData::new();
+
+    assert!(engine.set_counter(16) == Err(DbcError::OutOfRange));
+    assert!(engine.set_coolant_temp(216) == Err(DbcError::OutOfRange));
+    assert!(engine.set_torque(1000.5) == Err(DbcError::OutOfRange));
+    // Values without a name must still fit into the 3 bits of the signal
+    assert!(
+        engine.set_gear_selector(EngineDataGearSelector::Other(9)) == Err(DbcError::OutOfRange)
+    );
+    assert_eq!(engine, EngineData::new());
+
+    let mut gateway = GatewayInfo::new();
+    assert!(gateway.set_odometer(600_010) == Err(DbcError::OutOfRange));
+}
+
+#[test]
+fn multiplexing() {
+    let mut body = BodyStatus::new();
+    assert_eq!(body.doors_open(), Some(0));
+    assert_eq!(body.lights(), None);
+
+    assert!(body.set_voltage(12.5).is_ok());
+    assert!(body.set_lights(BodyStatusLights::HighBeam).is_ok());
+    assert_eq!(body.mode(), 1);
+    assert_eq!(body.doors_open(), None);
+    assert_eq!(body.lights(), Some(BodyStatusLights::HighBeam));
+    assert_eq!(body.voltage_raw(), Some(12_500));
+}
+
+#[test]
+fn frames() {
+    let mut engine = EngineData::new();
+    assert!(engine.set_counter(3).is_ok());
+
+    let frame = engine.to_tx_frame::<BufferSize8>().unwrap();
+    assert!(frame.get_id() == CanID::Standard(0x100));
+    assert_eq!(frame.data(), engine.as_bytes());
+
+    assert!(EngineData::from_bytes(&[0; 7]) == Err(DbcError::InvalidLength(7)));
+    assert_eq!(EngineData::from_bytes(frame.data()).ok(), Some(engine));
+
+    // CAN FD messages do not fit classic buffers
+    assert!(GatewayInfo::new().to_tx_frame::<BufferSize8>().is_none());
+    let frame = GatewayInfo::new().to_tx_frame::<BufferSize64>().unwrap();
+    assert!(frame.is_fd_format());
+    assert_eq!(frame.data().len(), 64);
+}
@@ -0,0 +1,84 @@
+//!
+//! Message RAM errors injected into the simulated module
+//!
+#![cfg(feature = "sim")]
+
+mod support;
+
+use support::{frame, loopback_nodes};
+use tc37x_hal::can::{
+    node::ecc::{
+        EccCheck, EccError, EccErrorKind, EccMonitor, EccTracking, UncorrectableEccPolicy,
+    },
+    sim::SimulatedModule,
+    CanID,
+};
+
+#[test]
+fn corrected_errors_are_reported() {
+    let module = SimulatedModule::take();
+    let node = module
+        .node(0)
+        .connect_internal_loopback()
+        .enable_ecc_interrupts()
+        .finalize();
+    let mut monitor = EccMonitor::new(module.ecc_tracking(0), UncorrectableEccPolicy::Escalate);
+
+    assert!(node.check_ecc(&mut monitor).is_none());
+    module.inject_ram_error(0, EccErrorKind::Corrected, 0x40);
+    assert!(
+        node.check_ecc(&mut monitor)
+            == Some(EccCheck::Handled(EccError {
+                kind: EccErrorKind::Corrected,
+                address: Some(0x40),
+            }))
+    );
+    assert!(node.check_ecc(&mut monitor).is_none());
+    assert_eq!(monitor.corrected_count(), 1);
+}
+
+#[test]
+fn reinitialize() {
+    let module = SimulatedModule::take();
+    let [tx, mut rx] = loopback_nodes(&module, 1, 2);
+    let mut tx = tx.reconfigure().enable_ecc_interrupts().finalize();
+    let mut monitor = EccMonitor::new((), UncorrectableEccPolicy::Reinitialize);
+
+    let mut tracking = module.ecc_tracking(0);
+    assert!(tracking.inject_error(EccErrorKind::Uncorrected));
+    let check = tx.check_ecc(&mut monitor).unwrap();
+    assert!(matches!(check, EccCheck::Handled(_)));
+    assert!(check.error().kind == EccErrorKind::Uncorrected);
+
+    // The node runs again
+    tx.acquire_transmit_buffer()
+        .unwrap()
+        .set_frame(frame(CanID::Standard(0x10), &[1, 2]))
+        .send();
+    assert_eq!(module.transfer(), 1);
+    assert!(rx.try_receive_fifo0().is_some());
+}
+
+#[test]
+fn escalate() {
+    let module = SimulatedModule::take();
+    let [tx, _rx] = loopback_nodes(&module, 1, 2);
+    let mut tx = tx.reconfigure().enable_ecc_interrupts().finalize();
+    let mut monitor = EccMonitor::new((), UncorrectableEccPolicy::Escalate);
+
+    module.inject_ram_error(0, EccErrorKind::Uncorrected, 0);
+    let check = tx.check_ecc(&mut monitor).unwrap();
+    assert!(matches!(check, EccCheck::Escalate(_)));
+    assert_eq!(monitor.uncorrected_count(), 1);
+
+    // The node stays in configuration mode until the application takes it over
+    tx.acquire_transmit_buffer()
+        .unwrap()
+        .set_frame(frame(CanID::Standard(0x10), &[1, 2]))
+        .send();
+    assert_eq!(module.transfer(), 0);
+
+    let tx = tx.reconfigure();
+    let _tx = tx.finalize();
+    assert_eq!(module.transfer(), 0);
+}
@@ -0,0 +1,149 @@
+//!
+//! Gateway between two nodes of the simulated module, a third node is the tester
+//!
+#![cfg(feature = "sim")]
+
+mod support;
+
+use support::{frame, loopback_nodes, remote};
+use tc37x_hal::{
+    can::{
+        gateway::{FrameFormat, Gateway, Port, Route, RouteAction, RouteFilter},
+        sim::SimulatedModule,
+        CanID,
+    },
+    time::Instant,
+};
+
+fn routes() -> [Route; 3] {
+    [
+        Route::new(
+            Port::A,
+            RouteFilter::Range {
+                from: CanID::Standard(0x100),
+                to: CanID::Standard(0x11F),
+            },
+            RouteAction::Rewrite {
+                destination: Port::B,
+                id: CanID::Standard(0x200),
+                mask: 0x700,
+            },
+        ),
+        Route::new(
+            Port::A,
+            RouteFilter::id(CanID::Standard(0x120)),
+            RouteAction::Forward {
+                destination: Port::B,
+            },
+        ),
+        Route::new(
+            Port::A,
+            RouteFilter::id(CanID::Standard(0x130)),
+            RouteAction::Forward {
+                destination: Port::B,
+            },
+        )
+        .with_format(FrameFormat::Fd {
+            bitrate_switching: false,
+        }),
+    ]
+}
+
+#[test]
+fn forwarded_frames_keep_their_order() {
+    let module = SimulatedModule::take();
+    let [a, b, mut tester] = loopback_nodes(&module, 4, 8);
+    let mut a = a.reconfigure().set_tx_fifo().finalize();
+    let mut b = b
+        .reconfigure()
+        .reserve_tx_buffers(1)
+        .set_tx_fifo()
+        .finalize();
+
+    // Sent one by one, with dedicated buffers the lowest id would go first
+    for id in [0x103, 0x102, 0x101] {
+        tester
+            .acquire_transmit_buffer()
+            .unwrap()
+            .set_frame(frame(CanID::Standard(id), &[id as u8]))
+            .send();
+        assert_eq!(module.transfer(), 1);
+    }
+
+    let mut routes = routes();
+    let mut gateway = Gateway::new(&mut routes);
+    gateway.process(&mut a, &mut b, &Instant::now(), |_, _, _| {});
+    assert_eq!(module.transfer(), 3);
+
+    let received: Vec<_> = core::iter::from_fn(|| tester.try_receive_fifo0())
+        .map(|frame| (frame.get_id(), frame.data()[0]))
+        .collect();
+    assert!(
+        received
+            == [
+                (CanID::Standard(0x203), 0x03),
+                (CanID::Standard(0x202), 0x02),
+                (CanID::Standard(0x201), 0x01),
+            ]
+    );
+    assert_eq!(gateway.routes()[0].counters().forwarded, 3);
+}
+
+#[test]
+fn full_fifo() {
+    let module = SimulatedModule::take();
+    let [a, b] = loopback_nodes(&module, 2, 8);
+    let mut a = a.reconfigure().set_tx_fifo().finalize();
+    let mut b = b.reconfigure().set_tx_fifo().finalize();
+
+    for id in [0x100, 0x101, 0x102] {
+        module.inject(CanID::Standard(id), &[]);
+    }
+
+    let mut routes = routes();
+    let mut gateway = Gateway::new(&mut routes);
+    gateway.process(&mut a, &mut b, &Instant::now(), |_, _, _| {});
+
+    let counters = gateway.routes()[0].counters();
+    assert_eq!((counters.forwarded, counters.lost), (2, 1));
+    assert!(b.acquire_transmit_buffer().is_none());
+
+    assert_eq!(module.transfer(), 2);
+    assert!(b.acquire_transmit_buffer().is_some());
+}
+
+#[test]
+fn remote_frames() {
+    let module = SimulatedModule::take();
+    let [a, b, mut tester] = loopback_nodes(&module, 2, 8);
+    let mut a = a.reconfigure().set_tx_fifo().finalize();
+    let mut b = b.reconfigure().set_tx_fifo().finalize();
+
+    for frame in [
+        remote(CanID::Standard(0x120), 3),
+        remote(CanID::Standard(0x130), 8),
+    ] {
+        tester
+            .acquire_transmit_buffer()
+            .unwrap()
+            .set_frame(frame)
+            .send();
+    }
+    assert_eq!(module.transfer(), 2);
+
+    let mut routes = routes();
+    let mut gateway = Gateway::new(&mut routes);
+    gateway.process(&mut a, &mut b, &Instant::now(), |_, _, _| {});
+    assert_eq!(module.transfer(), 1);
+
+    let received = tester.try_receive_fifo0().unwrap();
+    let header = received.header();
+    assert!(received.get_id() == CanID::Standard(0x120));
+    assert!(header.rtr());
+    assert_eq!(header.data_length(), 3);
+    assert!(tester.try_receive_fifo0().is_none());
+
+    // Remote frames cannot be converted to CAN FD
+    let counters = gateway.routes()[2].counters();
+    assert_eq!((counters.forwarded, counters.lost), (0, 1));
+}
@@ -0,0 +1,348 @@
+//!
+//! ISO-TP segmentation, flow control and timeouts between two channels
+//!
+#![cfg(feature = "sim")]
+
+mod support;
+
+use core::time::Duration;
+
+use tc37x_hal::{
+    can::{
+        isotp::{Addressing, IsoTp, IsoTpChannel, IsoTpConfig, IsoTpError, IsoTpEvent},
+        memory::module_ram::{BufferSize64, BufferSize8, NodeMemoryBuilder},
+        sim::{SimulatedModule, SimulatedRAM},
+        CanID,
+    },
+    time::Instant,
+};
+
+const TESTER: CanID = CanID::Standard(0x7E0);
+const ECU: CanID = CanID::Standard(0x7E8);
+
+fn at(millis: u64) -> Instant {
+    Instant::from_time_since_boot(Duration::from_millis(millis))
+}
+
+fn tester() -> IsoTpConfig {
+    IsoTpConfig::new(TESTER, ECU)
+}
+
+fn ecu() -> IsoTpConfig {
+    IsoTpConfig::new(ECU, TESTER)
+}
+
+/// Hand out at most one frame of `from`, confirm it and pass it to `to`
+fn forward(from: &mut IsoTp, to: &mut IsoTp, now: &Instant) -> Option<Vec<u8>> {
+    let frame = from.next_frame::<BufferSize64>(now)?;
+    from.on_transmitted(now);
+    to.on_frame(frame.get_id(), frame.data(), now);
+    Some(frame.data().to_vec())
+}
+
+/// Exchange frames until both sides are idle, one millisecond after another.
+/// Returns all frames on the bus and the events of both sides.
+fn transfer(
+    sender: IsoTpConfig,
+    receiver: IsoTpConfig,
+    message: &[u8],
+) -> (Vec<Vec<u8>>, Vec<u8>, Vec<IsoTpEvent>) {
+    let (mut tx_a, mut rx_a) = (vec![0; 5000], vec![0; 5000]);
+    let (mut tx_b, mut rx_b) = (vec![0; 5000], vec![0; 5000]);
+    let mut a = IsoTp::new(sender, &mut tx_a, &mut rx_a);
+    let mut b = IsoTp::new(receiver, &mut tx_b, &mut rx_b);
+    assert!(a.send(message).is_ok());
+
+    let mut frames = vec![];
+    let mut events = vec![];
+    for millis in 0..10_000 {
+        let now = at(millis);
+        loop {
+            let mut progress = false;
+            if let Some(frame) = forward(&mut a, &mut b, &now) {
+                frames.push(frame);
+                progress = true;
+            }
+            if let Some(frame) = forward(&mut b, &mut a, &now) {
+                frames.push(frame);
+                progress = true;
+            }
+            if !progress {
+                break;
+            }
+        }
+        events.extend(a.poll(&now));
+        events.extend(b.poll(&now));
+        if events.len() == 2 {
+            break;
+        }
+    }
+
+    (frames, b.received().to_vec(), events)
+}
+
+#[test]
+fn single_frame() {
+    let (frames, received, events) = transfer(tester(), ecu(), &[0x22, 0xF1, 0x90]);
+
+    assert_eq!(frames, [[0x03, 0x22, 0xF1, 0x90, 0xCC, 0xCC, 0xCC, 0xCC]]);
+    assert_eq!(received, [0x22, 0xF1, 0x90]);
+    assert!(events.contains(&IsoTpEvent::Sent));
+    assert!(events.contains(&IsoTpEvent::Received { length: 3 }));
+}
+
+#[test]
+fn segmentation() {
+    let message: Vec<u8> = (0..20).collect();
+    let (frames, received, events) = transfer(tester(), ecu(), &message);
+
+    assert_eq!(
+        frames,
+        [
+            vec![0x10, 20, 0, 1, 2, 3, 4, 5],
+            vec![0x30, 0, 0, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC],
+            vec![0x21, 6, 7, 8, 9, 10, 11, 12],
+            vec![0x22, 13, 14, 15, 16, 17, 18, 19],
+        ]
+    );
+    assert_eq!(received, message);
+    assert!(events.contains(&IsoTpEvent::Sent));
+    assert!(events.contains(&IsoTpEvent::Received { length: 20 }));
+}
+
+#[test]
+fn sequence_number_wraps() {
+    // 6 bytes in the first frame and 17 consecutive frames
+    let message: Vec<u8> = (0..125).collect();
+    let (frames, received, _) = transfer(tester(), ecu(), &message);
+
+    let sequence: Vec<u8> = frames.iter().skip(2).map(|frame| frame[0]).collect();
+    assert_eq!(
+        sequence[..17],
+        [
+            0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2A, 0x2B, 0x2C, 0x2D, 0x2E,
+            0x2F, 0x20, 0x21
+        ]
+    );
+    assert_eq!(received, message);
+}
+
+#[test]
+fn flow_control_per_block() {
+    let receiver = IsoTpConfig {
+        block_size: 2,
+        separation_time: Duration::from_micros(300),
+        ..ecu()
+    };
+    let message: Vec<u8> = (0..30).collect();
+    let (frames, received, _) = transfer(tester(), receiver, &message);
+
+    let types: Vec<u8> = frames.iter().map(|frame| frame[0]).collect();
+    assert_eq!(types, [0x10, 0x30, 0x21, 0x22, 0x30, 0x23, 0x24]);
+    // 300 µs are encoded as 0xF3
+    assert_eq!(frames[1][..3], [0x30, 2, 0xF3]);
+    assert_eq!(received, message);
+}
+
+#[test]
+fn separation_time() {
+    let (mut tx, mut rx) = ([0; 64], [0; 64]);
+    let mut sender = IsoTp::new(tester(), &mut tx, &mut rx);
+    assert!(sender.send(&[0; 20]).is_ok());
+
+    assert!(sender.next_frame::<BufferSize64>(&at(0)).is_some());
+    sender.on_transmitted(&at(0));
+    // Continue to send, no block limit, 10 ms between consecutive frames
+    sender.on_frame(ECU, &[0x30, 0, 10, 0, 0, 0, 0, 0], &at(0));
+
+    assert!(sender.next_frame::<BufferSize64>(&at(0)).is_some());
+    sender.on_transmitted(&at(0));
+    assert!(sender.next_frame::<BufferSize64>(&at(9)).is_none());
+    let last = sender.next_frame::<BufferSize64>(&at(10)).unwrap();
+    assert_eq!(last.data()[0], 0x22);
+    sender.on_transmitted(&at(10));
+    assert!(sender.poll(&at(10)) == Some(IsoTpEvent::Sent));
+}
+
+#[test]
+fn can_fd_with_escape_sequences() {
+    let fd = |config: IsoTpConfig| IsoTpConfig {
+        tx_data_length: 64,
+        padding: None,
+        addressing: Addressing::Mixed {
+            address_extension: 5,
+        },
+        ..config
+    };
+    let message: Vec<u8> = (0..5000).map(|value| value as u8).collect();
+
+    // More than 4095 bytes need the 32 bit length of the first frame
+    let (frames, received, _) = transfer(fd(tester()), fd(ecu()), &message);
+    assert_eq!(frames[0][..8], [5, 0x10, 0x00, 0x00, 0x00, 0x13, 0x88, 0]);
+    assert_eq!(frames[0].len(), 64);
+    assert_eq!(received, message);
+
+    // Single frames longer than 7 bytes need the length in the second byte
+    let (frames, received, _) = transfer(fd(tester()), fd(ecu()), &message[..40]);
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0][..3], [5, 0x00, 40]);
+    assert_eq!(frames[0].len(), 48);
+    assert_eq!(received, &message[..40]);
+}
+
+#[test]
+fn flow_control_timeout() {
+    let (mut tx, mut rx) = ([0; 64], [0; 64]);
+    let mut sender = IsoTp::new(tester(), &mut tx, &mut rx);
+    assert!(sender.send(&[0; 30]).is_ok());
+
+    sender.next_frame::<BufferSize64>(&at(0)).unwrap();
+    sender.on_transmitted(&at(0));
+    assert!(sender.poll(&at(999)).is_none());
+    assert!(
+        sender.poll(&at(1000)) == Some(IsoTpEvent::TransmitFailed(IsoTpError::FlowControlTimeout))
+    );
+    assert!(!sender.is_transmitting());
+}
+
+#[test]
+fn transmit_timeout() {
+    let (mut tx, mut rx) = ([0; 64], [0; 64]);
+    let mut sender = IsoTp::new(tester(), &mut tx, &mut rx);
+    assert!(sender.send(&[1, 2, 3]).is_ok());
+
+    sender.next_frame::<BufferSize64>(&at(0)).unwrap();
+    assert!(sender.awaits_confirmation());
+    assert!(sender.poll(&at(999)).is_none());
+    assert!(
+        sender.poll(&at(1000)) == Some(IsoTpEvent::TransmitFailed(IsoTpError::TransmitTimeout))
+    );
+}
+
+#[test]
+fn timed_out_frames_are_cancelled() {
+    let module = SimulatedModule::take();
+    let mut ram = unsafe { NodeMemoryBuilder::<SimulatedRAM>::steal_module_mem() };
+
+    let mut node = module
+        .node(0)
+        .connect_internal_loopback()
+        .set_tx::<BufferSize8>(ram.take_expect(1))
+        .set_rx_fifo0::<BufferSize8>(ram.take_expect(2))
+        .finalize();
+    let mut ecu_node = module
+        .node(1)
+        .connect_internal_loopback()
+        .set_rx_fifo0::<BufferSize8>(ram.take_expect(2))
+        .finalize();
+
+    let (mut tx, mut rx) = ([0; 64], [0; 64]);
+    let mut channel = IsoTpChannel::new(IsoTp::new(tester(), &mut tx, &mut rx));
+    assert!(channel.send(&[1, 2, 3]).is_ok());
+    assert!(channel.process(&mut node, &at(0)).is_none());
+
+    // The frame never made it to the bus, it is cancelled instead of going out late
+    assert!(
+        channel.process(&mut node, &at(1000))
+            == Some(IsoTpEvent::TransmitFailed(IsoTpError::TransmitTimeout))
+    );
+    assert_eq!(module.transfer(), 0);
+    assert!(ecu_node.try_receive_fifo0().is_none());
+
+    // The buffer is free for the next message
+    assert!(channel.send(&[4, 5, 6]).is_ok());
+    assert!(channel.process(&mut node, &at(1001)).is_none());
+    assert_eq!(module.transfer(), 1);
+    assert_eq!(
+        ecu_node.try_receive_fifo0().unwrap().data()[1..4],
+        [4, 5, 6]
+    );
+}
+
+#[test]
+fn consecutive_frame_timeout() {
+    let (mut tx, mut rx) = ([0; 64], [0; 64]);
+    let mut receiver = IsoTp::new(ecu(), &mut tx, &mut rx);
+
+    receiver.on_frame(TESTER, &[0x10, 20, 0, 1, 2, 3, 4, 5], &at(0));
+    receiver.next_frame::<BufferSize64>(&at(0)).unwrap();
+    receiver.on_transmitted(&at(0));
+    receiver.on_frame(TESTER, &[0x21, 6, 7, 8, 9, 10, 11, 12], &at(500));
+
+    // The timeout restarts with every consecutive frame
+    assert!(receiver.poll(&at(1499)).is_none());
+    assert!(
+        receiver.poll(&at(1500))
+            == Some(IsoTpEvent::ReceiveFailed(
+                IsoTpError::ConsecutiveFrameTimeout
+            ))
+    );
+}
+
+#[test]
+fn wrong_sequence_number() {
+    let (mut tx, mut rx) = ([0; 64], [0; 64]);
+    let mut receiver = IsoTp::new(ecu(), &mut tx, &mut rx);
+
+    receiver.on_frame(TESTER, &[0x10, 20, 0, 1, 2, 3, 4, 5], &at(0));
+    receiver.next_frame::<BufferSize64>(&at(0)).unwrap();
+    receiver.on_transmitted(&at(0));
+    receiver.on_frame(TESTER, &[0x22, 6, 7, 8, 9, 10, 11, 12], &at(1));
+
+    assert!(
+        receiver.poll(&at(1)) == Some(IsoTpEvent::ReceiveFailed(IsoTpError::WrongSequenceNumber))
+    );
+}
+
+#[test]
+fn receive_buffer_overflow() {
+    let (mut tx, mut rx) = ([0; 100], [0; 100]);
+    let mut receiver = IsoTp::new(ecu(), &mut tx, &mut rx);
+
+    receiver.on_frame(TESTER, &[0x10, 200, 0, 0, 0, 0, 0, 0], &at(0));
+    assert!(receiver.poll(&at(0)) == Some(IsoTpEvent::ReceiveFailed(IsoTpError::BufferOverflow)));
+    // The sender is told with an overflow flow control frame
+    let flow_control = receiver.next_frame::<BufferSize64>(&at(0)).unwrap();
+    assert_eq!(
+        flow_control.data(),
+        [0x32, 0, 0, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC]
+    );
+}
+
+#[test]
+fn flow_status_overflow_and_wait() {
+    let (mut tx, mut rx) = ([0; 64], [0; 64]);
+    let mut sender = IsoTp::new(tester(), &mut tx, &mut rx);
+
+    assert!(sender.send(&[0; 30]).is_ok());
+    sender.next_frame::<BufferSize64>(&at(0)).unwrap();
+    sender.on_transmitted(&at(0));
+    sender.on_frame(ECU, &[0x32, 0, 0, 0, 0, 0, 0, 0], &at(0));
+    assert!(sender.poll(&at(0)) == Some(IsoTpEvent::TransmitFailed(IsoTpError::Overflow)));
+
+    // No WAIT frames are accepted by default
+    assert!(sender.send(&[0; 30]).is_ok());
+    sender.next_frame::<BufferSize64>(&at(0)).unwrap();
+    sender.on_transmitted(&at(0));
+    sender.on_frame(ECU, &[0x31, 0, 0, 0, 0, 0, 0, 0], &at(0));
+    assert!(sender.poll(&at(0)) == Some(IsoTpEvent::TransmitFailed(IsoTpError::WaitLimitExceeded)));
+}
+
+#[test]
+fn wait_restarts_flow_control_timeout() {
+    let config = IsoTpConfig {
+        max_wait_frames: 2,
+        ..tester()
+    };
+    let (mut tx, mut rx) = ([0; 64], [0; 64]);
+    let mut sender = IsoTp::new(config, &mut tx, &mut rx);
+
+    assert!(sender.send(&[0; 30]).is_ok());
+    sender.next_frame::<BufferSize64>(&at(0)).unwrap();
+    sender.on_transmitted(&at(0));
+    sender.on_frame(ECU, &[0x31, 0, 0, 0, 0, 0, 0, 0], &at(800));
+    assert!(sender.poll(&at(1799)).is_none());
+    assert!(
+        sender.poll(&at(1800)) == Some(IsoTpEvent::TransmitFailed(IsoTpError::FlowControlTimeout))
+    );
+}
@@ -0,0 +1,223 @@
+//!
+//! Log lines compared with the output of `candump -L` and the python-can ASC writer
+//!
+#![cfg(feature = "sim")]
+
+mod support;
+
+use core::time::Duration;
+
+use support::{frame, remote};
+use tc37x_hal::{
+    can::{
+        log::{Asc, Candump, Direction, MAX_LINE},
+        memory::module_ram::{BufferSize64, BufferSize8},
+        CanID, CanTxFrame,
+    },
+    time::Instant,
+};
+
+fn at(micros: u64) -> Instant {
+    Instant::from_time_since_boot(Duration::from_micros(micros))
+}
+
+fn fd_frame(id: CanID, data: &[u8], bitrate_switching: bool) -> CanTxFrame<BufferSize64> {
+    let mut frame = frame(id, data);
+    frame.set_fd_format(bitrate_switching);
+    frame
+}
+
+fn line(buffer: &[u8], length: Option<usize>) -> &str {
+    core::str::from_utf8(&buffer[..length.unwrap()]).unwrap()
+}
+
+#[test]
+fn candump() {
+    let candump = Candump::new("vcan0");
+    let mut buffer = [0; MAX_LINE];
+    let cases = [
+        (
+            frame::<BufferSize8>(CanID::Standard(0x123), &[0x11, 0x22]),
+            12_345_678,
+            "(0000000012.345678) vcan0 123#1122\n",
+        ),
+        (
+            frame(CanID::Standard(0x7FF), &[0, 1, 2, 3, 4, 5, 6, 7]),
+            1_000_000,
+            "(0000000001.000000) vcan0 7FF#0001020304050607\n",
+        ),
+        (
+            frame(CanID::Extended(0x18DA_F110), &[]),
+            0,
+            "(0000000000.000000) vcan0 18DAF110#\n",
+        ),
+    ];
+
+    for (frame, micros, expected) in cases {
+        let length = candump.encode(&frame, &at(micros), &mut buffer);
+        assert_eq!(line(&buffer, length), expected);
+    }
+}
+
+#[test]
+fn candump_remote_frames() {
+    let candump = Candump::new("can0");
+    let mut buffer = [0; MAX_LINE];
+
+    let length = candump.encode(
+        &remote::<BufferSize8>(CanID::Extended(0x1AB_CDEF), 3),
+        &at(5),
+        &mut buffer,
+    );
+    assert_eq!(
+        line(&buffer, length),
+        "(0000000000.000005) can0 01ABCDEF#R3\n"
+    );
+    // A length of 0 is left out
+    let length = candump.encode(
+        &remote::<BufferSize8>(CanID::Standard(0x7), 0),
+        &at(5),
+        &mut buffer,
+    );
+    assert_eq!(line(&buffer, length), "(0000000000.000005) can0 007#R\n");
+}
+
+#[test]
+fn candump_can_fd() {
+    let mut candump = Candump::new("can0");
+    candump.set_epoch(Duration::from_secs(1_700_000_000));
+    let mut buffer = [0; MAX_LINE];
+
+    let data: Vec<u8> = (0..12).collect();
+    let length = candump.encode(
+        &fd_frame(CanID::Standard(0x123), &data, true),
+        &at(250),
+        &mut buffer,
+    );
+    assert_eq!(
+        line(&buffer, length),
+        "(1700000000.000250) can0 123##1000102030405060708090A0B\n"
+    );
+    let length = candump.encode(
+        &fd_frame(CanID::Standard(0x1), &[], false),
+        &at(0),
+        &mut buffer,
+    );
+    assert_eq!(line(&buffer, length), "(1700000000.000000) can0 001##0\n");
+}
+
+#[test]
+fn candump_line_too_long() {
+    let candump = Candump::new("can0");
+    let mut buffer = [0; 30];
+
+    let frame = frame::<BufferSize8>(CanID::Standard(0x123), &[0x11, 0x22]);
+    assert_eq!(candump.encode(&frame, &at(0), &mut buffer), None);
+}
+
+#[test]
+fn asc() {
+    let asc = Asc::new(1, at(1_000_000));
+    let mut buffer = [0; MAX_LINE];
+
+    let length = asc.header("Sun Oct 18 09:41:00.000 am 2026", &mut buffer);
+    assert_eq!(
+        line(&buffer, length),
+        "date Sun Oct 18 09:41:00.000 am 2026\n\
+         base hex  timestamps absolute\n\
+         no internal events logged\n"
+    );
+
+    let length = asc.encode(
+        &frame::<BufferSize8>(CanID::Standard(0x123), &[0x11, 0x22]),
+        Direction::Rx,
+        &at(2_250_000),
+        &mut buffer,
+    );
+    assert_eq!(
+        line(&buffer, length),
+        " 1.250000 1  123             Rx   d 2 11 22\n"
+    );
+
+    let data: Vec<u8> = (0..8).collect();
+    let length = asc.encode(
+        &frame::<BufferSize8>(CanID::Standard(0x7FF), &data),
+        Direction::Rx,
+        &at(1_000_000),
+        &mut buffer,
+    );
+    assert_eq!(
+        line(&buffer, length),
+        " 0.000000 1  7FF             Rx   d 8 00 01 02 03 04 05 06 07\n"
+    );
+
+    let asc = Asc::new(2, at(0));
+    let length = asc.encode(
+        &remote::<BufferSize8>(CanID::Extended(0x1AB_CDEF), 3),
+        Direction::Tx,
+        &at(12_500_000),
+        &mut buffer,
+    );
+    assert_eq!(
+        line(&buffer, length),
+        " 12.500000 2  1ABCDEFx        Tx   r 3 \n"
+    );
+}
+
+#[test]
+fn asc_can_fd() {
+    let mut buffer = [0; MAX_LINE];
+
+    let data: Vec<u8> = (0..12).collect();
+    let length = Asc::new(1, at(1_000_000)).encode(
+        &fd_frame(CanID::Standard(0x123), &data, true),
+        Direction::Tx,
+        &at(1_000_250),
+        &mut buffer,
+    );
+    assert_eq!(
+        line(&buffer, length),
+        " 0.000250 CANFD   1 Tx        123                                    1 0 9 12 \
+         00 01 02 03 04 05 06 07 08 09 0A 0B        0    0     3000        0        0        \
+         0        0        0\n"
+    );
+
+    let length = Asc::new(12, at(0)).encode(
+        &fd_frame(CanID::Extended(0x18DA_F110), &[0xAA; 16], false),
+        Direction::Rx,
+        &at(3_000_000),
+        &mut buffer,
+    );
+    assert_eq!(
+        line(&buffer, length),
+        " 3.000000 CANFD  12 Rx   18DAF110x                                    0 0 a 16 \
+         AA AA AA AA AA AA AA AA AA AA AA AA AA AA AA AA        0    0     1000        0        \
+         0        0        0        0\n"
+    );
+
+    // The longest line fits
+    let length = Asc::new(1, at(0)).encode(
+        &fd_frame(CanID::Extended(0x1FFF_FFFF), &[0xFF; 64], true),
+        Direction::Rx,
+        &at(99_999_000_000),
+        &mut buffer,
+    );
+    assert!(length.unwrap() < MAX_LINE);
+}
+
+#[test]
+fn asc_frame_before_start() {
+    let asc = Asc::new(1, at(5_000_000));
+    let mut buffer = [0; MAX_LINE];
+
+    let length = asc.encode(
+        &frame::<BufferSize8>(CanID::Standard(0x123), &[0x11]),
+        Direction::Tx,
+        &at(4_000_000),
+        &mut buffer,
+    );
+    assert_eq!(
+        line(&buffer, length),
+        " 0.000000 1  123             Tx   d 1 11\n"
+    );
+}
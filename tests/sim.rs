@@ -0,0 +1,382 @@
+//!
+//! The CAN driver on the host-side model of the module
+//!
+#![cfg(feature = "sim")]
+
+mod support;
+
+use core::time::Duration;
+use std::thread;
+
+use support::{frame, loopback_nodes};
+use tc37x_hal::{
+    can::{
+        memory::{
+            filter::{ExtendedFilter, FilterAction, StandardFilter},
+            module_ram::{BufferSize8, NodeMemoryBuilder},
+            rx::CanRxFrame,
+        },
+        node::{
+            bitrate_detection::BitrateCandidate,
+            filter::NonMatchingFrames,
+            options::NodeProtocolOptions,
+            priority::{FilterList, MessageStorage},
+            statistics::NodeStatistics,
+            transceive::TransmitStatus,
+            CanNode,
+        },
+        sim::{SimulatedModule, SimulatedRAM},
+        timing::Kbps,
+        CanID,
+    },
+    time::{advance_time, Instant},
+};
+
+/// The ids of all frames in FIFO 0
+fn received_ids(node: &mut support::LoopbackNode) -> Vec<CanID> {
+    core::iter::from_fn(|| node.try_receive_fifo0())
+        .map(|frame| frame.get_id())
+        .collect()
+}
+
+#[test]
+fn loopback() {
+    let module = SimulatedModule::take();
+    let [mut tx, mut rx] = loopback_nodes(&module, 2, 4);
+
+    let token = tx
+        .acquire_transmit_buffer()
+        .unwrap()
+        .set_frame(frame(CanID::Standard(0x123), &[1, 2, 3]))
+        .send();
+    assert!(tx.transmit_status(&token) == TransmitStatus::Pending);
+    assert!(rx.try_receive_fifo0().is_none());
+
+    assert_eq!(module.transfer(), 1);
+    assert!(tx.transmit_status(&token) == TransmitStatus::Sent);
+
+    let received = rx.try_receive_fifo0().unwrap();
+    assert!(received.get_id() == CanID::Standard(0x123));
+    assert_eq!(received.data(), &[1, 2, 3]);
+    assert!(rx.try_receive_fifo0().is_none());
+}
+
+#[test]
+fn arbitration_by_id() {
+    let module = SimulatedModule::take();
+    let [mut tx, mut rx] = loopback_nodes(&module, 4, 4);
+
+    for id in [0x300, 0x100, 0x200] {
+        tx.acquire_transmit_buffer()
+            .unwrap()
+            .set_frame(frame(CanID::Standard(id), &[]))
+            .send();
+    }
+    assert_eq!(module.transfer(), 3);
+
+    assert!(received_ids(&mut rx) == [0x100, 0x200, 0x300].map(CanID::Standard));
+}
+
+#[test]
+fn standard_filters() {
+    let module = SimulatedModule::take();
+    let mut ram = unsafe { NodeMemoryBuilder::<SimulatedRAM>::steal_module_mem() };
+
+    let mut node = module
+        .node(0)
+        .connect_internal_loopback()
+        .set_rx_fifo0::<BufferSize8>(ram.take_expect(8))
+        .set_standard_filters(
+            ram.take_expect(4),
+            &[
+                StandardFilter::classic(0x100, 0x7F0, FilterAction::StoreFifo0),
+                StandardFilter::range(0x200, 0x20F, FilterAction::Reject),
+                StandardFilter::dual(0x300, 0x301, FilterAction::SetPriorityStoreFifo0),
+                StandardFilter::range(0x400, 0x4FF, FilterAction::SetPriority),
+            ],
+        )
+        .set_non_matching_frames(NonMatchingFrames::Reject, NonMatchingFrames::AcceptFifo0)
+        .finalize();
+
+    for id in [0x105, 0x115, 0x205, 0x301, 0x500] {
+        module.inject(CanID::Standard(id), &[]);
+    }
+    let message = node.take_high_priority_message().unwrap();
+    assert!(message.storage == MessageStorage::Fifo0);
+    assert!(message.filter_list == FilterList::Standard);
+    assert_eq!((message.buffer_index, message.filter_index), (1, 2));
+    assert!(node.take_high_priority_message().is_none());
+
+    // Only flagged, not stored
+    module.inject(CanID::Standard(0x480), &[]);
+    let message = node.take_high_priority_message().unwrap();
+    assert!(message.storage == MessageStorage::NoFifo);
+    assert_eq!(message.filter_index, 3);
+
+    // Extended frames are accepted without filter elements
+    module.inject(CanID::Extended(0x105), &[]);
+
+    let frames: Vec<_> = core::iter::from_fn(|| node.try_receive_fifo0()).collect();
+    let ids: Vec<_> = frames.iter().map(|frame| frame.get_id()).collect();
+    assert!(
+        ids == [
+            CanID::Standard(0x105),
+            CanID::Standard(0x301),
+            CanID::Extended(0x105)
+        ]
+    );
+    assert_eq!(frames[1].header().filter_index(), 2);
+    assert!(!frames[1].header().accepted_non_matching_frame());
+    assert!(frames[2].header().accepted_non_matching_frame());
+}
+
+#[test]
+fn extended_filters() {
+    let module = SimulatedModule::take();
+    let mut ram = unsafe { NodeMemoryBuilder::<SimulatedRAM>::steal_module_mem() };
+
+    let mut node = module
+        .node(0)
+        .connect_internal_loopback()
+        .set_rx_fifo0::<BufferSize8>(ram.take_expect(8))
+        .set_extended_filters(
+            ram.take_expect(3),
+            &[
+                ExtendedFilter::range(0x18DA_0000, 0x18DA_FFFF, FilterAction::Reject),
+                ExtendedFilter::classic(0x18DA_F100, 0x1FFF_FF00, FilterAction::StoreFifo0),
+                ExtendedFilter::dual(
+                    0x18FE_F100,
+                    0x0CF0_0400,
+                    FilterAction::SetPriorityStoreFifo0,
+                ),
+            ],
+        )
+        .set_non_matching_frames(NonMatchingFrames::AcceptFifo0, NonMatchingFrames::Reject)
+        .finalize();
+
+    // The range filter comes first and rejects the frame of the classic filter
+    for id in [0x18DA_F110, 0x18FE_F100, 0x0CF0_0400, 0x1234_5678] {
+        module.inject(CanID::Extended(id), &[]);
+    }
+    module.inject(CanID::Standard(0x7FF), &[]);
+
+    let message = node.take_high_priority_message().unwrap();
+    assert!(message.storage == MessageStorage::Fifo0);
+    assert!(message.filter_list == FilterList::Extended);
+    assert_eq!((message.buffer_index, message.filter_index), (1, 2));
+
+    let ids: Vec<_> = core::iter::from_fn(|| node.try_receive_fifo0())
+        .map(|frame| frame.get_id())
+        .collect();
+    assert!(
+        ids == [
+            CanID::Extended(0x18FE_F100),
+            CanID::Extended(0x0CF0_0400),
+            CanID::Standard(0x7FF)
+        ]
+    );
+}
+
+#[test]
+fn fifo0_wraps_around() {
+    let module = SimulatedModule::take();
+    let [mut node] = loopback_nodes(&module, 1, 3);
+    let mut statistics = NodeStatistics::new(
+        BitrateCandidate::classic(Kbps::new(500)),
+        Duration::from_millis(100),
+    );
+
+    for id in [0x1, 0x2] {
+        module.inject(CanID::Standard(id), &[id as u8]);
+    }
+    assert!(received_ids(&mut node) == [0x1, 0x2].map(CanID::Standard));
+
+    // The put index wraps, the frame of the full FIFO is discarded
+    for id in [0x3, 0x4, 0x5, 0x6] {
+        module.inject(CanID::Standard(id), &[id as u8]);
+    }
+    node.update_statistics(&mut statistics);
+
+    let frames: Vec<_> = core::iter::from_fn(|| node.try_receive_fifo0()).collect();
+    let received: Vec<_> = frames
+        .iter()
+        .map(|frame| (frame.get_id(), frame.data()[0]))
+        .collect();
+    assert!(
+        received
+            == [
+                (CanID::Standard(0x3), 3),
+                (CanID::Standard(0x4), 4),
+                (CanID::Standard(0x5), 5),
+            ]
+    );
+
+    let snapshot = statistics.snapshot(&Instant::now());
+    assert_eq!((snapshot.lost, snapshot.fifo0_high_water_mark), (1, 3));
+
+    // The flag is cleared once sampled
+    node.update_statistics(&mut statistics);
+    assert_eq!(statistics.snapshot(&Instant::now()).lost, 1);
+}
+
+#[test]
+fn drain_and_lease() {
+    let module = SimulatedModule::take();
+    let [mut node] = loopback_nodes(&module, 1, 4);
+
+    for id in [0x1, 0x2, 0x3, 0x4] {
+        module.inject(CanID::Standard(id), &[id as u8, 0xAA]);
+    }
+
+    {
+        let lease = node.lease_fifo0().unwrap();
+        assert!(lease.header().id() == CanID::Standard(0x1));
+        assert_eq!(lease.data_length(), 2);
+        assert!(lease.data_word(0) == Some(0xAA01));
+        let mut data = [0; 8];
+        assert_eq!(lease.copy_data(&mut data), 2);
+        assert_eq!(data[..2], [0x1, 0xAA]);
+    }
+
+    // Frames left in the iterator are not acknowledged
+    let first: Vec<_> = node
+        .drain_fifo0()
+        .take(2)
+        .map(|frame| frame.get_id())
+        .collect();
+    assert!(first == [0x2, 0x3].map(CanID::Standard));
+
+    // The slots are free again, the put index wraps
+    module.inject(CanID::Standard(0x5), &[]);
+    module.inject(CanID::Standard(0x6), &[]);
+    module.inject(CanID::Standard(0x7), &[]);
+    let mut frames: [CanRxFrame<BufferSize8>; 4] = Default::default();
+    assert_eq!(node.receive_fifo0_into(&mut frames), 4);
+    let ids: Vec<_> = frames.iter().map(|frame| frame.get_id()).collect();
+    assert!(ids == [0x4, 0x5, 0x6, 0x7].map(CanID::Standard));
+
+    assert!(node.drain_fifo0().next().is_none());
+    assert!(node.lease_fifo0().is_none());
+}
+
+#[test]
+fn not_acknowledged_single_shot() {
+    let module = SimulatedModule::take();
+    let mut ram = unsafe { NodeMemoryBuilder::<SimulatedRAM>::steal_module_mem() };
+
+    // No other node is active, nobody acknowledges the frame
+    let mut node = module
+        .node(0)
+        .connect_internal_loopback()
+        .set_protocol_options(&NodeProtocolOptions::single_shot())
+        .set_tx::<BufferSize8>(ram.take_expect(1))
+        .finalize();
+
+    let token = node
+        .acquire_transmit_buffer()
+        .unwrap()
+        .set_frame(frame(CanID::Standard(0x123), &[1]))
+        .send();
+    assert_eq!(module.transfer(), 1);
+    assert!(node.transmit_status(&token) == TransmitStatus::NotSent);
+
+    // The buffer is free, a new request resets the outcome
+    let token = node
+        .acquire_transmit_buffer()
+        .unwrap()
+        .set_frame(frame(CanID::Standard(0x123), &[2]))
+        .send();
+    assert!(node.transmit_status(&token) == TransmitStatus::Pending);
+}
+
+#[test]
+fn transmit_timeout() {
+    let module = SimulatedModule::take();
+    let mut ram = unsafe { NodeMemoryBuilder::<SimulatedRAM>::steal_module_mem() };
+
+    // No other node is active, the frame is retried until it is cancelled
+    let mut node = module
+        .node(0)
+        .connect_internal_loopback()
+        .set_tx::<BufferSize8>(ram.take_expect(1))
+        .set_transmit_timeout(Duration::from_millis(10))
+        .finalize();
+
+    let token = node
+        .acquire_transmit_buffer()
+        .unwrap()
+        .set_frame(frame(CanID::Standard(0x123), &[1]))
+        .send();
+    assert_eq!(module.transfer(), 1);
+    assert!(node.transmit_status(&token) == TransmitStatus::Pending);
+    assert!(node.acquire_transmit_buffer().is_none());
+
+    advance_time(Duration::from_millis(9));
+    assert_eq!(node.cancel_timed_out_transmissions(&Instant::now()), 0);
+    advance_time(Duration::from_millis(1));
+    assert_eq!(node.cancel_timed_out_transmissions(&Instant::now()), 1);
+    assert!(node.transmit_status(&token) == TransmitStatus::TimedOut);
+    assert_eq!(module.transfer(), 0);
+
+    // The buffer is free again, nothing is cancelled twice
+    assert_eq!(node.cancel_timed_out_transmissions(&Instant::now()), 0);
+    let token = node
+        .acquire_transmit_buffer()
+        .unwrap()
+        .set_frame(frame(CanID::Standard(0x123), &[2]))
+        .send();
+    assert!(node.transmit_status(&token) == TransmitStatus::Pending);
+}
+
+#[test]
+fn split_halves_on_threads() {
+    let module = SimulatedModule::take();
+    let [node, mut other] = loopback_nodes(&module, 2, 4);
+    let (mut tx, mut rx) = node.split();
+
+    let (tx, token) = thread::scope(|scope| {
+        let sender = scope.spawn(move || {
+            let token = tx
+                .acquire_transmit_buffer()
+                .unwrap()
+                .set_frame(frame(CanID::Standard(0x100), &[1]))
+                .send();
+            (tx, token)
+        });
+        other
+            .acquire_transmit_buffer()
+            .unwrap()
+            .set_frame(frame(CanID::Standard(0x200), &[2]))
+            .send();
+        sender.join().unwrap()
+    });
+    assert_eq!(module.transfer(), 2);
+
+    let (tx, rx) = thread::scope(|scope| {
+        let receiver = scope.spawn(move || {
+            assert!(rx.take_rx_fifo0_interrupt());
+            let received = rx.try_receive_fifo0().unwrap();
+            assert!(received.get_id() == CanID::Standard(0x200));
+            assert_eq!(received.data(), &[2]);
+            assert!(rx.try_receive_fifo0().is_none());
+            rx
+        });
+        let sender = scope.spawn(move || {
+            assert!(tx.transmit_status(&token) == TransmitStatus::Sent);
+            tx
+        });
+        (sender.join().unwrap(), receiver.join().unwrap())
+    });
+    assert!(received_ids(&mut other) == [CanID::Standard(0x100)]);
+
+    // Joined again, the node sends and receives as before
+    let mut node = CanNode::join(tx, rx);
+    node.acquire_transmit_buffer()
+        .unwrap()
+        .set_frame(frame(CanID::Standard(0x101), &[]))
+        .send();
+    module.inject(CanID::Standard(0x201), &[]);
+    assert_eq!(module.transfer(), 1);
+    assert!(received_ids(&mut node) == [CanID::Standard(0x201)]);
+    assert!(received_ids(&mut other) == [0x201, 0x101].map(CanID::Standard));
+}
@@ -0,0 +1,307 @@
+//!
+//! SLCAN sessions recorded between `slcand` and an adapter, replayed byte by byte
+//!
+#![cfg(feature = "sim")]
+
+mod support;
+
+use std::collections::VecDeque;
+
+use core::time::Duration;
+
+use tc37x_hal::{
+    can::{
+        slcan::{ByteStream, Slcan, SlcanCommand, SlcanFrame},
+        CanID,
+    },
+    time::Instant,
+};
+
+/// Serial port with the bytes of the host queued, writes at most `chunk` bytes at once
+struct Serial {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+    chunk: usize,
+}
+
+impl Serial {
+    fn new(chunk: usize) -> Self {
+        Serial {
+            input: VecDeque::new(),
+            output: Vec::new(),
+            chunk,
+        }
+    }
+}
+
+impl ByteStream for Serial {
+    fn read(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> usize {
+        let count = bytes.len().min(self.chunk);
+        self.output.extend_from_slice(&bytes[..count]);
+        count
+    }
+}
+
+enum Step {
+    /// Bytes sent by the host
+    Host(&'static [u8]),
+    /// A frame received from the bus at the given millisecond
+    Bus(SlcanFrame, u64),
+}
+
+/// What the node was asked to do
+#[derive(PartialEq)]
+enum Request {
+    Open {
+        nominal: u32,
+        data: Option<u32>,
+        /// Synchronization jump width, pre-scaler and time segments given with `s`
+        timing: Option<(u8, u16, u8, u8)>,
+        listen_only: bool,
+    },
+    Close,
+    Transmit {
+        id: CanID,
+        data: Vec<u8>,
+        remote: bool,
+        fd_format: bool,
+        bitrate_switching: bool,
+    },
+}
+
+/// Replay the steps, every command is completed successfully. Returns the requests
+/// to the node and everything written to the host.
+fn replay(steps: &[Step], chunk: usize) -> (Vec<Request>, Vec<u8>) {
+    let mut slcan = Slcan::new(0x1A2B);
+    let mut serial = Serial::new(chunk);
+    let mut requests = vec![];
+
+    for step in steps {
+        match step {
+            Step::Host(bytes) => serial.input.extend(bytes.iter()),
+            Step::Bus(frame, millis) => {
+                let now = Instant::from_time_since_boot(Duration::from_millis(*millis));
+                slcan.on_frame(frame, &now);
+            }
+        }
+        while let Some(command) = slcan.poll(&mut serial) {
+            requests.push(match command {
+                SlcanCommand::Open {
+                    bitrate,
+                    nominal_timing,
+                    listen_only,
+                } => Request::Open {
+                    nominal: bitrate.nominal.value(),
+                    data: bitrate.data.map(|data| data.value()),
+                    timing: nominal_timing.map(|timing| {
+                        (
+                            timing.sync_jump_width(),
+                            timing.pre_scaler(),
+                            timing.tseg1(),
+                            timing.tseg2(),
+                        )
+                    }),
+                    listen_only,
+                },
+                SlcanCommand::Close => Request::Close,
+                SlcanCommand::Transmit(frame) => Request::Transmit {
+                    id: frame.id,
+                    data: frame.data().to_vec(),
+                    remote: frame.remote,
+                    fd_format: frame.fd_format,
+                    bitrate_switching: frame.bitrate_switching,
+                },
+            });
+            slcan.complete(true);
+        }
+    }
+    slcan.flush(&mut serial);
+
+    (requests, serial.output)
+}
+
+fn transmit(id: CanID, data: &[u8]) -> Request {
+    Request::Transmit {
+        id,
+        data: data.to_vec(),
+        remote: false,
+        fd_format: false,
+        bitrate_switching: false,
+    }
+}
+
+/// `slcand -o -c -b 011C -F` followed by `cansend` and `candump`
+fn session() -> Vec<Step> {
+    vec![
+        // slcand flushes the adapter, closes it and configures it, 250 kbit/s in the
+        // SJA1000 bus timing registers
+        Step::Host(b"\r\r\rC\rs011C\rY2\rV\rN\rZ1\rO\r"),
+        Step::Host(b"t12321122\r"),
+        Step::Host(b"T123456783112233\r"),
+        Step::Host(b"r1232\r"),
+        Step::Host(b"b1239000102030405060708090A0B\r"),
+        Step::Bus(
+            SlcanFrame::new(CanID::Standard(0x7FF), &[0xDE, 0xAD], false, false),
+            1234,
+        ),
+        Step::Bus(
+            SlcanFrame::new(
+                CanID::Extended(0x18DA_F110),
+                &[0x02, 0x10, 0x03],
+                false,
+                false,
+            ),
+            61_000,
+        ),
+        Step::Bus(SlcanFrame::remote(CanID::Standard(0x100), 4), 61_001),
+        Step::Bus(
+            SlcanFrame::new(CanID::Standard(0x10), &[0x55; 12], true, true),
+            61_002,
+        ),
+        Step::Host(b"F\rC\r"),
+        // Frames after closing are not passed on
+        Step::Bus(
+            SlcanFrame::new(CanID::Standard(0x7FF), &[0xDE, 0xAD], false, false),
+            62_000,
+        ),
+    ]
+}
+
+const SESSION_OUTPUT: &[u8] = b"\r\r\rV1013\rN1A2B\r\r\r\
+z\rZ\rz\rz\r\
+t7FF2DEAD04D2\rT18DAF110302100303E8\rr100403E9\rb010955555555555555555555555503EA\r\
+F00\r\r";
+
+#[test]
+fn recorded_session() {
+    let (requests, output) = replay(&session(), usize::MAX);
+
+    assert!(
+        requests
+            == [
+                Request::Open {
+                    nominal: 250,
+                    data: Some(2_000),
+                    // Time quanta of 4 SJA1000 clock periods are 20 periods at 80 MHz
+                    timing: Some((1, 20, 13, 2)),
+                    listen_only: false,
+                },
+                transmit(CanID::Standard(0x123), &[0x11, 0x22]),
+                transmit(CanID::Extended(0x1234_5678), &[0x11, 0x22, 0x33]),
+                Request::Transmit {
+                    id: CanID::Standard(0x123),
+                    data: vec![],
+                    remote: true,
+                    fd_format: false,
+                    bitrate_switching: false,
+                },
+                Request::Transmit {
+                    id: CanID::Standard(0x123),
+                    data: (0..12).collect(),
+                    remote: false,
+                    fd_format: true,
+                    bitrate_switching: true,
+                },
+                Request::Close,
+            ]
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output),
+        String::from_utf8_lossy(SESSION_OUTPUT)
+    );
+}
+
+#[test]
+fn recorded_session_on_slow_serial_port() {
+    let (_, output) = replay(&session(), 1);
+
+    assert_eq!(
+        String::from_utf8_lossy(&output),
+        String::from_utf8_lossy(SESSION_OUTPUT)
+    );
+}
+
+#[test]
+fn invalid_commands() {
+    let steps = [
+        // Open without bitrate
+        Step::Host(b"O\r"),
+        // Transmit while closed
+        Step::Host(b"t12321122\r"),
+        // Pre-scaler out of range, single time quantum in the first segment
+        Step::Host(b"S9\rs3F1C\rs0110\rs01\rX\r"),
+        Step::Host(b"S4\rO\r"),
+        // Bitrate while open, open twice
+        Step::Host(b"S6\rO\r"),
+        // Identifier out of range, too long for classic CAN, wrong number of digits
+        Step::Host(b"t8000\rt1239000102030405060708090A0B\rt123211\r"),
+        Step::Host(b"C\r"),
+    ];
+    let (requests, output) = replay(&steps, usize::MAX);
+
+    assert!(
+        requests
+            == [
+                Request::Open {
+                    nominal: 125,
+                    data: None,
+                    timing: None,
+                    listen_only: false,
+                },
+                Request::Close,
+            ]
+    );
+    assert_eq!(
+        output,
+        b"\x07\x07\x07\x07\x07\x07\x07\r\r\x07\x07\x07\x07\x07\r"
+    );
+}
+
+#[test]
+fn listen_only() {
+    let steps = [
+        Step::Host(b"S8\rL\r"),
+        Step::Host(b"t12321122\r"),
+        Step::Bus(SlcanFrame::new(CanID::Standard(0x1), &[1], false, false), 0),
+    ];
+    let (requests, output) = replay(&steps, usize::MAX);
+
+    assert!(
+        requests
+            == [Request::Open {
+                nominal: 1_000,
+                data: None,
+                timing: None,
+                listen_only: true,
+            }]
+    );
+    assert_eq!(output, b"\r\r\x07t001101\r");
+}
+
+#[test]
+fn overrun() {
+    let mut slcan = Slcan::new(0);
+    let mut serial = Serial::new(0);
+    serial.input.extend(b"S6\rO\r");
+    assert!(matches!(
+        slcan.poll(&mut serial),
+        Some(SlcanCommand::Open { .. })
+    ));
+    slcan.complete(true);
+
+    // The host does not read, the output fills up
+    let frame = SlcanFrame::new(CanID::Standard(0x123), &[0; 8], false, false);
+    for _ in 0..100 {
+        slcan.on_frame(&frame, &Instant::from_time_since_boot(Duration::ZERO));
+    }
+
+    serial.chunk = usize::MAX;
+    serial.input.extend(b"F\r");
+    assert!(slcan.poll(&mut serial).is_none());
+    slcan.flush(&mut serial);
+    assert!(serial.output.ends_with(b"F08\r"));
+}
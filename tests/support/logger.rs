@@ -0,0 +1,24 @@
+//!
+//! defmt needs a logger and a panic handler to link the host tests, the output is
+//! dropped and defmt panics become test failures
+//!
+
+#[defmt::global_logger]
+struct Logger;
+
+unsafe impl defmt::Logger for Logger {
+    fn acquire() {}
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {}
+
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+defmt::timestamp!("{=u32}", 0);
+
+#[defmt::panic_handler]
+fn panic() -> ! {
+    panic!("defmt panic")
+}
@@ -0,0 +1,63 @@
+//!
+//! Helpers shared by the host tests
+//!
+// Every test file includes the module, but not every test uses every helper
+#![allow(dead_code)]
+
+use tc37x_hal::can::{
+    memory::module_ram::{BufferSize8, CanBuffer, NodeMemoryBuilder},
+    node::{
+        connection::{Connection, InternalBusConnected, PinDisconnected},
+        receive::RxFifo0,
+        transceive::TxDedicated,
+        CanNode, Running,
+    },
+    sim::{SimPin, SimulatedModule, SimulatedRAM},
+    CanID, CanTxFrame,
+};
+
+mod logger;
+
+/// A running node of the simulated module on the internal loop-back bus
+pub type LoopbackNode<'a> = CanNode<
+    'a,
+    Connection<PinDisconnected<SimPin>, InternalBusConnected>,
+    Running,
+    TxDedicated<'a, BufferSize8, SimulatedRAM>,
+    RxFifo0<'a, BufferSize8, SimulatedRAM>,
+    SimulatedModule,
+>;
+
+/// The first `N` nodes of the module on the internal loop-back bus, each with `tx`
+/// transmit buffers and `rx` elements in FIFO 0. Use
+/// [reconfigure](CanNode::reconfigure) for other settings.
+pub fn loopback_nodes<const N: usize>(
+    module: &SimulatedModule,
+    tx: usize,
+    rx: usize,
+) -> [LoopbackNode<'_>; N] {
+    let mut ram = unsafe { NodeMemoryBuilder::<SimulatedRAM>::steal_module_mem() };
+
+    core::array::from_fn(|index| {
+        module
+            .node(index)
+            .connect_internal_loopback()
+            .set_tx(ram.take_expect(tx))
+            .set_rx_fifo0(ram.take_expect(rx))
+            .finalize()
+    })
+}
+
+pub fn frame<B: CanBuffer>(id: CanID, data: &[u8]) -> CanTxFrame<B> {
+    let mut frame = CanTxFrame::default();
+    frame.set_id(id);
+    frame.set_data(data);
+    frame
+}
+
+pub fn remote<B: CanBuffer>(id: CanID, length: usize) -> CanTxFrame<B> {
+    let mut frame = CanTxFrame::default();
+    frame.set_id(id);
+    frame.set_remote_request(length);
+    frame
+}